@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate criterion;
+extern crate gridist;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+
+use gridist::agent::{AlwaysAstar, RepeatedAstar};
+use gridist::cost::MoveMetric;
+use gridist::experiment::{Experiment, Verbosity};
+use gridist::grid::{Distance, Measure};
+use gridist::parser::grid_from_file;
+
+const ALGORITHMS: &[&str] = &["astar", "rastar"];
+
+/// Maps to run on, with the trial counts to run on each. Enigma is much
+/// larger than the synthetic maps, so it only runs the smaller trial count.
+const SCENARIOS: &[(&str, &[usize])] = &[("Mini", &[5, 20]),
+                                         ("Open", &[5, 20]),
+                                         ("Rooms", &[5, 20]),
+                                         ("Maze", &[5, 20]),
+                                         ("Enigma", &[5])];
+
+/// Runs every trial in `experiment` with `algorithm`, returning the total
+/// number of expansions performed.
+fn run(experiment: &mut Experiment, algorithm: &str, trials: usize) -> usize {
+    let heuristic = Distance::octile_heuristic;
+    let metric = MoveMetric::Length;
+
+    let data = match algorithm {
+        "astar" => experiment.run(AlwaysAstar::new(heuristic, metric)),
+        "rastar" => experiment.run(RepeatedAstar::new(heuristic, metric)),
+        other => panic!("Unknown algorithm: {}", other),
+    };
+
+    (0..trials).map(|i| data[i].expansions).sum()
+}
+
+fn experiments(c: &mut Criterion) {
+    for &(map, trial_counts) in SCENARIOS {
+        let mut group = c.benchmark_group(map);
+        group.sample_size(10);
+
+        for &trials in trial_counts {
+            for algorithm in ALGORITHMS {
+                let grid = grid_from_file(&format!("maps/{}.map", map));
+                let mut experiment =
+                    Experiment::trials(grid, 0, trials, 0, Verbosity::Zero);
+
+                let expansions = run(&mut experiment, algorithm, trials);
+                group.throughput(Throughput::Elements(expansions as u64));
+
+                let id = BenchmarkId::new(*algorithm, trials);
+                group.bench_with_input(id, &trials, |b, &trials| {
+                    b.iter(|| run(&mut experiment, algorithm, trials))
+                });
+            }
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, experiments);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate criterion;
+extern crate gridist;
+
+use criterion::{black_box, Criterion};
+
+use gridist::grid::{Distance, Grid, Measure, Point};
+use gridist::heuristic::HeuristicCache;
+use gridist::landmarks::Landmarks;
+use gridist::parser::grid_from_file;
+
+/// Adds up the estimates from every tile of `grid` to `target`, as a search
+/// that expands the whole map towards it would.
+fn sweep<H>(grid: &Grid, target: &Point, heuristic: H) -> Distance
+    where H: Fn(&Point, &Point) -> Distance
+{
+    let mut total = 0.0;
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            total += heuristic(&Point::new(y, x), target);
+        }
+    }
+    total
+}
+
+/// Compares computing estimates with looking them up once cached, on a large
+/// map, for a cheap heuristic and a costlier one. The cache is filled before
+/// measuring, as it is after the first search towards a target.
+fn cache(c: &mut Criterion) {
+    let grid = grid_from_file("maps/Enigma.map");
+    let target = Point::new(grid.height() / 2, grid.width() / 2);
+    let landmarks = Landmarks::from_grid(&grid, 8);
+    let landmarks = |from: &Point, to: &Point| landmarks.estimate(from, to);
+
+    let mut group = c.benchmark_group("heuristic_cache");
+
+    group.bench_function("octile_direct", |b| {
+        b.iter(|| sweep(&grid, black_box(&target), Distance::octile_heuristic))
+    });
+    let octile = HeuristicCache::new(Distance::octile_heuristic, &grid);
+    sweep(&grid, &target, |from, to| octile.estimate(from, to));
+    group.bench_function("octile_cached", |b| {
+        b.iter(|| {
+            sweep(&grid,
+                  black_box(&target),
+                  |from, to| octile.estimate(from, to))
+        })
+    });
+    group.bench_function("landmarks_direct", |b| {
+        b.iter(|| sweep(&grid, black_box(&target), landmarks))
+    });
+    let cached = HeuristicCache::new(landmarks, &grid);
+    sweep(&grid, &target, |from, to| cached.estimate(from, to));
+    group.bench_function("landmarks_cached", |b| {
+        b.iter(|| {
+            sweep(&grid,
+                  black_box(&target),
+                  |from, to| cached.estimate(from, to))
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, cache);
+criterion_main!(benches);
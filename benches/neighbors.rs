@@ -0,0 +1,77 @@
+#[macro_use]
+extern crate criterion;
+extern crate gridist;
+
+use criterion::{black_box, Criterion};
+
+use gridist::grid::{Direction, Distance, Grid, Measure, Point, Tile};
+use gridist::parser::grid_from_file;
+use gridist::search::astar;
+
+/// Adds up the cost of every move out of every tile of `grid`, finding the
+/// neighbors from the coordinates of the tiles and looking each one up, as
+/// planners did before `Grid::successors` yielded indices.
+fn sweep_points(grid: &Grid) -> Distance {
+    let mut total = 0.0;
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            let neighbors = Point::new(y, x)
+                .neighbors_within(grid.height(), grid.width());
+            for (direction, neighbor) in neighbors.iter().enumerate() {
+                let index = match neighbor.and_then(|n| grid.index_of(&n)) {
+                    Some(index) if grid.tile(index).passable() => index,
+                    _ => continue,
+                };
+                let direction = Direction::ALL[direction];
+                total += grid.move_cost(None, direction, index);
+            }
+        }
+    }
+    total
+}
+
+/// Adds up the same costs as `sweep_points`, through `Grid::successors`.
+fn sweep_indices(grid: &Grid) -> Distance {
+    let mut total = 0.0;
+    for index in 0..grid.height() * grid.width() {
+        for (_, _, cost) in grid.successors(index, None, Tile::passable) {
+            total += cost;
+        }
+    }
+    total
+}
+
+/// Compares finding the neighbors of tiles by their coordinates with
+/// finding them by their indices, on a large map, alone and within A*
+/// searches across it.
+fn neighbors(c: &mut Criterion) {
+    let mut grid = grid_from_file("maps/Enigma.map");
+    assert_eq!(sweep_points(&grid), sweep_indices(&grid));
+
+    let mut group = c.benchmark_group("neighbors");
+
+    group.bench_function("point_neighbors", |b| {
+        b.iter(|| sweep_points(black_box(&grid)))
+    });
+    group.bench_function("successors", |b| {
+        b.iter(|| sweep_indices(black_box(&grid)))
+    });
+
+    let source = Point::new(grid.height() / 4, grid.width() / 4);
+    let target = Point::new(3 * grid.height() / 4, 3 * grid.width() / 4);
+    group.bench_function("astar", |b| {
+        b.iter(|| {
+            astar(&mut grid,
+                  black_box(&source),
+                  black_box(&target),
+                  Distance::octile_heuristic,
+                  Tile::passable)
+                .map(|data| data.expansions)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, neighbors);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+//! Runs an agent defined outside of gridist over random trials on a map,
+//! next to repeated A*. The agent plans with weighted A*, which inflates the
+//! octile heuristic to expand fewer nodes, and follows its plan until it
+//! finds its next move blocked. It is registered under a name of its own,
+//! as any built-in agent, and takes the weight as a parameter.
+//!
+//!     cargo run --release --example custom_agent [<map>] [<trials>]
+
+extern crate gridist;
+
+use std::env::args;
+
+use gridist::agent::{Agent, Datum};
+use gridist::experiment::{Experiment, Verbosity};
+use gridist::grid::{Distance, Grid, Measure, Point, Tile};
+use gridist::parser::grid_from_file;
+use gridist::registry::{parameter, Parameters, Registry};
+use gridist::search::{astar, Path};
+
+const SEED: usize = 0;
+
+/// Plans with A* over the octile heuristic inflated by `weight`, which finds
+/// paths at most `weight` times as costly as the optimal ones over what the
+/// agent believes.
+struct WeightedAstar {
+    weight: Distance,
+    /// The rest of the plan, backwards, as searches return it.
+    path: Path,
+}
+
+impl Agent for WeightedAstar {
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        let mut expansions = 0;
+        if !self.path.last().is_some_and(|next| grid[next].freespace()) {
+            let weight = self.weight;
+            let heuristic = |from: &Point, to: &Point| {
+                weight * Distance::octile_heuristic(from, to)
+            };
+            let data = astar(grid,
+                             location,
+                             target,
+                             heuristic,
+                             Tile::freespace)?;
+            expansions = data.expansions;
+            self.path = data.path;
+        }
+        self.path
+            .pop()
+            .map(|next| Datum::towards(location, &next, expansions))
+    }
+
+    fn reset(&mut self) {
+        self.path.clear();
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+fn main() {
+    let map = args().nth(1).unwrap_or_else(|| "maps/Rooms.map".to_owned());
+    let trials = args().nth(2).map_or(20, |trials| {
+        trials.parse().expect("The number of trials must be a number.")
+    });
+
+    let mut registry = Registry::default();
+    registry.register("wastar", |parameters, _| {
+        Ok(Box::new(WeightedAstar {
+                        weight: parameter(parameters, "weight", 2.0)?,
+                        path: Path::new(),
+                    }))
+    });
+
+    let mut experiment = Experiment::trials(grid_from_file(&map),
+                                            0,
+                                            trials,
+                                            SEED,
+                                            Verbosity::Zero);
+    let parameters = Parameters::new();
+    for name in &["rastar", "wastar"] {
+        let agent = registry.build(name, &parameters, experiment.grid())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let data = experiment.run(agent);
+        println!("{}: {}", name, data.summary());
+    }
+}
@@ -0,0 +1,62 @@
+//! Generates a map out of noise, connects the whole of it, and compares two
+//! agents over the same random trials on it, trial by trial, with a
+//! Wilcoxon signed-rank test on their costs.
+//!
+//!     cargo run --example generate_and_compare [<seed> [<first> <second>]]
+
+extern crate gridist;
+
+use std::env::args;
+
+use gridist::comparison::compare;
+use gridist::experiment::{Experiment, Region, Verbosity};
+use gridist::generator::{ensure_connected, NoiseGenerator};
+use gridist::registry::{Parameters, Registry};
+
+const HEIGHT: usize = 64;
+const WIDTH: usize = 64;
+const TRIALS: usize = 50;
+
+fn main() {
+    let args = args().skip(1).collect::<Vec<_>>();
+    let seed = args.first().map_or(0, |seed| {
+        seed.parse().expect("The seed must be a number.")
+    });
+    let (first, second) = match args.len() {
+        3 => (args[1].as_str(), args[2].as_str()),
+        _ => ("astar", "rastar"),
+    };
+
+    let generator = NoiseGenerator {
+        seed: seed,
+        ..NoiseGenerator::default()
+    };
+    let mut grid = generator.generate(HEIGHT, WIDTH);
+    let carved = ensure_connected(&mut grid, &[Region::Anywhere])
+        .expect("The whole map is a valid region.");
+    println!("Generated a {}x{} map with seed {}, carving {} tiles to \
+              connect it:",
+             HEIGHT,
+             WIDTH,
+             seed,
+             carved.len());
+    print!("{}", grid);
+
+    let registry = Registry::default();
+    let parameters = Parameters::new();
+    let mut experiment =
+        Experiment::trials(grid, 0, TRIALS, seed, Verbosity::Zero);
+    let mut run = |name: &str| {
+        let agent = registry.build(name, &parameters, experiment.grid())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let data = experiment.run(agent);
+        println!("{}: {}", name, data.summary());
+        data
+    };
+    let first_data = run(first);
+    let second_data = run(second);
+    println!("{} vs. {}: {}",
+             second,
+             first,
+             compare(&first_data, &second_data));
+}
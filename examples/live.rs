@@ -0,0 +1,41 @@
+//! Streams a few random trials to the viewer in `examples/live.html`, which
+//! renders them as they run.
+//!
+//!     cargo run --release --features live --example live [<map>] [<address>]
+//!
+//! The viewer connects to `ws://127.0.0.1:9001/` by default; another address
+//! can be given after a `#` in its URL. Trials start once it has connected.
+
+extern crate gridist;
+
+use std::env::args;
+use std::time::Duration;
+
+use gridist::agent::RepeatedAstar;
+use gridist::cost::MoveMetric;
+use gridist::experiment::{Experiment, Verbosity};
+use gridist::grid::{Distance, Measure};
+use gridist::live::Streamer;
+use gridist::parser::grid_from_file;
+
+const TRIALS: usize = 10;
+const DELAY: u64 = 50;
+
+fn main() {
+    let map = args().nth(1).unwrap_or_else(|| "maps/Rooms.map".to_owned());
+    let address = args().nth(2).unwrap_or_else(|| "127.0.0.1:9001".to_owned());
+    let grid = grid_from_file(&map);
+
+    let mut streamer = Streamer::bind(&address)
+        .unwrap_or_else(|e| panic!("Cannot listen on {}: {}", address, e));
+    streamer.set_delay(Some(Duration::from_millis(DELAY)));
+    println!("Waiting for a viewer on ws://{}/ ...", streamer.local_addr());
+    streamer.wait_for_client();
+
+    let mut experiment =
+        Experiment::trials(grid, 0, TRIALS, 0, Verbosity::Zero);
+    experiment.add_observer(Box::new(streamer));
+    let data = experiment.run(RepeatedAstar::new(Distance::octile_heuristic,
+                                                 MoveMetric::Length));
+    println!("{}", data.summary());
+}
@@ -0,0 +1,48 @@
+//! Rolls out a uniformly random policy in the `env` interface, reporting the
+//! return of each episode.
+//!
+//!     cargo run --release --example random_rollout [<map>]
+
+extern crate gridist;
+extern crate rand;
+
+use std::env::args;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use gridist::env::{Action, Env};
+use gridist::grid::Point;
+use gridist::parser::grid_from_file;
+
+const EPISODES: usize = 5;
+const MAX_STEPS: usize = 100_000;
+
+fn main() {
+    let map = args().nth(1).unwrap_or_else(|| "maps/Open.map".to_owned());
+    let mut grid = grid_from_file(&map);
+    let source = Point::new(1, 1);
+    let target = Point::new(grid.height() / 2, grid.width() / 2);
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[0][..]);
+    let mut env = Env::new(&mut grid, 2);
+
+    for episode in 0..EPISODES {
+        env.reset(source, target);
+        let mut total = 0.0;
+        let mut steps = 0;
+        let mut done = false;
+        while !done && steps < MAX_STEPS {
+            let action = Action::from_index(rng.gen_range(0, Action::COUNT))
+                .unwrap();
+            let step = env.step(action);
+            total += step.reward;
+            done = step.done;
+            steps += 1;
+        }
+        println!("Episode {}: return {:.2} over {} steps ({}).",
+                 episode,
+                 total,
+                 steps,
+                 if done { "reached target" } else { "gave up" });
+    }
+}
@@ -0,0 +1,102 @@
+//! Steps through a single trial a move at a time, driving an `Instance`
+//! from outside rather than running the trial with `run_once`, and renders
+//! the agent's beliefs after every move. The agent is repeated A*, given a
+//! long-range scanner with a few scans, which it uses when the moves ahead
+//! of it go through tiles it has not seen.
+//!
+//!     cargo run --example step_by_step [<map> <starty> <startx> <endy> <endx>]
+
+extern crate gridist;
+
+use std::env::args;
+use std::process::exit;
+
+use gridist::agent::{Action, Agent, Datum, RepeatedAstar};
+use gridist::cost::MoveMetric;
+use gridist::execution::Scanner;
+use gridist::experiment::Verbosity;
+use gridist::grid::{Distance, Grid, Measure, Point, Sensor};
+use gridist::instance::Instance;
+use gridist::observer::render_beliefs;
+use gridist::parser::grid_from_file;
+
+const MAX_STEPS: usize = 1000;
+
+/// Stands in for the agent in the instance, which only carries out the
+/// moves chosen here.
+struct Driven;
+
+impl Agent for Driven {
+    fn act(&mut self, _: &mut Grid, _: &Point, _: &Point) -> Option<Datum> {
+        None
+    }
+}
+
+fn main() {
+    let args = args().skip(1).collect::<Vec<_>>();
+    let (map, source, target) = if args.len() == 5 {
+        let coordinates = args[1..]
+            .iter()
+            .map(|c| c.parse().expect("Coordinates must be numbers."))
+            .collect::<Vec<usize>>();
+        (args[0].clone(),
+         Point::new(coordinates[0], coordinates[1]),
+         Point::new(coordinates[2], coordinates[3]))
+    } else {
+        ("maps/Mini.map".to_owned(), Point::new(0, 0), Point::new(9, 9))
+    };
+
+    let scanner = Scanner {
+        sensor: Sensor {
+            radius: 4,
+            terrain_radius: None,
+        },
+        cost: 1.0,
+        budget: Some(3),
+    };
+    let mut agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+    agent.set_scanner(Some(scanner));
+
+    let mut grid = grid_from_file(&map);
+    let mut instance = Instance::new(&mut grid, Driven, Verbosity::Zero);
+    instance.set_scanner(Some(scanner));
+    instance.start(source);
+    agent.reset();
+
+    let mut frame = String::new();
+    for step in 1..MAX_STEPS + 1 {
+        let location = instance.location();
+        let datum = match agent.act(instance.grid_mut(), &location, &target) {
+            Some(datum) => datum,
+            None => {
+                println!("The agent believes the target cannot be reached.");
+                exit(1);
+            }
+        };
+        if datum.action == Action::Sense {
+            instance.scan();
+        } else {
+            let next = datum.action
+                .target(&location)
+                .expect("Agents never move off the grid.");
+            instance.move_agent(next);
+        }
+
+        frame.clear();
+        let location = instance.location();
+        render_beliefs(instance.grid(), &location, &target, &mut frame)
+            .expect("Rendering into a string cannot fail.");
+        println!("Step {}: {:?} after {} expansions",
+                 step,
+                 datum.action,
+                 datum.expansions);
+        print!("{}", frame);
+
+        if instance.location() == target {
+            println!("Reached the target in {} steps.", step);
+            return;
+        }
+    }
+    println!("Gave up after {} steps.", MAX_STEPS);
+}
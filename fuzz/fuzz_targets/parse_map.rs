@@ -0,0 +1,25 @@
+//! Feeds arbitrary bytes to the map parser, which must reject malformed maps
+//! without panicking, and checks that every map it accepts survives being
+//! written and read again in each format.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate gridist;
+
+use gridist::parser::{try_grid_from_reader, write_grid, MapFormat};
+
+fuzz_target!(|data: &[u8]| {
+    let grid = match try_grid_from_reader(data) {
+        Ok(grid) => grid,
+        Err(_) => return,
+    };
+
+    for &format in &[MapFormat::Movingai, MapFormat::Rle] {
+        let mut written = Vec::new();
+        write_grid(&grid, format, &mut written).unwrap();
+        let read = try_grid_from_reader(&written[..]).unwrap();
+        assert_eq!(read.content_hash(), grid.content_hash());
+    }
+});
@@ -1,12 +1,105 @@
-use grid::{Distance, Grid, Point, Tile};
-use search::{astar, Path};
+use std::cmp::{max, min, Ordering};
+use std::fmt;
+use std::fs::File;
+use std::io::{stdin, BufRead, BufReader, Read, Result as IOResult, Stdin};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path as FilePath, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+use cost::MoveMetric;
+use cpd::Cpd;
+use execution::Scanner;
+use experiment::Verbosity;
+use field::Metric;
+use grid::{Belief, Direction, Distance, Grid, Measure, Movement, Point,
+           Tile};
+use observer::Printer;
+use paths::PathCache;
+use quadtree::Quadtree;
+use reservation::ReservationTable;
+use search::{astar, astar_until, quadtree_astar, space_time_astar, Path};
+
+/// What an agent does in a step. Every action takes the agent at most one
+/// tile away, so agents cannot jump across the grid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Action {
+    /// Move to the neighbor in the given direction.
+    Move(Direction),
+    /// Stay in place.
+    Wait,
+    /// Stay in place, scanning the tiles around with the long-range scanner
+    /// (see `Instance::set_scanner`). Without a scan left, this is waiting.
+    Sense,
+}
+
+impl Action {
+    /// The number of distinct actions.
+    pub const COUNT: usize = 10;
+
+    /// Returns the action numbered `index`: the eight moves come first, in
+    /// direction order, followed by waiting and sensing.
+    pub fn from_index(index: usize) -> Option<Action> {
+        match index {
+            8 => Some(Action::Wait),
+            9 => Some(Action::Sense),
+            _ => Direction::from_index(index).map(Action::Move),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match *self {
+            Action::Move(direction) => direction.index(),
+            Action::Wait => 8,
+            Action::Sense => 9,
+        }
+    }
+
+    /// Returns the action that takes an agent at `from` to `to`: waiting if
+    /// they are the same point, or a move if `to` is a neighbor of `from`.
+    /// No action reaches any other point.
+    pub fn towards(from: &Point, to: &Point) -> Option<Action> {
+        if from == to {
+            Some(Action::Wait)
+        } else {
+            Direction::between(from, to).map(Action::Move)
+        }
+    }
+
+    /// Returns where the action takes an agent at `from`, unless it would
+    /// move off the top or left edge of the grid.
+    pub fn target(&self, from: &Point) -> Option<Point> {
+        match *self {
+            Action::Move(direction) => direction.from(from),
+            Action::Wait | Action::Sense => Some(*from),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Datum {
-    pub action: Point,
+    pub action: Action,
     pub expansions: usize,
 }
 
+impl Datum {
+    /// Builds the datum of the action that takes an agent at `location` to
+    /// `target`, for agents that plan over points.
+    ///
+    /// Panics if `target` is neither `location` nor one of its neighbors.
+    pub fn towards(location: &Point,
+                   target: &Point,
+                   expansions: usize)
+                   -> Datum {
+        Datum {
+            action: Action::towards(location, target)
+                .expect("Attempted to act towards a non-neighbor."),
+            expansions: expansions,
+        }
+    }
+}
+
 pub trait Agent {
     fn act(&mut self,
            grid: &mut Grid,
@@ -15,16 +108,125 @@ pub trait Agent {
            -> Option<Datum>;
 
     fn reset(&mut self) {}
+
+    /// Gives the agent until `deadline` to decide on its next move, if
+    /// given, before every call to `act`. Agents capable of anytime planning
+    /// stop searching then, and follow the best plan they found; others
+    /// ignore it.
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
+
+    /// Tells the agent that its last move failed, bumping into the tile at
+    /// `point` (see `ExecutionModel::Bump`), so it is still where it was.
+    /// Agents that keep a plan drop it, since it no longer starts at their
+    /// location.
+    fn bumped(&mut self, _point: &Point) {}
+
+    /// Tells the agent that the target moved to `target`, in pursuits (see
+    /// `Instance::set_pursuit`). Agents that keep a plan drop it, since it
+    /// no longer ends at the target.
+    fn target_moved(&mut self, _target: &Point) {}
+
+    /// Gives the agent the long-range scanner it may sense with in every
+    /// trial, if any (see `Action::Sense`). Agents that never sense ignore
+    /// it.
+    fn set_scanner(&mut self, _scanner: Option<Scanner>) {}
+
+    /// The rest of the plan the agent is following, if it keeps one. Plans
+    /// are kept backwards, as searches return them, so the next move is the
+    /// last point.
+    fn current_plan(&self) -> Option<&Path> {
+        None
+    }
+
+    /// The moves the agent intends to make after its last action, in order.
+    /// Agents that do not keep a plan return an empty path.
+    fn planned_path(&self) -> Path {
+        remaining(self.current_plan())
+    }
+
+    /// The metric the agent's moves are measured with in the results: their
+    /// length, unless the agent was built with another metric. Baselines
+    /// the agent is compared to are measured with it too.
+    /// This is kept apart from how the agent weighs moves while planning,
+    /// which is up to its heuristic and the grid's cost model.
+    fn metric(&self) -> MoveMetric {
+        MoveMetric::Length
+    }
+}
+
+/// Whether `point` lies within `grid` and is believed to be free.
+fn free(grid: &Grid, point: &Point) -> bool {
+    grid.try_get(point).is_ok_and(Tile::freespace)
+}
+
+/// Lists the moves left in `path`, which agents keep in reverse so that the
+/// next move can be popped off its end.
+fn remaining(path: Option<&Path>) -> Path {
+    path.iter().flat_map(|path| path.iter().rev()).cloned().collect()
+}
+
+impl<A> Agent for Box<A>
+    where A: Agent + ?Sized
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        (**self).act(grid, location, target)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        (**self).set_deadline(deadline)
+    }
+
+    fn bumped(&mut self, point: &Point) {
+        (**self).bumped(point)
+    }
+
+    fn target_moved(&mut self, target: &Point) {
+        (**self).target_moved(target)
+    }
+
+    fn set_scanner(&mut self, scanner: Option<Scanner>) {
+        (**self).set_scanner(scanner)
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        (**self).current_plan()
+    }
+
+    fn planned_path(&self) -> Path {
+        (**self).planned_path()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        (**self).metric()
+    }
 }
 
 #[derive(Debug)]
 pub struct AlwaysAstar<H> {
     heuristic: H,
+    metric: MoveMetric,
+    deadline: Option<Instant>,
+    /// The rest of the path found by the last search, which is only kept
+    /// to report it, since the agent searches again before every move.
+    path: Option<Path>,
 }
 
 impl<H> AlwaysAstar<H> {
-    pub fn new(heuristic: H) -> AlwaysAstar<H> {
-        AlwaysAstar { heuristic: heuristic }
+    pub fn new(heuristic: H, metric: MoveMetric) -> AlwaysAstar<H> {
+        AlwaysAstar {
+            heuristic: heuristic,
+            metric: metric,
+            deadline: None,
+            path: None,
+        }
     }
 }
 
@@ -36,31 +238,110 @@ impl<H> Agent for AlwaysAstar<H>
            location: &Point,
            target: &Point)
            -> Option<Datum> {
-        astar(grid, location, target, &self.heuristic, Tile::freespace)
-            .and_then(|mut data| {
-                data.path.pop().map(|next| {
-                                        Datum {
-                                            action: next,
-                                            expansions: data.expansions,
-                                        }
-                                    })
-            })
+        let mut data = astar_until(grid,
+                                   location,
+                                   target,
+                                   &self.heuristic,
+                                   Tile::freespace,
+                                   self.deadline)?;
+        let next = data.path.pop();
+        let expansions = data.expansions;
+        self.path = Some(data.path);
+        next.map(|next| Datum::towards(location, &next, expansions))
+    }
+
+    fn reset(&mut self) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
     }
 }
 
 #[derive(Debug)]
 pub struct RepeatedAstar<H> {
     heuristic: H,
+    metric: MoveMetric,
     path: Option<Path>,
+    /// How close to the rest of the path a change of beliefs has to be for
+    /// the agent to replan, if it replans before finding its next move
+    /// blocked.
+    replan_radius: Option<usize>,
+    /// The paths planned in earlier trials, if the agent reuses them.
+    cache: Option<PathCache>,
+    /// When the agent has to decide on its next move by, if it is limited.
+    deadline: Option<Instant>,
+    /// The long-range scanner the agent may sense with, if it has one.
+    scanner: Option<Scanner>,
+    /// The scans made in the current trial.
+    scans: usize,
+    /// Whether the agent scanned in its last step, which may have shown its
+    /// path to be blocked further on than its next move.
+    scanned: bool,
 }
 
 impl<H> RepeatedAstar<H>
     where H: Fn(&Point, &Point) -> Distance
 {
-    pub fn new(heuristic: H) -> RepeatedAstar<H> {
+    pub fn new(heuristic: H, metric: MoveMetric) -> RepeatedAstar<H> {
         RepeatedAstar {
             heuristic: heuristic,
+            metric: metric,
             path: None,
+            replan_radius: None,
+            cache: None,
+            deadline: None,
+            scanner: None,
+            scans: 0,
+            scanned: false,
+        }
+    }
+
+    /// Makes the agent keep every path it plans, across trials, and follow
+    /// a kept path through its location to its target, if it is still
+    /// believed free, instead of searching. Meant for trials that retain
+    /// the beliefs of those before them, where kept paths stay as good as
+    /// when they were planned until the agent learns otherwise.
+    pub fn set_reuse_paths(&mut self, reuse: bool) {
+        self.cache = if reuse { Some(PathCache::new()) } else { None };
+    }
+
+    /// Makes the agent replan as soon as it finds a tile within `radius`
+    /// moves of the rest of its path to be impassable, or of some costlier
+    /// terrain, rather than only once its next move is blocked. Smaller radii
+    /// replan less often, but follow worse paths for longer.
+    pub fn set_replan_radius(&mut self, radius: Option<usize>) {
+        self.replan_radius = radius;
+    }
+
+    /// Drops the path if the beliefs changed within the replanning radius
+    /// of it since the agent last acted.
+    fn check_changes(&mut self, grid: &mut Grid) {
+        let radius = match self.replan_radius {
+            Some(radius) => radius,
+            None => return,
+        };
+        grid.record_belief_changes();
+        let changes = grid.take_belief_changes();
+        let near = self.path.as_ref().is_some_and(|path| {
+            changes.iter().any(|change| {
+                path.iter().any(|point| {
+                    Metric::Chebyshev.distance(point, change) <=
+                    radius as Distance
+                })
+            })
+        });
+        if near {
+            self.path = None;
         }
     }
 
@@ -69,16 +350,93 @@ impl<H> RepeatedAstar<H>
                    location: &Point,
                    target: &Point)
                    -> usize {
-        astar(grid, location, target, &self.heuristic, Tile::freespace)
-            .map_or(0, |data| {
-                self.path = Some(data.path);
-                data.expansions
-            })
+        if let Some(ref cache) = self.cache {
+            if let Some(path) = cache.get(grid, location, target) {
+                self.path = Some(path);
+                return 0;
+            }
+        }
+
+        let data = astar_until(grid,
+                               location,
+                               target,
+                               &self.heuristic,
+                               Tile::freespace,
+                               self.deadline);
+        let expansions = data.as_ref().map_or(0, |data| data.expansions);
+        profile_span!("plan",
+                      initial_h = data.as_ref().map(|data| data.initial_h));
+        // Without a new plan, what is left of the old one is blocked.
+        self.path = data.map(|data| data.path);
+        // Plans cut short by the deadline do not reach the target, and are
+        // not worth keeping.
+        let complete = self.path
+            .as_ref()
+            .is_some_and(|path| path.first() == Some(target));
+        if let (Some(cache), Some(path), true) =
+            (self.cache.as_mut(), &self.path, complete) {
+            cache.insert(location, target, path);
+        }
+        expansions
     }
 
     fn follow_path(&mut self) -> Option<Point> {
         self.path.as_mut().and_then(|path| path.pop())
     }
+
+    /// Whether scanning before moving from `location` to `next` is worth
+    /// its cost, which it is when the moves along the path the scanner
+    /// would show, onto tiles the agent has not seen, cost more than the
+    /// scan. Those are the moves the agent would otherwise make trusting
+    /// the freespace assumption, and the ones it may find blocked and have
+    /// to make again, around them.
+    fn worth_scanning(&self,
+                      grid: &Grid,
+                      location: &Point,
+                      next: &Point)
+                      -> bool {
+        let scanner = match self.scanner {
+            Some(scanner) if scanner.available(self.scans) => scanner,
+            _ => return false,
+        };
+        let sensor = scanner.sensor;
+        let reach = max(sensor.radius, sensor.terrain_radius.unwrap_or(0));
+        let rest = self.path.iter().flat_map(|path| path.iter().rev());
+        let mut from = *location;
+        let mut unseen = 0.0;
+        for point in Some(next).into_iter().chain(rest) {
+            if Metric::Chebyshev.distance(location, point) > reach as Distance {
+                break;
+            }
+            if grid.try_get(point)
+                   .is_ok_and(|tile| *tile.belief() == Belief::Unknown) {
+                unseen += grid.cost_under(&self.metric, None, &from, point);
+            }
+            from = *point;
+        }
+        unseen > scanner.cost
+    }
+
+    /// Decides on moving from `location` to `next`, the next move on the
+    /// path, or on scanning first, if it is worth it, keeping the move for
+    /// later.
+    fn step(&mut self,
+            grid: &Grid,
+            location: &Point,
+            next: Point,
+            expansions: usize)
+            -> Datum {
+        if !self.worth_scanning(grid, location, &next) {
+            return Datum::towards(location, &next, expansions);
+        }
+        self.path.get_or_insert_with(Path::new).push(next);
+        self.scans += 1;
+        self.scanned = true;
+        Datum {
+            action: Action::Sense,
+            expansions: expansions,
+        }
+    }
 }
 
 impl<H> Agent for RepeatedAstar<H>
@@ -89,25 +447,1028 @@ impl<H> Agent for RepeatedAstar<H>
            location: &Point,
            target: &Point)
            -> Option<Datum> {
+        self.check_changes(grid);
+        if self.scanned {
+            self.scanned = false;
+            let blocked = self.path
+                .as_ref()
+                .is_some_and(|path| !path.iter().all(|p| free(grid, p)));
+            if blocked {
+                self.path = None;
+            }
+        }
         if let Some(next) = self.follow_path() {
-            if grid[&next].freespace() {
-                return Some(Datum {
-                                action: next,
-                                expansions: 0,
-                            });
+            if free(grid, &next) {
+                return Some(self.step(grid, location, next, 0));
             }
         }
 
         let expansions = self.update_path(grid, location, target);
-        self.follow_path().map(|next| {
-                                   Datum {
-                                       action: next,
-                                       expansions: expansions,
-                                   }
-                               })
+        self.follow_path()
+            .map(|next| self.step(grid, location, next, expansions))
+    }
+
+    fn reset(&mut self) {
+        self.path = None;
+        self.scans = 0;
+        self.scanned = false;
+    }
+
+    fn bumped(&mut self, _point: &Point) {
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    fn set_scanner(&mut self, scanner: Option<Scanner>) {
+        self.scanner = scanner;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// Plans like `RepeatedAstar`, but over a quadtree of the tiles it believes
+/// to be free, rebuilt every time some tile on the path is found blocked.
+/// Open areas are searched as single blocks, which keeps planning cheap on
+/// large, mostly open maps, at the price of paths that are not always
+/// optimal.
+#[derive(Debug)]
+pub struct QuadtreeAstar<H> {
+    heuristic: H,
+    metric: MoveMetric,
+    path: Option<Path>,
+}
+
+impl<H> QuadtreeAstar<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    pub fn new(heuristic: H, metric: MoveMetric) -> QuadtreeAstar<H> {
+        QuadtreeAstar {
+            heuristic: heuristic,
+            metric: metric,
+            path: None,
+        }
+    }
+}
+
+impl<H> Agent for QuadtreeAstar<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        // Paths over blocks are not always optimal, so following one into a
+        // dead end could lead back to tiles already visited. Replanning as
+        // soon as any tile ahead is blocked avoids that.
+        if self.path
+               .as_ref()
+               .is_some_and(|path| path.iter().all(|point| free(grid, point))) {
+            if let Some(next) = self.path.as_mut().and_then(|path| path.pop()) {
+                return Some(Datum::towards(location, &next, 0));
+            }
+        }
+
+        let tree = Quadtree::from_grid(grid, Tile::freespace);
+        let data = quadtree_astar(&tree, location, target, &self.heuristic);
+        let expansions = data.as_ref().map_or(0, |data| data.expansions);
+        self.path = data.map(|data| data.path);
+        self.path
+            .as_mut()
+            .and_then(|path| path.pop())
+            .map(|next| Datum::towards(location, &next, expansions))
+    }
+
+    fn reset(&mut self) {
+        self.path = None;
+    }
+
+    fn bumped(&mut self, _point: &Point) {
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// Knows the true map from the start: plans a single optimal path with A*
+/// over the actual terrain and follows it. Serves as a baseline that pays
+/// nothing for partial observability.
+#[derive(Debug)]
+pub struct Clairvoyant<H> {
+    heuristic: H,
+    metric: MoveMetric,
+    path: Option<Path>,
+}
+
+impl<H> Clairvoyant<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    pub fn new(heuristic: H, metric: MoveMetric) -> Clairvoyant<H> {
+        Clairvoyant {
+            heuristic: heuristic,
+            metric: metric,
+            path: None,
+        }
+    }
+}
+
+impl<H> Agent for Clairvoyant<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        let mut expansions = 0;
+        if self.path.is_none() {
+            let data = astar(grid,
+                             location,
+                             target,
+                             &self.heuristic,
+                             Tile::passable)?;
+            expansions = data.expansions;
+            self.path = Some(data.path);
+        }
+
+        self.path
+            .as_mut()
+            .and_then(|path| path.pop())
+            .map(|next| Datum::towards(location, &next, expansions))
+    }
+
+    fn reset(&mut self) {
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// Knows the true map, like `Clairvoyant`, but looks up every move in a
+/// compressed path database instead of searching. The database is built
+/// the first time the agent meets a map (or read from the `cache`
+/// directory, where it is stored once built), which is slow, but after that
+/// every move costs a lookup, and no expansions.
+#[derive(Debug)]
+pub struct CompressedPaths {
+    cache: Option<PathBuf>,
+    metric: MoveMetric,
+    cpd: Option<Cpd>,
+    /// Whether the database is known to match the map of the current trial.
+    checked: bool,
+    /// The moves looked up towards the target, kept backwards like the
+    /// plans of other agents.
+    path: Option<Path>,
+}
+
+impl CompressedPaths {
+    pub fn new(cache: Option<PathBuf>, metric: MoveMetric) -> CompressedPaths {
+        CompressedPaths {
+            cache: cache,
+            metric: metric,
+            cpd: None,
+            checked: false,
+            path: None,
+        }
+    }
+}
+
+impl Agent for CompressedPaths {
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        if !self.checked {
+            if !self.cpd.as_ref().is_some_and(|cpd| cpd.matches(grid)) {
+                self.cpd = Some(Cpd::cached(grid, self.cache.as_deref()));
+            }
+            self.checked = true;
+        }
+
+        if self.path.is_none() {
+            let cpd = self.cpd.as_ref()?;
+            let mut path = Path::new();
+            let mut point = *location;
+            while point != *target {
+                point = cpd.first_move(&point, target)?;
+                path.push(point);
+            }
+            path.reverse();
+            self.path = Some(path);
+        }
+
+        self.path
+            .as_mut()
+            .and_then(|path| path.pop())
+            .map(|next| Datum::towards(location, &next, 0))
+    }
+
+    fn reset(&mut self) {
+        self.checked = false;
+        self.path = None;
+    }
+
+    /// Drops the path, and checks the database against the map again, since
+    /// it led into an obstacle.
+    fn bumped(&mut self, _point: &Point) {
+        self.checked = false;
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// Follows the gradient of a potential field instead of searching: the
+/// target attracts the agent by the heuristic distance to it, and every tile
+/// believed to be blocked closer than `range` repels it by
+/// `repulsion * (1/d - 1/range)^2`, where `d` is the Euclidean distance to
+/// the tile. At every step the agent moves to its free neighbor of lowest
+/// potential, which costs no expansions.
+///
+/// When no neighbor is lower than its location, the agent is stuck in a
+/// local minimum, and escapes by following an A* path towards the target
+/// until it reaches a tile of lower potential than the minimum. Escapes
+/// thus always end below the last minimum, unless newly seen obstacles
+/// raise the potential in the meantime.
+#[derive(Debug)]
+pub struct PotentialField<H> {
+    heuristic: H,
+    metric: MoveMetric,
+    repulsion: Distance,
+    range: usize,
+    /// The potential of the local minimum being escaped, if any.
+    minimum: Option<Distance>,
+    path: Option<Path>,
+}
+
+impl<H> PotentialField<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    pub fn new(heuristic: H,
+               metric: MoveMetric,
+               repulsion: Distance,
+               range: usize)
+               -> PotentialField<H> {
+        PotentialField {
+            heuristic: heuristic,
+            metric: metric,
+            repulsion: repulsion,
+            range: range,
+            minimum: None,
+            path: None,
+        }
+    }
+
+    fn potential(&self,
+                 grid: &Grid,
+                 point: &Point,
+                 target: &Point)
+                 -> Distance {
+        let mut potential = (self.heuristic)(point, target);
+        let range = self.range as Distance;
+        let rows = point.y().saturating_sub(self.range)..
+                   min(point.y() + self.range + 1, grid.height());
+        for y in rows {
+            let columns = point.x().saturating_sub(self.range)..
+                          min(point.x() + self.range + 1, grid.width());
+            for x in columns {
+                let obstacle = Point::new(y, x);
+                let d = Distance::euclidean_heuristic(point, &obstacle);
+                if d > 0.0 && d < range && !free(grid, &obstacle) {
+                    potential += self.repulsion *
+                                 (1.0 / d - 1.0 / range).powi(2);
+                }
+            }
+        }
+        potential
+    }
+
+    /// Returns the free neighbor of `location` the movement rules allow
+    /// moving onto with the lowest potential, if it is lower than that of
+    /// `location` itself.
+    fn descend(&self,
+               grid: &Grid,
+               location: &Point,
+               target: &Point)
+               -> Option<Point> {
+        let here = self.potential(grid, location, target);
+        let index = grid.index_of(location)?;
+        grid.successors(index, None, Tile::freespace)
+            .map(|(next, _, _)| grid.point_of(next))
+            .map(|next| (self.potential(grid, &next, target), next))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+            .filter(|&(potential, _)| potential < here)
+            .map(|(_, next)| next)
+    }
+}
+
+impl<H> Agent for PotentialField<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        if let Some(minimum) = self.minimum {
+            if self.potential(grid, location, target) < minimum {
+                self.minimum = None;
+                self.path = None;
+            }
+        }
+
+        if self.minimum.is_none() {
+            match self.descend(grid, location, target) {
+                Some(next) => {
+                    return Some(Datum::towards(location, &next, 0))
+                }
+                None => {
+                    self.minimum = Some(self.potential(grid, location, target))
+                }
+            }
+        }
+
+        let next = self.path.as_mut().and_then(|path| path.pop());
+        if let Some(next) = next.filter(|next| free(grid, next)) {
+            return Some(Datum::towards(location, &next, 0));
+        }
+
+        let mut data = astar(grid,
+                             location,
+                             target,
+                             &self.heuristic,
+                             Tile::freespace)?;
+        let expansions = data.expansions;
+        let next = data.path.pop();
+        self.path = Some(data.path);
+        next.map(|next| Datum::towards(location, &next, expansions))
     }
 
     fn reset(&mut self) {
+        self.minimum = None;
         self.path = None;
     }
+
+    fn bumped(&mut self, _point: &Point) {
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// The offsets of the eight neighbors of a tile, clockwise from north.
+const RING: [(isize, isize); 8] = [(-1, 0),
+                                   (-1, 1),
+                                   (0, 1),
+                                   (1, 1),
+                                   (1, 0),
+                                   (1, -1),
+                                   (0, -1),
+                                   (-1, -1)];
+
+/// Returns the point at `offset` from `point`, unless it would have a
+/// negative coordinate.
+fn offset(point: &Point, offset: (isize, isize)) -> Option<Point> {
+    let y = point.y() as isize + offset.0;
+    let x = point.x() as isize + offset.1;
+    if y >= 0 && x >= 0 {
+        Some(Point::new(y as usize, x as usize))
+    } else {
+        None
+    }
+}
+
+/// Lists the tiles on the straight line from `from` to `to`, both included,
+/// each a neighbor of the previous one, and unless `diagonal`, in one of the
+/// four straight directions from it.
+fn line(from: &Point, to: &Point, diagonal: bool) -> Vec<Point> {
+    let (mut y, mut x) = (from.y() as isize, from.x() as isize);
+    let (ty, tx) = (to.y() as isize, to.x() as isize);
+    let (dy, dx) = ((ty - y).abs(), (tx - x).abs());
+    let (sy, sx) = ((ty - y).signum(), (tx - x).signum());
+    let mut error = dx - dy;
+    let mut points = vec![*from];
+    while (y, x) != (ty, tx) {
+        let doubled = 2 * error;
+        if doubled > -dy {
+            error -= dy;
+            x += sx;
+            if !diagonal && doubled < dx {
+                points.push(Point::new(y as usize, x as usize));
+            }
+        }
+        if doubled < dx {
+            error += dx;
+            y += sy;
+        }
+        points.push(Point::new(y as usize, x as usize));
+    }
+    points
+}
+
+#[derive(Debug)]
+enum BugMode {
+    /// Moving along the m-line, at the tile with this position on it.
+    ToGoal(usize),
+    /// Following the boundary of an obstacle, which was hit at the tile
+    /// with position `hit` on the m-line. The tile in direction `wall` (in
+    /// `RING`) of the agent is blocked.
+    Follow { hit: usize, wall: usize },
+}
+
+/// Navigates without planning, as in the Bug2 algorithm: moves along the
+/// straight line (the m-line) from where the trial started to the target
+/// until it is blocked, then follows the boundary of the obstacle, keeping
+/// it on its left, until it crosses the m-line again closer to the target.
+/// Gives up when following the boundary leads back to where the obstacle
+/// was hit. Never expands any tile. Only makes the moves the movement rules
+/// allow: on maps without diagonal moves, the m-line steps straight.
+#[derive(Debug, Default)]
+pub struct Bug2 {
+    line: Vec<Point>,
+    mode: Option<BugMode>,
+}
+
+impl Bug2 {
+    pub fn new() -> Bug2 {
+        Bug2::default()
+    }
+
+    /// Takes a step along the boundary of the obstacle hit at position
+    /// `hit` on the m-line: moves to the first free tile clockwise from the
+    /// blocked tile in direction `wall`, and keeps the last blocked tile
+    /// passed over as the wall to scan from next.
+    fn follow(&mut self,
+              grid: &Grid,
+              location: &Point,
+              hit: usize,
+              wall: usize)
+              -> Option<Datum> {
+        let turns = (1..RING.len()).map(|turn| (wall + turn) % RING.len());
+        for direction in turns {
+            let next = match offset(location, RING[direction]) {
+                Some(next) if allowed(grid, location, &next) => next,
+                _ => continue,
+            };
+            let (dy, dx) = RING[direction];
+            let (wy, wx) = RING[(direction + RING.len() - 1) % RING.len()];
+            self.mode = Some(BugMode::Follow {
+                                 hit: hit,
+                                 wall: ring_direction((wy - dy, wx - dx)),
+                             });
+            return Some(Datum::towards(location, &next, 0));
+        }
+        None
+    }
+}
+
+/// Whether the movement rules allow moving from `from` onto `to`, which
+/// must be free.
+fn allowed(grid: &Grid, from: &Point, to: &Point) -> bool {
+    match (grid.index_of(from), grid.index_of(to)) {
+        (Some(from), Some(to)) => {
+            grid.successors(from, None, Tile::freespace)
+                .any(|(next, _, _)| next == to)
+        }
+        _ => false,
+    }
+}
+
+/// Returns the position in `RING` of the neighbor at `offset`.
+fn ring_direction(offset: (isize, isize)) -> usize {
+    RING.iter()
+        .position(|&o| o == offset)
+        .expect("Attempted to find the direction of a non-neighbor.")
+}
+
+impl Agent for Bug2 {
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        let position = match self.mode {
+            None => {
+                self.line = line(location,
+                                 target,
+                                 grid.movement() != Movement::Cardinal);
+                0
+            }
+            Some(BugMode::ToGoal(position)) => position,
+            Some(BugMode::Follow { hit, wall }) => {
+                match self.line.iter().position(|p| p == location) {
+                    Some(position) if position > hit => position,
+                    Some(position) if position == hit => return None,
+                    _ => return self.follow(grid, location, hit, wall),
+                }
+            }
+        };
+
+        let next = *self.line.get(position + 1)?;
+        if !allowed(grid, location, &next) {
+            let wall = ring_direction((next.y() as isize -
+                                       location.y() as isize,
+                                       next.x() as isize -
+                                       location.x() as isize));
+            return self.follow(grid, location, position, wall);
+        }
+        self.mode = Some(BugMode::ToGoal(position + 1));
+        Some(Datum::towards(location, &next, 0))
+    }
+
+    fn reset(&mut self) {
+        self.line.clear();
+        self.mode = None;
+    }
+
+    /// Starts over along a new m-line from where the agent is, as it did
+    /// not reach the tile it was headed to.
+    fn bumped(&mut self, _point: &Point) {
+        self.mode = None;
+    }
+
+    /// Starts over along a new m-line towards where the target is now.
+    fn target_moved(&mut self, _target: &Point) {
+        self.mode = None;
+    }
+}
+
+/// Plans through space and time around the tiles reserved in a
+/// `ReservationTable`, waiting in place when needed. Like `RepeatedAstar`,
+/// it follows its plan until the next step is believed to be blocked.
+/// Plans look at most `horizon` steps ahead. Since waiting leaves the agent
+/// where it was, trials with this agent should not abort on oscillation.
+#[derive(Debug)]
+pub struct SpaceTimeAstar<H> {
+    heuristic: H,
+    metric: MoveMetric,
+    reservations: ReservationTable,
+    horizon: usize,
+    time: usize,
+    path: Option<Path>,
+}
+
+impl<H> SpaceTimeAstar<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    pub fn new(heuristic: H,
+               metric: MoveMetric,
+               reservations: ReservationTable,
+               horizon: usize)
+               -> SpaceTimeAstar<H> {
+        SpaceTimeAstar {
+            heuristic: heuristic,
+            metric: metric,
+            reservations: reservations,
+            horizon: horizon,
+            time: 0,
+            path: None,
+        }
+    }
+}
+
+impl<H> Agent for SpaceTimeAstar<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        self.time += 1;
+        let next = self.path.as_mut().and_then(|path| path.pop());
+        if let Some(next) = next.filter(|next| free(grid, next)) {
+            return Some(Datum::towards(location, &next, 0));
+        }
+
+        let start = self.time - 1;
+        let data = space_time_astar(grid,
+                                    location,
+                                    target,
+                                    start..start + self.horizon + 1,
+                                    &self.reservations,
+                                    &self.heuristic,
+                                    Tile::freespace);
+        data.and_then(|mut data| {
+            let expansions = data.expansions;
+            let next = data.path.pop();
+            self.path = Some(data.path);
+            next.map(|next| Datum::towards(location, &next, expansions))
+        })
+    }
+
+    fn reset(&mut self) {
+        self.time = 0;
+        self.path = None;
+    }
+
+    fn bumped(&mut self, _point: &Point) {
+        self.path = None;
+    }
+
+    fn target_moved(&mut self, _target: &Point) {
+        self.path = None;
+    }
+
+    fn current_plan(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    fn metric(&self) -> MoveMetric {
+        self.metric
+    }
+}
+
+/// A line of a script replayed by `ScriptedAgent`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scripted {
+    /// Take the action, wherever the agent is.
+    Act(Action),
+    /// Move to the location, which must be where the agent is or one of its
+    /// neighbors.
+    Visit(Point),
+}
+
+impl FromStr for Scripted {
+    type Err = String;
+
+    /// Reads an action (`wait`, `sense` or the name of a direction, such as
+    /// `north` or `southeast`) or a location, written either as `y x` or as
+    /// `(y, x)`.
+    fn from_str(line: &str) -> Result<Scripted, String> {
+        match line {
+            "wait" => return Ok(Scripted::Act(Action::Wait)),
+            "sense" => return Ok(Scripted::Act(Action::Sense)),
+            _ => (),
+        }
+        if let Some(direction) = Direction::ALL
+               .iter()
+               .find(|direction| direction.name() == line) {
+            return Ok(Scripted::Act(Action::Move(*direction)));
+        }
+        let coordinates = line
+            .split(|c: char| c.is_whitespace() || "(),".contains(c))
+            .filter(|word| !word.is_empty())
+            .map(|word| word.parse())
+            .collect::<Result<Vec<usize>, _>>();
+        match coordinates.as_ref().map(|c| c.as_slice()) {
+            Ok(&[y, x]) => Ok(Scripted::Visit(Point::new(y, x))),
+            _ => Err(format!("Invalid action or location '{}'.", line)),
+        }
+    }
+}
+
+/// Replays a fixed sequence of actions and moves to given locations, such as
+/// one recorded from an external planner, giving up once the sequence is
+/// exhausted. Every trial starts again from the first line.
+#[derive(Debug)]
+pub struct ScriptedAgent {
+    script: Vec<Scripted>,
+    next: usize,
+    /// Where the last action taken was meant to take the agent.
+    location: Option<Point>,
+}
+
+impl ScriptedAgent {
+    pub fn new(script: Vec<Scripted>) -> ScriptedAgent {
+        ScriptedAgent {
+            script: script,
+            next: 0,
+            location: None,
+        }
+    }
+
+    /// Replays `actions`, in order.
+    pub fn from_actions(actions: Vec<Action>) -> ScriptedAgent {
+        ScriptedAgent::new(actions.into_iter().map(Scripted::Act).collect())
+    }
+
+    /// Reads a script with one action or location per line (see
+    /// `Scripted::from_str`). Blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn from_reader<R>(reader: R) -> Result<ScriptedAgent, String>
+        where R: Read
+    {
+        let mut script = Vec::new();
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = line.parse().map_err(|_: String| {
+                    format!("Invalid action or location '{}' on line {}.",
+                            line,
+                            number + 1)
+                })?;
+            script.push(step);
+        }
+        Ok(ScriptedAgent::new(script))
+    }
+
+    pub fn from_file<P>(filename: &P) -> Result<ScriptedAgent, String>
+        where P: AsRef<FilePath> + ?Sized
+    {
+        let file = File::open(filename).map_err(|e| e.to_string())?;
+        ScriptedAgent::from_reader(file)
+    }
+}
+
+impl Agent for ScriptedAgent {
+    /// Gives up at the first action or location that lies outside the grid,
+    /// or at the first location that is not a neighbor of the agent's
+    /// location (nor the location itself).
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           _target: &Point)
+           -> Option<Datum> {
+        let step = self.script.get(self.next).cloned();
+        self.next += 1;
+        let action = match step? {
+            Scripted::Act(action) => action,
+            Scripted::Visit(next) => Action::towards(location, &next)?,
+        };
+        let next = action.target(location)
+            .filter(|next| grid.try_get(next).is_ok())?;
+        self.location = Some(next);
+        Some(Datum {
+                 action: action,
+                 expansions: 0,
+             })
+    }
+
+    fn reset(&mut self) {
+        self.next = 0;
+        self.location = None;
+    }
+
+    /// Lists the locations the rest of the script leads to, from where the
+    /// last action was meant to take the agent, up to the first action
+    /// taken from an unknown location.
+    fn planned_path(&self) -> Path {
+        let mut location = self.location;
+        let mut path = Path::new();
+        for step in &self.script[self.next.min(self.script.len())..] {
+            location = match *step {
+                Scripted::Act(action) => {
+                    location.and_then(|location| action.target(&location))
+                }
+                Scripted::Visit(next) => Some(next),
+            };
+            match location {
+                Some(location) => path.push(location),
+                None => break,
+            }
+        }
+        path
+    }
+}
+
+/// The keys accepted by `InteractiveAgent`, in the direction order of
+/// `Point::neighbors`. Each direction has a letter and a numeric keypad key.
+const KEYS: [(u8, u8); 8] = [(b'q', b'7'),
+                             (b'w', b'8'),
+                             (b'e', b'9'),
+                             (b'a', b'4'),
+                             (b'd', b'6'),
+                             (b'z', b'1'),
+                             (b'x', b'2'),
+                             (b'c', b'3')];
+
+/// The keys that make `InteractiveAgent` give up: `Q`, Ctrl-C and Ctrl-D.
+const QUIT_KEYS: [u8; 3] = [b'Q', 0x03, 0x04];
+
+/// The byte that starts the escape sequences sent by arrow keys.
+const ESCAPE: u8 = 0x1b;
+
+/// Standard input, with the terminal (if it is one) set to pass on every
+/// key as soon as it is pressed, without echoing it, as `InteractiveAgent`
+/// needs. Ctrl-C is passed on as a key too. The terminal is set back when
+/// the keyboard is dropped. Where terminals cannot be set (off Unix, or when
+/// the input is not a terminal), keys arrive a line at a time.
+pub struct Keyboard {
+    stdin: Stdin,
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl Keyboard {
+    #[cfg(unix)]
+    pub fn new() -> Keyboard {
+        let stdin = stdin();
+        let fd = stdin.as_raw_fd();
+        // Safe: the terminal settings are plain data, filled in by
+        // `tcgetattr` before they are read.
+        let saved = unsafe {
+            let mut saved = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut saved) == 0 {
+                let mut raw = saved;
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                libc::tcsetattr(fd, libc::TCSANOW, &raw);
+                Some(saved)
+            } else {
+                None
+            }
+        };
+        Keyboard {
+            stdin: stdin,
+            saved: saved,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn new() -> Keyboard {
+        Keyboard { stdin: stdin() }
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
+impl Read for Keyboard {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        self.stdin.read(buffer)
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Some(ref saved) = self.saved {
+                // Safe: `saved` holds the settings read when created.
+                unsafe {
+                    libc::tcsetattr(self.stdin.as_raw_fd(),
+                                    libc::TCSANOW,
+                                    saved);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Keyboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keyboard").finish_non_exhaustive()
+    }
+}
+
+/// Lets a person choose every move. At each step the agent's beliefs are
+/// drawn through a `Printer`, and keys are read from `input` until one
+/// makes a move: one of `qweadzxc`, `78946123` on a numeric keypad, or an
+/// arrow key, or `s` (or `5`) to wait. Other keys are ignored. The agent
+/// gives up on `Q`, Ctrl-C or Ctrl-D, or at the end of the input.
+#[derive(Debug)]
+pub struct InteractiveAgent<R> {
+    input: R,
+    printer: Printer,
+}
+
+impl<R> InteractiveAgent<R>
+    where R: Read
+{
+    /// Creates an agent that reads keys from `input` (see `Keyboard`) and
+    /// draws to `out`.
+    pub fn new<W>(input: R, out: W) -> InteractiveAgent<R>
+        where W: fmt::Write + 'static
+    {
+        InteractiveAgent {
+            input: input,
+            printer: Printer::to(Verbosity::Zero, out),
+        }
+    }
+
+    /// Reads the next byte of input, or `None` at its end.
+    fn byte(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        match self.input.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    /// Reads the next key, turning arrow keys (sent as `ESC [` or `ESC O`
+    /// and a letter from `A` to `D`) into the letters of their directions.
+    /// Returns `None` if the person quit.
+    fn key(&mut self) -> Option<u8> {
+        let key = self.byte()?;
+        if QUIT_KEYS.contains(&key) {
+            return None;
+        }
+        if key != ESCAPE {
+            return Some(key);
+        }
+        match self.byte()? {
+            b'[' | b'O' => {}
+            _ => return Some(ESCAPE),
+        }
+        // Sequences may carry parameters (as with modifier keys) before
+        // their final byte.
+        let mut last = self.byte()?;
+        while !(0x40..=0x7e).contains(&last) {
+            last = self.byte()?;
+        }
+        Some(match last {
+                 b'A' => b'w',
+                 b'B' => b'x',
+                 b'C' => b'd',
+                 b'D' => b'a',
+                 _ => ESCAPE,
+             })
+    }
+}
+
+impl<R> Agent for InteractiveAgent<R>
+    where R: Read
+{
+    fn act(&mut self,
+           grid: &mut Grid,
+           location: &Point,
+           target: &Point)
+           -> Option<Datum> {
+        self.printer.draw(grid, location, target);
+        self.printer.message("Move (qweadzxc or arrows, s to wait, Q to quit)");
+        loop {
+            let key = self.key()?;
+            let action = if key == b's' || key == b'5' {
+                Action::Wait
+            } else {
+                let direction = match KEYS.iter().position(|&(letter, digit)| {
+                    key == letter || key == digit
+                }) {
+                    Some(direction) => Direction::ALL[direction],
+                    None => continue,
+                };
+                Action::Move(direction)
+            };
+
+            if action.target(location).is_some_and(|next| free(grid, &next)) {
+                return Some(Datum {
+                                action: action,
+                                expansions: 0,
+                            });
+            }
+            self.printer.message("Cannot move there.");
+        }
+    }
 }
@@ -1,51 +1,430 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, File};
+use std::io::{stdin, stdout, BufReader, BufWriter, Read,
+              Result as IOResult, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
 use docopt::Docopt;
 use serde::de;
 
-use agent::{AlwaysAstar, RepeatedAstar};
-use experiment::{Experiment, Verbosity};
-use instance::Data;
-use grid::{Distance, Measure, Point};
-use parser::grid_from_file;
+use comparison::compare;
+use cost::Composite;
+use execution::{ExecutionModel, Heatmap, Scanner};
+use experiment::{validate_endpoints, Experiment, Perturbation, Region,
+                 Sampling, TrialOrder, Verbosity};
+use generator::{ensure_connected, NoiseGenerator};
+use instance::{Data, Outcome};
+use grid::{Distance, Grid, GridMeta, Point, Sensor};
+use manifest::{default_path, Manifest};
+use metrics;
+use observer::{read_searches, replay_search, BeliefDump, ResultLog,
+               SearchLog, Trace};
+use overlay::read_overlay_file;
+use parser::{terrain_from_symbol, try_grid_from_file, try_grid_from_file_as,
+             try_grid_from_reader, try_grid_from_reader_as,
+             try_pairs_from_file, try_patch_from_file,
+             try_scenarios_from_file, try_suite_from_file, write_grid,
+             write_patch, LoadError, MapFormat, MAX_TILES};
+use plan::PlanOptions;
+use pursuit::{Capture, Flee, Pursuit};
+use registry::{Parameters, Registry};
+use serve::Service;
+use suite::SuiteSummary;
+
+/// Exit status when every trial was solved, or when some were unsolved and
+/// `--fail-on-unsolved` was not given.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit status when the command line is invalid.
+pub const EXIT_USAGE: i32 = 1;
+/// Exit status when the map could not be read or parsed, or its hash is not
+/// the one given with `--expect-hash`. Also used when the `--patch` or the
+/// `--overlay` cannot be read or applied, and when the maps given to `diff`
+/// have different sizes.
+pub const EXIT_PARSE_FAILURE: i32 = 2;
+/// Exit status when `--fail-on-unsolved` was given and some trial was not
+/// solved.
+pub const EXIT_UNSOLVED: i32 = 3;
+/// Exit status when some trial ran for longer than `--timeout`.
+pub const EXIT_TIMEOUT: i32 = 4;
+/// Exit status when the results, or any other output, could not be written.
+pub const EXIT_WRITE_FAILURE: i32 = 5;
+/// Exit status when the run was interrupted (with Ctrl-C), as is customary
+/// for processes ended by SIGINT.
+pub const EXIT_INTERRUPTED: i32 = 130;
 
-const USAGE: &'static str = "
+/// Set once the run is interrupted, to stop starting new trials.
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+const USAGE: &str = "
 Usage:
-    gridist <map> <trials> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>] [--from=<from>] [--seed=<seed>]
-    gridist <map> <starty> <startx> <endy> <endx> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>]
+    gridist generate noise <height> <width> <output> [--seed=<seed>] [--scale=<scale>] [--octaves=<octaves>] [--water=<level>] [--swamp=<level>] [--trees=<level>] [--min-region=<fraction>] [--ensure-connected] [--source-region=<region>] [--target-region=<region>] [--to=<format>]
+    gridist suite <suite> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>] [--seed=<seed>] [--max-attempts=<attempts>] [--source-region=<region>] [--target-region=<region>] [--timeout=<timeout>] [--max-steps=<steps>] [--fail-on-unsolved] [--catch-panics] [--validate-moves] [--execution=<model>] [--bump-cost=<cost>] [--pursuit=<capture>] [--retain-beliefs] [--sensor-radius=<radius>] [--terrain-radius=<radius>] [--observe-terrain] [--scan-radius=<radius>] [--scan-cost=<cost>] [--scan-budget=<scans>] [--clearance=<radius>] [--cost=<cost>] [--safety-weight=<weight>] [--turn-weight=<weight>] [--doubt-weight=<weight>] [--terrain-weights=<weights>] [--param=<param>...] [--output=<output>] [--tag=<tag>]
+    gridist serve <map> [--listen=<address>] [--planner-threads=<n>]
+    gridist bench <map> <trials> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--seed=<seed>] [--reps=<reps>] [--warmup=<runs>] [--param=<param>...]
+    gridist bench <map> --pairs=<pairs> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--reps=<reps>] [--warmup=<runs>] [--param=<param>...]
+    gridist <map> <trials> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>] [--log-level=<level>] [--trace-steps] [--trace-search] [--from=<from>] [--seed=<seed>] [--max-attempts=<attempts>] [--allow-unreachable] [--source-region=<region>] [--target-region=<region>] [--format=<format>] [--timeout=<timeout>] [--move-budget=<budget>] [--max-steps=<steps>] [--perturb=<fraction>] [--perturb-seed=<seed>] [--fail-on-unsolved] [--allow-oscillation] [--keep-disconnected] [--catch-panics] [--validate-moves] [--execution=<model>] [--bump-cost=<cost>] [--pursuit=<capture>] [--retain-beliefs] [--order=<order>] [--order-seed=<seed>] [--sensor-radius=<radius>] [--terrain-radius=<radius>] [--observe-terrain] [--scan-radius=<radius>] [--scan-cost=<cost>] [--scan-budget=<scans>] [--clearance=<radius>] [--cost=<cost>] [--safety-weight=<weight>] [--turn-weight=<weight>] [--doubt-weight=<weight>] [--terrain-weights=<weights>] [--overlay-weight=<weight>] [--overlay=<overlay>] [--with-baseline] [--metrics=<metrics>] [--param=<param>...] [--sweep=<sweep>...] [--heatmap=<heatmap>] [--tidy=<file>] [--dump-beliefs=<dir>] [--dump-search=<file>] [--output=<output>] [--manifest=<manifest>] [--tag=<tag>] [--expect-hash=<hash>] [--patch=<patch>]
+    gridist <map> <starty> <startx> <endy> <endx> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>] [--log-level=<level>] [--trace-steps] [--trace-search] [--format=<format>] [--timeout=<timeout>] [--move-budget=<budget>] [--max-steps=<steps>] [--perturb=<fraction>] [--perturb-seed=<seed>] [--fail-on-unsolved] [--allow-oscillation] [--keep-disconnected] [--catch-panics] [--validate-moves] [--execution=<model>] [--bump-cost=<cost>] [--pursuit=<capture>] [--sensor-radius=<radius>] [--terrain-radius=<radius>] [--observe-terrain] [--scan-radius=<radius>] [--scan-cost=<cost>] [--scan-budget=<scans>] [--clearance=<radius>] [--cost=<cost>] [--safety-weight=<weight>] [--turn-weight=<weight>] [--doubt-weight=<weight>] [--terrain-weights=<weights>] [--overlay-weight=<weight>] [--overlay=<overlay>] [--with-baseline] [--metrics=<metrics>] [--allow-unreachable] [--param=<param>...] [--sweep=<sweep>...] [--heatmap=<heatmap>] [--tidy=<file>] [--dump-beliefs=<dir>] [--dump-search=<file>] [--output=<output>] [--manifest=<manifest>] [--tag=<tag>] [--expect-hash=<hash>] [--patch=<patch>]
+    gridist <map> --pairs=<pairs> [--algorithm=<algorithm>] [--heuristic=<heuristic>] [--verbosity=<verbosity>] [--log-level=<level>] [--trace-steps] [--trace-search] [--format=<format>] [--timeout=<timeout>] [--move-budget=<budget>] [--max-steps=<steps>] [--perturb=<fraction>] [--perturb-seed=<seed>] [--fail-on-unsolved] [--allow-oscillation] [--keep-disconnected] [--catch-panics] [--validate-moves] [--execution=<model>] [--bump-cost=<cost>] [--pursuit=<capture>] [--retain-beliefs] [--order=<order>] [--order-seed=<seed>] [--sensor-radius=<radius>] [--terrain-radius=<radius>] [--observe-terrain] [--scan-radius=<radius>] [--scan-cost=<cost>] [--scan-budget=<scans>] [--clearance=<radius>] [--cost=<cost>] [--safety-weight=<weight>] [--turn-weight=<weight>] [--doubt-weight=<weight>] [--terrain-weights=<weights>] [--overlay-weight=<weight>] [--overlay=<overlay>] [--with-baseline] [--metrics=<metrics>] [--param=<param>...] [--sweep=<sweep>...] [--heatmap=<heatmap>] [--tidy=<file>] [--dump-beliefs=<dir>] [--dump-search=<file>] [--output=<output>] [--manifest=<manifest>] [--tag=<tag>] [--expect-hash=<hash>] [--patch=<patch>]
+    gridist convert <input> <output> --to=<format>
+    gridist diff <map-a> <map-b> [--write-patch=<patch>]
+    gridist replay <map> <searches> [--trial=<trial>]
     gridist --help
 
 Arguments:
-    <map>              Path to a map file in the movingai.com format.
+    <map>              Path to a map file, or - to read the map from stdin.
     <trials>           Number of randomized trials to run.
     <starty>/<startx>  Starting point coordinates for single run.
     <endy>/<endx>      End point coordinates for single run.
+    <input>            Path to a map to convert, or - to read it from stdin.
+    <output>           Path to write the converted or generated map to.
+    <map-a>/<map-b>    Paths to two versions of a map, of the same size, to
+                       list the tiles that differ between.
+    <height>/<width>   Size of the map to generate.
+    <searches>         Path to searches written with --dump-search, to replay
+                       one expansion at a time over <map>.
+    <suite>            Path to a suite of maps, with one `map trials
+                       [weight]` line per map (relative to the suite), to
+                       run random trials on each and report the results of
+                       every map and their means over the suite, weighted
+                       by the weights of the maps (1 by default).
 
 Options:
     -h, --help               Show this screen.
     --algorithm=<algorithm>  The algorithm to use [default: rastar].
     --heuristic=<heuristic>  The heuristic function to use [default: octile].
-    --verbosity=<verbosity>  Level of verbosity [0-2] [default: 1].
+    --verbosity=<verbosity>  Level of verbosity [0-2]: 0 logs nothing, 1 logs
+                             every trial, and 2 also traces steps
+                             [default: 1].
+    --log-level=<level>      Log the start of every trial at level 1, or
+                             nothing at 0, whatever the verbosity.
+    --trace-steps            Render the agent's beliefs after every step.
+    --trace-search           List every node the agent's searches expand, with
+                             its f, g and h values.
     --from=<from>            Trial index at which to start running [default: 0].
-    --seed=<seed>            A seed for generating random trials.
+    --pairs=<pairs>          Run the trials listed in this file, one per line
+                             as sy sx ty tx, instead of random ones. Results
+                             are labeled by line number. Files ending in
+                             .scen are read as movingai scenarios instead,
+                             and their results are also summarized by
+                             bucket (success rate, mean suboptimality and
+                             mean expansions).
+    --seed=<seed>            A seed for generating random trials or maps.
+    --max-attempts=<attempts>  Give up on generating a random trial after
+                             drawing this many pairs of points [default: 10000].
+    --source-region=<region>  Draw the sources of random trials from this
+                             region: anywhere, edges (the border of the map),
+                             interior, or a rectangle given as
+                             top,left,bottom,right [default: anywhere].
+    --target-region=<region>  Draw the targets of random trials from this
+                             region, as above [default: anywhere].
+    --format=<format>        Read the map in this format, rather than in the
+                             one its header declares.
+    --to=<format>            The format to convert the map to.
+    --timeout=<timeout>      Give up on trials after this many seconds.
+    --move-budget=<budget>   Give the agent this many seconds (as 0.001) to
+                             decide on every move. The astar and rastar
+                             algorithms then follow the best plan they found
+                             once it runs out, and moves decided on later
+                             are reported as budget overruns.
+    --max-steps=<steps>      Give up on trials after this many moves: a
+                             number, a factor of the straight-line distance
+                             between the endpoints (as in 10x), or auto, for
+                             10 times the length of an optimal path.
+    --perturb=<fraction>     Give this fraction of the tiles of the map some
+                             other terrain, drawn at random, for every
+                             trial, and restore them once it ends, to
+                             measure how robust agents are to small
+                             changes in the map.
+    --perturb-seed=<seed>    Perturb the map with this seed, which every
+                             trial mixes with its endpoints [default: 0].
+    --fail-on-unsolved       Exit with an error if any trial is unsolved.
+    --allow-oscillation      Keep running trials where the agent returns to a
+                             location without having learned anything new.
+    --keep-disconnected      Keep running trials where the agent believes the
+                             goal cannot be reached, until it gives up, and
+                             report the expansions it performs meanwhile.
+    --catch-panics           Record trials where the agent panics as errored,
+                             with the panic's message, and go on with the
+                             next trial, instead of aborting the run.
+    --validate-moves         Check every move before it is made, and end the
+                             trial at the first one that leaves the grid,
+                             enters impassable terrain or breaks the
+                             movement rules, as an illegal move.
+    --execution=<model>      How moves are carried out: direct, or bump to
+                             have moves onto impassable terrain fail, leaving
+                             the agent in place, aware of the tile
+                             [default: direct].
+    --bump-cost=<cost>       The cost of every failed move, with bump
+                             execution [default: 1].
+    --pursuit=<capture>      Make the target flee from the agent after every
+                             move, and solve trials once the agent catches
+                             it: on the same tile (colocation), or on the
+                             same or a neighboring tile (adjacency). Best
+                             with --max-steps, since the target may flee
+                             forever.
+    --retain-beliefs         Begin every trial with what the agent learned
+                             about the map in the trials before it, instead
+                             of forgetting it.
+    --order=<order>          The order to run trials in: given, shuffle or
+                             difficulty (see Orders). Results are still
+                             listed in the given order [default: given].
+    --order-seed=<seed>      Shuffle trials with this seed [default: 0].
+    --sensor-radius=<radius>  How many moves away the agent sees whether tiles
+                             are passable [default: 1].
+    --terrain-radius=<radius>  How many moves away the agent sees the exact
+                             terrain of tiles, if at all.
+    --observe-terrain        Let the agent see the exact terrain of every tile
+                             within the sensor radius.
+    --scan-radius=<radius>   Give the agent a long-range scanner, which it
+                             may use instead of moving to see the exact
+                             terrain of tiles this many moves away.
+    --scan-cost=<cost>       The cost of every scan [default: 1].
+    --scan-budget=<scans>    How many scans the agent may make in every
+                             trial, if limited.
+    --clearance=<radius>     Keep the agent this many moves away from tiles it
+                             believes to be impassable, as if they were
+                             inflated to cover its footprint [default: 0].
+    --cost=<cost>            How agents weigh the cost of moves while
+                             planning, as a comma-separated list of the
+                             costs to combine [default: geometric].
+    --safety-weight=<weight>  The extra cost, as a fraction of a move's
+                             length, of moving next to an obstacle under the
+                             safety cost [default: 1].
+    --turn-weight=<weight>   The extra cost of changing direction under the
+                             turns cost [default: 1].
+    --doubt-weight=<weight>  The extra cost, as a fraction of a move's length,
+                             of every move between an unobserved tile and
+                             known space under the doubt cost [default: 0.1].
+    --overlay-weight=<weight>  The factor of a tile's value in the cost layer
+                             added to moves onto it under the overlay cost
+                             [default: 1].
+    --terrain-weights=<weights>  The factors of the length of moves onto
+                             each terrain under the terrain cost, as a
+                             comma-separated list of terrain symbols and
+                             factors of at least 1 (as .=2,T=3). Terrains
+                             left out keep a factor of 1.
+    --overlay=<overlay>      Lay the layers of values in this JSON file over
+                             the map, as an object mapping the name of every
+                             layer to its height, width and values in
+                             row-major order. The overlay cost reads the
+                             layer named cost.
+    --with-baseline          Also run every trial with an agent that knows the
+                             map, and report how much more each agent paid,
+                             under the --cost and the agent's metric.
+    --metrics=<metrics>      Also measure these metrics (see Metrics) over
+                             every trial, as a comma-separated list, and
+                             report them with the rest of its results.
+    --allow-unreachable      Accept a goal that cannot be reached from the
+                             start, in a single run, or keep random trials
+                             whose points are not connected, reporting them
+                             as unreachable.
+    --param=<param>          Pass name=value to the algorithm as a parameter.
+    --sweep=<sweep>          Run every combination of the values given as
+                             name=value,value,... for each swept parameter
+                             (including the algorithm), over the same trials,
+                             and report one summary row per combination,
+                             followed by paired comparisons of their costs.
+    --heatmap=<heatmap>      Write the number of times the agent occupied each
+                             tile, over all trials, to this file.
+    --tidy=<file>            Also write the results of every trial, for every
+                             combination of swept parameters, to this file
+                             in long format: as CSV with one row per trial
+                             and metric, labeled with the map, the tag and
+                             the algorithm's parameters, ready for R or
+                             pandas.
+    --dump-beliefs=<dir>     Write what the agent believes about the map at
+                             the end of each trial to <dir>/trial-<i>.rle
+                             (in a subdirectory per combination of swept
+                             parameters, when sweeping).
+    --dump-search=<file>     Write every search the agent runs to this file,
+                             one JSON object per line with the trial, the
+                             agent's location and target, and the nodes
+                             expanded (with their f, g, h and episode), to
+                             be replayed with the replay command. May refer
+                             to swept parameters (see Output paths).
+    --output=<output>        Write results to this file instead of stdout
+                             (see Output paths). When sweeping, combinations
+                             whose paths differ are written to separate
+                             files, each with its own manifest. Otherwise,
+                             every trial is written as soon as it ends, and
+                             the file is rewritten once all have.
+    --manifest=<manifest>    Write a JSON manifest describing the run to this
+                             file. Defaults to <output> with its extension
+                             replaced by .manifest.json.
+    --tag=<tag>              Label every row of results with this tag, and
+                             record it in the manifest.
+    --expect-hash=<hash>     Abort unless the hash of the map's contents (as
+                             recorded in results and manifests) is this one.
+    --patch=<patch>          Change the map, once loaded, as listed in this
+                             patch file (see --write-patch).
+    --trial=<trial>          Only replay the searches of this trial.
+    --listen=<address>       Answer the queries of serve over TCP, on this
+                             address (as 127.0.0.1:7878), rather than over
+                             stdin and stdout.
+    --planner-threads=<n>    Answer every query of serve with this many threads
+                             (at most one per core) searching at once, on
+                             maps of 2^20 tiles (about a million) or more,
+                             to answer sooner. Paths are just as cheap, but
+                             take more expansions [default: 1].
+    --reps=<reps>            Time this many runs of the trials with bench,
+                             and report the mean and standard deviation of
+                             their wall time and expansions [default: 10].
+    --warmup=<runs>          Run the trials this many times with bench before
+                             timing them, to warm up caches [default: 1].
+    --scale=<scale>          The size, in tiles, of the coarsest features of
+                             generated maps [default: 32].
+    --octaves=<octaves>      The number of layers of ever finer noise added
+                             to generated maps [default: 4].
+    --water=<level>          Generate water where the elevation, from -1 to 1,
+                             is below this level [default: -0.2].
+    --swamp=<level>          Generate swamp where the elevation is below this
+                             level, and not water [default: -0.1].
+    --trees=<level>          Generate trees where the vegetation, from -1 to
+                             1, is above this level [default: 0.1].
+    --min-region=<fraction>  Loosen the levels above until the largest region
+                             of ground covers this much of the generated map
+                             [default: 0.5].
+    --ensure-connected       Carve corridors of ground through the generated
+                             map until every passable tile within the source
+                             or target regions (as given for random trials)
+                             can be reached from every other.
+    --write-patch=<patch>    Also write the differing tiles to this file, as
+                             a patch that turns <map-a> into <map-b>.
 
 Algorithms:
     astar        Do a full A* search at every step.
-    rastar       Do a full A* search and follow as long as possible.
+    bug2         Head straight for the goal, and follow the boundary of
+                 obstacles in the way until back on the straight line from
+                 the start, closer to the goal. Never searches.
+    cpd          Know the map, and look up every move in a database of
+                 optimal first moves between all pairs of tiles. Building
+                 the database is slow on large maps; it is stored in (and
+                 read back from) the directory given as the cache
+                 parameter, if any.
+    human        Ask for every move on the terminal, pressed as a key
+                 (qweadzxc, the numeric keypad or the arrows, s to wait, Q
+                 to give up). Works best with a single run and a small map.
+    potential    Move down the gradient of a potential field, attracted by
+                 the goal and repelled by obstacles, and escape local
+                 minima with A*. Takes the repulsion (default 1) and range
+                 (default 3) parameters.
+    qastar       Like rastar, but search a quadtree of the map, where open
+                 areas are single nodes. Cheaper on large open maps, but
+                 paths are not always optimal.
+    rastar       Do a full A* search and follow as long as possible. Given
+                 the replan_radius parameter, also search again as soon as
+                 a tile within that many moves of the rest of the path is
+                 found to be blocked or of costlier terrain. Given
+                 reuse_paths=true, keep every path planned, and follow a
+                 kept path through the agent's location to the goal while
+                 it is believed free instead of searching (best with
+                 --retain-beliefs).
+                 Given a scanner (see --scan-radius), scan before moving
+                 whenever the moves onto unseen tiles that the scan would
+                 show cost more than the scan.
+    scripted     Replay the moves in the file given as the script
+                 parameter, with one location (y x) or action (wait,
+                 sense, or a direction such as north or southeast) per
+                 line.
+
+Costs:
+    geometric  Every move costs its length.
+    safety     Moves onto tiles believed to be next to an obstacle cost
+               1 + --safety-weight times their length.
+    turns      Moves in a different direction from the one before them cost
+               an extra --turn-weight.
+    doubt      Moves onto tiles not yet observed cost 1 + --doubt-weight
+               times their length for every move between them and the
+               nearest observed tile, trusting unknown space less the
+               farther it is from what the agent has seen.
+    overlay    Moves onto a tile cost an extra --overlay-weight times the
+               tile's value in the cost layer of the --overlay.
+    terrain    Moves onto a tile cost the factor given to its terrain by
+               the --terrain-weights times their length. Tiles whose
+               terrain the agent has not seen count as open ground.
+    Turns are charged by where searches first reach every tile from, rather
+    than by every direction they could arrive in, so plans under the turns
+    cost may pay for more turns than they need to.
+    Unless every move costs just its length, results report the cost of the
+    executed paths under the combined costs, judged by the true map, next to
+    their length. Otherwise, these only change how agents plan. Agents that
+    take a heuristic, and cpd, also take the metric parameter, which sets how
+    the moves they make are measured in the results: length (the default) or
+    unit (every move costs 1).
+
+Metrics:
+    turns      How many times the agent changed heading along its path
+               (turns), and by how many degrees it turned in all
+               (turning_angle), to compare how smooth paths are.
+
+Exit status:
+    0  Every trial was solved (or --fail-on-unsolved was not given).
+    1  The command line is invalid.
+    2  The map could not be read or parsed, or did not match --expect-hash,
+       or the patch could not be read or applied, or the maps to diff are
+       not the same size.
+    3  Some trial was unsolved (or oscillating, disconnected, errored, out
+       of steps, or ended at an illegal move) and the option --fail-on-unsolved
+       was given.
+    4  Some trial timed out.
+    5  The results, or another output, could not be written.
+    130  The run was interrupted. The results of the trials that ended are
+         still written; interrupting again quits at once.
+
+Formats:
+    movingai   The movingai.com benchmark format.
+    rle        The movingai format with every row run-length encoded, to keep
+               large maps small. Maps in either format are recognized as such
+               when read, unless --format says otherwise.
+
+Orders:
+    given       The order in which trials are generated or listed.
+    shuffle     A random order, drawn with --order-seed, independently of
+                the trials themselves.
+    difficulty  From the shortest trial to the longest, by the octile
+                distance between their endpoints.
+
+Output paths:
+    The names of output files may refer to the parameters of the run, as
+    recorded in the manifest, with {name}: for example, {seed}, {heuristic}
+    or {algorithm} (or {algo}). They may also refer to {map}, the name of the
+    map without extensions, and to {tag}. Results and beliefs may also refer
+    to swept parameters. Missing directories are created.
 
 Heuristics:
     euclidean  The Euclidean distance metric (sqrt(dy^2+dx^2)).
+    landmarks  The largest difference between the true costs from one of a
+               few landmarks to either point, or octile if larger. Takes the
+               landmarks parameter, the number of landmarks (default 8).
     octile     The octile distance metric (max(dy,dx)-min(dy,dx)+sqrt(2)*min(dy,dx)).
+    perfect    The true cost of an optimal path, looked up in the database
+               built by cpd, which is stored in the directory given as the
+               cache parameter, if any.
+    Both landmarks and perfect know the true map, so they may overestimate
+    the cost of paths through tiles the agent has not seen yet. Given the
+    parameter cache_heuristic=true, any heuristic keeps its estimate from
+    every tile to the current target once computed, until the target
+    changes: this pays off with the costlier heuristics, such as landmarks.
 ";
 
-#[derive(Debug, Deserialize)]
-enum Algorithm {
-    Astar,
-    Rastar,
+#[derive(Clone, Debug, Deserialize)]
+enum Format {
+    Movingai,
+    Rle,
 }
 
-#[derive(Debug, Deserialize)]
-enum Heuristic {
-    Euclidean,
-    Octile,
+impl Format {
+    fn map_format(&self) -> MapFormat {
+        match *self {
+            Format::Movingai => MapFormat::Movingai,
+            Format::Rle => MapFormat::Rle,
+        }
+    }
 }
 
 impl<'de> de::Deserialize<'de> for Verbosity {
@@ -56,74 +435,1445 @@ impl<'de> de::Deserialize<'de> for Verbosity {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 struct Args {
+    cmd_convert: bool,
+    cmd_diff: bool,
+    cmd_replay: bool,
+    cmd_generate: bool,
+    cmd_suite: bool,
+    cmd_serve: bool,
+    cmd_bench: bool,
     arg_map: String,
+    arg_map_a: String,
+    arg_map_b: String,
+    arg_input: String,
+    arg_output: String,
+    arg_searches: String,
+    arg_suite: String,
+    arg_height: usize,
+    arg_width: usize,
     arg_trials: Option<usize>,
     arg_startx: usize,
     arg_starty: usize,
     arg_endx: usize,
     arg_endy: usize,
-    flag_algorithm: Algorithm,
-    flag_heuristic: Heuristic,
+    flag_algorithm: String,
+    flag_heuristic: String,
     flag_verbosity: Verbosity,
+    flag_log_level: Option<u8>,
+    flag_trace_steps: bool,
+    flag_trace_search: bool,
+    flag_format: Option<Format>,
+    flag_to: Option<Format>,
     flag_from: usize,
+    flag_pairs: Option<String>,
+    flag_timeout: Option<f64>,
+    flag_move_budget: Option<f64>,
+    flag_perturb: Option<f64>,
+    flag_perturb_seed: usize,
+    flag_max_steps: Option<String>,
+    flag_fail_on_unsolved: bool,
+    flag_allow_oscillation: bool,
+    flag_keep_disconnected: bool,
+    flag_catch_panics: bool,
+    flag_validate_moves: bool,
+    flag_execution: String,
+    flag_bump_cost: f64,
+    flag_pursuit: Option<String>,
+    flag_retain_beliefs: bool,
+    flag_order: String,
+    flag_order_seed: usize,
+    flag_allow_unreachable: bool,
+    flag_sensor_radius: usize,
+    flag_terrain_radius: Option<usize>,
+    flag_observe_terrain: bool,
+    flag_scan_radius: Option<usize>,
+    flag_scan_cost: f64,
+    flag_scan_budget: Option<usize>,
+    flag_clearance: usize,
+    flag_cost: String,
+    flag_safety_weight: f64,
+    flag_turn_weight: f64,
+    flag_doubt_weight: f64,
+    flag_overlay_weight: f64,
+    flag_terrain_weights: Option<String>,
+    flag_overlay: Option<String>,
+    flag_with_baseline: bool,
+    flag_metrics: Option<String>,
+    flag_param: Vec<String>,
+    flag_sweep: Vec<String>,
     flag_seed: usize,
+    flag_max_attempts: usize,
+    flag_source_region: String,
+    flag_target_region: String,
+    flag_heatmap: Option<String>,
+    flag_tidy: Option<String>,
+    flag_dump_beliefs: Option<String>,
+    flag_dump_search: Option<String>,
+    flag_output: Option<String>,
+    flag_manifest: Option<String>,
+    flag_tag: Option<String>,
+    flag_expect_hash: Option<String>,
+    flag_patch: Option<String>,
+    flag_write_patch: Option<String>,
+    flag_trial: Option<usize>,
+    flag_listen: Option<String>,
+    flag_planner_threads: usize,
+    flag_reps: usize,
+    flag_warmup: usize,
+    flag_scale: f64,
+    flag_octaves: usize,
+    flag_water: f64,
+    flag_swamp: f64,
+    flag_trees: f64,
+    flag_min_region: f64,
+    flag_ensure_connected: bool,
+}
+
+/// Splits an argument of the form `name=value`.
+fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    let mut parts = assignment.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => {
+            Ok((name.to_owned(), value.to_owned()))
+        }
+        _ => Err(format!("Expected name=value, found '{}'.", assignment)),
+    }
+}
+
+/// Splits a `--sweep` argument of the form `name=value,value,...`.
+fn parse_sweep(sweep: &str) -> Result<(String, Vec<String>), String> {
+    let (name, values) = parse_assignment(sweep)?;
+    Ok((name, values.split(',').map(|v| v.to_owned()).collect()))
+}
+
+/// Lists every combination of one value for each swept parameter.
+fn combinations(sweeps: &[(String, Vec<String>)]) -> Vec<Parameters> {
+    let mut combinations = vec![Parameters::new()];
+    for (name, values) in sweeps {
+        combinations = combinations.into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(name.clone(), value.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+/// Describes a combination of swept parameters as `name=value name=value`.
+fn label(combination: &Parameters) -> String {
+    combination.iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Names the map for output paths: its file name without extensions.
+fn map_name(args: &Args) -> &str {
+    if args.arg_map == "-" {
+        return "stdin";
+    }
+    Path::new(&args.arg_map)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or("")
+}
+
+/// Fills in the name of an output file given as `template`, replacing every
+/// `{name}` with the value of that parameter of the run, as recorded in the
+/// manifest, or of `combination` of swept parameters. Also recognizes
+/// `{map}`, `{algo}` (short for `{algorithm}`) and `{tag}`, which is left
+/// empty without a `--tag`.
+fn output_path(template: &str,
+               args: &Args,
+               combination: &Parameters)
+               -> Result<String, String> {
+    let mut variables = parameters(args);
+    variables.insert("map".to_owned(), map_name(args).to_owned());
+    variables.entry("tag".to_owned()).or_default();
+    variables.extend(combination.clone());
+    let algorithm = variables["algorithm"].clone();
+    variables.insert("algo".to_owned(), algorithm);
+
+    let mut path = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .map(|close| open + close)
+            .ok_or_else(|| {
+                format!("Unclosed '{{' in output path '{}'.", template)
+            })?;
+        let name = &rest[open + 1..close];
+        let value = variables.get(name).ok_or_else(|| {
+            format!("Unknown variable '{{{}}}' in output path '{}'.",
+                    name,
+                    template)
+        })?;
+        path.push_str(&rest[..open]);
+        path.push_str(value);
+        rest = &rest[close + 1..];
+    }
+    path.push_str(rest);
+    Ok(path)
+}
+
+/// Checks that every output path in `args` can be filled in, before running
+/// anything. Results and beliefs may refer to swept parameters; the
+/// manifest, heatmap and tidy results, which cover every combination, may
+/// not.
+fn check_output_paths(args: &Args) -> Result<(), String> {
+    let sweeps = args.flag_sweep
+        .iter()
+        .map(|sweep| parse_sweep(sweep))
+        .collect::<Result<Vec<_>, _>>()?;
+    let combination = combinations(&sweeps).swap_remove(0);
+    for template in args.flag_output
+        .iter()
+        .chain(&args.flag_dump_beliefs)
+        .chain(&args.flag_dump_search) {
+        output_path(template, args, &combination)?;
+    }
+    for template in args.flag_manifest
+        .iter()
+        .chain(&args.flag_heatmap)
+        .chain(&args.flag_tidy) {
+        output_path(template, args, &Parameters::new())?;
+    }
+    Ok(())
+}
+
+/// Creates the directories leading to `path` that do not exist yet.
+fn create_parent<P>(path: &P) -> IOResult<()>
+    where P: AsRef<Path> + ?Sized
+{
+    match path.as_ref().parent() {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Collects the parameters the agent named by `--algorithm` is built with.
+fn agent_parameters(args: &Args) -> Result<Parameters, String> {
+    let mut parameters = Parameters::new();
+    parameters.insert("heuristic".to_owned(), args.flag_heuristic.clone());
+    for param in &args.flag_param {
+        let (name, value) = parse_assignment(param)?;
+        parameters.insert(name, value);
+    }
+    Ok(parameters)
+}
+
+/// Runs `experiment` once for each combination of swept parameters (or just
+/// once, if nothing is swept), returning the results of each combination.
+fn run_algorithm(experiment: &mut Experiment,
+                 registry: &Registry,
+                 args: &Args)
+                 -> Result<Vec<(Parameters, Data)>, String> {
+    let base = agent_parameters(args)?;
+    let sweeps = args.flag_sweep
+        .iter()
+        .map(|sweep| parse_sweep(sweep))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut results = Vec::new();
+    for combination in combinations(&sweeps) {
+        let mut parameters = base.clone();
+        parameters.extend(combination.clone());
+        let algorithm = parameters.remove("algorithm")
+            .unwrap_or_else(|| args.flag_algorithm.clone());
+
+        let agent = registry.build(&algorithm, &parameters, experiment.grid())
+            .map_err(|e| e.to_string())?;
+        if let Some(ref directory) = args.flag_dump_beliefs {
+            let mut directory =
+                PathBuf::from(output_path(directory, args, &combination)?);
+            if !sweeps.is_empty() {
+                directory.push(label(&combination).replace(' ', "_"));
+            }
+            create_dir_all(&directory)
+                .map_err(|e| format!("{}: {}", directory.display(), e))?;
+            let mut dump = BeliefDump::new(directory);
+            dump.set_order(experiment.run_order());
+            experiment.add_observer(Box::new(dump));
+        }
+        if let Some(ref template) = args.flag_dump_search {
+            let path = output_path(template, args, &combination)?;
+            let log = open_search_log(&path, experiment)
+                .map_err(|e| format!("Could not write to {}: {}", path, e))?;
+            experiment.add_observer(Box::new(log));
+        }
+        if let (true, Some(template)) = (sweeps.is_empty(), &args.flag_output) {
+            let path = output_path(template, args, &combination)?;
+            let log = open_log(&path, experiment)
+                .map_err(|e| format!("Could not write to {}: {}", path, e))?;
+            experiment.add_observer(Box::new(log));
+        }
+        results.push((combination, experiment.run(agent)));
+        experiment.take_observers();
+        if interrupted() {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Starts writing every search run by `experiment` to the file at `path`.
+fn open_search_log(path: &str,
+                   experiment: &Experiment)
+                   -> IOResult<SearchLog<BufWriter<File>>> {
+    create_parent(&path)?;
+    let mut log = SearchLog::new(BufWriter::new(File::create(path)?));
+    log.set_order(experiment.run_order());
+    Ok(log)
+}
+
+/// Starts writing the results of every trial run by `experiment` to the
+/// file at `path` as soon as the trial ends.
+fn open_log(path: &str,
+            experiment: &Experiment)
+            -> IOResult<ResultLog<BufWriter<File>>> {
+    create_parent(&path)?;
+    let mut out = BufWriter::new(File::create(path)?);
+    write_map_meta(&mut out, &experiment.grid().meta())?;
+    out.flush()?;
+    let mut log = ResultLog::new(out,
+                                 experiment.tag().map(str::to_owned),
+                                 experiment.lines());
+    log.set_order(experiment.run_order());
+    Ok(log)
+}
+
+/// Makes the first interrupt (with Ctrl-C) stop the run once the current
+/// trial ends, so that the results of the trials that ended are written,
+/// and the second one quit at once.
+fn catch_interrupts() {
+    INTERRUPT.get_or_init(|| {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let flag = interrupt.clone();
+        let caught = ctrlc::set_handler(move || {
+            if flag.swap(true, Ordering::SeqCst) {
+                process::exit(EXIT_INTERRUPTED);
+            }
+            eprintln!("Interrupted: finishing the current trial. Interrupt \
+                       again to quit at once.");
+        });
+        if let Err(e) = caught {
+            eprintln!("Cannot catch interrupts: {}", e);
+        }
+        interrupt
+    });
+}
+
+fn interrupted() -> bool {
+    INTERRUPT.get()
+        .is_some_and(|interrupt| interrupt.load(Ordering::SeqCst))
+}
+
+fn run_on_grid(grid: Grid,
+               registry: &Registry,
+               args: &Args)
+               -> Result<Vec<(Parameters, Data)>, String> {
+    let mut experiment = build_experiment(grid, args)?;
+    run_algorithm(&mut experiment, registry, args)
+}
+
+/// Sets up the experiment described by `args` on `grid`, with its random
+/// trials, if any, generated.
+fn build_experiment(grid: Grid, args: &Args) -> Result<Experiment, String> {
+    let mut experiment = if let Some(ref path) = args.flag_pairs {
+        if path.ends_with(".scen") {
+            let scenarios = try_scenarios_from_file(path)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            Experiment::scenarios(grid, scenarios, verbosity(args))?
+        } else {
+            let pairs = try_pairs_from_file(path)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            Experiment::pairs(grid, pairs, verbosity(args))?
+        }
+    } else if let Some(trials) = args.arg_trials {
+        let mut experiment = Experiment::trials(grid,
+                                                args.flag_from,
+                                                args.flag_from + trials,
+                                                args.flag_seed,
+                                                verbosity(args));
+        experiment.set_sampling(Sampling {
+                                    attempts: args.flag_max_attempts,
+                                    allow_unreachable:
+                                        args.flag_allow_unreachable,
+                                    sources: args.flag_source_region.parse()?,
+                                    targets: args.flag_target_region.parse()?,
+                                });
+        experiment
+    } else {
+        let mut grid = grid;
+        let source = Point::new(args.arg_starty, args.arg_startx);
+        let target = Point::new(args.arg_endy, args.arg_endx);
+        validate_endpoints(&mut grid,
+                           &source,
+                           &target,
+                           !args.flag_allow_unreachable)
+                .map_err(|e| format!("Invalid trial: {}", e))?;
+        Experiment::single(grid, source, target, verbosity(args))
+    };
+    experiment.set_timeout(args.flag_timeout.map(Duration::from_secs_f64));
+    experiment.set_move_budget(args.flag_move_budget
+                                   .map(Duration::from_secs_f64));
+    experiment.set_perturbation(perturbation(args)?);
+    experiment.set_step_limit(args.flag_max_steps
+                                  .as_ref()
+                                  .map(|limit| limit.parse())
+                                  .transpose()?);
+    if let Some(interrupt) = INTERRUPT.get() {
+        experiment.set_interrupt(interrupt.clone());
+    }
+    experiment.set_detect_oscillation(!args.flag_allow_oscillation);
+    experiment.set_detect_disconnection(!args.flag_keep_disconnected);
+    experiment.set_catch_panics(args.flag_catch_panics);
+    experiment.set_validate_moves(args.flag_validate_moves);
+    experiment.set_execution(execution_model(args)?);
+    experiment.set_scanner(scanner(args)?);
+    experiment.set_pursuit(pursuit(args)?);
+    experiment.set_retain_beliefs(args.flag_retain_beliefs);
+    experiment.set_trial_order(trial_order(args)?);
+    experiment.set_trace(trace(args));
+    experiment.set_sensor(sensor(args));
+    experiment.set_clearance(args.flag_clearance);
+    experiment.set_cost_model(cost_model(args)?);
+    experiment.set_with_baseline(args.flag_with_baseline);
+    if let Some(ref names) = args.flag_metrics {
+        for name in names.split(',') {
+            experiment.add_metric(metrics::build(name)?);
+        }
+    }
+    experiment.set_tag(args.flag_tag.clone());
+    experiment.prepare()?;
+    Ok(experiment)
+}
+
+/// Describes how much is logged, with `--log-level` taking precedence over
+/// the verbosity (whose steps are traced anyway, see `trace`).
+fn verbosity(args: &Args) -> Verbosity {
+    match args.flag_log_level {
+        Some(level) => Verbosity::new(level.min(1)),
+        None => args.flag_verbosity,
+    }
+}
+
+/// Describes what is traced while trials run.
+fn trace(args: &Args) -> Trace {
+    Trace {
+        steps: args.flag_trace_steps || args.flag_verbosity == Verbosity::Two,
+        search: args.flag_trace_search,
+        record_search: args.flag_dump_search.is_some(),
+    }
+}
+
+/// Describes the agent's sensor. `--terrain-radius` takes precedence over
+/// `--observe-terrain`.
+fn sensor(args: &Args) -> Sensor {
+    let observed = if args.flag_observe_terrain {
+        Some(args.flag_sensor_radius)
+    } else {
+        None
+    };
+    Sensor {
+        radius: args.flag_sensor_radius,
+        terrain_radius: args.flag_terrain_radius.or(observed),
+    }
+}
+
+/// Describes the agent's long-range scanner, if it has one.
+fn scanner(args: &Args) -> Result<Option<Scanner>, String> {
+    let radius = match args.flag_scan_radius {
+        Some(radius) => radius,
+        None => return Ok(None),
+    };
+    if args.flag_scan_cost < 0.0 {
+        return Err(format!("Invalid scan cost {}: it cannot be negative.",
+                           args.flag_scan_cost));
+    }
+    Ok(Some(Scanner {
+                sensor: Sensor {
+                    radius: radius,
+                    terrain_radius: Some(radius),
+                },
+                cost: args.flag_scan_cost,
+                budget: args.flag_scan_budget,
+            }))
+}
+
+/// Describes how agents weigh the cost of moves, combining the costs listed.
+fn cost_model(args: &Args) -> Result<Composite, String> {
+    let weight = |name, weight: f64| if weight >= 0.0 {
+        Ok(weight)
+    } else {
+        Err(format!("Invalid {} weight {}: expected a non-negative number.",
+                    name,
+                    weight))
+    };
+
+    let mut model = Composite::default();
+    for cost in args.flag_cost.split(',') {
+        match cost {
+            "geometric" => (),
+            "safety" => model.risk = weight("safety", args.flag_safety_weight)?,
+            "turns" => model.turn = weight("turn", args.flag_turn_weight)?,
+            "doubt" => {
+                model.doubt = weight("doubt", args.flag_doubt_weight)?
+            }
+            "overlay" => {
+                model.layer = weight("overlay", args.flag_overlay_weight)?
+            }
+            "terrain" => model.terrain = terrain_weights(args)?,
+            _ => return Err(format!("Unknown cost '{}'.", cost)),
+        }
+    }
+    model.check()?;
+    Ok(model)
+}
+
+/// Reads the factors of moves onto every terrain under the terrain cost.
+fn terrain_weights(args: &Args) -> Result<[Distance; 5], String> {
+    let mut weights = [1.0; 5];
+    let listed = args.flag_terrain_weights.as_ref().map_or("", |w| w.as_str());
+    for entry in listed.split(',').filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.splitn(2, '=');
+        let symbol = parts.next().unwrap_or("");
+        let terrain = match (symbol.len(), symbol.chars().next()) {
+            (1, Some(symbol)) => terrain_from_symbol(symbol),
+            _ => None,
+        };
+        let weight = parts.next().and_then(|weight| weight.parse().ok());
+        match (terrain, weight) {
+            (Some(terrain), Some(weight)) if weight >= 1.0 => {
+                weights[terrain.code() as usize] = weight;
+            }
+            _ => {
+                return Err(format!("Invalid terrain weight '{}': expected a \
+                                    terrain symbol and a number of at \
+                                    least 1, as T=2.",
+                                   entry))
+            }
+        }
+    }
+    Ok(weights)
+}
+
+/// Describes how the map is perturbed for every trial, if it is.
+fn perturbation(args: &Args) -> Result<Option<Perturbation>, String> {
+    match args.flag_perturb {
+        Some(fraction) if (0.0..=1.0).contains(&fraction) => {
+            Ok(Some(Perturbation {
+                        fraction: fraction,
+                        seed: args.flag_perturb_seed,
+                    }))
+        }
+        Some(fraction) => {
+            Err(format!("Invalid perturbation {}: expected a fraction from \
+                         0 to 1.",
+                        fraction))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Describes the order in which trials run.
+fn trial_order(args: &Args) -> Result<TrialOrder, String> {
+    match args.flag_order.as_str() {
+        "given" => Ok(TrialOrder::Given),
+        "shuffle" => Ok(TrialOrder::Shuffled(args.flag_order_seed)),
+        "difficulty" => Ok(TrialOrder::Difficulty),
+        order => Err(format!("Unknown order '{}'.", order)),
+    }
+}
+
+fn execution_model(args: &Args) -> Result<ExecutionModel, String> {
+    match args.flag_execution.as_str() {
+        "direct" => Ok(ExecutionModel::Direct),
+        "bump" if args.flag_bump_cost >= 0.0 => {
+            Ok(ExecutionModel::Bump(args.flag_bump_cost))
+        }
+        "bump" => {
+            Err(format!("Invalid bump cost {}: it cannot be negative.",
+                        args.flag_bump_cost))
+        }
+        execution => Err(format!("Unknown execution model '{}'.", execution)),
+    }
+}
+
+/// Makes targets flee with `--pursuit`, if given.
+fn pursuit(args: &Args) -> Result<Option<Pursuit>, String> {
+    let capture = match args.flag_pursuit.as_deref() {
+        None => return Ok(None),
+        Some("colocation") => Capture::Colocation,
+        Some("adjacency") => Capture::Adjacency,
+        Some(capture) => {
+            return Err(format!("Unknown capture rule '{}'.", capture))
+        }
+    };
+    Ok(Some(Pursuit::new(Flee, capture)))
+}
+
+/// Reads the map at `path`, taking `-` to mean the standard input.
+fn read_map(path: &str) -> Result<Grid, LoadError> {
+    read_map_as(path, None)
+}
+
+/// Like `read_map`, but reads the map in `format`, if given, rather than in
+/// the format its header declares.
+fn read_map_as(path: &str,
+               format: Option<MapFormat>)
+               -> Result<Grid, LoadError> {
+    match (path, format) {
+        ("-", format) => read_map_from(stdin().lock(), format),
+        (path, Some(format)) => try_grid_from_file_as(path, format),
+        (path, None) => try_grid_from_file(path),
+    }
+}
+
+/// Reads a map from `reader`, in `format` if given.
+fn read_map_from<R>(reader: R,
+                    format: Option<MapFormat>)
+                    -> Result<Grid, LoadError>
+    where R: Read
+{
+    match format {
+        Some(format) => try_grid_from_reader_as(reader, format),
+        None => try_grid_from_reader(reader),
+    }
+}
+
+/// Reads the map named by `args`, in the format given with `--format`, if
+/// any.
+fn load_grid(args: &Args) -> Result<Grid, LoadError> {
+    read_map_as(&args.arg_map,
+                args.flag_format.as_ref().map(Format::map_format))
+}
+
+/// Applies the patch given with `--patch`, if any, to `grid`.
+fn apply_patch(args: &Args, grid: &mut Grid) -> Result<(), String> {
+    if let Some(ref path) = args.flag_patch {
+        let changes = try_patch_from_file(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        grid.apply(&changes).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Lays the layers read from the `--overlay` file, if any, over `grid`.
+fn load_overlay(args: &Args, grid: &mut Grid) -> Result<(), String> {
+    if let Some(ref path) = args.flag_overlay {
+        let overlay = read_overlay_file(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        grid.set_overlay(overlay).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Lists the tiles that differ between `<map-a>` and `<map-b>`, writing them
+/// to the `--write-patch` file if given, and returns the status the process
+/// should exit with.
+fn diff(args: &Args) -> i32 {
+    let mut grids = Vec::with_capacity(2);
+    for path in &[&args.arg_map_a, &args.arg_map_b] {
+        match read_map(path) {
+            Ok(grid) => grids.push(grid),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return EXIT_PARSE_FAILURE;
+            }
+        }
+    }
+    let changes = match grids[0].diff(&grids[1]) {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+
+    println!("Differing tiles: {}", changes.len());
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    if let Some(ref path) = args.flag_write_patch {
+        let written = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_patch(&changes, &mut out)?;
+            out.flush()
+        });
+        if let Err(e) = written {
+            eprintln!("Could not write to {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+    EXIT_SUCCESS
 }
 
-fn get_heuristic(argument: &Heuristic) -> (fn(&Point, &Point) -> Distance) {
-    match *argument {
-        Heuristic::Euclidean => Distance::euclidean_heuristic,
-        Heuristic::Octile => Distance::octile_heuristic,
+/// Replays the searches in `<searches>` (those of `--trial`, if given) over
+/// `<map>`, one expansion at a time as lines are read from stdin, and
+/// returns the status the process should exit with.
+fn replay(args: &Args) -> i32 {
+    let grid = match read_map(&args.arg_map) {
+        Ok(grid) => grid,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_map, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+    let searches = match File::open(&args.arg_searches)
+              .and_then(|file| read_searches(BufReader::new(file))) {
+        Ok(searches) => searches,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_searches, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+
+    let stdin = stdin();
+    let mut input = stdin.lock();
+    let mut output = stdout();
+    for search in &searches {
+        if args.flag_trial.is_some_and(|trial| trial != search.trial) {
+            continue;
+        }
+        match replay_search(&grid, search, &mut input, &mut output) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                eprintln!("Cannot replay the search: {}", e);
+                return EXIT_USAGE;
+            }
+        }
     }
+    EXIT_SUCCESS
 }
 
-fn run_algorithm(experiment: &mut Experiment, args: &Args) -> Data {
+/// Answers queries for paths on `<map>`, over stdin and stdout or over TCP
+/// with `--listen`, until the input ends, and returns the status the process
+/// should exit with.
+fn serve(args: &Args) -> i32 {
+    let grid = match read_map(&args.arg_map) {
+        Ok(grid) => grid,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_map, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+    let options = PlanOptions {
+        threads: args.flag_planner_threads,
+        ..PlanOptions::default()
+    };
+    let mut service = Service::new(grid, options);
+    let served = match args.flag_listen {
+        Some(ref address) => {
+            service.listen(address.as_str(), |address| {
+                eprintln!("Answering queries on {}", address);
+            })
+        }
+        None => service.serve(stdin().lock(), &mut stdout().lock()),
+    };
+    match served {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Cannot answer queries: {}", e);
+            EXIT_USAGE
+        }
+    }
+}
 
-    let heuristic = get_heuristic(&args.flag_heuristic);
+/// Runs the trials on `<map>` with `--algorithm`, `--warmup` times and then
+/// `--reps` times more, writing the mean and standard deviation of the wall
+/// time and expansions of the timed runs, and returns the status the
+/// process should exit with.
+fn bench(args: &Args, registry: &Registry) -> i32 {
+    if args.flag_reps == 0 {
+        eprintln!("Invalid number of repetitions: 0.");
+        return EXIT_USAGE;
+    }
+    let grid = match load_grid(args) {
+        Ok(grid) => grid,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_map, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+    let meta = grid.meta();
+    let runs = match time_runs(grid, registry, args) {
+        Ok(runs) => runs,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let (times, expansions): (Vec<_>, Vec<_>) = runs.into_iter().unzip();
+    let (time, time_deviation) = mean_and_deviation(&times);
+    let (expansions, expansions_deviation) = mean_and_deviation(&expansions);
+    let mut out = stdout().lock();
+    write_map_meta(&mut out, &meta)
+        .and_then(|_| {
+            writeln!(out,
+                     "{}: {} runs ({} warm-up), mean wall time {:.6} s \
+                      (stddev {:.6} s), mean expansions {:.1} (stddev {:.1})",
+                     args.flag_algorithm,
+                     times.len(),
+                     args.flag_warmup,
+                     time,
+                     time_deviation,
+                     expansions,
+                     expansions_deviation)
+        })
+        .expect("Could not write results to stdout");
+    EXIT_SUCCESS
+}
+
+/// Runs the trials described by `args` on `grid` with a new agent every time,
+/// returning the wall time (in seconds) and the total expansions of every
+/// run after the warm-up runs.
+fn time_runs(grid: Grid,
+             registry: &Registry,
+             args: &Args)
+             -> Result<Vec<(f64, f64)>, String> {
+    let mut args = args.clone();
+    args.flag_verbosity = Verbosity::Zero;
+    let mut experiment = build_experiment(grid, &args)?;
+    let parameters = agent_parameters(&args)?;
+    let mut runs = Vec::with_capacity(args.flag_reps);
+    for run in 0..args.flag_warmup + args.flag_reps {
+        let agent = registry.build(&args.flag_algorithm,
+                                   &parameters,
+                                   experiment.grid())
+            .map_err(|e| e.to_string())?;
+        let started = Instant::now();
+        let data = experiment.run(agent);
+        let elapsed = started.elapsed().as_secs_f64();
+        if run >= args.flag_warmup {
+            let expansions: usize = data.iter().map(|d| d.expansions).sum();
+            runs.push((elapsed, expansions as f64));
+        }
+    }
+    Ok(runs)
+}
+
+/// Returns the mean and the sample standard deviation of `values`, which
+/// must not be empty. The deviation of a single value is 0.
+fn mean_and_deviation(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let squares = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>();
+    (mean, (squares / (n - 1.0)).sqrt())
+}
 
-    match args.flag_algorithm {
-        Algorithm::Astar => experiment.run(AlwaysAstar::new(heuristic)),
-        Algorithm::Rastar => experiment.run(RepeatedAstar::new(heuristic)),
+/// Converts the map at `<input>` to the format given with `--to`, writing it
+/// to `<output>`, and returns the status the process should exit with.
+fn convert(args: &Args) -> i32 {
+    let grid = match read_map(&args.arg_input) {
+        Ok(grid) => grid,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_input, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+    write_output(&grid, args)
+}
+
+/// Generates a map of `<height>` by `<width>` tiles from noise, writing it
+/// to `<output>` in the format given with `--to`, and returns the status the
+/// process should exit with.
+fn generate(args: &Args) -> i32 {
+    if args.arg_height.checked_mul(args.arg_width)
+           .is_none_or(|tiles| tiles > MAX_TILES) {
+        eprintln!("Maps of {}x{} tiles are larger than the {} supported.",
+                  args.arg_height,
+                  args.arg_width,
+                  MAX_TILES);
+        return EXIT_USAGE;
+    }
+    let generator = NoiseGenerator {
+        seed: args.flag_seed,
+        scale: args.flag_scale,
+        octaves: args.flag_octaves,
+        water: args.flag_water,
+        swamp: args.flag_swamp,
+        trees: args.flag_trees,
+        min_region: args.flag_min_region,
+    };
+    let mut grid = generator.generate(args.arg_height, args.arg_width);
+    if args.flag_ensure_connected {
+        let connected = [&args.flag_source_region, &args.flag_target_region]
+            .iter()
+            .map(|region| region.parse())
+            .collect::<Result<Vec<Region>, _>>()
+            .and_then(|regions| ensure_connected(&mut grid, &regions));
+        if let Err(e) = connected {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
     }
+    write_output(&grid, args)
 }
 
-fn run_from_args(args: &Args) -> Data {
-    let grid = grid_from_file(&args.arg_map);
+/// Writes `grid` to `<output>` in the format given with `--to`, and returns
+/// the status the process should exit with.
+fn write_output(grid: &Grid, args: &Args) -> i32 {
+    let format = args.flag_to
+        .as_ref()
+        .map_or(MapFormat::Movingai, Format::map_format);
 
-    let mut experiment = if let Some(trials) = args.arg_trials {
-        Experiment::trials(grid,
-                           args.flag_from,
-                           args.flag_from + trials,
-                           args.flag_seed,
-                           args.flag_verbosity)
+    let written = File::create(&args.arg_output).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write_grid(grid, format, &mut out)?;
+        out.flush()
+    });
+    match written {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Could not write to {}: {}", args.arg_output, e);
+            EXIT_WRITE_FAILURE
+        }
+    }
+}
+
+/// Runs random trials on every map of `<suite>`, writes the summary of the
+/// results on each map followed by their weighted means over the suite, and
+/// returns the status the process should exit with.
+fn suite(args: &Args, registry: &Registry) -> i32 {
+    let entries = match try_suite_from_file(&args.arg_suite) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_suite, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+
+    catch_interrupts();
+    let mut metas = Vec::with_capacity(entries.len());
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        let mut map_args = args.clone();
+        map_args.arg_map = entry.map.to_string_lossy().into_owned();
+        map_args.arg_trials = Some(entry.trials);
+        map_args.flag_output = None;
+        let grid = match load_grid(&map_args) {
+            Ok(grid) => grid,
+            Err(e) => {
+                eprintln!("{}: {}", map_args.arg_map, e);
+                return EXIT_PARSE_FAILURE;
+            }
+        };
+        metas.push(grid.meta());
+        match run_on_grid(grid, registry, &map_args) {
+            Ok(mut map_results) => results.push(map_results.swap_remove(0)),
+            Err(e) => {
+                eprintln!("{}: {}", map_args.arg_map, e);
+                return EXIT_USAGE;
+            }
+        }
+        if interrupted() {
+            break;
+        }
+    }
+
+    let write_report = |mut out: &mut dyn Write| {
+        let mut summaries = Vec::with_capacity(results.len());
+        for ((entry, meta), (_, data)) in
+            entries.iter().zip(&metas).zip(&results) {
+            write_map_meta(&mut out, meta)?;
+            write_tag(&mut out, data)?;
+            let summary = data.summary();
+            writeln!(out, "{}: {}", entry.map.display(), summary)?;
+            summaries.push((entry.weight, summary));
+        }
+        if let Some(ref tag) = args.flag_tag {
+            write!(out, "[{}] ", tag)?;
+        }
+        writeln!(out, "suite: {}", SuiteSummary::new(&summaries))
+    };
+    let written = match args.flag_output {
+        Some(ref path) => {
+            create_parent(path)
+                .and_then(|_| File::create(path))
+                .and_then(|file| {
+                    let mut out = BufWriter::new(file);
+                    write_report(&mut out)?;
+                    out.flush()
+                })
+                .map_err(|e| format!("Could not write to {}: {}", path, e))
+        }
+        None => {
+            write_report(&mut stdout().lock())
+                .map_err(|e| format!("Could not write results to stdout: {}",
+                                     e))
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        return EXIT_WRITE_FAILURE;
+    }
+
+    exit_status(args, &results)
+}
+
+#[cfg(test)]
+fn run_from_args(args: &Args) -> Vec<(Parameters, Data)> {
+    let mut grid = load_grid(args).unwrap();
+    apply_patch(args, &mut grid).unwrap();
+    load_overlay(args, &mut grid).unwrap();
+    run_on_grid(grid, &Registry::default(), args).unwrap()
+}
+
+fn exit_status(args: &Args, results: &[(Parameters, Data)]) -> i32 {
+    let count = |outcome| {
+        results.iter().map(|(_, data)| data.count(outcome)).sum::<usize>()
+    };
+
+    if interrupted() {
+        EXIT_INTERRUPTED
+    } else if count(Outcome::TimedOut) > 0 {
+        EXIT_TIMEOUT
+    } else if args.flag_fail_on_unsolved &&
+              count(Outcome::Unsolved) + count(Outcome::Oscillating) +
+              count(Outcome::Disconnected) + count(Outcome::Errored) +
+              count(Outcome::OutOfSteps) + count(Outcome::IllegalMove) > 0 {
+        EXIT_UNSOLVED
     } else {
-        Experiment::single(grid,
-                           Point::new(args.arg_starty, args.arg_startx),
-                           Point::new(args.arg_endy, args.arg_endx),
-                           args.flag_verbosity)
+        EXIT_SUCCESS
+    }
+}
+
+/// Lists the parameters that take effect in the run described by `args`.
+fn parameters(args: &Args) -> BTreeMap<String, String> {
+    let mut parameters = BTreeMap::new();
+    let mut set = |name: &str, value: String| {
+        parameters.insert(name.to_owned(), value);
+    };
+
+    set("algorithm", args.flag_algorithm.clone());
+    set("heuristic", args.flag_heuristic.clone());
+    set("verbosity", (args.flag_verbosity as u8).to_string());
+    if let Some(level) = args.flag_log_level {
+        set("log_level", level.to_string());
+    }
+    let trace = trace(args);
+    if trace.steps {
+        set("trace_steps", "true".to_owned());
+    }
+    if trace.search {
+        set("trace_search", "true".to_owned());
+    }
+    set("with_baseline", args.flag_with_baseline.to_string());
+    if let Some(budget) = args.flag_move_budget {
+        set("move_budget", budget.to_string());
+    }
+    if let Some(fraction) = args.flag_perturb {
+        set("perturb", fraction.to_string());
+        set("perturb_seed", args.flag_perturb_seed.to_string());
+    }
+    if let Some(ref names) = args.flag_metrics {
+        set("metrics", names.clone());
+    }
+    if let Some(ref directory) = args.flag_dump_beliefs {
+        set("dump_beliefs", directory.clone());
+    }
+    if let Some(ref path) = args.flag_dump_search {
+        set("dump_search", path.clone());
+    }
+    if let Some(ref patch) = args.flag_patch {
+        set("patch", patch.clone());
+    }
+    if let Some(ref tag) = args.flag_tag {
+        set("tag", tag.clone());
+    }
+    let sensor = sensor(args);
+    set("sensor_radius", sensor.radius.to_string());
+    if let Some(radius) = sensor.terrain_radius {
+        set("terrain_radius", radius.to_string());
+    }
+    set("clearance", args.flag_clearance.to_string());
+    if let Some(ref steps) = args.flag_max_steps {
+        set("max_steps", steps.clone());
+    }
+    set("cost", args.flag_cost.clone());
+    let costs = args.flag_cost.split(',').collect::<Vec<_>>();
+    if costs.contains(&"safety") {
+        set("safety_weight", args.flag_safety_weight.to_string());
+    }
+    if costs.contains(&"turns") {
+        set("turn_weight", args.flag_turn_weight.to_string());
+    }
+    if costs.contains(&"doubt") {
+        set("doubt_weight", args.flag_doubt_weight.to_string());
+    }
+    if costs.contains(&"overlay") {
+        set("overlay_weight", args.flag_overlay_weight.to_string());
+    }
+    if let Some(ref weights) = args.flag_terrain_weights {
+        set("terrain_weights", weights.clone());
+    }
+    if let Some(ref overlay) = args.flag_overlay {
+        set("overlay", overlay.clone());
+    }
+    if args.flag_retain_beliefs {
+        set("retain_beliefs", "true".to_owned());
+    }
+    if args.flag_validate_moves {
+        set("validate_moves", "true".to_owned());
+    }
+    if args.flag_execution != "direct" {
+        set("execution", args.flag_execution.clone());
+        set("bump_cost", args.flag_bump_cost.to_string());
+    }
+    if let Some(radius) = args.flag_scan_radius {
+        set("scan_radius", radius.to_string());
+        set("scan_cost", args.flag_scan_cost.to_string());
+        if let Some(budget) = args.flag_scan_budget {
+            set("scan_budget", budget.to_string());
+        }
+    }
+    if let Some(ref capture) = args.flag_pursuit {
+        set("pursuit", capture.clone());
+    }
+    if args.flag_order != "given" {
+        set("order", args.flag_order.clone());
+        if args.flag_order == "shuffle" {
+            set("order_seed", args.flag_order_seed.to_string());
+        }
+    }
+    if let Some(ref pairs) = args.flag_pairs {
+        set("pairs", pairs.clone());
+    } else if let Some(trials) = args.arg_trials {
+        set("trials", trials.to_string());
+        set("from", args.flag_from.to_string());
+        set("seed", args.flag_seed.to_string());
+        set("max_attempts", args.flag_max_attempts.to_string());
+        set("source_region", args.flag_source_region.clone());
+        set("target_region", args.flag_target_region.clone());
+        set("allow_unreachable", args.flag_allow_unreachable.to_string());
+    } else {
+        set("start",
+            Point::new(args.arg_starty, args.arg_startx).to_string());
+        set("end", Point::new(args.arg_endy, args.arg_endx).to_string());
+    }
+
+    for param in &args.flag_param {
+        if let Ok((name, value)) = parse_assignment(param) {
+            set(&format!("param.{}", name), value);
+        }
+    }
+    for sweep in &args.flag_sweep {
+        if let Ok((name, values)) = parse_assignment(sweep) {
+            set(&format!("sweep.{}", name), values);
+        }
+    }
+
+    parameters
+}
+
+/// Writes the results of every trial, or one summary row per combination of
+/// swept parameters when sweeping, followed by one row per scenario bucket
+/// when the trials came from a scenario.
+fn write_results<W, R>(out: &mut W, args: &Args, results: &[R]) -> IOResult<()>
+    where W: Write,
+          R: Borrow<(Parameters, Data)>
+{
+    let results = results.iter().map(Borrow::borrow).collect::<Vec<_>>();
+    if args.flag_sweep.is_empty() {
+        for (_, data) in &results {
+            data.write(out)?;
+            data.write_buckets(out)?;
+        }
+    } else {
+        for (combination, data) in &results {
+            write_tag(out, data)?;
+            writeln!(out, "{}: {}", label(combination), data.summary())?;
+            data.write_buckets(out)?;
+        }
+        for (i, (first, first_data)) in results.iter().enumerate() {
+            for (second, second_data) in &results[i + 1..] {
+                write_tag(out, first_data)?;
+                writeln!(out,
+                         "{} vs. {}: {}",
+                         label(second),
+                         label(first),
+                         compare(first_data, second_data))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quotes `field` for CSV if it contains a comma, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Writes the results of every trial in long format, as CSV with one row
+/// per trial and metric (see `Data::write_tidy`), labeled with the map,
+/// the tag, and every parameter of the algorithm, given or swept.
+fn write_tidy<W>(out: &mut W,
+                 args: &Args,
+                 results: &[(Parameters, Data)],
+                 hash: &str)
+                 -> IOResult<()>
+    where W: Write
+{
+    let mut base = agent_parameters(args).unwrap_or_default();
+    base.insert("algorithm".to_owned(), args.flag_algorithm.clone());
+    let mut columns = base.keys().cloned().collect::<Vec<_>>();
+    for (combination, _) in results {
+        for name in combination.keys() {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+    }
+
+    let header = columns.iter().map(|name| csv_field(name)).collect::<Vec<_>>();
+    writeln!(out,
+             "map,map_hash,tag,{},trial,metric,value",
+             header.join(","))?;
+    for (combination, data) in results {
+        let mut prefix = vec![csv_field(&args.arg_map),
+                              hash.to_owned(),
+                              csv_field(data.tag().unwrap_or(""))];
+        for name in &columns {
+            let value = combination.get(name).or_else(|| base.get(name));
+            prefix.push(csv_field(value.map_or("", String::as_str)));
+        }
+        data.write_tidy(out, &prefix.join(","))?;
+    }
+    Ok(())
+}
+
+/// Starts a row of results from `data` with its tag, if it has one.
+fn write_tag<W>(out: &mut W, data: &Data) -> IOResult<()>
+    where W: Write
+{
+    match data.tag() {
+        Some(tag) => write!(out, "[{}] ", tag),
+        None => Ok(()),
+    }
+}
+
+/// Checks the hash of the map against the one given with `--expect-hash`,
+/// if any.
+fn check_map_hash(args: &Args, manifest: &Manifest) -> Result<(), String> {
+    match args.flag_expect_hash {
+        Some(ref expected)
+            if !expected.eq_ignore_ascii_case(manifest.map_hash()) => {
+            Err(format!("Map hash is {}, but {} was expected.",
+                        manifest.map_hash(),
+                        expected))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes the hash and the description of the map the results come from,
+/// as comment lines.
+fn write_map_meta<W>(out: &mut W, meta: &GridMeta) -> IOResult<()>
+    where W: Write
+{
+    writeln!(out, "# map hash: {:016x}", meta.hash)?;
+    writeln!(out, "# map: {}", meta)
+}
+
+/// The path of an output file, and the results written to it.
+type OutputGroup<'a> = (String, Vec<&'a (Parameters, Data)>);
+
+/// Groups `results` by the file they are written to, when `--output` refers
+/// to swept parameters, keeping them in order.
+fn group_by_output<'a>(template: &str,
+                       args: &Args,
+                       results: &'a [(Parameters, Data)])
+                       -> Result<Vec<OutputGroup<'a>>, String> {
+    let mut groups: Vec<OutputGroup> = Vec::new();
+    for result in results {
+        let path = output_path(template, args, &result.0)?;
+        match groups.iter_mut().find(|group| group.0 == path) {
+            Some(group) => group.1.push(result),
+            None => groups.push((path, vec![result])),
+        }
+    }
+    Ok(groups)
+}
+
+/// Writes the results, the heatmap, the tidy results and the manifests
+/// asked for on the command line, or returns why one could not be written.
+fn write_outputs(args: &Args,
+                 results: &[(Parameters, Data)],
+                 manifest: &Manifest)
+                 -> Result<(), String> {
+    let write_report = |mut out: &mut dyn Write,
+                        results: &[&(Parameters, Data)]| {
+        write_map_meta(&mut out, manifest.grid())?;
+        write_results(&mut out, args, results)
+    };
+    let mut outputs = Vec::new();
+    if let Some(ref template) = args.flag_output {
+        for (output, group) in group_by_output(template, args, results)? {
+            create_parent(&output)
+                .and_then(|_| File::create(&output))
+                .and_then(|file| {
+                    write_report(&mut BufWriter::new(file), &group)
+                })
+                .map_err(|e| format!("Could not write to {}: {}", output, e))?;
+            outputs.push(output);
+        }
+    } else {
+        let stdout = ::std::io::stdout();
+        write_report(&mut stdout.lock(), &results.iter().collect::<Vec<_>>())
+            .map_err(|e| format!("Could not write results to stdout: {}", e))?;
+    }
+
+    if let Some(ref path) = args.flag_heatmap {
+        let path = output_path(path, args, &Parameters::new())?;
+        let mut heatmap = Heatmap::default();
+        for (_, data) in results {
+            heatmap.merge(data.heatmap());
+        }
+        create_parent(&path)
+            .and_then(|_| File::create(&path))
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                write_map_meta(&mut out, manifest.grid())?;
+                heatmap.write(&mut out)
+            })
+            .map_err(|e| format!("Could not write to {}: {}", path, e))?;
+    }
+
+    if let Some(ref path) = args.flag_tidy {
+        let path = output_path(path, args, &Parameters::new())?;
+        create_parent(&path)
+            .and_then(|_| File::create(&path))
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                write_tidy(&mut out, args, results, manifest.map_hash())?;
+                out.flush()
+            })
+            .map_err(|e| format!("Could not write to {}: {}", path, e))?;
+    }
+
+    let manifest_paths = match args.flag_manifest {
+        Some(ref path) => {
+            vec![PathBuf::from(output_path(path, args, &Parameters::new())?)]
+        }
+        None => outputs.iter().map(default_path).collect(),
     };
+    for path in manifest_paths {
+        create_parent(&path)
+            .and_then(|_| manifest.write(&path))
+            .map_err(|e| {
+                format!("Could not write to {}: {}", path.display(), e)
+            })?;
+    }
+    Ok(())
+}
 
-    run_algorithm(&mut experiment, args)
+/// Runs the experiment described by the command line, writes its results,
+/// and returns the status the process should exit with.
+pub fn run_experiment_from_cli() -> i32 {
+    run_experiment_from_cli_with(&Registry::default())
 }
 
-pub fn run_experiment_from_cli() -> Data {
+/// Like `run_experiment_from_cli`, but looks up `--algorithm` in `registry`,
+/// so that programs embedding this crate can offer their own agents.
+pub fn run_experiment_from_cli_with(registry: &Registry) -> i32 {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
 
-    run_from_args(&args)
+    if args.cmd_convert {
+        return convert(&args);
+    }
+    if args.cmd_diff {
+        return diff(&args);
+    }
+    if args.cmd_replay {
+        return replay(&args);
+    }
+    if args.cmd_generate {
+        return generate(&args);
+    }
+    if args.cmd_suite {
+        return suite(&args, registry);
+    }
+    if args.cmd_serve {
+        return serve(&args);
+    }
+    if args.cmd_bench {
+        return bench(&args, registry);
+    }
+
+    let mut grid = match load_grid(&args) {
+        Ok(grid) => grid,
+        Err(e) => {
+            eprintln!("{}: {}", args.arg_map, e);
+            return EXIT_PARSE_FAILURE;
+        }
+    };
+    if let Err(e) = apply_patch(&args, &mut grid)
+           .and_then(|_| load_overlay(&args, &mut grid)) {
+        eprintln!("{}", e);
+        return EXIT_PARSE_FAILURE;
+    }
+    if let Err(e) = check_output_paths(&args) {
+        eprintln!("{}", e);
+        return EXIT_USAGE;
+    }
+    let mut manifest = Manifest::new(&args.arg_map, &grid, parameters(&args));
+    if let Err(e) = check_map_hash(&args, &manifest) {
+        eprintln!("{}: {}", args.arg_map, e);
+        return EXIT_PARSE_FAILURE;
+    }
+
+    catch_interrupts();
+    let results = match run_on_grid(grid, registry, &args) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    manifest.finish();
+
+    if let Err(e) = write_outputs(&args, &results, &manifest) {
+        eprintln!("{}", e);
+        return EXIT_WRITE_FAILURE;
+    }
+
+    exit_status(&args, &results)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use generator::largest_region;
+    use instance::Datum;
+    use rle;
+
     #[test]
     fn run_experiment() {
         let argv = vec!["gridist", "maps/Mini.map", "2", "--seed=10"];
         let args: Args = Docopt::new(USAGE)
-            .and_then(|d| d.argv(argv.into_iter()).deserialize())
+            .and_then(|d| d.argv(argv).deserialize())
             .unwrap();
 
         println!("Args:\n{:?}", args);
@@ -131,15 +1881,948 @@ mod tests {
         run_from_args(&args);
     }
 
+    #[test]
+    fn read_map_from_stdin() {
+        let argv = vec!["gridist", "-", "2", "--format=movingai"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        assert_eq!(args.arg_map, "-");
+        assert_eq!(args.arg_trials, Some(2));
+
+        // Maps are read in the format given with --format, and in the one
+        // their header declares without it.
+        let map = "type octile\nheight 1\nwidth 3\nmap\n2T.\n";
+        let read = |options: &[&str]| {
+            let mut argv = vec!["gridist", "-", "2"];
+            argv.extend(options);
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let format = args.flag_format.as_ref().map(Format::map_format);
+            read_map_from(map.as_bytes(), format).map(|grid| grid.to_string())
+        };
+        assert_eq!(read(&["--format=rle"]).unwrap(), "TT.\n");
+        assert!(read(&["--format=movingai"]).is_err());
+        assert!(read(&[]).is_err());
+    }
+
+    #[test]
+    fn serve_over_tcp() {
+        let argv = vec!["gridist",
+                        "serve",
+                        "maps/Mini.map",
+                        "--listen=127.0.0.1:0",
+                        "--planner-threads=4"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        assert!(args.cmd_serve);
+        assert_eq!(args.arg_map, "maps/Mini.map");
+        assert_eq!(args.flag_listen.as_deref(), Some("127.0.0.1:0"));
+        assert_eq!(args.flag_planner_threads, 4);
+    }
+
+    #[test]
+    fn bench_repeated_runs() {
+        let argv = vec!["gridist",
+                        "bench",
+                        "maps/Mini.map",
+                        "3",
+                        "--algorithm=astar",
+                        "--reps=4"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(args.cmd_bench);
+        assert_eq!(args.arg_trials, Some(3));
+        assert_eq!((args.flag_reps, args.flag_warmup), (4, 1));
+
+        let grid = load_grid(&args).unwrap();
+        let runs = time_runs(grid, &Registry::default(), &args).unwrap();
+        assert_eq!(runs.len(), 4);
+        // Every run faces the same trials with a new agent.
+        assert!(runs.iter().all(|&(_, expansions)| expansions == runs[0].1));
+        assert!(runs[0].1 > 0.0);
+
+        assert_eq!(mean_and_deviation(&[2.0]), (2.0, 0.0));
+        let (mean, deviation) = mean_and_deviation(&[1.0, 2.0, 3.0]);
+        assert_eq!((mean, deviation), (2.0, 1.0));
+    }
+
+    #[test]
+    fn sweep_over_combinations() {
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "3",
+                        "--sweep=algorithm=astar,rastar",
+                        "--sweep=heuristic=octile,euclidean"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let labels: Vec<String> =
+            results.iter().map(|(c, _)| label(c)).collect();
+        assert_eq!(labels,
+                   vec!["algorithm=astar heuristic=octile",
+                        "algorithm=astar heuristic=euclidean",
+                        "algorithm=rastar heuristic=octile",
+                        "algorithm=rastar heuristic=euclidean"]);
+        for (_, data) in &results {
+            assert_eq!(data.summary().solved, 3);
+        }
+    }
+
+    #[test]
+    fn exit_with_unsolved_status() {
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "0",
+                        "0",
+                        "1",
+                        "1",
+                        "--fail-on-unsolved"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let mut solved = Data::new(1);
+        solved.push(Datum { outcome: Outcome::Solved, ..Datum::default() });
+        let mut unsolved = Data::new(1);
+        unsolved.push(Datum::default());
+        let mut timed_out = Data::new(1);
+        timed_out.push(Datum { outcome: Outcome::TimedOut, ..Datum::default() });
+
+        let mut results = vec![(Parameters::new(), solved)];
+        assert_eq!(exit_status(&args, &results), EXIT_SUCCESS);
+
+        results.push((Parameters::new(), unsolved));
+        assert_eq!(exit_status(&args, &results), EXIT_UNSOLVED);
+
+        results.push((Parameters::new(), timed_out));
+        assert_eq!(exit_status(&args, &results), EXIT_TIMEOUT);
+    }
+
     #[test]
     fn run_fixed_trial() {
         let argv = vec!["gridist", "maps/Mini.map", "0", "0", "9", "9"];
         let args: Args = Docopt::new(USAGE)
-            .and_then(|d| d.argv(argv.into_iter()).deserialize())
+            .and_then(|d| d.argv(argv).deserialize())
             .unwrap();
 
         println!("Args:\n{:?}", args);
 
         run_from_args(&args);
     }
+
+    #[test]
+    fn run_listed_pairs() {
+        let path = ::std::env::temp_dir().join("gridist-pairs.txt");
+        ::std::fs::write(&path, "0 0 9 9\n\n9 0 0 9\n").unwrap();
+
+        let argv = vec!["gridist".to_owned(),
+                        "maps/Mini.map".to_owned(),
+                        format!("--pairs={}", path.display())];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let mut output = Vec::new();
+        write_results(&mut output, &args, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Line 1: "));
+        assert!(output.contains("\nLine 3: "));
+
+        ::std::fs::write(&path, "0 0 1 1\n").unwrap();
+        let grid = load_grid(&args).unwrap();
+        assert_eq!(run_on_grid(grid, &Registry::default(), &args).err(),
+                   Some("Line 1: goal (1, 1) is impassable.".to_owned()));
+    }
+
+    #[test]
+    fn write_tidy_results() {
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "2",
+                        "--sweep=algorithm=astar,rastar",
+                        "--param=replan_radius=2",
+                        "--tag=a,b"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let mut output = Vec::new();
+        write_tidy(&mut output, &args, &results, "00ff").unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0],
+                   "map,map_hash,tag,algorithm,heuristic,replan_radius,\
+                    trial,metric,value");
+        assert_eq!(lines[1],
+                   "maps/Mini.map,00ff,\"a,b\",astar,octile,2,0,outcome,\
+                    solved");
+        assert!(lines.contains(&"maps/Mini.map,00ff,\"a,b\",rastar,octile,2,1,\
+                                 outcome,solved"));
+        let costs = lines.iter()
+            .filter(|line| line.contains(",cost,"))
+            .count();
+        assert_eq!(costs, 4);
+    }
+
+    #[test]
+    fn export_custom_metrics() {
+        let argv = vec!["gridist", "maps/Mini.map", "2", "--metrics=turns"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let mut output = Vec::new();
+        write_tidy(&mut output, &args, &results, "00ff").unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let turns = output.lines()
+            .filter(|line| line.contains(",turns,") ||
+                           line.contains(",turning_angle,"))
+            .count();
+        assert_eq!(turns, 4);
+
+        let mut output = Vec::new();
+        write_results(&mut output, &args, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(" [turns "));
+
+        let argv = vec!["gridist", "maps/Mini.map", "2", "--metrics=wiggles"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(run_on_grid(load_grid(&args).unwrap(),
+                            &Registry::default(),
+                            &args)
+                        .is_err());
+    }
+
+    #[test]
+    fn summarize_scenario_buckets() {
+        let path = ::std::env::temp_dir().join("gridist-buckets.scen");
+        ::std::fs::write(&path,
+                         "version 1
+0\tMini.map\t10\t10\t0\t0\t9\t0\t9
+4\tMini.map\t10\t10\t0\t0\t9\t9\t18
+0\tMini.map\t10\t10\t9\t0\t0\t0\t9
+")
+                .unwrap();
+
+        let argv = vec!["gridist".to_owned(),
+                        "maps/Mini.map".to_owned(),
+                        format!("--pairs={}", path.display())];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let buckets = results[0].1.buckets().unwrap();
+        assert_eq!(buckets.iter().map(|b| b.bucket).collect::<Vec<_>>(),
+                   vec![0, 4]);
+        assert_eq!((buckets[0].trials, buckets[0].solved), (2, 2));
+        assert_eq!(buckets[0].suboptimality, 1.0);
+        assert_eq!((buckets[1].trials, buckets[1].solved), (1, 1));
+
+        let mut output = Vec::new();
+        write_results(&mut output, &args, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Line 2: "));
+        assert!(output.contains("\nBucket 0: 2/2 solved (success rate \
+                                 1.0000), mean suboptimality 1.0000, mean "));
+        assert!(output.contains("\nBucket 4: 1/1 solved"));
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sweep_in_single_run() {
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "0",
+                        "0",
+                        "9",
+                        "9",
+                        "--sweep=algorithm=astar,rastar"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        assert_eq!(results.len(), 2);
+        assert_eq!(exit_status(&args, &results), EXIT_SUCCESS);
+
+        let mut output = Vec::new();
+        write_results(&mut output, &args, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("algorithm=astar: 1/1 solved"));
+        assert!(output.contains("algorithm=rastar vs. algorithm=astar: "));
+
+        // Each combination runs as it would alone, without the beliefs left
+        // by the one before it.
+        for (i, algorithm) in ["astar", "rastar"].iter().enumerate() {
+            let flag = format!("--algorithm={}", algorithm);
+            let argv = vec!["gridist", "maps/Mini.map", "0", "0", "9", "9",
+                            &flag];
+            let alone: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let alone = &run_from_args(&alone)[0].1[0];
+            let swept = &results[i].1[0];
+            assert_eq!(swept.steps, alone.steps);
+            assert_eq!(swept.expansions, alone.expansions);
+            assert_eq!(swept.cost, alone.cost);
+        }
+    }
+
+    #[test]
+    fn tag_outputs() {
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "2",
+                        "--tag=baseline",
+                        "--output=results/{tag}_{map}_{algo}.txt",
+                        "--sweep=heuristic=octile,euclidean"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        assert_eq!(output_path(args.flag_output.as_ref().unwrap(),
+                               &args,
+                               &Parameters::new()),
+                   Ok("results/baseline_Mini_rastar.txt".to_owned()));
+        assert_eq!(parameters(&args).get("tag").map(String::as_str),
+                   Some("baseline"));
+
+        let results = run_from_args(&args);
+        let mut output = Vec::new();
+        write_results(&mut output, &args, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.lines().all(|line| line.starts_with("[baseline] ")));
+
+        let mut output = Vec::new();
+        results[0].1.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("[baseline] Trial 0: "));
+        assert!(output.contains("\n[baseline] Trial 1: "));
+    }
+
+    #[test]
+    fn fill_in_output_paths() {
+        let directory = ::std::env::temp_dir().join("gridist-outputs");
+        let _ = ::std::fs::remove_dir_all(&directory);
+        let template = format!("--output={}/{{map}}/{{algorithm}}-{{seed}}.txt",
+                               directory.display());
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "2",
+                        "--seed=4",
+                        &template,
+                        "--sweep=algorithm=astar,rastar",
+                        "--sweep=heuristic=octile,euclidean"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert_eq!(check_output_paths(&args), Ok(()));
+
+        let results = run_from_args(&args);
+        let groups = group_by_output(args.flag_output.as_ref().unwrap(),
+                                     &args,
+                                     &results)
+            .unwrap();
+        let paths = groups.iter()
+            .map(|(path, group)| (path.clone(), group.len()))
+            .collect::<Vec<_>>();
+        let mini = directory.join("Mini").display().to_string();
+        assert_eq!(paths,
+                   vec![(format!("{}/astar-4.txt", mini), 2),
+                        (format!("{}/rastar-4.txt", mini), 2)]);
+
+        let grid = load_grid(&args).unwrap();
+        let manifest = Manifest::new(&args.arg_map, &grid, parameters(&args));
+        assert_eq!(write_outputs(&args, &results, &manifest), Ok(()));
+        let astar = ::std::fs::read_to_string(&paths[0].0).unwrap();
+        assert!(astar.contains("\nalgorithm=astar heuristic=euclidean: "));
+        assert!(!astar.contains("rastar"));
+        assert!(default_path(&paths[1].0).exists());
+        // Neither the heatmap nor the tidy results can go under a file.
+        let under_file = format!("{}/out.csv", paths[0].0);
+        for unwritable in &[Args {
+                                flag_heatmap: Some(under_file.clone()),
+                                ..args.clone()
+                            },
+                            Args {
+                                flag_tidy: Some(under_file.clone()),
+                                ..args.clone()
+                            }] {
+            let written = write_outputs(unwritable, &results, &manifest);
+            assert!(written.unwrap_err()
+                        .starts_with(&format!("Could not write to {}: ",
+                                              under_file)));
+        }
+
+        let fill = |template: &str| {
+            output_path(template, &args, &Parameters::new())
+        };
+        assert_eq!(fill("{heuristic}_{trials}"), Ok("octile_2".to_owned()));
+        assert_eq!(fill("{algo}{tag}"), Ok("rastar".to_owned()));
+        assert_eq!(fill("{nope}.txt"),
+                   Err("Unknown variable '{nope}' in output path \
+                        '{nope}.txt'."
+                               .to_owned()));
+        assert_eq!(fill("{seed"),
+                   Err("Unclosed '{' in output path '{seed'.".to_owned()));
+
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "2",
+                        "--manifest={repulsion}.json",
+                        "--sweep=repulsion=1,2"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(check_output_paths(&args).is_err());
+    }
+
+    #[test]
+    fn validate_single_run_endpoints() {
+        let run = |argv: Vec<&str>| {
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let grid = load_grid(&args).unwrap();
+            run_on_grid(grid, &Registry::default(), &args).err()
+        };
+
+        assert_eq!(run(vec!["gridist", "maps/Mini.map", "0", "0", "10", "0"]),
+                   Some("Invalid trial: goal (10, 0) is outside the 10x10 \
+                         map."
+                            .to_owned()));
+        assert_eq!(run(vec!["gridist", "maps/Mini.map", "1", "1", "0", "0"]),
+                   Some("Invalid trial: start (1, 1) is impassable."
+                            .to_owned()));
+
+        let path = ::std::env::temp_dir().join("gridist-walled.map");
+        ::std::fs::write(&path, "type octile\nheight 1\nwidth 3\nmap\n.T.")
+            .unwrap();
+        let map = path.display().to_string();
+        let unreachable = vec!["gridist", &map, "0", "0", "0", "2"];
+        assert_eq!(run(unreachable.clone()),
+                   Some("Invalid trial: goal (0, 2) cannot be reached from \
+                         start (0, 0)."
+                                .to_owned()));
+        let mut allowed = unreachable;
+        allowed.push("--allow-unreachable");
+        assert_eq!(run(allowed), None);
+    }
+
+    #[test]
+    fn check_expected_map_hash() {
+        let parse = |argv: Vec<&str>| -> Args {
+            Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap()
+        };
+
+        let args = parse(vec!["gridist", "maps/Mini.map", "1"]);
+        let grid = load_grid(&args).unwrap();
+        let manifest = Manifest::new(&args.arg_map, &grid, parameters(&args));
+        assert!(check_map_hash(&args, &manifest).is_ok());
+
+        let expected = format!("--expect-hash={}",
+                               manifest.map_hash().to_uppercase());
+        let args = parse(vec!["gridist", "maps/Mini.map", "1", &expected]);
+        assert!(check_map_hash(&args, &manifest).is_ok());
+
+        let args = parse(vec!["gridist",
+                              "maps/Mini.map",
+                              "1",
+                              "--expect-hash=0123456789abcdef"]);
+        assert!(check_map_hash(&args, &manifest).is_err());
+
+        let mut output = Vec::new();
+        write_map_meta(&mut output, manifest.grid()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   format!("# map hash: {}\n\
+                            # map: Mini (octile, {}x{}) from maps/Mini.map\n",
+                           manifest.map_hash(),
+                           grid.height(),
+                           grid.width()));
+    }
+
+    #[test]
+    fn see_farther() {
+        let run = |sensor: &str| {
+            let argv = vec!["gridist",
+                            "maps/Mini.map",
+                            "0",
+                            "0",
+                            "9",
+                            "9",
+                            sensor];
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let results = run_from_args(&args);
+            let datum = &results[0].1[0];
+            (datum.episodes, datum.steps)
+        };
+
+        // Seeing farther means discovering obstacles sooner, and replanning
+        // less often.
+        assert_eq!(run("--sensor-radius=1").0, 4);
+        assert_eq!(run("--sensor-radius=2").0, 3);
+        assert_eq!(run("--sensor-radius=3").0, 2);
+
+        // Knowing the exact terrain does not change passability.
+        assert_eq!(run("--terrain-radius=3"), run("--sensor-radius=3"));
+    }
+
+    #[test]
+    fn scan_within_budget() {
+        let run = |budget: &str| {
+            let argv = vec!["gridist",
+                            "maps/Mini.map",
+                            "0",
+                            "0",
+                            "9",
+                            "9",
+                            "--scan-radius=3",
+                            "--scan-cost=0.5",
+                            budget];
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let results = run_from_args(&args);
+            results[0].1[0].scans.unwrap()
+        };
+
+        assert!(run("--scan-budget=100") > 1);
+        assert_eq!(run("--scan-budget=1"), 1);
+        assert_eq!(run("--scan-budget=0"), 0);
+    }
+
+    #[test]
+    fn compare_to_baseline() {
+        let argv = vec!["gridist", "maps/Mini.map", "5", "--with-baseline"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        let results = run_from_args(&args);
+        let data = &results[0].1;
+        for datum in data.iter() {
+            assert!(datum.regret().unwrap() >= -1e-9);
+        }
+        assert!(data.summary().regret.is_some());
+    }
+
+    #[test]
+    fn split_logging_from_tracing() {
+        let parse = |options: &[&str]| {
+            let mut argv = vec!["gridist", "maps/Mini.map", "5"];
+            argv.extend(options);
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            (verbosity(&args), trace(&args))
+        };
+
+        assert_eq!(parse(&[]), (Verbosity::One, Trace::default()));
+        assert_eq!(parse(&["--verbosity=2", "--log-level=0"]),
+                   (Verbosity::Zero,
+                    Trace {
+                        steps: true,
+                        search: false,
+                        record_search: false,
+                    }));
+        assert_eq!(parse(&["--log-level=0", "--trace-search"]),
+                   (Verbosity::Zero,
+                    Trace {
+                        steps: false,
+                        search: true,
+                        record_search: false,
+                    }));
+    }
+
+    #[test]
+    fn weigh_safety() {
+        let run = |cost: &[&str]| {
+            let mut argv = vec!["gridist", "maps/Mini.map", "5", "--seed=3"];
+            argv.extend(cost);
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let results = run_from_args(&args);
+            results[0].1.summary()
+        };
+
+        assert_eq!(run(&[]).model_cost, None);
+        let safe = run(&["--cost=safety", "--safety-weight=2"]);
+        assert!(safe.model_cost.unwrap() >= safe.cost);
+        let turns = run(&["--cost=geometric,turns", "--turn-weight=3"]);
+        assert!(turns.model_cost.unwrap() >= turns.cost);
+        let doubt = run(&["--cost=doubt", "--doubt-weight=0.5"]);
+        assert!(doubt.solved > 0);
+        let terrain = run(&["--cost=terrain", "--terrain-weights=.=2,T=3"]);
+        assert!((terrain.model_cost.unwrap() - 2.0 * terrain.cost).abs() <
+                1e-9);
+
+        let weights = |listed: &str| {
+            let argv = vec!["gridist",
+                            "maps/Mini.map",
+                            "5",
+                            "--cost=terrain",
+                            listed];
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            terrain_weights(&args)
+        };
+        assert_eq!(weights("--terrain-weights=T=3,S=1.5"),
+                   Ok([1.0, 1.0, 3.0, 1.5, 1.0]));
+        assert!(weights("--terrain-weights=T=-1").is_err());
+        assert!(weights("--terrain-weights=.=0.5").is_err());
+        assert!(weights("--terrain-weights=X=2").is_err());
+        assert!(weights("--terrain-weights=TT=2").is_err());
+
+        // A cost of 1 on every tile adds 1 to every move.
+        let path = ::std::env::temp_dir().join(format!("gridist-weigh-safety-\
+                                                        {}.json",
+                                                       ::std::process::id()));
+        ::std::fs::write(&path,
+                         format!(r#"{{"cost": {{"height": 10, "width": 10,
+                                                "values": [{}]}}}}"#,
+                                 vec!["1"; 100].join(",")))
+                .unwrap();
+        let overlay = format!("--overlay={}", path.display());
+        let overlaid = run(&["--cost=overlay", &overlay]);
+        let moves = overlaid.model_cost.unwrap() - overlaid.cost;
+        assert!((moves - overlaid.steps).abs() < 1e-9);
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn convert_between_formats() {
+        let directory = ::std::env::temp_dir();
+        let rle = directory.join("gridist-convert.map.rle");
+        let movingai = directory.join("gridist-convert.map");
+        let argv = vec!["gridist",
+                        "convert",
+                        "maps/Rooms.map",
+                        rle.to_str().unwrap(),
+                        "--to=rle"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(args.cmd_convert);
+        assert_eq!(convert(&args), EXIT_SUCCESS);
+
+        let argv = vec!["gridist",
+                        "convert",
+                        rle.to_str().unwrap(),
+                        movingai.to_str().unwrap(),
+                        "--to=movingai"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert_eq!(convert(&args), EXIT_SUCCESS);
+
+        let original = try_grid_from_file("maps/Rooms.map").unwrap();
+        let compressed = ::std::fs::metadata(&rle).unwrap().len();
+        let restored = try_grid_from_file(&movingai).unwrap();
+        assert!(compressed <
+                ::std::fs::metadata("maps/Rooms.map").unwrap().len());
+        assert_eq!(restored.to_string(), original.to_string());
+
+        ::std::fs::remove_file(&rle).unwrap();
+        ::std::fs::remove_file(&movingai).unwrap();
+    }
+
+    #[test]
+    fn diff_and_patch_maps() {
+        let directory = ::std::env::temp_dir();
+        let edited = directory.join("gridist-diff.map");
+        let patch = directory.join("gridist-diff.patch");
+        let original = ::std::fs::read_to_string("maps/Mini.map").unwrap();
+        ::std::fs::write(&edited, original.replace(".T.TT..TT.", ".T.TT.TTT."))
+            .unwrap();
+
+        let argv = vec!["gridist",
+                        "diff",
+                        "maps/Mini.map",
+                        edited.to_str().unwrap(),
+                        "--write-patch",
+                        patch.to_str().unwrap()];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(args.cmd_diff);
+        assert_eq!(diff(&args), EXIT_SUCCESS);
+
+        let changes = try_patch_from_file(&patch).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].point, Point::new(3, 6));
+
+        // Running on the patched map is the same as running on the edited
+        // one.
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "3",
+                        "--patch",
+                        patch.to_str().unwrap()];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        let mut grid = load_grid(&args).unwrap();
+        apply_patch(&args, &mut grid).unwrap();
+        let expected = try_grid_from_file(&edited).unwrap();
+        assert_eq!(grid.content_hash(), expected.content_hash());
+        assert!(apply_patch(&args, &mut grid).is_err());
+
+        ::std::fs::remove_file(&edited).unwrap();
+        ::std::fs::remove_file(&patch).unwrap();
+    }
+
+    #[test]
+    fn generate_noise_maps() {
+        let path = ::std::env::temp_dir().join("gridist-noise.map.rle");
+        let hash = |seed: &str| {
+            let argv = vec!["gridist",
+                            "generate",
+                            "noise",
+                            "30",
+                            "50",
+                            path.to_str().unwrap(),
+                            seed,
+                            "--scale=10",
+                            "--to=rle"];
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            assert!(args.cmd_generate);
+            assert_eq!(generate(&args), EXIT_SUCCESS);
+            let map = ::std::fs::read_to_string(&path).unwrap();
+            assert!(map.starts_with("type octile-rle\nheight 30\nwidth 50\n"));
+            try_grid_from_file(&path).unwrap().content_hash()
+        };
+        assert_eq!(hash("--seed=2"), hash("--seed=2"));
+        assert!(hash("--seed=2") != hash("--seed=3"));
+
+        let argv = vec!["gridist",
+                        "generate",
+                        "noise",
+                        "30",
+                        "50",
+                        path.to_str().unwrap(),
+                        "--scale=4",
+                        "--trees=-0.1",
+                        "--min-region=0",
+                        "--ensure-connected",
+                        "--source-region=edges"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert_eq!(generate(&args), EXIT_SUCCESS);
+        let grid = try_grid_from_file(&path).unwrap();
+        assert_eq!(largest_region(&grid),
+                   grid.iter().flatten().filter(|tile| tile.passable()).count());
+
+        let argv = vec!["gridist", "generate", "noise", "99999", "99999",
+                        path.to_str().unwrap()];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert_eq!(generate(&args), EXIT_USAGE);
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn run_suites() {
+        let directory = ::std::env::temp_dir();
+        let path = directory.join("gridist.suite");
+        let output = directory.join("gridist-suite.txt");
+        let maps = Path::new(env!("CARGO_MANIFEST_DIR")).join("maps");
+        ::std::fs::write(&path,
+                         format!("# map trials weight\n{} 3\n{} 2 3\n",
+                                 maps.join("Mini.map").display(),
+                                 maps.join("Open.map").display()))
+                .unwrap();
+        let argv = vec!["gridist",
+                        "suite",
+                        path.to_str().unwrap(),
+                        "--seed=4",
+                        "--verbosity=0",
+                        "--output",
+                        output.to_str().unwrap()];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(args.cmd_suite);
+        assert_eq!(suite(&args, &Registry::default()), EXIT_SUCCESS);
+
+        let report = ::std::fs::read_to_string(&output).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("# map hash: "));
+        assert!(lines[1].starts_with("# map: Mini (octile, "));
+        assert!(lines[2].contains("Mini.map: 3/3 solved"));
+        assert!(lines[5].contains("Open.map: 2/2 solved"));
+        assert!(lines[6].starts_with("suite: 5/5 solved over 2 maps, \
+                                      weighted success rate 1.0000"));
+
+        // The report cannot be written under the suite, which is a file.
+        let unwritable = Args {
+            flag_output: Some(path.join("report.txt")
+                                  .to_string_lossy()
+                                  .into_owned()),
+            ..args.clone()
+        };
+        assert_eq!(suite(&unwritable, &Registry::default()),
+                   EXIT_WRITE_FAILURE);
+
+        ::std::fs::write(&path, "Missing.map 3\n").unwrap();
+        assert_eq!(suite(&args, &Registry::default()), EXIT_PARSE_FAILURE);
+
+        ::std::fs::remove_file(&path).unwrap();
+        ::std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn dump_and_replay_searches() {
+        let path = ::std::env::temp_dir().join("gridist-searches.jsonl");
+        let argv = vec!["gridist".to_owned(),
+                        "maps/Mini.map".to_owned(),
+                        "0".to_owned(),
+                        "0".to_owned(),
+                        "9".to_owned(),
+                        "9".to_owned(),
+                        "--verbosity=0".to_owned(),
+                        format!("--dump-search={}", path.display())];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        let results = run_from_args(&args);
+
+        let file = File::open(&path).unwrap();
+        let searches = read_searches(BufReader::new(file)).unwrap();
+        assert_eq!(searches.len(), results[0].1[0].episodes);
+        for pair in searches.windows(2) {
+            assert!(pair[0].expansions[0].episode <
+                    pair[1].expansions[0].episode);
+        }
+        let first = &searches[0];
+        assert_eq!((first.trial, first.location, first.target),
+                   (0, Point::new(0, 0), Point::new(9, 9)));
+        assert_eq!(first.expansions[0].point, first.location);
+        assert_eq!(first.expansions.last().unwrap().point, first.target);
+
+        let grid = try_grid_from_file("maps/Mini.map").unwrap();
+        let mut output = Vec::new();
+        let mut input = &b"\n\nq\n"[..];
+        assert!(!replay_search(&grid, first, &mut input, &mut output)
+                     .unwrap());
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("Expansion ").count(), 3);
+        assert!(output.contains(&format!("Expansion 3 of {} (episode {})",
+                                         first.expansions.len(),
+                                         first.expansions[2].episode)));
+
+        ::std::fs::remove_file(&path).unwrap();
+
+        let argv = vec!["gridist", "replay", "maps/Mini.map", "searches.jsonl",
+                        "--trial=2"];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        assert!(args.cmd_replay);
+        assert_eq!((args.arg_searches.as_str(), args.flag_trial),
+                   ("searches.jsonl", Some(2)));
+    }
+
+    #[test]
+    fn reorder_trials() {
+        let run = |order: &str| {
+            let argv = vec!["gridist",
+                            "maps/Mini.map",
+                            "6",
+                            "--seed=4",
+                            order,
+                            "--order-seed=1"];
+            let args: Args = Docopt::new(USAGE)
+                .and_then(|d| d.argv(argv).deserialize())
+                .unwrap();
+            let mut output = Vec::new();
+            write_results(&mut output, &args, &run_from_args(&args)).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        // Trials are independent unless beliefs are retained, so the
+        // results are the same whatever the order.
+        let given = run("--order=given");
+        assert_eq!(run("--order=shuffle"), given);
+        assert_eq!(run("--order=difficulty"), given);
+
+        let grid = try_grid_from_file("maps/Mini.map").unwrap();
+        let pairs = vec![(1, Point::new(4, 0), Point::new(9, 8)),
+                         (2, Point::new(6, 9), Point::new(5, 9)),
+                         (3, Point::new(5, 8), Point::new(8, 0))];
+        let mut experiment = Experiment::pairs(grid, pairs, Verbosity::Zero)
+            .unwrap();
+        assert_eq!(experiment.run_order(), [0, 1, 2]);
+        experiment.set_trial_order(TrialOrder::Difficulty);
+        assert_eq!(experiment.run_order(), [1, 2, 0]);
+        experiment.set_trial_order(TrialOrder::Shuffled(1));
+        let mut shuffled = experiment.run_order();
+        shuffled.sort();
+        assert_eq!(shuffled, [0, 1, 2]);
+    }
+
+    #[test]
+    fn dump_final_beliefs() {
+        let directory = ::std::env::temp_dir().join("gridist-dump-beliefs");
+        let _ = ::std::fs::remove_dir_all(&directory);
+        let argv = vec!["gridist",
+                        "maps/Mini.map",
+                        "2",
+                        "--with-baseline",
+                        "--dump-beliefs",
+                        directory.to_str().unwrap()];
+        let args: Args = Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+
+        run_from_args(&args);
+
+        let mut files = ::std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, vec!["trial-0.rle", "trial-1.rle"]);
+
+        let dump = ::std::fs::read_to_string(directory.join("trial-0.rle"))
+            .unwrap();
+        let mut lines = dump.lines();
+        assert_eq!(lines.next(), Some("type beliefs-rle"));
+        let grid = load_grid(&args).unwrap();
+        assert_eq!(lines.next(), Some(&*format!("height {}", grid.height())));
+        let rows = lines.skip(2)
+            .map(|line| rle::decode(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), grid.height());
+        assert!(rows.iter().all(|row| row.len() == grid.width()));
+        assert!(rows.iter().flatten().any(|&c| c == '+'));
+
+        ::std::fs::remove_dir_all(&directory).unwrap();
+    }
 }
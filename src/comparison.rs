@@ -0,0 +1,219 @@
+use std::cmp::Ordering;
+use std::f64::consts::SQRT_2;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use grid::Distance;
+use instance::Data;
+
+/// Cost differences smaller than this are considered ties, since costs
+/// accumulated over different paths carry rounding errors.
+const TOLERANCE: Distance = 1e-9;
+
+/// The result of a Wilcoxon signed-rank test on paired differences, using
+/// the normal approximation (with a correction for tied ranks).
+#[derive(Debug, PartialEq)]
+pub struct Wilcoxon {
+    /// Number of non-zero differences.
+    pub n: usize,
+    /// The smaller of the positive and negative rank sums.
+    pub statistic: f64,
+    pub z: f64,
+    /// Two-sided p-value.
+    pub p_value: f64,
+}
+
+/// Paired comparison of the costs of two runs over the same trials,
+/// restricted to the trials solved in both.
+#[derive(Debug, PartialEq)]
+pub struct Comparison {
+    /// Per-trial differences (second cost minus first cost), by trial index.
+    pub differences: Vec<(usize, Distance)>,
+    /// Trials where the second run was cheaper.
+    pub wins: usize,
+    /// Trials where the second run was more expensive.
+    pub losses: usize,
+    pub ties: usize,
+    pub mean_difference: Distance,
+    /// `None` when every difference is zero.
+    pub wilcoxon: Option<Wilcoxon>,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f,
+               "{} wins, {} losses, {} ties, mean difference {}",
+               self.wins,
+               self.losses,
+               self.ties,
+               self.mean_difference)?;
+        if let Some(ref wilcoxon) = self.wilcoxon {
+            write!(f,
+                   ", Wilcoxon W={} (n={}, z={:.3}, p={:.4})",
+                   wilcoxon.statistic,
+                   wilcoxon.n,
+                   wilcoxon.z,
+                   wilcoxon.p_value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the costs in `second` against those in `first`, trial by trial.
+/// Both must come from running the same trials.
+pub fn compare(first: &Data, second: &Data) -> Comparison {
+    let differences: Vec<(usize, Distance)> = first.iter()
+        .zip(second.iter())
+        .enumerate()
+        .filter(|&(_, (a, b))| a.solved() && b.solved())
+        .map(|(i, (a, b))| {
+                 let difference = b.cost - a.cost;
+                 (i, if difference.abs() < TOLERANCE { 0.0 } else { difference })
+             })
+        .collect();
+
+    let wins = differences.iter().filter(|&&(_, d)| d < 0.0).count();
+    let losses = differences.iter().filter(|&&(_, d)| d > 0.0).count();
+    let mean_difference = if differences.is_empty() {
+        0.0
+    } else {
+        differences.iter().map(|&(_, d)| d).sum::<Distance>() /
+        differences.len() as Distance
+    };
+    let wilcoxon = wilcoxon(&differences.iter()
+                                 .map(|&(_, d)| d)
+                                 .collect::<Vec<_>>());
+
+    Comparison {
+        wins: wins,
+        losses: losses,
+        ties: differences.len() - wins - losses,
+        mean_difference: mean_difference,
+        wilcoxon: wilcoxon,
+        differences: differences,
+    }
+}
+
+/// Runs a Wilcoxon signed-rank test on `differences`, ignoring zeros.
+pub fn wilcoxon(differences: &[f64]) -> Option<Wilcoxon> {
+    let mut nonzero: Vec<f64> =
+        differences.iter().cloned().filter(|&d| d != 0.0).collect();
+    if nonzero.is_empty() {
+        return None;
+    }
+    nonzero.sort_by(|a, b| {
+                        a.abs().partial_cmp(&b.abs()).unwrap_or(Ordering::Equal)
+                    });
+
+    // Assign ranks, averaging over runs of equal magnitudes.
+    let n = nonzero.len();
+    let mut positive = 0.0;
+    let mut tie_correction = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && nonzero[j + 1].abs() == nonzero[i].abs() {
+            j += 1;
+        }
+        let rank = (i + j + 2) as f64 / 2.0;
+        for d in &nonzero[i..j + 1] {
+            if *d > 0.0 {
+                positive += rank;
+            }
+        }
+        let tied = (j - i + 1) as f64;
+        tie_correction += (tied * tied * tied - tied) / 48.0;
+        i = j + 1;
+    }
+
+    let total = (n * (n + 1)) as f64 / 2.0;
+    let negative = total - positive;
+    let mean = total / 2.0;
+    let variance = (n * (n + 1) * (2 * n + 1)) as f64 / 24.0 - tie_correction;
+    let z = if variance > 0.0 {
+        (positive - mean) / variance.sqrt()
+    } else {
+        0.0
+    };
+
+    Some(Wilcoxon {
+             n: n,
+             statistic: positive.min(negative),
+             z: z,
+             p_value: (2.0 * (1.0 - normal_cdf(z.abs()))).min(1.0),
+         })
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / SQRT_2))
+}
+
+/// Approximates the error function (Abramowitz and Stegun, 7.1.26), with an
+/// absolute error below 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let polynomial = t *
+                     (0.254_829_592 +
+                      t *
+                      (-0.284_496_736 +
+                       t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use instance::{Datum, Outcome};
+
+    fn data(costs: &[Option<Distance>]) -> Data {
+        let mut data = Data::new(costs.len());
+        for cost in costs {
+            data.push(match *cost {
+                          Some(cost) => {
+                              Datum {
+                                  cost: cost,
+                                  outcome: Outcome::Solved,
+                                  ..Datum::default()
+                              }
+                          }
+                          None => Datum::default(),
+                      });
+        }
+        data
+    }
+
+    #[test]
+    fn counts_paired_outcomes() {
+        let first = data(&[Some(3.0), Some(5.0), Some(2.0), None, Some(4.0)]);
+        let second = data(&[Some(2.0), Some(6.0), Some(2.0), Some(1.0), None]);
+
+        let comparison = compare(&first, &second);
+
+        assert_eq!(comparison.differences, vec![(0, -1.0), (1, 1.0), (2, 0.0)]);
+        assert_eq!(comparison.wins, 1);
+        assert_eq!(comparison.losses, 1);
+        assert_eq!(comparison.ties, 1);
+        assert_eq!(comparison.mean_difference, 0.0);
+    }
+
+    #[test]
+    fn signed_rank_test() {
+        assert_eq!(wilcoxon(&[0.0, 0.0]), None);
+
+        // Ranks 1, 2.5, 2.5, 4, 5, with only the smallest one negative.
+        let result = wilcoxon(&[-1.0, 2.0, 2.0, 3.0, 4.0, 0.0]).unwrap();
+        assert_eq!(result.n, 5);
+        assert_eq!(result.statistic, 1.0);
+        assert!((result.z - 1.7609).abs() < 1e-3);
+        assert!((result.p_value - 0.0782).abs() < 1e-3);
+    }
+
+    #[test]
+    fn error_function() {
+        assert!(erf(0.0).abs() < 1e-7);
+        assert!((erf(1.0) - 0.842_700_79).abs() < 1e-6);
+        assert!((erf(-2.0) + 0.995_322_27).abs() < 1e-6);
+    }
+}
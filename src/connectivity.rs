@@ -0,0 +1,134 @@
+//! Regions of the tiles an agent believes it may occupy, so that whether a
+//! target is still reachable can be told without searching for a path.
+
+use grid::{Direction, Grid, Point, Tile};
+
+const UNLABELED: usize = usize::MAX;
+
+/// Labels every free tile of a grid (as told by `Tile::freespace`) with the
+/// region of free tiles it belongs to, where tiles are connected to their
+/// eight neighbors, or only to the four straight ones when the movement
+/// rules of the grid do not connect tiles diagonally.
+///
+/// Beliefs only ever become more certain until the grid is forgotten, so
+/// tiles only ever stop being free, and regions only ever split. Taking a
+/// tile out of its region leaves the rest of the region connected unless
+/// the free tiles around it are only connected through it, which
+/// `separates` tells by looking at its neighbors alone. Only then do the
+/// labels need to be rebuilt.
+#[derive(Clone, Debug)]
+pub struct Connectivity {
+    labels: Vec<usize>,
+}
+
+impl Connectivity {
+    /// Labels the regions of free tiles of `grid`, as currently believed.
+    pub fn from_grid(grid: &Grid) -> Connectivity {
+        let mut labels = vec![UNLABELED; grid.height() * grid.width()];
+        let mut stack = Vec::new();
+        let diagonal = grid.movement().connects_diagonally();
+        for start in 0..labels.len() {
+            if labels[start] != UNLABELED || !grid.tile(start).freespace() {
+                continue;
+            }
+            labels[start] = start;
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                let point = grid.point_of(index);
+                for (direction, neighbor) in grid.neighbors(&point) {
+                    if !diagonal && Direction::ALL[direction].is_diagonal() {
+                        continue;
+                    }
+                    if labels[neighbor] == UNLABELED &&
+                       grid.tile(neighbor).freespace() {
+                        labels[neighbor] = start;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+        Connectivity { labels: labels }
+    }
+
+    /// Whether the tiles at `from` and `to` were free and in the same region
+    /// when the labels were built.
+    pub fn connected(&self, from: usize, to: usize) -> bool {
+        self.labels[from] != UNLABELED && self.labels[from] == self.labels[to]
+    }
+
+    /// Whether the tile at `point` may be the only connection between some
+    /// of the free tiles around it, so that blocking it may split its region.
+    /// Without diagonal connections, only its four straight neighbors need
+    /// to stay connected, though possibly through the others.
+    pub fn separates(grid: &Grid, point: &Point) -> bool {
+        let diagonal = grid.movement().connects_diagonally();
+        // The free neighbors, and whether each must stay connected.
+        let free = Direction::ALL.iter()
+            .zip(grid.neighbor_points(point).iter())
+            .filter_map(|(direction, neighbor)| {
+                neighbor.filter(|neighbor| {
+                            grid.get(neighbor).is_some_and(Tile::freespace)
+                        })
+                    .map(|neighbor| {
+                             (neighbor, diagonal || !direction.is_diagonal())
+                         })
+            })
+            .collect::<Vec<_>>();
+        let start = match free.iter().position(|&(_, needed)| needed) {
+            Some(start) => start,
+            None => return false,
+        };
+
+        let adjacent = |a: &Point, b: &Point| {
+            let (dy, dx) = (a.y().abs_diff(b.y()), a.x().abs_diff(b.x()));
+            if diagonal {
+                dy <= 1 && dx <= 1
+            } else {
+                dy + dx == 1
+            }
+        };
+        let mut reached = vec![false; free.len()];
+        let mut stack = vec![start];
+        reached[start] = true;
+        while let Some(i) = stack.pop() {
+            for j in 0..free.len() {
+                if !reached[j] && adjacent(&free[i].0, &free[j].0) {
+                    reached[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        free.iter()
+            .zip(&reached)
+            .any(|(&(_, needed), &reached)| needed && !reached)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn tell_separating_tiles() {
+        let mut grid = grid_from_str("type octile\nheight 3\nwidth 3\nmap\n\
+                                      .T.\n...\n.T.");
+        for y in 0..3 {
+            for x in 0..3 {
+                grid.look(&Point::new(y, x));
+            }
+        }
+
+        // The middle tile is the only link between the left and right
+        // columns.
+        assert!(Connectivity::separates(&grid, &Point::new(1, 1)));
+        // Every free tile around the others is next to another one.
+        assert!(!Connectivity::separates(&grid, &Point::new(0, 0)));
+        assert!(!Connectivity::separates(&grid, &Point::new(1, 0)));
+
+        let connectivity = Connectivity::from_grid(&grid);
+        assert!(connectivity.connected(0, 8));
+        assert!(!connectivity.connected(0, 1));
+    }
+}
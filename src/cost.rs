@@ -0,0 +1,378 @@
+//! Cost models: how much single moves between neighboring tiles cost, used
+//! alike by agents planning over their beliefs and by the results judging
+//! the moves agents actually made.
+
+use std::f64::consts::SQRT_2;
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use grid::{Direction, Distance, Terrain};
+
+/// The cost of waiting in place for one step, the same for searches through
+/// time, for environments and in results, under every cost model.
+pub const WAIT_COST: Distance = 1.0;
+
+/// The length of a move in every direction, in the order of
+/// `Direction::ALL`.
+const LENGTHS: [Distance; 8] = [SQRT_2, 1.0, SQRT_2, 1.0, 1.0, SQRT_2, 1.0,
+                                SQRT_2];
+
+/// Returns the length of a move in `direction`: 1 for straight moves, and
+/// sqrt(2) for diagonal ones. Cost models measure every move from it.
+pub fn length(direction: Direction) -> Distance {
+    LENGTHS[direction.index()]
+}
+
+/// A move between neighboring tiles, with everything a cost model may take
+/// into account.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    /// The direction of the move.
+    pub direction: Direction,
+    /// The direction of the move before it, if there was one.
+    pub previous: Option<Direction>,
+    /// The terrain of the tile moved onto.
+    pub terrain: Terrain,
+    /// Whether the tile moved onto is next to an obstacle.
+    pub exposed: bool,
+    /// How many moves away from the tile moved onto the nearest observed
+    /// tile is: zero for tiles that have been observed.
+    pub unknown: Distance,
+    /// The value of the tile moved onto in the cost layer of the grid's
+    /// overlay (see `overlay::COST_LAYER`), or zero if there is none.
+    pub layer: Distance,
+}
+
+/// Measures the cost of moves. Grids are shared between threads that plan
+/// over them at once, so cost models must be too.
+pub trait CostModel: Debug + Send + Sync {
+    fn cost(&self, step: &Step) -> Distance;
+
+    /// Whether the cost of a move may depend on `Step::exposed`. The grid
+    /// only keeps track of the tiles next to obstacles when it does.
+    fn exposure_matters(&self) -> bool {
+        false
+    }
+
+    /// Whether the cost of a move may depend on `Step::unknown`. The grid
+    /// only keeps track of how far tiles are from observed ones when it does.
+    fn doubt_matters(&self) -> bool {
+        false
+    }
+
+    /// Whether the cost of a move may depend on `Step::layer`. The grid only
+    /// looks the cost layer up when it does.
+    fn layer_matters(&self) -> bool {
+        false
+    }
+
+    /// Whether every move costs exactly its length, in which case results
+    /// need not report costs under the model besides the length of paths.
+    fn is_length(&self) -> bool {
+        false
+    }
+}
+
+/// How the moves agents make are measured in the results, next to their
+/// cost under the grid's cost model.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MoveMetric {
+    /// Moves cost their length (see `length`).
+    #[default]
+    Length,
+    /// Every move costs 1, so that costs count moves.
+    Unit,
+}
+
+impl MoveMetric {
+    /// Returns the cost of a move in `direction`.
+    pub fn measure(&self, direction: Direction) -> Distance {
+        match *self {
+            MoveMetric::Length => length(direction),
+            MoveMetric::Unit => 1.0,
+        }
+    }
+}
+
+impl FromStr for MoveMetric {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<MoveMetric, String> {
+        match name {
+            "length" => Ok(MoveMetric::Length),
+            "unit" => Ok(MoveMetric::Unit),
+            other => Err(format!("Unknown metric '{}'.", other)),
+        }
+    }
+}
+
+impl CostModel for MoveMetric {
+    fn cost(&self, step: &Step) -> Distance {
+        self.measure(step.direction)
+    }
+
+    fn is_length(&self) -> bool {
+        *self == MoveMetric::Length
+    }
+}
+
+/// A move's length, multiplied by a factor for the terrain moved onto and
+/// by `1 + risk` if that tile is next to an obstacle and by
+/// `1 + doubt * unknown` if it has not been observed, plus a penalty for
+/// changing direction and a weight of the tile's value in the cost layer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Composite {
+    /// The factor for moving onto each terrain, by `Terrain::code`, at
+    /// least 1 (see `check`).
+    pub terrain: [Distance; 5],
+    /// The fraction of its cost added to a move onto a tile next to an
+    /// obstacle, so that agents keep away from walls where they can.
+    pub risk: Distance,
+    /// The fraction of its cost added to a move onto an unobserved tile for
+    /// every move between that tile and the nearest observed one, so that
+    /// agents trust their optimism about unknown space less the farther it
+    /// reaches: zero is plain optimism, and the larger it is, the closer
+    /// agents keep to what they have seen.
+    pub doubt: Distance,
+    /// The cost added to a move in a different direction from the move
+    /// before it. Searches keep a single way of reaching every tile, not one
+    /// for every direction it can be entered in, so plans under a turn cost
+    /// are only approximately optimal: they may reach a tile as cheaply as
+    /// possible, but facing a direction that costs turns later on.
+    pub turn: Distance,
+    /// The factor of the value of the tile moved onto in the cost layer
+    /// added to the cost of a move.
+    pub layer: Distance,
+}
+
+impl Default for Composite {
+    /// Moves cost their length.
+    fn default() -> Composite {
+        Composite {
+            terrain: [1.0; 5],
+            risk: 0.0,
+            doubt: 0.0,
+            turn: 0.0,
+            layer: 0.0,
+        }
+    }
+}
+
+impl Composite {
+    /// Checks that none of the factors and weights of the model is negative
+    /// (or not a number), since searches cannot trust the costs they find if
+    /// moves may cost less than nothing. Searches also estimate costs with
+    /// heuristics that measure moves over open ground, so those estimates
+    /// must not exceed what moves cost: terrain factors must be at least 1.
+    pub fn check(&self) -> Result<(), String> {
+        let weights = [("risk", self.risk),
+                       ("doubt", self.doubt),
+                       ("turn", self.turn),
+                       ("layer", self.layer)];
+        for &(name, weight) in &weights {
+            if weight < 0.0 || weight.is_nan() {
+                return Err(format!("Invalid {} weight {}: expected a \
+                                    non-negative number.",
+                                   name,
+                                   weight));
+            }
+        }
+        for &factor in &self.terrain {
+            if factor < 1.0 || factor.is_nan() {
+                return Err(format!("Invalid terrain weight {}: expected a \
+                                    number of at least 1, as heuristics \
+                                    assume moves cost at least their length.",
+                                   factor));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CostModel for Composite {
+    fn cost(&self, step: &Step) -> Distance {
+        let mut cost = length(step.direction) *
+                       self.terrain[step.terrain.code() as usize];
+        if step.exposed {
+            cost *= 1.0 + self.risk;
+        }
+        if step.unknown > 0.0 {
+            cost *= 1.0 + self.doubt * step.unknown;
+        }
+        if step.previous.is_some_and(|previous| previous != step.direction) {
+            cost += self.turn;
+        }
+        debug_assert!(self.layer >= 0.0 && step.layer >= 0.0,
+                      "Layer costs must not be negative.");
+        cost + self.layer * step.layer
+    }
+
+    fn exposure_matters(&self) -> bool {
+        self.risk != 0.0
+    }
+
+    fn doubt_matters(&self) -> bool {
+        self.doubt != 0.0
+    }
+
+    fn layer_matters(&self) -> bool {
+        self.layer != 0.0
+    }
+
+    fn is_length(&self) -> bool {
+        *self == Composite::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::SQRT_2;
+
+    use grid::{Measure, Point, Tile};
+    use parser::grid_from_str;
+    use search::astar;
+
+    #[test]
+    fn combine_costs() {
+        let step = Step {
+            direction: Direction::NorthWest,
+            previous: None,
+            terrain: Terrain::Ground,
+            exposed: false,
+            unknown: 0.0,
+            layer: 2.0,
+        };
+        let plain = Composite::default();
+        assert_eq!(plain.cost(&step), SQRT_2);
+        let north = Step {
+            direction: Direction::North,
+            ..step
+        };
+        assert_eq!(plain.cost(&north), 1.0);
+        assert!(plain.is_length() && !plain.exposure_matters());
+
+        let model = Composite {
+            terrain: [1.0, 1.0, 1.0, 3.0, 1.0],
+            risk: 0.5,
+            doubt: 0.0,
+            turn: 2.0,
+            layer: 0.0,
+        };
+        assert_eq!(model.cost(&north), 1.0);
+        let east = Some(Direction::East);
+        assert_eq!(model.cost(&Step {
+                                  previous: Some(Direction::North),
+                                  ..north
+                              }),
+                   1.0);
+        assert_eq!(model.cost(&Step { previous: east, ..north }), 3.0);
+        assert_eq!(model.cost(&Step {
+                                  previous: east,
+                                  terrain: Terrain::Swamp,
+                                  exposed: true,
+                                  ..north
+                              }),
+                   3.0 * 1.5 + 2.0);
+        assert!(!model.is_length() && model.exposure_matters());
+
+        let doubtful = Composite {
+            doubt: 0.5,
+            ..Composite::default()
+        };
+        assert_eq!(doubtful.cost(&north), 1.0);
+        assert_eq!(doubtful.cost(&Step { unknown: 3.0, ..north }), 2.5);
+        assert!(doubtful.doubt_matters() && !doubtful.exposure_matters());
+        assert!(!plain.doubt_matters());
+
+        let annotated = Composite {
+            layer: 0.25,
+            ..Composite::default()
+        };
+        assert_eq!(annotated.cost(&north), 1.5);
+        assert!(annotated.layer_matters() && !plain.layer_matters());
+
+        assert!(plain.check().is_ok() && annotated.check().is_ok());
+        assert!(model.check().is_ok());
+        assert!(Composite {
+                        layer: -1.0,
+                        ..Composite::default()
+                    }
+                    .check()
+                    .is_err());
+        assert!(Composite {
+                        terrain: [1.0, 1.0, -2.0, 1.0, 1.0],
+                        ..Composite::default()
+                    }
+                    .check()
+                    .is_err());
+        // Heuristics would overestimate moves onto cheap ground.
+        assert!(Composite {
+                        terrain: [0.5, 1.0, 1.0, 1.0, 1.0],
+                        ..Composite::default()
+                    }
+                    .check()
+                    .is_err());
+    }
+
+    #[test]
+    fn measure_moves() {
+        let step = Step {
+            direction: Direction::SouthEast,
+            previous: Some(Direction::North),
+            terrain: Terrain::Water,
+            exposed: true,
+            unknown: 1.0,
+            layer: 1.0,
+        };
+        // Metrics measure only the moves, whatever the terrain.
+        assert_eq!(MoveMetric::Length.cost(&step), SQRT_2);
+        assert_eq!(MoveMetric::Unit.cost(&step), 1.0);
+        assert!(MoveMetric::Length.is_length());
+        assert!(!MoveMetric::Unit.is_length());
+
+        assert_eq!("unit".parse(), Ok(MoveMetric::Unit));
+        assert_eq!("chebyshev".parse::<MoveMetric>(),
+                   Err("Unknown metric 'chebyshev'.".to_owned()));
+    }
+
+    #[test]
+    fn plan_around_turns_approximately() {
+        let mut grid = grid_from_str("type octile\nheight 4\nwidth 4\nmap\n\
+                                      ....\n.@..\n....\n@...\n");
+        for y in 0..4 {
+            for x in 0..4 {
+                grid.reveal(&Point::new(y, x));
+            }
+        }
+        grid.set_cost_model(Composite {
+                                turn: 1.5,
+                                ..Composite::default()
+                            });
+
+        let source = Point::new(0, 0);
+        let mut path = astar(&mut grid,
+                             &source,
+                             &Point::new(3, 3),
+                             Distance::octile_heuristic,
+                             Tile::freespace)
+            .unwrap()
+            .path;
+        path.push(source);
+        path.reverse();
+        let mut previous = None;
+        let mut cost = 0.0;
+        for pair in path.windows(2) {
+            cost += grid.model_cost(previous, &pair[0], &pair[1]).unwrap();
+            previous = Direction::between(&pair[0], &pair[1]);
+        }
+
+        // The plan goes down, diagonally and then right, turning twice, as
+        // it reaches the tiles along the diagonal more cheaply that way than
+        // going right first. Going along the top row and down the last
+        // column turns once, and costs just 6 + 1.5.
+        assert!((cost - (2.0 + 2.0 * SQRT_2 + 3.0)).abs() < 1e-9);
+        assert!(cost > 7.5);
+    }
+}
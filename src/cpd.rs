@@ -0,0 +1,398 @@
+//! Compressed path databases: for every pair of tiles of a fully known map,
+//! the first move of an optimal path from one to the other, found by
+//! searching once from every tile. The moves towards all targets from a
+//! given source are stored as runs of equal moves, which keeps the database
+//! small, since nearby targets are mostly reached by the same first move.
+
+use std::fs::{create_dir_all, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path as FilePath;
+use std::time::Instant;
+
+use cost::length;
+use grid::{Direction, Distance, Grid, Movement, Point};
+use rle;
+use search::dijkstra;
+
+/// The move stored for targets that cannot be reached, or for the source
+/// itself.
+const NONE: u8 = 8;
+
+/// Symbols for the moves in files, in the order of `Point::neighbors`,
+/// followed by the symbol for `NONE`.
+const SYMBOLS: [char; 9] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', '-'];
+
+/// Identifies the databases built for `grid`: its `Grid::content_hash`,
+/// mixed with its movement rules, since optimal paths depend on both. Maps
+/// that allow every move keep their content hash.
+fn key(grid: &Grid) -> u64 {
+    let movement = match grid.movement() {
+        Movement::Octile => 0,
+        Movement::NoCornerCutting => 1,
+        Movement::Cardinal => 2,
+    };
+    grid.content_hash() ^ 0x9e37_79b9_7f4a_7c15_u64.wrapping_mul(movement)
+}
+
+/// The first move of an optimal path between every pair of tiles of a grid,
+/// over its true terrain, with every move costing its length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cpd {
+    height: usize,
+    width: usize,
+    /// The `key` of the grid the database was built for.
+    hash: u64,
+    /// For every source tile, the runs of equal moves towards the targets,
+    /// as (index of the first target, move) pairs.
+    rows: Vec<Vec<(usize, u8)>>,
+}
+
+impl Cpd {
+    /// Builds the database for `grid` with a Dijkstra search from every
+    /// passable tile, which takes time quadratic in the size of the map.
+    pub fn build(grid: &Grid) -> Cpd {
+        profile_span!("cpd");
+
+        let rows = (0..grid.height() * grid.width())
+            .map(|source| {
+                     let moves = dijkstra(grid, &grid.point_of(source))
+                         .first_moves
+                         .iter()
+                         .map(|direction| direction.unwrap_or(NONE))
+                         .collect::<Vec<_>>();
+                     compress(&moves)
+                 })
+            .collect();
+        Cpd {
+            height: grid.height(),
+            width: grid.width(),
+            hash: key(grid),
+            rows: rows,
+        }
+    }
+
+    /// Whether the database was built for a grid with the same contents and
+    /// movement rules as `grid`.
+    pub fn matches(&self, grid: &Grid) -> bool {
+        self.height == grid.height() && self.width == grid.width() &&
+        self.hash == key(grid)
+    }
+
+    /// The total number of runs stored.
+    pub fn runs(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).sum()
+    }
+
+    fn index_of(&self, point: &Point) -> Option<usize> {
+        if point.y() < self.height && point.x() < self.width {
+            Some(point.y() * self.width + point.x())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the next tile on an optimal path from `from` to `to`, or
+    /// `None` if `to` cannot be reached from `from`, or is `from` itself.
+    pub fn first_move(&self, from: &Point, to: &Point) -> Option<Point> {
+        let row = &self.rows[self.index_of(from)?];
+        let target = self.index_of(to)?;
+        let run = row.partition_point(|&(start, _)| start <= target);
+        match row[run - 1].1 {
+            NONE => None,
+            direction => from.neighbors()[direction as usize],
+        }
+    }
+
+    /// Returns the cost of an optimal path from `from` to `to`, found by
+    /// following first moves, or `None` if there is no such path. Since it
+    /// is exact, it can serve as a perfect heuristic.
+    pub fn distance(&self, from: &Point, to: &Point) -> Option<Distance> {
+        let mut location = *from;
+        let mut cost = 0.0;
+        while location != *to {
+            let next = self.first_move(&location, to)?;
+            cost += length(Direction::between(&location, &next).unwrap());
+            location = next;
+        }
+        Some(cost)
+    }
+
+    /// Writes the database as text: a header followed by one run-length
+    /// encoded line of moves per source tile.
+    pub fn write<W>(&self, out: &mut W) -> Result<(), String>
+        where W: Write
+    {
+        let targets = self.height * self.width;
+        let mut write = || -> ::std::io::Result<()> {
+            writeln!(out, "cpd")?;
+            writeln!(out, "hash {:016x}", self.hash)?;
+            writeln!(out, "height {}", self.height)?;
+            writeln!(out, "width {}", self.width)?;
+            for row in &self.rows {
+                let mut moves = Vec::with_capacity(targets);
+                for (i, &(start, direction)) in row.iter().enumerate() {
+                    let end = row.get(i + 1).map_or(targets, |run| run.0);
+                    moves.extend((start..end)
+                                     .map(|_| SYMBOLS[direction as usize]));
+                }
+                writeln!(out, "{}", rle::encode(moves))?;
+            }
+            Ok(())
+        };
+        write().map_err(|e| e.to_string())
+    }
+
+    /// Reads a database written by `write`.
+    pub fn read<R>(input: R) -> Result<Cpd, String>
+        where R: BufRead
+    {
+        let mut lines = input.lines();
+        if next_line(&mut lines, "header")? != "cpd" {
+            return Err("Not a compressed path database.".to_owned());
+        }
+        let hash = header(&mut lines, "hash")?;
+        let hash = u64::from_str_radix(&hash, 16)
+            .map_err(|_| format!("Invalid hash '{}'.", hash))?;
+        let height = header(&mut lines, "height")?;
+        let height = height.parse::<usize>()
+            .map_err(|_| format!("Invalid height '{}'.", height))?;
+        let width = header(&mut lines, "width")?;
+        let width = width.parse::<usize>()
+            .map_err(|_| format!("Invalid width '{}'.", width))?;
+        let targets = height.checked_mul(width)
+            .ok_or_else(|| "The grid is too large.".to_owned())?;
+
+        let mut rows = Vec::new();
+        for source in 0..targets {
+            let line = next_line(&mut lines, "rows")?;
+            let symbols = rle::decode_at_most(&line, targets)
+                .map_err(|e| format!("Row {}: {}", source, e))?;
+            if symbols.len() != targets {
+                return Err(format!("Row {} has {} moves instead of {}.",
+                                   source,
+                                   symbols.len(),
+                                   targets));
+            }
+            let mut moves = Vec::with_capacity(targets);
+            for symbol in symbols {
+                let direction = SYMBOLS.iter()
+                    .position(|&s| s == symbol)
+                    .ok_or_else(|| {
+                                    format!("Row {}: Invalid move '{}'.",
+                                            source,
+                                            symbol)
+                                })?;
+                moves.push(direction as u8);
+            }
+            rows.push(compress(&moves));
+        }
+
+        Ok(Cpd {
+               height: height,
+               width: width,
+               hash: hash,
+               rows: rows,
+           })
+    }
+
+    /// Returns the database for `grid`, read from the directory `cache` if
+    /// it was stored there before, or built and then stored there. Problems
+    /// with the cache are reported to standard error, along with how long
+    /// it took to obtain the database and how large it is, but are not
+    /// fatal: the database is built instead of read, or not stored.
+    pub fn cached(grid: &Grid, cache: Option<&FilePath>) -> Cpd {
+        let timer = Instant::now();
+        let path = cache.map(|cache| {
+                                 cache.join(format!("{:016x}.cpd", key(grid)))
+                             });
+
+        if let Some(ref path) = path {
+            if path.exists() {
+                let cpd = File::open(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|file| Cpd::read(BufReader::new(file)));
+                match cpd {
+                    Ok(ref cpd) if cpd.matches(grid) => {
+                        eprintln!("Read the compressed path database from {} \
+                                   in {:.3}s ({} runs).",
+                                  path.display(),
+                                  timer.elapsed().as_secs_f64(),
+                                  cpd.runs());
+                        return cpd.clone();
+                    }
+                    Ok(_) => {
+                        eprintln!("{}: Built for a different map.",
+                                  path.display())
+                    }
+                    Err(e) => eprintln!("{}: {}", path.display(), e),
+                }
+            }
+        }
+
+        let cpd = Cpd::build(grid);
+        eprintln!("Built the compressed path database in {:.3}s ({} runs).",
+                  timer.elapsed().as_secs_f64(),
+                  cpd.runs());
+        if let Some(path) = path {
+            let stored = path.parent()
+                .map_or(Ok(()), create_dir_all)
+                .and_then(|_| File::create(&path))
+                .map_err(|e| e.to_string())
+                .and_then(|file| cpd.write(&mut BufWriter::new(file)))
+                .and_then(|_| {
+                              path.metadata()
+                                  .map(|metadata| metadata.len())
+                                  .map_err(|e| e.to_string())
+                          });
+            match stored {
+                Ok(bytes) => {
+                    eprintln!("Stored it in {} ({} bytes).",
+                              path.display(),
+                              bytes)
+                }
+                Err(e) => eprintln!("{}: {}", path.display(), e),
+            }
+        }
+        cpd
+    }
+}
+
+fn next_line<L>(lines: &mut L, what: &str) -> Result<String, String>
+    where L: Iterator<Item = ::std::io::Result<String>>
+{
+    match lines.next() {
+        Some(line) => line.map_err(|e| e.to_string()),
+        None => Err(format!("Missing {}.", what)),
+    }
+}
+
+/// Reads a header line of the form `name value`, returning the value.
+fn header<L>(lines: &mut L, name: &str) -> Result<String, String>
+    where L: Iterator<Item = ::std::io::Result<String>>
+{
+    let line = next_line(lines, name)?;
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(word), Some(value), None) if word == name => Ok(value.to_owned()),
+        _ => Err(format!("Expected '{} <value>', found '{}'.", name, line)),
+    }
+}
+
+/// Groups `moves` into runs of equal moves.
+fn compress(moves: &[u8]) -> Vec<(usize, u8)> {
+    let mut runs: Vec<(usize, u8)> = Vec::new();
+    for (target, &direction) in moves.iter().enumerate() {
+        if runs.last().is_none_or(|run| run.1 != direction) {
+            runs.push((target, direction));
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::{GridBuilder, Measure, Tile};
+    use parser::grid_from_str;
+    use search::astar;
+
+    #[test]
+    fn look_up_optimal_moves() {
+        let mut grid = grid_from_str("type octile\nheight 5\nwidth 6\nmap\n\
+                                      ......\n.TTTT.\n.T..T.\n.T.TT.\n\
+                                      .TTT..");
+        let cpd = Cpd::build(&grid);
+        assert!(cpd.matches(&grid));
+        assert!(cpd.runs() < 30 * 30);
+
+        for from in 0..30 {
+            for to in 0..30 {
+                let (from, to) = (grid.point_of(from), grid.point_of(to));
+                if from == to || !grid[&from].passable() ||
+                   !grid[&to].passable() {
+                    continue;
+                }
+                let expected = astar(&mut grid,
+                                     &from,
+                                     &to,
+                                     Distance::octile_heuristic,
+                                     Tile::passable)
+                        .map(|data| {
+                    let mut cost = 0.0;
+                    let mut location = from;
+                    for point in data.path.iter().rev() {
+                        cost += Distance::octile_heuristic(&location, point);
+                        location = *point;
+                    }
+                    cost
+                });
+                match (cpd.distance(&from, &to), expected) {
+                    (Some(cost), Some(expected)) => {
+                        assert!((cost - expected).abs() < 1e-9)
+                    }
+                    (cost, expected) => assert_eq!(cost, expected),
+                }
+            }
+        }
+        // The enclosed room cannot be left.
+        assert_eq!(cpd.first_move(&Point::new(2, 2), &Point::new(0, 0)), None);
+        assert_eq!(cpd.first_move(&Point::new(2, 2), &Point::new(3, 2)),
+                   Some(Point::new(3, 2)));
+
+        let mut text = Vec::new();
+        cpd.write(&mut text).unwrap();
+        assert_eq!(Cpd::read(&text[..]), Ok(cpd.clone()));
+        let text = String::from_utf8(text).unwrap();
+        assert!(Cpd::read(text.replacen('a', "z", 1).as_bytes()).is_err());
+        assert!(Cpd::read(text.replacen("width 6", "width 7", 1).as_bytes())
+                    .is_err());
+
+        let open = GridBuilder::new(5, 6).build().unwrap();
+        assert!(!cpd.matches(&open));
+    }
+
+    #[test]
+    fn follow_movement_rules() {
+        let octile = grid_from_str("type octile\nheight 3\nwidth 3\nmap\n\
+                                    ...\n...\n...");
+        let tile = grid_from_str("type tile\nheight 3\nwidth 3\nmap\n\
+                                  ...\n...\n...");
+        let (from, to) = (Point::new(0, 0), Point::new(2, 2));
+
+        let cpd = Cpd::build(&octile);
+        assert_eq!(cpd.first_move(&from, &to), Some(Point::new(1, 1)));
+        assert!(!cpd.matches(&tile));
+
+        let cpd = Cpd::build(&tile);
+        assert!(cpd.matches(&tile) && !cpd.matches(&octile));
+        assert_eq!(cpd.distance(&from, &to), Some(4.0));
+        let mut location = from;
+        while location != to {
+            let next = cpd.first_move(&location, &to).unwrap();
+            assert!(next.y() == location.y() || next.x() == location.x());
+            location = next;
+        }
+    }
+
+    #[test]
+    fn cache_databases() {
+        let directory = ::std::env::temp_dir().join("gridist-cpd");
+        let _ = ::std::fs::remove_dir_all(&directory);
+        let grid = grid_from_str("type octile\nheight 3\nwidth 4\nmap\n\
+                                  ....\n.TT.\n....");
+        let path = directory.join(format!("{:016x}.cpd", grid.content_hash()));
+
+        let built = Cpd::cached(&grid, Some(&directory));
+        assert_eq!(built, Cpd::build(&grid));
+        let stored = File::open(&path).unwrap();
+        assert_eq!(Cpd::read(BufReader::new(stored)), Ok(built.clone()));
+        assert_eq!(Cpd::cached(&grid, Some(&directory)), built);
+
+        // Databases that cannot be read are built again, and replaced.
+        ::std::fs::write(&path, "cpd\nhash 0\n").unwrap();
+        assert_eq!(Cpd::cached(&grid, Some(&directory)), built);
+        let stored = File::open(&path).unwrap();
+        assert_eq!(Cpd::read(BufReader::new(stored)), Ok(built));
+    }
+}
@@ -0,0 +1,224 @@
+//! A reset/step interface to the simulator, in the style of reinforcement
+//! learning environments. Instead of an `Agent` choosing every move, the
+//! caller picks one `Action` at a time and gets back what the agent senses.
+
+pub use agent::Action;
+pub use cost::WAIT_COST;
+
+use agent::{Agent, Datum};
+use cost::length;
+use execution::Scanner;
+use experiment::Verbosity;
+use grid::{Belief, Grid, Matrix, Point, Tile};
+use instance::Instance;
+
+/// What the agent senses after a reset or a step.
+#[derive(Debug)]
+pub struct Observation {
+    pub location: Point,
+    pub target: Point,
+    /// The beliefs about the square of tiles centered on the agent, encoded
+    /// with `Belief::code`. Tiles beyond the edges of the grid are
+    /// impassable.
+    pub patch: Matrix,
+}
+
+#[derive(Debug)]
+pub struct Step {
+    pub observation: Observation,
+    /// The negated cost of the action.
+    pub reward: f64,
+    /// Whether the agent has reached the target.
+    pub done: bool,
+}
+
+/// Stands in for the agent in the underlying `Instance`, since the actions
+/// come from the caller instead.
+#[derive(Debug)]
+struct Controlled;
+
+impl Agent for Controlled {
+    fn act(&mut self, _: &mut Grid, _: &Point, _: &Point) -> Option<Datum> {
+        None
+    }
+}
+
+pub struct Env<'a> {
+    instance: Instance<'a, Controlled>,
+    radius: usize,
+    target: Point,
+}
+
+impl<'a> Env<'a> {
+    /// Creates an environment over `grid`, whose observations cover the
+    /// tiles up to `radius` steps away from the agent in each direction.
+    pub fn new(grid: &'a mut Grid, radius: usize) -> Env<'a> {
+        Env {
+            instance: Instance::new(grid, Controlled, Verbosity::Zero),
+            radius: radius,
+            target: Point::new(0, 0),
+        }
+    }
+
+    /// Gives the agent a long-range scanner to sense with, if given (see
+    /// `Instance::set_scanner`).
+    pub fn set_scanner(&mut self, scanner: Option<Scanner>) {
+        self.instance.set_scanner(scanner);
+    }
+
+    /// Starts a new episode going from `source` to `target`, forgetting
+    /// everything learned about the grid.
+    pub fn reset(&mut self, source: Point, target: Point) -> Observation {
+        self.instance.grid_mut().forget();
+        self.target = target;
+        self.instance.start(source);
+        self.observe()
+    }
+
+    /// Performs `action`. Moves off the grid or onto impassable terrain
+    /// leave the agent in place, but are charged as if they had succeeded.
+    /// Sensing is charged as a scan, if the agent has a scan left, and as
+    /// waiting otherwise.
+    pub fn step(&mut self, action: Action) -> Step {
+        let location = self.instance.location();
+        let cost = match action {
+            Action::Wait => {
+                self.instance.move_agent(location);
+                WAIT_COST
+            }
+            Action::Sense => {
+                match self.instance.scanner() {
+                    Some(scanner) if self.instance.scan() => scanner.cost,
+                    _ => {
+                        self.instance.move_agent(location);
+                        WAIT_COST
+                    }
+                }
+            }
+            Action::Move(direction) => {
+                let grid = self.instance.grid();
+                let next = action.target(&location);
+                if let Some(next) = next.filter(|next| {
+                    grid.get(next).is_some_and(Tile::passable)
+                }) {
+                    self.instance.move_agent(next);
+                }
+                length(direction)
+            }
+        };
+
+        Step {
+            observation: self.observe(),
+            reward: -cost,
+            done: self.instance.location() == self.target,
+        }
+    }
+
+    fn observe(&self) -> Observation {
+        let grid = self.instance.grid();
+        let location = self.instance.location();
+        let side = 2 * self.radius + 1;
+
+        let mut patch = Vec::with_capacity(side * side);
+        for dy in 0..side {
+            for dx in 0..side {
+                let y = (location.y() + dy).checked_sub(self.radius);
+                let x = (location.x() + dx).checked_sub(self.radius);
+                let tile = match (y, x) {
+                    (Some(y), Some(x)) => grid.get(&Point::new(y, x)),
+                    _ => None,
+                };
+                patch.push(tile.map_or(Belief::Impassable.code(),
+                                       |tile| tile.belief().code()));
+            }
+        }
+
+        Observation {
+            location: location,
+            target: self.target,
+            patch: Matrix::new(side, side, patch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::{Direction, Sensor};
+    use parser::grid_from_str;
+
+    use std::f64::consts::SQRT_2;
+
+    #[test]
+    fn step_through_grid() {
+        let mut grid = grid_from_str("type octile
+height 3
+width 3
+map
+...
+.T.
+...");
+
+        let mut env = Env::new(&mut grid, 1);
+        let observation = env.reset(Point::new(0, 0), Point::new(2, 2));
+        assert_eq!(observation.patch.as_slice(), &[2, 2, 2, 2, 1, 1, 2, 1, 2]);
+
+        // Moving into the trees fails, but still costs.
+        let step = env.step(Action::Move(Direction::SouthEast));
+        assert_eq!(step.observation.location, Point::new(0, 0));
+        assert_eq!(step.reward, -SQRT_2);
+
+        let step = env.step(Action::Wait);
+        assert_eq!(step.reward, -WAIT_COST);
+        assert!(!step.done);
+
+        env.step(Action::Move(Direction::East));
+        env.step(Action::Move(Direction::East));
+        let step = env.step(Action::from_index(6).unwrap());
+        assert!(!step.done);
+        let step = env.step(Action::Move(Direction::South));
+        assert_eq!(step.observation.location, Point::new(2, 2));
+        assert_eq!(step.reward, -1.0);
+        assert!(step.done);
+
+        let observation = env.reset(Point::new(2, 2), Point::new(0, 0));
+        assert_eq!(observation.patch.get(&Point::new(0, 2)), Some(2));
+        assert_eq!(observation.patch.get(&Point::new(2, 0)), Some(2));
+    }
+
+    #[test]
+    fn sense_with_a_scanner() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 5
+map
+....T");
+
+        let mut env = Env::new(&mut grid, 4);
+        env.reset(Point::new(0, 0), Point::new(0, 4));
+        // Without a scanner, sensing is waiting.
+        let step = env.step(Action::Sense);
+        assert_eq!(step.reward, -WAIT_COST);
+        assert_eq!(step.observation.patch.get(&Point::new(4, 8)), Some(0));
+        // Waits are charged in the results as they are in rewards.
+        assert_eq!(env.instance.datum().steps, 1);
+        assert_eq!(env.instance.datum().cost, WAIT_COST);
+
+        env.set_scanner(Some(Scanner {
+                                 sensor: Sensor {
+                                     radius: 4,
+                                     terrain_radius: None,
+                                 },
+                                 cost: 0.5,
+                                 budget: Some(1),
+                             }));
+        env.reset(Point::new(0, 0), Point::new(0, 4));
+        let step = env.step(Action::Sense);
+        assert_eq!(step.reward, -0.5);
+        assert_eq!(step.observation.location, Point::new(0, 0));
+        assert_eq!(step.observation.patch.get(&Point::new(4, 8)), Some(2));
+        // The only scan allowed is spent.
+        assert_eq!(env.step(Action::Sense).reward, -WAIT_COST);
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::{Result as IOResult, Write};
+
+use grid::{Distance, Point, Sensor};
+
+/// How the moves the agent chooses are carried out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExecutionModel {
+    /// Every move is carried out as chosen, even onto terrain the agent
+    /// could not actually enter.
+    #[default]
+    Direct,
+    /// Moves onto impassable terrain fail: the agent bumps into the tile,
+    /// stays where it was, learns that the tile is blocked, and is charged
+    /// this cost for the attempt.
+    Bump(Distance),
+}
+
+/// A long-range scanner, which the agent uses by sensing (see
+/// `Action::Sense`) instead of moving. A scan observes the tiles around the
+/// agent as far as its sensor reaches, as a step that costs the agent this
+/// much, and agents may only scan so many times in a trial, if the scanner
+/// has a budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scanner {
+    pub sensor: Sensor,
+    pub cost: Distance,
+    /// The number of scans allowed in every trial, if they are limited.
+    pub budget: Option<usize>,
+}
+
+impl Scanner {
+    /// Whether the scanner may be used again, after `scans` scans.
+    pub fn available(&self, scans: usize) -> bool {
+        self.budget.is_none_or(|budget| scans < budget)
+    }
+}
+
+/// Number of times the agent occupied each tile of a grid, over one or more
+/// trials.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Heatmap {
+    height: usize,
+    width: usize,
+    counts: Vec<usize>,
+}
+
+impl Heatmap {
+    pub fn new(height: usize, width: usize) -> Heatmap {
+        Heatmap {
+            height: height,
+            width: width,
+            counts: vec![0; height * width],
+        }
+    }
+
+    /// Records a visit to `point`, returning the number of times it has been
+    /// visited so far, including this one.
+    pub fn visit(&mut self, point: &Point) -> usize {
+        let index = point.y() * self.width + point.x();
+        self.counts[index] += 1;
+        self.counts[index]
+    }
+
+    pub fn get(&self, point: &Point) -> usize {
+        if point.y() < self.height && point.x() < self.width {
+            self.counts[point.y() * self.width + point.x()]
+        } else {
+            0
+        }
+    }
+
+    /// Returns the largest number of visits to any single tile.
+    pub fn max(&self) -> usize {
+        self.counts.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// Adds the visits in `other` to these. An empty heat map takes on the
+    /// dimensions of `other`.
+    pub fn merge(&mut self, other: &Heatmap) {
+        if self.counts.is_empty() {
+            *self = Heatmap::new(other.height, other.width);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += *other;
+        }
+    }
+
+    /// Writes the visit counts as one line of space-separated numbers per
+    /// row of the grid.
+    pub fn write<W>(&self, out: &mut W) -> IOResult<()>
+        where W: Write
+    {
+        for row in self.counts.chunks(self.width.max(1)) {
+            let row: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+            writeln!(out, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_and_merge_visits() {
+        let mut first = Heatmap::new(2, 3);
+        assert_eq!(first.visit(&Point::new(1, 2)), 1);
+        assert_eq!(first.visit(&Point::new(1, 2)), 2);
+        assert_eq!(first.visit(&Point::new(0, 0)), 1);
+
+        let mut total = Heatmap::default();
+        total.merge(&first);
+        total.merge(&first);
+
+        assert_eq!(total.get(&Point::new(1, 2)), 4);
+        assert_eq!(total.get(&Point::new(5, 5)), 0);
+        assert_eq!(total.max(), 4);
+
+        let mut out = Vec::new();
+        total.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2 0 0\n0 0 4\n");
+    }
+}
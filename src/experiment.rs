@@ -1,6 +1,21 @@
-use agent::Agent;
-use grid::{Grid, Point};
-use instance::{Data, Instance};
+use std::mem::take;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use agent::{Agent, Clairvoyant};
+use cost::{CostModel, MoveMetric};
+use execution::{ExecutionModel, Scanner};
+use grid::{Distance, Grid, Measure, Point, Sensor, Terrain,
+           TileChange};
+use instance::{build_trials, Data, Instance};
+use metrics;
+use observer::{Observer, Trace};
+use parser::Scenario;
+use pursuit::Pursuit;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub enum Verbosity {
@@ -19,10 +34,290 @@ impl Verbosity {
     }
 }
 
+/// Checks that `point` lies within `grid` and is passable, naming it `name`
+/// otherwise.
+fn check_endpoint(grid: &Grid, name: &str, point: &Point) -> Result<(), String> {
+    match grid.try_get(point) {
+        Err(e) => Err(format!("{} {}", name, e)),
+        Ok(tile) if !tile.passable() => {
+            Err(format!("{} {} is impassable.", name, point))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Checks that a trial from `source` to `target` can be run on `grid`: both
+/// points must be within the grid and passable and, if `require_path` is
+/// set, connected by a path.
+pub fn validate_endpoints(grid: &mut Grid,
+                          source: &Point,
+                          target: &Point,
+                          require_path: bool)
+                          -> Result<(), String> {
+    check_endpoint(grid, "start", source)?;
+    check_endpoint(grid, "goal", target)?;
+    if require_path && !grid.has_path(source, target) {
+        return Err(format!("goal {} cannot be reached from start {}.",
+                           target,
+                           source));
+    }
+    Ok(())
+}
+
+/// The number of candidate pairs of points drawn for a single random trial,
+/// by default, before giving up.
+pub const DEFAULT_ATTEMPTS: usize = 10_000;
+
+/// A part of the grid that the endpoints of random trials are drawn from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    /// The whole grid.
+    Anywhere,
+    /// The tiles on the border of the grid.
+    Edges,
+    /// Every tile not on the border of the grid.
+    Interior,
+    /// The tiles from `(top, left)` to `(bottom, right)`, inclusive.
+    Rectangle {
+        top: usize,
+        left: usize,
+        bottom: usize,
+        right: usize,
+    },
+}
+
+impl Region {
+    /// Whether `point` lies within the region of a grid of `height` rows and
+    /// `width` columns.
+    pub fn contains(&self, point: &Point, height: usize, width: usize) -> bool {
+        let (y, x) = (point.y(), point.x());
+        if y >= height || x >= width {
+            return false;
+        }
+        match *self {
+            Region::Anywhere => true,
+            Region::Edges => {
+                y == 0 || x == 0 || y + 1 == height || x + 1 == width
+            }
+            Region::Interior => {
+                y > 0 && x > 0 && y + 1 < height && x + 1 < width
+            }
+            Region::Rectangle { top, left, bottom, right } => {
+                top <= y && y <= bottom && left <= x && x <= right
+            }
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses `anywhere`, `edges`, `interior`, or a rectangle given as
+    /// `top,left,bottom,right`.
+    fn from_str(region: &str) -> Result<Region, String> {
+        match region {
+            "anywhere" => Ok(Region::Anywhere),
+            "edges" => Ok(Region::Edges),
+            "interior" => Ok(Region::Interior),
+            _ => {
+                let corners = region.split(',')
+                    .map(|c| c.trim().parse())
+                    .collect::<Result<Vec<usize>, _>>();
+                match corners.as_ref().map(|c| c.as_slice()) {
+                    Ok(&[top, left, bottom, right]) if top <= bottom &&
+                                                       left <= right => {
+                        Ok(Region::Rectangle {
+                               top: top,
+                               left: left,
+                               bottom: bottom,
+                               right: right,
+                           })
+                    }
+                    _ => {
+                        Err(format!("Invalid region '{}': expected \
+                                     anywhere, edges, interior or \
+                                     top,left,bottom,right.",
+                                    region))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The factor of the optimal length of a trial that `StepLimit::Auto`
+/// allows the agent to move.
+pub const AUTO_STEP_FACTOR: f64 = 10.0;
+
+/// How many moves the agent may make in a trial before it is ended with
+/// `Outcome::OutOfSteps`, worked out for every trial from its endpoints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepLimit {
+    /// The same number of moves in every trial.
+    Absolute(usize),
+    /// This many times the straight-line distance between the endpoints.
+    Straight(f64),
+    /// `AUTO_STEP_FACTOR` times the length of an optimal path over the true
+    /// map. Trials with no such path are not limited.
+    Auto,
+}
+
+impl FromStr for StepLimit {
+    type Err = String;
+
+    /// Parses a number of moves, a factor of the straight-line distance
+    /// followed by `x` (as in `10x`), or `auto`.
+    fn from_str(limit: &str) -> Result<StepLimit, String> {
+        let invalid = || {
+            format!("Invalid step limit '{}': expected a number of steps, a \
+                     factor like 10x, or auto.",
+                    limit)
+        };
+        if limit == "auto" {
+            Ok(StepLimit::Auto)
+        } else if let Some(factor) = limit.strip_suffix('x') {
+            match factor.parse() {
+                Ok(factor) if factor > 0.0 => Ok(StepLimit::Straight(factor)),
+                _ => Err(invalid()),
+            }
+        } else {
+            limit.parse().map(StepLimit::Absolute).map_err(|_| invalid())
+        }
+    }
+}
+
+/// The terrains a perturbed tile may be given.
+const PERTURBED_TERRAINS: [Terrain; 4] =
+    [Terrain::Ground, Terrain::Trees, Terrain::Swamp, Terrain::Water];
+
+/// Varies the map from one trial to the next, by giving a fraction of its
+/// tiles some other terrain for the length of the trial, to measure how
+/// robust agents are to small changes in the map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Perturbation {
+    /// The fraction of the tiles changed, from 0 to 1. Out-of-bounds tiles
+    /// and the endpoints of the trial are never changed.
+    pub fraction: f64,
+    pub seed: usize,
+}
+
+impl Perturbation {
+    /// Draws the changes to make to `grid` for the trial from `source` to
+    /// `target`. They depend only on the seed and the endpoints, so every
+    /// agent, whatever the order trials run in, faces the same map in the
+    /// same trial. The changes may cut the target off from the source.
+    pub fn changes(&self,
+                   grid: &Grid,
+                   source: &Point,
+                   target: &Point)
+                   -> Vec<TileChange> {
+        let mut rng: StdRng = SeedableRng::from_seed([self.seed,
+                                                      source.y(),
+                                                      source.x(),
+                                                      target.y(),
+                                                      target.x()]
+                                                             .as_ref());
+        let mut candidates = (0..grid.height() * grid.width())
+            .map(|index| grid.point_of(index))
+            .filter(|point| point != source && point != target)
+            .filter(|point| grid[point].terrain() != Terrain::OutOfBounds)
+            .collect::<Vec<_>>();
+        let count = (self.fraction * candidates.len() as f64).round() as usize;
+        rng.shuffle(&mut candidates);
+        candidates.truncate(count);
+        candidates.sort_by_key(|point| (point.y(), point.x()));
+        candidates.into_iter()
+            .map(|point| {
+                let from = grid[&point].terrain();
+                let others = PERTURBED_TERRAINS.iter()
+                    .filter(|&&terrain| terrain != from)
+                    .collect::<Vec<_>>();
+                TileChange {
+                    point: point,
+                    from: from,
+                    to: *others[rng.gen_range(0, others.len())],
+                }
+            })
+            .collect()
+    }
+}
+
+/// The order in which trials run. Results keep the order of the trials, so
+/// only effects that carry over from one trial to the next (such as
+/// retained beliefs) depend on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrialOrder {
+    /// The order the trials were generated or listed in.
+    Given,
+    /// A random order, drawn with the given seed.
+    Shuffled(usize),
+    /// From the easiest trial to the hardest, as estimated by the octile
+    /// distance between their endpoints. Ties keep their order.
+    Difficulty,
+}
+
+/// How random trials are sampled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sampling {
+    /// How many candidate pairs to draw for a single trial before giving up.
+    pub attempts: usize,
+    /// Whether to keep pairs of points with no path between them, as trials
+    /// known to be unsolvable.
+    pub allow_unreachable: bool,
+    /// Where sources are drawn from.
+    pub sources: Region,
+    /// Where targets are drawn from.
+    pub targets: Region,
+}
+
+impl Default for Sampling {
+    fn default() -> Sampling {
+        Sampling {
+            attempts: DEFAULT_ATTEMPTS,
+            allow_unreachable: false,
+            sources: Region::Anywhere,
+            targets: Region::Anywhere,
+        }
+    }
+}
+
+/// Generates the endpoints of the trials of an experiment, given the range
+/// of trials to run and a seed. `Sampling` draws them at random from regions
+/// of the grid; other generators may place them anywhere, or read them from
+/// somewhere else.
+pub trait TrialGenerator {
+    /// Generates the trials numbered `start` to `end` for `grid`, as
+    /// (source, target, reachable) triples, where `reachable` tells whether
+    /// a path joins the endpoints (see `Grid::has_path`). Trials should only
+    /// depend on `seed` and their number, so that the trials numbered
+    /// `start` to `end` are the same whatever `start` is.
+    fn generate(&self,
+                grid: &mut Grid,
+                start: usize,
+                end: usize,
+                seed: usize)
+                -> Result<Vec<(Point, Point, bool)>, String>;
+}
+
+impl TrialGenerator for Sampling {
+    /// Draws pairs of distinct passable points from the regions of the
+    /// sampling, which must be connected unless it allows otherwise.
+    fn generate(&self,
+                grid: &mut Grid,
+                start: usize,
+                end: usize,
+                seed: usize)
+                -> Result<Vec<(Point, Point, bool)>, String> {
+        build_trials(grid, start, end, seed, self)
+    }
+}
+
 struct RandomTrialData {
     start: usize,
     end: usize,
     seed: usize,
+    generator: Box<dyn TrialGenerator>,
+    pairs: Option<Vec<(Point, Point, bool)>>,
 }
 
 struct PointPair {
@@ -33,12 +328,38 @@ struct PointPair {
 enum Configuration {
     Trials(RandomTrialData),
     Single(PointPair),
+    Pairs(Vec<(usize, Point, Point)>),
 }
 
 pub struct Experiment {
     grid: Grid,
     config: Configuration,
     verbosity: Verbosity,
+    timeout: Option<Duration>,
+    move_budget: Option<Duration>,
+    perturbation: Option<Perturbation>,
+    interrupt: Option<Arc<AtomicBool>>,
+    detect_oscillation: bool,
+    detect_disconnection: bool,
+    catch_panics: bool,
+    validate_moves: bool,
+    execution: ExecutionModel,
+    scanner: Option<Scanner>,
+    pursuit: Option<Pursuit>,
+    retain_beliefs: bool,
+    trial_order: TrialOrder,
+    trace: Trace,
+    step_limit: Option<StepLimit>,
+    with_baseline: bool,
+    /// The results of the clairvoyant baseline, and the metric its moves
+    /// were measured with.
+    baseline: Option<(MoveMetric, Data)>,
+    observers: Vec<Box<dyn Observer>>,
+    metrics: Vec<Box<dyn metrics::Metric>>,
+    tag: Option<String>,
+    /// The scenario bucket and optimal length of every trial, when they
+    /// were read from a scenario.
+    buckets: Option<Vec<(usize, Distance)>>,
 }
 
 impl Experiment {
@@ -54,8 +375,32 @@ impl Experiment {
                                               start: start,
                                               end: end,
                                               seed: seed,
+                                              generator:
+                                                  Box::new(Sampling::default()),
+                                              pairs: None,
                                           }),
             verbosity: verbosity,
+            timeout: None,
+            move_budget: None,
+            perturbation: None,
+            interrupt: None,
+            detect_oscillation: true,
+            detect_disconnection: true,
+            catch_panics: false,
+            validate_moves: false,
+            execution: ExecutionModel::default(),
+            scanner: None,
+            pursuit: None,
+            retain_beliefs: false,
+            trial_order: TrialOrder::Given,
+            trace: Trace::default(),
+            step_limit: None,
+            with_baseline: false,
+            baseline: None,
+            observers: Vec::new(),
+            metrics: Vec::new(),
+            tag: None,
+            buckets: None,
         }
     }
 
@@ -71,23 +416,577 @@ impl Experiment {
                                               target: target,
                                           }),
             verbosity: verbosity,
+            timeout: None,
+            move_budget: None,
+            perturbation: None,
+            interrupt: None,
+            detect_oscillation: true,
+            detect_disconnection: true,
+            catch_panics: false,
+            validate_moves: false,
+            execution: ExecutionModel::default(),
+            scanner: None,
+            pursuit: None,
+            retain_beliefs: false,
+            trial_order: TrialOrder::Given,
+            trace: Trace::default(),
+            step_limit: None,
+            with_baseline: false,
+            baseline: None,
+            observers: Vec::new(),
+            metrics: Vec::new(),
+            tag: None,
+            buckets: None,
         }
     }
 
-    pub fn run<A>(&mut self, agent: A) -> Data
-        where A: Agent
+    /// Creates an experiment running exactly the given (line, source,
+    /// target) trials, where `line` identifies the trial in the results.
+    /// Fails if some source or target is outside the grid or impassable.
+    pub fn pairs(grid: Grid,
+                 pairs: Vec<(usize, Point, Point)>,
+                 verbosity: Verbosity)
+                 -> Result<Experiment, String> {
+        for &(line, source, target) in &pairs {
+            check_endpoint(&grid, "start", &source)
+                .and_then(|_| check_endpoint(&grid, "goal", &target))
+                .map_err(|e| format!("Line {}: {}", line, e))?;
+        }
+
+        Ok(Experiment {
+               grid: grid,
+               config: Configuration::Pairs(pairs),
+               verbosity: verbosity,
+               timeout: None,
+               move_budget: None,
+               perturbation: None,
+               interrupt: None,
+               detect_oscillation: true,
+               detect_disconnection: true,
+               catch_panics: false,
+               validate_moves: false,
+               execution: ExecutionModel::default(),
+               scanner: None,
+               pursuit: None,
+               retain_beliefs: false,
+               trial_order: TrialOrder::Given,
+               trace: Trace::default(),
+               step_limit: None,
+               with_baseline: false,
+               baseline: None,
+               observers: Vec::new(),
+               metrics: Vec::new(),
+               tag: None,
+               buckets: None,
+           })
+    }
+
+    /// Creates an experiment running the trials of a movingai scenario,
+    /// whose results are also reported by bucket. Fails like `pairs`.
+    pub fn scenarios(grid: Grid,
+                     scenarios: Vec<Scenario>,
+                     verbosity: Verbosity)
+                     -> Result<Experiment, String> {
+        let pairs = scenarios.iter()
+            .map(|scenario| (scenario.line, scenario.source, scenario.target))
+            .collect();
+        let mut experiment = Experiment::pairs(grid, pairs, verbosity)?;
+        experiment.buckets = Some(scenarios.iter()
+                                      .map(|scenario| {
+                                               (scenario.bucket,
+                                                scenario.optimal)
+                                           })
+                                      .collect());
+        Ok(experiment)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Limits the wall-clock time each trial may run for.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Gives agents this long to decide on every move (see
+    /// `Instance::set_move_budget`).
+    pub fn set_move_budget(&mut self, budget: Option<Duration>) {
+        self.move_budget = budget;
+    }
+
+    /// Perturbs the map for every trial, if given. Trials are still marked
+    /// unreachable by the unperturbed map.
+    pub fn set_perturbation(&mut self, perturbation: Option<Perturbation>) {
+        self.perturbation = perturbation;
+    }
+
+    /// Stops running trials once `interrupt` is set, so that the results of
+    /// those that ran can still be reported.
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.interrupt = Some(interrupt);
+    }
+
+    /// Returns the lines that identify the trials in the results, when they
+    /// were read from a file of pairs.
+    pub fn lines(&self) -> Option<Vec<usize>> {
+        match self.config {
+            Configuration::Pairs(ref pairs) => {
+                Some(pairs.iter().map(|pair| pair.0).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets how much of the grid the agent observes at every step.
+    pub fn set_sensor(&mut self, sensor: Sensor) {
+        self.grid.set_sensor(sensor);
+    }
+
+    /// Sets how far from obstacles the agent must stay, in moves.
+    pub fn set_clearance(&mut self, clearance: usize) {
+        self.grid.set_clearance(clearance);
+    }
+
+    /// Sets how agents weigh the cost of moves while planning, and how the
+    /// moves they make are judged.
+    pub fn set_cost_model<M>(&mut self, cost_model: M)
+        where M: CostModel + 'static
     {
-        let mut instance = Instance::new(&mut self.grid, agent, self.verbosity);
+        self.grid.set_cost_model(cost_model);
+        self.baseline = None;
+    }
+
+    /// Sets whether trials are cut short when the agent starts oscillating.
+    pub fn set_detect_oscillation(&mut self, detect: bool) {
+        self.detect_oscillation = detect;
+    }
+
+    /// Sets whether trials are cut short when the agent comes to believe the
+    /// target cannot be reached.
+    pub fn set_detect_disconnection(&mut self, detect: bool) {
+        self.detect_disconnection = detect;
+    }
 
+    /// Limits how many moves the agent may make in each trial.
+    pub fn set_step_limit(&mut self, step_limit: Option<StepLimit>) {
+        self.step_limit = step_limit;
+    }
+
+    /// Sets what is traced while trials run, besides what the verbosity
+    /// implies.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = trace;
+    }
+
+    /// Sets whether trials where the agent panics are recorded as errored,
+    /// going on with the next trial, instead of aborting the experiment.
+    pub fn set_catch_panics(&mut self, catch: bool) {
+        self.catch_panics = catch;
+    }
+
+    /// Sets whether trials end at the first illegal move of the agent,
+    /// instead of trusting it to move only where it may.
+    pub fn set_validate_moves(&mut self, validate: bool) {
+        self.validate_moves = validate;
+    }
+
+    /// Sets how the moves agents choose are carried out in every trial.
+    pub fn set_execution(&mut self, execution: ExecutionModel) {
+        self.execution = execution;
+    }
+
+    /// Gives the agent a long-range scanner in every trial, if given (see
+    /// `Instance::set_scanner`).
+    pub fn set_scanner(&mut self, scanner: Option<Scanner>) {
+        self.scanner = scanner;
+    }
+
+    /// Makes the target of every trial flee from the agent as `pursuit`
+    /// decides, if given (see `Instance::set_pursuit`).
+    pub fn set_pursuit(&mut self, pursuit: Option<Pursuit>) {
+        self.pursuit = pursuit;
+    }
+
+    /// Sets whether trials begin with the beliefs the agent had at the end of
+    /// the trial before, instead of forgetting everything it saw.
+    pub fn set_retain_beliefs(&mut self, retain: bool) {
+        self.retain_beliefs = retain;
+    }
+
+    /// Sets the order in which trials run.
+    pub fn set_trial_order(&mut self, order: TrialOrder) {
+        self.trial_order = order;
+    }
+
+    /// Lists the endpoints of every trial, in the order they are reported.
+    /// Random trials are left out until they have been generated.
+    fn endpoints(&self) -> Vec<(Point, Point)> {
         match self.config {
             Configuration::Trials(ref trials) => {
-                instance.run_trials(trials.start, trials.end, trials.seed)
+                trials.pairs
+                    .iter()
+                    .flatten()
+                    .map(|&(source, target, _)| (source, target))
+                    .collect()
+            }
+            Configuration::Pairs(ref pairs) => {
+                pairs.iter()
+                    .map(|&(_, source, target)| (source, target))
+                    .collect()
             }
             Configuration::Single(ref single) => {
-                let mut data = Data::new(1);
-                data.push(instance.run_once(single.source, single.target));
+                vec![(single.source, single.target)]
+            }
+        }
+    }
+
+    /// Returns the positions of the trials in the results, in the order in
+    /// which they run.
+    pub fn run_order(&self) -> Vec<usize> {
+        let endpoints = self.endpoints();
+        let mut order = (0..endpoints.len()).collect::<Vec<_>>();
+        match self.trial_order {
+            TrialOrder::Given => (),
+            TrialOrder::Shuffled(seed) => {
+                let mut rng: StdRng = SeedableRng::from_seed([seed].as_ref());
+                rng.shuffle(&mut order);
+            }
+            TrialOrder::Difficulty => {
+                let difficulty = |i: usize| {
+                    Distance::octile_heuristic(&endpoints[i].0, &endpoints[i].1)
+                };
+                order.sort_by(|&a, &b| {
+                                  difficulty(a).partial_cmp(&difficulty(b))
+                                      .unwrap()
+                              });
+            }
+        }
+        order
+    }
+
+    /// Sets how random trials are sampled. Has no effect on other kinds of
+    /// experiments, or once trials have been generated.
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.set_trial_generator(sampling);
+    }
+
+    /// Sets how the trials of an experiment created with `trials` are
+    /// generated, instead of sampling them at random. Has no effect on other
+    /// kinds of experiments, or once trials have been generated.
+    pub fn set_trial_generator<G>(&mut self, generator: G)
+        where G: TrialGenerator + 'static
+    {
+        if let Configuration::Trials(ref mut trials) = self.config {
+            trials.generator = Box::new(generator);
+        }
+    }
+
+    /// Generates the random trials, if this experiment has any and they have
+    /// not been generated yet. Fails if the map does not have enough valid
+    /// pairs of points to sample from, or if some generated endpoint is
+    /// outside the grid or impassable.
+    pub fn prepare(&mut self) -> Result<(), String> {
+        if let Configuration::Trials(ref mut trials) = self.config {
+            if trials.pairs.is_none() {
+                let pairs = trials.generator
+                    .generate(&mut self.grid,
+                              trials.start,
+                              trials.end,
+                              trials.seed)?;
+                let grid = &self.grid;
+                for (i, &(source, target, _)) in pairs.iter().enumerate() {
+                    check_endpoint(grid, "start", &source)
+                        .and_then(|_| check_endpoint(grid, "goal", &target))
+                        .map_err(|e| format!("Trial {}: {}", i, e))?;
+                }
+                trials.pairs = Some(pairs);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets whether every trial is also run by a clairvoyant agent, which
+    /// knows the true map, to measure the regret of other agents.
+    pub fn set_with_baseline(&mut self, with_baseline: bool) {
+        self.with_baseline = with_baseline;
+    }
+
+    /// Labels the results of every agent run by the experiment with `tag`.
+    pub fn set_tag(&mut self, tag: Option<String>) {
+        self.tag = tag;
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Adds `observer` to be notified of the events of every trial run by
+    /// the experiment, except those of the clairvoyant baseline.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Removes every observer, returning them.
+    pub fn take_observers(&mut self) -> Vec<Box<dyn Observer>> {
+        take(&mut self.observers)
+    }
+
+    /// Adds `metric` to be measured over every trial run by the experiment,
+    /// except those of the clairvoyant baseline.
+    pub fn add_metric(&mut self, metric: Box<dyn metrics::Metric>) {
+        self.metrics.push(metric);
+    }
+
+    /// Runs every trial in the experiment with `agent`. Random trials are
+    /// generated the first time the experiment runs (unless `prepare` did
+    /// so already), and reused afterwards, so that every agent faces the
+    /// same trials. The clairvoyant baseline, if enabled, plans under the
+    /// same cost model and has its moves measured with the agent's metric,
+    /// and is run again only for agents measured with another metric.
+    ///
+    /// Panics if the random trials cannot be generated.
+    pub fn run<A>(&mut self, agent: A) -> Data
+        where A: Agent
+    {
+        let metric = agent.metric();
+        let mut data = self.run_agent(agent);
+        if self.with_baseline {
+            if self.baseline.as_ref().is_none_or(|b| b.0 != metric) {
+                let clairvoyant = Clairvoyant::new(Distance::octile_heuristic,
+                                                   metric);
+                let observers = self.take_observers();
+                let metrics = take(&mut self.metrics);
+                self.baseline = Some((metric, self.run_agent(clairvoyant)));
+                self.observers = observers;
+                self.metrics = metrics;
+            }
+            data.set_baselines(&self.baseline.as_ref().unwrap().1);
+        }
+        data
+    }
+
+    fn run_agent<A>(&mut self, agent: A) -> Data
+        where A: Agent
+    {
+        self.prepare().unwrap_or_else(|e| panic!("{}", e));
+        let order = self.run_order();
+        let endpoints = self.endpoints();
+        let trials = order.iter().map(|&i| endpoints[i]).collect::<Vec<_>>();
+
+        let mut instance = Instance::new(&mut self.grid, agent, self.verbosity);
+        instance.set_timeout(self.timeout);
+        instance.set_move_budget(self.move_budget);
+        instance.set_perturbation(self.perturbation);
+        instance.set_interrupt(self.interrupt.clone());
+        instance.set_detect_oscillation(self.detect_oscillation);
+        instance.set_detect_disconnection(self.detect_disconnection);
+        instance.set_catch_panics(self.catch_panics);
+        instance.set_validate_moves(self.validate_moves);
+        instance.set_execution(self.execution);
+        instance.set_scanner(self.scanner);
+        instance.set_pursuit(self.pursuit.take());
+        instance.set_retain_beliefs(self.retain_beliefs);
+        instance.set_trace(self.trace);
+        instance.set_step_limit(self.step_limit);
+        for observer in self.observers.drain(..) {
+            instance.add_observer(observer);
+        }
+        for metric in self.metrics.drain(..) {
+            instance.add_metric(metric);
+        }
+
+        let mut data = match self.config {
+            Configuration::Trials(ref random) => {
+                let pairs = random.pairs.as_ref().map_or(&[][..], |p| p);
+                let mut data = instance.run_pairs(&trials);
+                data.restore_order(&order);
+                data.mark_unreachable(pairs.iter().map(|pair| pair.2));
+                data.set_seed(Some(random.seed));
+                data
+            }
+            Configuration::Pairs(ref pairs) => {
+                let mut data = instance.run_pairs(&trials);
+                data.restore_order(&order);
+                data.set_lines(pairs.iter().map(|pair| pair.0).collect());
+                if let Some(ref buckets) = self.buckets {
+                    data.set_buckets(buckets.clone());
+                }
                 data
             }
+            // Like any other trial, the single one starts from forgotten
+            // beliefs, so that every agent run on it faces the same map.
+            Configuration::Single(_) => instance.run_pairs(&trials),
+        };
+        self.observers = instance.take_observers();
+        self.metrics = instance.take_metrics();
+        self.pursuit = instance.take_pursuit();
+        data.set_tag(self.tag.clone());
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use agent::RepeatedAstar;
+    use cost::Composite;
+    use parser::grid_from_str;
+
+    /// Goes from one corner of the grid to the opposite one, turning around
+    /// every trial.
+    struct Corners;
+
+    impl TrialGenerator for Corners {
+        fn generate(&self,
+                    grid: &mut Grid,
+                    start: usize,
+                    end: usize,
+                    _seed: usize)
+                    -> Result<Vec<(Point, Point, bool)>, String> {
+            let corners = [Point::new(0, 0),
+                           Point::new(grid.height() - 1, grid.width() - 1)];
+            Ok((start..end)
+                   .map(|i| {
+                            let (source, target) =
+                                (corners[i % 2], corners[1 - i % 2]);
+                            (source, target, grid.has_path(&source, &target))
+                        })
+                   .collect())
+        }
+    }
+
+    #[test]
+    fn generate_trials() {
+        let grid = grid_from_str("type octile\nheight 3\nwidth 4\nmap\n\
+                                  ....\n.T..\n....");
+        let mut experiment = Experiment::trials(grid, 1, 4, 0, Verbosity::Zero);
+        experiment.set_trial_generator(Corners);
+        let data = experiment.run(RepeatedAstar::new(Distance::octile_heuristic,
+                                                     MoveMetric::Length));
+        assert_eq!(data.len(), 3);
+        assert!(data.iter().all(|datum| datum.solved()));
+        assert_eq!(data[0].steps, 3);
+        assert_eq!(data.seed(), Some(0));
+
+        // Generated endpoints are checked like those read from files.
+        let grid = grid_from_str("type octile\nheight 2\nwidth 2\nmap\n\
+                                  ..\n.T");
+        let mut experiment = Experiment::trials(grid, 0, 1, 0, Verbosity::Zero);
+        experiment.set_trial_generator(Corners);
+        assert_eq!(experiment.prepare(),
+                   Err("Trial 0: goal (1, 1) is impassable.".to_owned()));
+    }
+    #[test]
+    fn perturb_every_trial() {
+        let grid = grid_from_str("type octile\nheight 3\nwidth 5\nmap\n\
+                                  @....\n.T...\n....@");
+        let (source, target) = (Point::new(1, 0), Point::new(0, 4));
+        let perturbation = Perturbation {
+            fraction: 0.5,
+            seed: 3,
+        };
+        let changes = perturbation.changes(&grid, &source, &target);
+        // Half of the 11 tiles that may change, rounded.
+        assert_eq!(changes.len(), 6);
+        assert_eq!(perturbation.changes(&grid, &source, &target), changes);
+        for change in &changes {
+            assert!(change.point != source && change.point != target);
+            assert_eq!(grid[&change.point].terrain(), change.from);
+            assert!(change.from != Terrain::OutOfBounds);
+            assert!(change.to != change.from);
         }
+        assert!(perturbation.changes(&grid, &target, &source) != changes);
+
+        // With every other tile blocked, the agent never gets far, and the
+        // map is restored afterwards.
+        let grid = grid_from_str("type octile\nheight 1\nwidth 5\nmap\n\
+                                  .....");
+        let hash = grid.content_hash();
+        let mut experiment = Experiment::single(grid,
+                                                Point::new(0, 0),
+                                                Point::new(0, 4),
+                                                Verbosity::Zero);
+        experiment.set_perturbation(Some(Perturbation {
+                                             fraction: 1.0,
+                                             seed: 0,
+                                         }));
+        let data = experiment.run(RepeatedAstar::new(Distance::octile_heuristic,
+                                                     MoveMetric::Length));
+        assert!(!data[0].solved());
+        assert_eq!(experiment.grid().content_hash(), hash);
+    }
+
+    #[test]
+    fn measure_baselines_like_agents() {
+        let map = "type octile\nheight 5\nwidth 6\nmap\n\
+                   ......\n.TT.T.\n...T..\n.T....\n......";
+        let clairvoyant = |metric, model: Composite| {
+            let mut experiment = Experiment::trials(grid_from_str(map),
+                                                    0,
+                                                    4,
+                                                    0,
+                                                    Verbosity::Zero);
+            experiment.set_trial_generator(Corners);
+            experiment.set_cost_model(model);
+            experiment.run(Clairvoyant::new(Distance::octile_heuristic, metric))
+        };
+
+        let mut experiment = Experiment::trials(grid_from_str(map),
+                                                0,
+                                                4,
+                                                0,
+                                                Verbosity::Zero);
+        experiment.set_trial_generator(Corners);
+        experiment.set_with_baseline(true);
+        let unit = experiment.run(RepeatedAstar::new(
+            Distance::octile_heuristic, MoveMetric::Unit));
+        let reference = clairvoyant(MoveMetric::Unit, Composite::default());
+        for i in 0..4 {
+            assert_eq!(unit[i].baseline, Some(reference[i].cost));
+        }
+        // Agents measured with another metric get their own baseline.
+        let length = experiment.run(RepeatedAstar::new(
+            Distance::octile_heuristic, MoveMetric::Length));
+        let reference = clairvoyant(MoveMetric::Length, Composite::default());
+        for i in 0..4 {
+            assert_eq!(length[i].baseline, Some(reference[i].cost));
+        }
+
+        // Under a cost model, baselines and regrets are measured by it.
+        let model = Composite {
+            risk: 1.0,
+            ..Composite::default()
+        };
+        experiment.set_cost_model(model);
+        let safe = experiment.run(RepeatedAstar::new(
+            Distance::octile_heuristic, MoveMetric::Length));
+        let reference = clairvoyant(MoveMetric::Length, model);
+        for i in 0..4 {
+            assert_eq!(safe[i].baseline, reference[i].model_cost);
+            assert!(safe[i].regret().unwrap() >= -1e-9);
+        }
+    }
+
+    #[test]
+    fn single_trial_forgets_beliefs() {
+        let grid = grid_from_str("type octile\nheight 3\nwidth 5\nmap\n\
+                                  .....\nTTTT.\n.....");
+        let mut experiment = Experiment::single(grid,
+                                                Point::new(0, 0),
+                                                Point::new(2, 0),
+                                                Verbosity::Zero);
+        let agent = || RepeatedAstar::new(Distance::octile_heuristic,
+                                          MoveMetric::Length);
+        let first = experiment.run(agent());
+        // The trees found in the first run are not known to the second.
+        let second = experiment.run(agent());
+        assert!(first[0].solved());
+        assert!(first[0].episodes > 1);
+        assert_eq!(second[0].steps, first[0].steps);
+        assert_eq!(second[0].episodes, first[0].episodes);
+        assert_eq!(second[0].expansions, first[0].expansions);
+        assert_eq!(second[0].cost, first[0].cost);
     }
 }
@@ -0,0 +1,444 @@
+//! Distance transforms: for every tile, the distance to the nearest
+//! obstacle, kept up to date as single tiles become or stop being obstacles.
+
+use std::cmp::{max, Ordering};
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+
+use grid::{Distance, Grid, Measure, Point, Tile};
+
+/// How distances between tiles are measured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    /// The number of moves between tiles, ignoring their cost.
+    Chebyshev,
+    /// The cost of moving between tiles over open ground (the chamfer
+    /// distance with weights 1 and sqrt(2)).
+    Chamfer,
+    /// The straight-line distance between tiles.
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(&self, from: &Point, to: &Point) -> Distance {
+        match *self {
+            Metric::Chebyshev => {
+                max(from.y().abs_diff(to.y()), from.x().abs_diff(to.x())) as
+                Distance
+            }
+            Metric::Chamfer => Distance::octile_heuristic(from, to),
+            Metric::Euclidean => Distance::euclidean_heuristic(from, to),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Metric, String> {
+        match name {
+            "chebyshev" => Ok(Metric::Chebyshev),
+            "chamfer" => Ok(Metric::Chamfer),
+            "euclidean" => Ok(Metric::Euclidean),
+            other => Err(format!("Unknown metric '{}'.", other)),
+        }
+    }
+}
+
+/// A tile waiting to pass its nearest obstacle on to its neighbors.
+struct Wave {
+    distance: Distance,
+    index: usize,
+}
+
+impl PartialEq for Wave {
+    fn eq(&self, other: &Wave) -> bool {
+        self.distance.eq(&other.distance)
+    }
+}
+
+impl Eq for Wave {}
+
+impl PartialOrd for Wave {
+    fn partial_cmp(&self, other: &Wave) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Wave {
+    fn cmp(&self, other: &Wave) -> Ordering {
+        other.distance.partial_cmp(&self.distance).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The distance from every tile of a grid to the nearest obstacle, under a
+/// given metric. Tiles with no obstacle anywhere are infinitely far.
+///
+/// Building the field is exact for every metric. Updates spread outwards
+/// from the tile that changed, passing each tile's nearest obstacle on to
+/// its neighbors, and only visit the tiles whose nearest obstacle changes
+/// (although removing an obstacle first scans the whole field for them).
+/// They are exact for the Chebyshev and chamfer metrics; with the Euclidean
+/// metric, a tile may very rarely be left a fraction of a tile farther than
+/// its nearest obstacle, until the next `recompute`.
+#[derive(Clone, Debug)]
+pub struct DistanceField {
+    height: usize,
+    width: usize,
+    metric: Metric,
+    obstacles: Vec<bool>,
+    distances: Vec<Distance>,
+    nearest: Vec<Option<usize>>,
+}
+
+impl DistanceField {
+    /// Builds an empty field, with no obstacles.
+    pub fn new(height: usize, width: usize, metric: Metric) -> DistanceField {
+        DistanceField {
+            height: height,
+            width: width,
+            metric: metric,
+            obstacles: vec![false; height * width],
+            distances: vec![Distance::INFINITY; height * width],
+            nearest: vec![None; height * width],
+        }
+    }
+
+    /// Builds the field for `grid`, treating the tiles that satisfy
+    /// `obstacle` as obstacles.
+    pub fn from_grid<F>(grid: &Grid,
+                        metric: Metric,
+                        obstacle: F)
+                        -> DistanceField
+        where F: Fn(&Tile) -> bool
+    {
+        let mut field = DistanceField::new(grid.height(), grid.width(), metric);
+        for (flag, tile) in field.obstacles
+                .iter_mut()
+                .zip(grid.iter().flat_map(|row| row.iter())) {
+            *flag = obstacle(tile);
+        }
+        field.recompute();
+        field
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn index_of(&self, point: &Point) -> Option<usize> {
+        if point.y() < self.height && point.x() < self.width {
+            Some(point.y() * self.width + point.x())
+        } else {
+            None
+        }
+    }
+
+    fn point_of(&self, index: usize) -> Point {
+        Point::new(index / self.width, index % self.width)
+    }
+
+    /// Returns the distance from `point` to the nearest obstacle, if the
+    /// point lies within the field.
+    pub fn get(&self, point: &Point) -> Option<Distance> {
+        self.index_of(point).map(|index| self.distances[index])
+    }
+
+    /// Returns the distance from every tile to the nearest obstacle, in
+    /// row-major order.
+    pub fn distances(&self) -> &[Distance] {
+        &self.distances
+    }
+
+    /// Returns the obstacle nearest to `point`, if there is any.
+    pub fn nearest(&self, point: &Point) -> Option<Point> {
+        self.index_of(point)
+            .and_then(|index| self.nearest[index])
+            .map(|index| self.point_of(index))
+    }
+
+    pub fn is_obstacle(&self, point: &Point) -> bool {
+        self.index_of(point).is_some_and(|index| self.obstacles[index])
+    }
+
+    /// Removes every obstacle.
+    pub fn clear(&mut self) {
+        for index in 0..self.obstacles.len() {
+            self.obstacles[index] = false;
+            self.distances[index] = Distance::INFINITY;
+            self.nearest[index] = None;
+        }
+    }
+
+    /// Makes `point` an obstacle, or stops it from being one, updating the
+    /// distances of the tiles around it.
+    pub fn set_obstacle(&mut self, point: &Point, obstacle: bool) {
+        let index = match self.index_of(point) {
+            Some(index) if self.obstacles[index] != obstacle => index,
+            _ => return,
+        };
+        self.obstacles[index] = obstacle;
+
+        let mut waves = BinaryHeap::new();
+        if obstacle {
+            self.distances[index] = 0.0;
+            self.nearest[index] = Some(index);
+            waves.push(Wave {
+                           distance: 0.0,
+                           index: index,
+                       });
+        } else {
+            // Forget every distance measured to the removed obstacle, and
+            // take them up again from the tiles around them.
+            let orphans = (0..self.nearest.len())
+                .filter(|&other| self.nearest[other] == Some(index))
+                .collect::<Vec<_>>();
+            for &orphan in &orphans {
+                self.distances[orphan] = Distance::INFINITY;
+                self.nearest[orphan] = None;
+            }
+            for &orphan in &orphans {
+                for neighbor in self.neighbors(orphan) {
+                    if let Some(nearest) = self.nearest[neighbor] {
+                        self.offer(orphan, nearest, &mut waves);
+                    }
+                }
+            }
+        }
+        self.spread(waves);
+    }
+
+    /// Recomputes every distance from scratch, exactly.
+    pub fn recompute(&mut self) {
+        for index in 0..self.obstacles.len() {
+            self.distances[index] = Distance::INFINITY;
+            self.nearest[index] = None;
+        }
+
+        match self.metric {
+            Metric::Chebyshev | Metric::Chamfer => {
+                let mut waves = BinaryHeap::new();
+                for index in 0..self.obstacles.len() {
+                    if self.obstacles[index] {
+                        self.offer(index, index, &mut waves);
+                    }
+                }
+                self.spread(waves);
+            }
+            Metric::Euclidean => self.recompute_euclidean(),
+        }
+    }
+
+    /// Computes exact Euclidean distances with the separable algorithm of
+    /// Felzenszwalb and Huttenlocher: first the nearest obstacle within each
+    /// column, then, along each row, the lower envelope of the parabolas
+    /// centered on those.
+    fn recompute_euclidean(&mut self) {
+        let (height, width) = (self.height, self.width);
+
+        let mut column_nearest = vec![None; height * width];
+        for x in 0..width {
+            let mut above = None;
+            for y in 0..height {
+                if self.obstacles[y * width + x] {
+                    above = Some(y);
+                }
+                column_nearest[y * width + x] = above;
+            }
+            let mut below = None;
+            for y in (0..height).rev() {
+                if self.obstacles[y * width + x] {
+                    below = Some(y);
+                }
+                let nearest = &mut column_nearest[y * width + x];
+                *nearest = match (*nearest, below) {
+                    (Some(above), Some(below)) if below - y < y - above => {
+                        Some(below)
+                    }
+                    (None, below) => below,
+                    (above, _) => above,
+                };
+            }
+        }
+
+        let square = |a: usize, b: usize| (a.abs_diff(b) as Distance).powi(2);
+        let mut vertices: Vec<usize> = Vec::with_capacity(width);
+        let mut starts: Vec<Distance> = Vec::with_capacity(width);
+        for y in 0..height {
+            let row = &column_nearest[y * width..(y + 1) * width];
+            let height_at = |x: usize| row[x].map(|ny| square(ny, y));
+
+            vertices.clear();
+            starts.clear();
+            for x in 0..width {
+                let fx = match height_at(x) {
+                    Some(fx) => fx + (x * x) as Distance,
+                    None => continue,
+                };
+                while let Some(&last) = vertices.last() {
+                    let fl = height_at(last).unwrap() +
+                             (last * last) as Distance;
+                    let start = (fx - fl) / (2 * (x - last)) as Distance;
+                    if start <= *starts.last().unwrap() {
+                        vertices.pop();
+                        starts.pop();
+                    } else {
+                        starts.push(start);
+                        break;
+                    }
+                }
+                if vertices.is_empty() {
+                    starts.push(Distance::NEG_INFINITY);
+                }
+                vertices.push(x);
+            }
+
+            let mut k = 0;
+            for x in 0..width {
+                if vertices.is_empty() {
+                    break;
+                }
+                while k + 1 < vertices.len() && starts[k + 1] < x as Distance {
+                    k += 1;
+                }
+                let nx = vertices[k];
+                let nearest = row[nx].unwrap() * width + nx;
+                let index = y * width + x;
+                self.nearest[index] = Some(nearest);
+                self.distances[index] =
+                    self.metric.distance(&self.point_of(index),
+                                         &self.point_of(nearest));
+            }
+        }
+    }
+
+    fn neighbors(&self, index: usize) -> Vec<usize> {
+        let point = self.point_of(index);
+        let rows = point.y().saturating_sub(1)..
+                   (point.y() + 2).min(self.height);
+        let mut neighbors = Vec::with_capacity(8);
+        for y in rows {
+            let columns = point.x().saturating_sub(1)..
+                          (point.x() + 2).min(self.width);
+            for x in columns {
+                if (y, x) != (point.y(), point.x()) {
+                    neighbors.push(y * self.width + x);
+                }
+            }
+        }
+        neighbors
+    }
+
+    /// Makes `nearest` the nearest obstacle to the tile at `index`, if it is
+    /// nearer than the current one, and queues the tile to pass it on.
+    fn offer(&mut self,
+             index: usize,
+             nearest: usize,
+             waves: &mut BinaryHeap<Wave>) {
+        let distance = self.metric
+            .distance(&self.point_of(index), &self.point_of(nearest));
+        if distance < self.distances[index] {
+            self.distances[index] = distance;
+            self.nearest[index] = Some(nearest);
+            waves.push(Wave {
+                           distance: distance,
+                           index: index,
+                       });
+        }
+    }
+
+    /// Passes the nearest obstacle of every queued tile on to its
+    /// neighbors, for as long as that brings them nearer to an obstacle.
+    fn spread(&mut self, mut waves: BinaryHeap<Wave>) {
+        while let Some(wave) = waves.pop() {
+            if wave.distance > self.distances[wave.index] {
+                continue;
+            }
+            let nearest = match self.nearest[wave.index] {
+                Some(nearest) => nearest,
+                None => continue,
+            };
+            for neighbor in self.neighbors(wave.index) {
+                self.offer(neighbor, nearest, &mut waves);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, StdRng};
+
+    const METRICS: [Metric; 3] =
+        [Metric::Chebyshev, Metric::Chamfer, Metric::Euclidean];
+
+    /// Measures every distance by brute force.
+    fn brute_force(field: &DistanceField) -> Vec<Distance> {
+        let obstacles = (0..field.obstacles.len())
+            .filter(|&index| field.obstacles[index])
+            .map(|index| field.point_of(index))
+            .collect::<Vec<_>>();
+        (0..field.obstacles.len())
+            .map(|index| {
+                let point = field.point_of(index);
+                obstacles.iter()
+                    .map(|obstacle| field.metric.distance(&point, obstacle))
+                    .fold(Distance::INFINITY, Distance::min)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transform_distances() {
+        for &metric in &METRICS {
+            let mut field = DistanceField::new(3, 5, metric);
+            assert_eq!(field.get(&Point::new(1, 1)), Some(Distance::INFINITY));
+
+            field.set_obstacle(&Point::new(0, 0), true);
+            field.set_obstacle(&Point::new(2, 4), true);
+            assert_eq!(field.get(&Point::new(0, 0)), Some(0.0));
+            assert_eq!(field.get(&Point::new(0, 4)), Some(2.0));
+            assert_eq!(field.nearest(&Point::new(1, 1)),
+                       Some(Point::new(0, 0)));
+            assert_eq!(field.get(&Point::new(3, 0)), None);
+            assert_eq!(field.distances, brute_force(&field));
+
+            field.set_obstacle(&Point::new(0, 0), false);
+            assert_eq!(field.get(&Point::new(0, 0)),
+                       Some(metric.distance(&Point::new(0, 0),
+                                            &Point::new(2, 4))));
+            assert_eq!(field.distances, brute_force(&field));
+        }
+    }
+
+    #[test]
+    fn update_incrementally() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[5][..]);
+        for &metric in &METRICS {
+            let mut field = DistanceField::new(20, 30, metric);
+            for _ in 0..200 {
+                let point = Point::new(rng.gen_range(0, 20),
+                                       rng.gen_range(0, 30));
+                let free = rng.gen_weighted_bool(3);
+                field.set_obstacle(&point, !free);
+
+                let incremental = field.distances.clone();
+                let exact = brute_force(&field);
+                let error = incremental.iter()
+                    .zip(&exact)
+                    .map(|(a, b)| if a == b { 0.0 } else { a - b })
+                    .fold(0.0, Distance::max);
+                if metric == Metric::Euclidean {
+                    assert!(error < 0.5, "error {}", error);
+                } else {
+                    assert_eq!(error, 0.0);
+                }
+
+                field.recompute();
+                assert_eq!(field.distances, exact);
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Naturalistic maps generated from gradient noise, for benchmarking on
+//! maps of any size.
+
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+use experiment::Region;
+use grid::{Grid, GridBuilder, Terrain, TileChange};
+
+/// How much the thresholds are loosened every time the largest passable
+/// region of a generated map turns out to be too small.
+const LOOSENING: f64 = 0.05;
+
+/// Two-dimensional Perlin noise, with values roughly within [-1, 1].
+#[derive(Debug)]
+struct Perlin {
+    permutation: Vec<usize>,
+}
+
+impl Perlin {
+    fn new<R>(rng: &mut R) -> Perlin
+        where R: Rng
+    {
+        let mut permutation: Vec<usize> = (0..256).collect();
+        rng.shuffle(&mut permutation);
+        let repeated = permutation.clone();
+        permutation.extend(repeated);
+        Perlin { permutation: permutation }
+    }
+
+    /// The dot product of the gradient chosen by `hash`, out of the eight
+    /// octile directions, and the offset (`dy`, `dx`) from its corner.
+    fn gradient(hash: usize, dy: f64, dx: f64) -> f64 {
+        match hash & 7 {
+            0 => dx,
+            1 => -dx,
+            2 => dy,
+            3 => -dy,
+            4 => dx + dy,
+            5 => -dx + dy,
+            6 => dx - dy,
+            _ => -dx - dy,
+        }
+    }
+
+    fn noise(&self, y: f64, x: f64) -> f64 {
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+
+        let (y0, x0) = (y.floor(), x.floor());
+        let (dy, dx) = (y - y0, x - x0);
+        let yi = (y0 as i64 & 255) as usize;
+        let xi = (x0 as i64 & 255) as usize;
+        let p = &self.permutation;
+        let hash = |j: usize, i: usize| p[p[xi + i] + yi + j];
+
+        let (v, u) = (fade(dy), fade(dx));
+        let top = lerp(u,
+                       Perlin::gradient(hash(0, 0), dy, dx),
+                       Perlin::gradient(hash(0, 1), dy, dx - 1.0));
+        let bottom = lerp(u,
+                          Perlin::gradient(hash(1, 0), dy - 1.0, dx),
+                          Perlin::gradient(hash(1, 1), dy - 1.0, dx - 1.0));
+        lerp(v, top, bottom).clamp(-1.0, 1.0)
+    }
+
+    /// Sums `octaves` layers of noise, each of twice the frequency and half
+    /// the amplitude of the one before, normalized back into [-1, 1].
+    fn fractal(&self, y: f64, x: f64, octaves: usize) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut range = 0.0;
+        for _ in 0..octaves.max(1) {
+            total += amplitude * self.noise(y * frequency, x * frequency);
+            range += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+        total / range
+    }
+}
+
+/// Generates maps out of two noise fields: an elevation, whose lowest areas
+/// are water and then swamp, and a vegetation, whose densest areas (out of
+/// the water and swamp) are trees. The rest is ground.
+///
+/// Only ground is passable, so the thresholds are loosened, as needed, until
+/// the largest region of ground covers at least `min_region` of the map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseGenerator {
+    pub seed: usize,
+    /// The size, in tiles, of the coarsest features of the map.
+    pub scale: f64,
+    /// The number of layers of ever finer noise added to the coarsest one.
+    pub octaves: usize,
+    /// Tiles of elevation below this, within [-1, 1], are water.
+    pub water: f64,
+    /// Tiles of elevation below this, and not water, are swamp.
+    pub swamp: f64,
+    /// Tiles of vegetation above this, within [-1, 1], are trees.
+    pub trees: f64,
+    /// The smallest fraction of the map that the largest region of ground
+    /// (where tiles are connected to their eight neighbors) must cover.
+    pub min_region: f64,
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> NoiseGenerator {
+        NoiseGenerator {
+            seed: 0,
+            scale: 32.0,
+            octaves: 4,
+            water: -0.2,
+            swamp: -0.1,
+            trees: 0.1,
+            min_region: 0.5,
+        }
+    }
+}
+
+impl NoiseGenerator {
+    /// Generates a map of `height` rows and `width` columns. The same
+    /// generator always generates the same map.
+    pub fn generate(&self, height: usize, width: usize) -> Grid {
+        let mut rng: StdRng = SeedableRng::from_seed(&[self.seed][..]);
+        let elevation = Perlin::new(&mut rng);
+        let vegetation = Perlin::new(&mut rng);
+        let scale = self.scale.max(1.0);
+        let mut fields = Vec::with_capacity(height * width);
+        for y in 0..height {
+            for x in 0..width {
+                let (y, x) = (y as f64 / scale, x as f64 / scale);
+                fields.push((elevation.fractal(y, x, self.octaves),
+                             vegetation.fractal(y, x, self.octaves)));
+            }
+        }
+
+        let min_region = self.min_region.min(1.0) * (height * width) as f64;
+        let mut loosening = 0.0;
+        loop {
+            let terrain = fields.iter()
+                .map(|&(elevation, vegetation)| {
+                    if elevation < self.water - loosening {
+                        Terrain::Water
+                    } else if elevation < self.swamp - loosening {
+                        Terrain::Swamp
+                    } else if vegetation > self.trees + loosening {
+                        Terrain::Trees
+                    } else {
+                        Terrain::Ground
+                    }
+                })
+                .collect::<Vec<_>>();
+            // Once every threshold is out of the range of the noise, the
+            // whole map is ground, and a single region.
+            let exhausted = self.water - loosening < -1.0 &&
+                            self.swamp - loosening < -1.0 &&
+                            self.trees + loosening > 1.0;
+            let grid = GridBuilder::new(height, width)
+                .terrain_with(|point| terrain[point.y() * width + point.x()])
+                .build()
+                .expect("Generated maps are within their bounds.");
+            if exhausted || largest_region(&grid) as f64 >= min_region {
+                return grid;
+            }
+            loosening += LOOSENING;
+        }
+    }
+}
+
+/// Labels every passable tile of `grid` with the region of passable tiles it
+/// belongs to, where tiles are connected to their eight neighbors, and
+/// returns the labels (`None` for impassable tiles) and the size of each
+/// region.
+fn label_regions(grid: &Grid) -> (Vec<Option<usize>>, Vec<usize>) {
+    let mut labels = vec![None; grid.height() * grid.width()];
+    let mut sizes = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..labels.len() {
+        if labels[start].is_some() || !grid.tile(start).passable() {
+            continue;
+        }
+        let label = sizes.len();
+        labels[start] = Some(label);
+        stack.push(start);
+        let mut size = 0;
+        while let Some(index) = stack.pop() {
+            size += 1;
+            for (_, neighbor) in grid.neighbors(&grid.point_of(index)) {
+                if labels[neighbor].is_none() && grid.tile(neighbor).passable() {
+                    labels[neighbor] = Some(label);
+                    stack.push(neighbor);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    (labels, sizes)
+}
+
+/// Returns the number of tiles in the largest region of passable tiles of
+/// `grid`, where tiles are connected to their eight neighbors.
+pub fn largest_region(grid: &Grid) -> usize {
+    label_regions(grid).1.into_iter().max().unwrap_or(0)
+}
+
+/// Carves corridors of ground through `grid` until every passable tile
+/// within `regions` can be reached from every other, so that no trial
+/// between them is unsolvable. Every region without passable tiles first
+/// has its first tile turned to ground. Corridors are found by a search
+/// that carves as few tiles as possible, from the largest region of
+/// passable tiles to the nearest one left out.
+///
+/// Returns the tiles changed, as a patch, or an error if some region does
+/// not overlap the grid.
+pub fn ensure_connected(grid: &mut Grid,
+                        regions: &[Region])
+                        -> Result<Vec<TileChange>, String> {
+    let (height, width) = (grid.height(), grid.width());
+    let mut changes = Vec::new();
+    let mut carve = |grid: &mut Grid, index: usize| {
+        let change = TileChange {
+            point: grid.point_of(index),
+            from: grid.tile(index).terrain(),
+            to: Terrain::Ground,
+        };
+        grid.apply(&[change])
+            .expect("Carved tiles are within the grid.");
+        changes.push(change);
+    };
+
+    let mut within = vec![false; height * width];
+    for region in regions {
+        let tiles = (0..height * width)
+            .filter(|&index| region.contains(&grid.point_of(index), height, width))
+            .collect::<Vec<_>>();
+        if tiles.is_empty() {
+            return Err(format!("Region {:?} does not fit in a {}x{} map.",
+                               region,
+                               height,
+                               width));
+        }
+        if !tiles.iter().any(|&index| grid.tile(index).passable()) {
+            carve(grid, tiles[0]);
+        }
+        for index in tiles {
+            within[index] = true;
+        }
+    }
+
+    loop {
+        let (labels, sizes) = label_regions(grid);
+        let mut wanted = vec![false; sizes.len()];
+        for (index, label) in labels.iter().enumerate() {
+            if let (true, &Some(label)) = (within[index], label) {
+                wanted[label] = true;
+            }
+        }
+        let main = match (0..sizes.len())
+                  .filter(|&label| wanted[label])
+                  .max_by_key(|&label| sizes[label]) {
+            Some(main) => main,
+            None => break,
+        };
+        if wanted.iter().filter(|&&wanted| wanted).count() == 1 {
+            break;
+        }
+
+        // Breadth-first search with zero-cost steps onto passable tiles and
+        // unit-cost steps onto the tiles to carve, from the main region.
+        let mut parents = vec![None; height * width];
+        let mut carved = vec![usize::MAX; height * width];
+        let mut open = VecDeque::new();
+        for (index, &label) in labels.iter().enumerate() {
+            if label == Some(main) {
+                carved[index] = 0;
+                open.push_back(index);
+            }
+        }
+        let mut reached = None;
+        while let Some(index) = open.pop_front() {
+            if let Some(label) = labels[index] {
+                if label != main && wanted[label] {
+                    reached = Some(index);
+                    break;
+                }
+            }
+            for (_, neighbor) in grid.neighbors(&grid.point_of(index)) {
+                let cost = if grid.tile(neighbor).passable() { 0 } else { 1 };
+                if carved[index] + cost < carved[neighbor] {
+                    carved[neighbor] = carved[index] + cost;
+                    parents[neighbor] = Some(index);
+                    if cost == 0 {
+                        open.push_front(neighbor);
+                    } else {
+                        open.push_back(neighbor);
+                    }
+                }
+            }
+        }
+
+        let mut index = reached.expect("Every tile can be carved into.");
+        while let Some(parent) = parents[index] {
+            if !grid.tile(index).passable() {
+                carve(grid, index);
+            }
+            index = parent;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::{Distance, Measure, Point, Tile};
+    use parser::grid_from_str;
+    use search::astar;
+
+    #[test]
+    fn generate_naturalistic_maps() {
+        let generator = NoiseGenerator {
+            seed: 3,
+            scale: 8.0,
+            ..NoiseGenerator::default()
+        };
+        let grid = generator.generate(40, 60);
+        assert_eq!((grid.height(), grid.width()), (40, 60));
+        assert_eq!(grid.to_string(), generator.generate(40, 60).to_string());
+        let other = NoiseGenerator { seed: 4, ..generator.clone() };
+        assert!(grid.to_string() != other.generate(40, 60).to_string());
+
+        let map = grid.to_string();
+        for symbol in &['.', 'T', 'S', 'W'] {
+            assert!(map.contains(*symbol), "No '{}' in\n{}", symbol, map);
+        }
+        assert!(largest_region(&grid) >= 40 * 60 / 2);
+    }
+
+    #[test]
+    fn loosen_thresholds_for_a_large_region() {
+        let generator = NoiseGenerator {
+            seed: 1,
+            scale: 4.0,
+            water: 0.5,
+            swamp: 0.6,
+            trees: -0.5,
+            min_region: 0.9,
+            ..NoiseGenerator::default()
+        };
+        let grid = generator.generate(30, 30);
+        assert!(largest_region(&grid) >= 810);
+
+        let mut empty = generator.generate(0, 0);
+        assert!(ensure_connected(&mut empty, &[Region::Anywhere]).is_err());
+        assert_eq!((empty.height(), empty.width()), (0, 0));
+    }
+
+    #[test]
+    fn carve_corridors_between_regions() {
+        let mut grid = grid_from_str("type octile
+height 5
+width 7
+map
+..T.TTT
+..T.TTT
+TTT.TTT
+TTTTTT.
+@@@@@@.");
+        let left = Region::Rectangle {
+            top: 0,
+            left: 0,
+            bottom: 1,
+            right: 1,
+        };
+        let right = Region::Rectangle {
+            top: 3,
+            left: 6,
+            bottom: 4,
+            right: 6,
+        };
+        let changes = ensure_connected(&mut grid, &[left, right]).unwrap();
+        // One tile joins the left corner to the middle column, and two more
+        // join the middle column to the right one.
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().all(|change| change.to == Terrain::Ground));
+        let path = astar(&mut grid,
+                         &Point::new(0, 0),
+                         &Point::new(4, 6),
+                         Distance::octile_heuristic,
+                         Tile::passable);
+        assert!(path.is_some());
+        assert!(ensure_connected(&mut grid, &[left, right])
+                    .unwrap()
+                    .is_empty());
+
+        let mut walled = grid_from_str("type octile
+height 3
+width 3
+map
+TTT
+TTT
+TTT");
+        let changes = ensure_connected(&mut walled, &[Region::Edges]).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(largest_region(&walled), 1);
+
+        let generator = NoiseGenerator {
+            seed: 2,
+            scale: 4.0,
+            min_region: 0.0,
+            ..NoiseGenerator::default()
+        };
+        let mut grid = generator.generate(30, 30);
+        ensure_connected(&mut grid, &[Region::Anywhere]).unwrap();
+        let (_, sizes) = label_regions(&grid);
+        assert_eq!(sizes.len(), 1);
+    }
+}
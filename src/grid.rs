@@ -1,15 +1,21 @@
 use std::cmp::{max, min};
 use std::f64::consts::SQRT_2;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::mem::take;
 use std::ops::{Index, IndexMut};
-use std::slice::Iter;
+use std::path::{Path, PathBuf};
+use std::slice::Chunks;
 
+use connectivity::Connectivity;
+use cost::{Composite, CostModel, Step, WAIT_COST};
+use field::{DistanceField, Metric};
+use overlay::{check_layer, Layer, Overlay, COST_LAYER};
 use search::astar;
+#[cfg(feature = "json")]
+use serde::Serializer;
 
-pub const COST: [Distance; 8] = [SQRT_2, 1.0, SQRT_2, 1.0, 1.0, SQRT_2, 1.0,
-                                 SQRT_2];
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Point {
     pub y: usize,
     pub x: usize,
@@ -28,25 +34,225 @@ impl Point {
         self.y
     }
 
+    /// Returns the eight points around this one, starting at the top left
+    /// and going row by row. Points that would lie at negative coordinates,
+    /// or beyond the largest `usize`, are `None`; others may still lie outside
+    /// of a grid, which `neighbors_within` accounts for.
     pub fn neighbors(&self) -> [Option<Point>; 8] {
+        let up = self.y.checked_sub(1);
+        let down = self.y.checked_add(1);
+        let left = self.x.checked_sub(1);
+        let right = self.x.checked_add(1);
+        let rows = [up, Some(self.y), down];
+        let columns = [left, Some(self.x), right];
+
         let mut ns = [None; 8];
-        if self.y > 0 {
-            if self.x > 0 {
-                ns[0] = Some(Point::new(self.y - 1, self.x - 1));
+        let mut direction = 0;
+        for (i, row) in rows.iter().enumerate() {
+            for (j, column) in columns.iter().enumerate() {
+                if i == 1 && j == 1 {
+                    continue;
+                }
+                ns[direction] = match (*row, *column) {
+                    (Some(y), Some(x)) => Some(Point::new(y, x)),
+                    _ => None,
+                };
+                direction += 1;
             }
-            ns[1] = Some(Point::new(self.y - 1, self.x));
-            ns[2] = Some(Point::new(self.y - 1, self.x + 1));
         }
-        if self.x > 0 {
-            ns[3] = Some(Point::new(self.y, self.x - 1));
-            ns[5] = Some(Point::new(self.y + 1, self.x - 1));
-        }
-        ns[4] = Some(Point::new(self.y, self.x + 1));
-        ns[6] = Some(Point::new(self.y + 1, self.x));
-        ns[7] = Some(Point::new(self.y + 1, self.x + 1));
 
         ns
     }
+
+    /// Like `neighbors`, but leaves out the points that lie outside of a grid
+    /// of `height` rows and `width` columns.
+    pub fn neighbors_within(&self,
+                            height: usize,
+                            width: usize)
+                            -> [Option<Point>; 8] {
+        let mut ns = self.neighbors();
+        for neighbor in &mut ns {
+            *neighbor = neighbor.filter(|point| {
+                point.y < height && point.x < width
+            });
+        }
+        ns
+    }
+}
+
+/// The direction of a move between neighboring tiles. Directions are
+/// numbered by their position in `Point::neighbors`, from the top left and
+/// row by row.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Direction {
+    NorthWest,
+    North,
+    NorthEast,
+    West,
+    East,
+    SouthWest,
+    South,
+    SouthEast,
+}
+
+impl Direction {
+    /// Every direction, in the order of `Point::neighbors`.
+    pub const ALL: [Direction; 8] = [Direction::NorthWest,
+                                     Direction::North,
+                                     Direction::NorthEast,
+                                     Direction::West,
+                                     Direction::East,
+                                     Direction::SouthWest,
+                                     Direction::South,
+                                     Direction::SouthEast];
+
+    /// The name of the direction in lowercase, as `northwest`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Direction::NorthWest => "northwest",
+            Direction::North => "north",
+            Direction::NorthEast => "northeast",
+            Direction::West => "west",
+            Direction::East => "east",
+            Direction::SouthWest => "southwest",
+            Direction::South => "south",
+            Direction::SouthEast => "southeast",
+        }
+    }
+
+    /// Returns the position of the direction in `Point::neighbors`.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// Returns the direction at `index` in `Point::neighbors`, if any.
+    pub fn from_index(index: usize) -> Option<Direction> {
+        Direction::ALL.get(index).cloned()
+    }
+
+    /// Returns the direction of the move from `from` to `to`, if they are
+    /// neighbors.
+    pub fn between(from: &Point, to: &Point) -> Option<Direction> {
+        from.neighbors()
+            .iter()
+            .position(|neighbor| *neighbor == Some(*to))
+            .map(|index| Direction::ALL[index])
+    }
+
+    /// Returns the point one move away from `point` in this direction, if
+    /// its coordinates are not negative.
+    pub fn from(&self, point: &Point) -> Option<Point> {
+        point.neighbors()[self.index()]
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        self.corners().is_some()
+    }
+
+    /// Returns the two straight directions a diagonal move combines, whose
+    /// tiles are the corners the move cuts, or `None` for straight moves.
+    pub fn corners(&self) -> Option<(Direction, Direction)> {
+        match *self {
+            Direction::NorthWest => Some((Direction::North, Direction::West)),
+            Direction::NorthEast => Some((Direction::North, Direction::East)),
+            Direction::SouthWest => Some((Direction::South, Direction::West)),
+            Direction::SouthEast => Some((Direction::South, Direction::East)),
+            _ => None,
+        }
+    }
+}
+
+/// Which moves between neighboring tiles agents that plan with
+/// `Grid::successors` may make.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Movement {
+    /// Moves in all eight directions, including diagonal moves between two
+    /// blocked tiles.
+    #[default]
+    Octile,
+    /// Moves in all eight directions, but diagonal moves only where both of
+    /// the tiles they cut the corners of may be moved onto.
+    NoCornerCutting,
+    /// Moves in the four straight directions only.
+    Cardinal,
+}
+
+impl Movement {
+    /// Whether diagonal moves connect tiles that straight moves alone do
+    /// not. Diagonal moves that never cut corners do not.
+    pub fn connects_diagonally(&self) -> bool {
+        *self == Movement::Octile
+    }
+}
+
+/// The movement model a map declares in the `type` line of its header.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum MapType {
+    /// Moves in all eight directions.
+    #[default]
+    Octile,
+    /// Moves in the four straight directions only.
+    Tile,
+}
+
+impl MapType {
+    /// The name of the type in map headers.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MapType::Octile => "octile",
+            MapType::Tile => "tile",
+        }
+    }
+
+    /// The movement rules of maps of this type.
+    pub fn movement(&self) -> Movement {
+        match *self {
+            MapType::Octile => Movement::Octile,
+            MapType::Tile => Movement::Cardinal,
+        }
+    }
+}
+
+/// What identifies a map, as recorded next to results so that they still
+/// say which map they come from after the map file moves or changes.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct GridMeta {
+    /// The name of the map: the one it was given, or else the stem of the
+    /// file it was read from.
+    pub name: Option<String>,
+    /// The file the map was read from, as it was given.
+    pub path: Option<PathBuf>,
+    pub map_type: MapType,
+    pub height: usize,
+    pub width: usize,
+    /// The `Grid::content_hash` of the map, written in hexadecimal.
+    #[cfg_attr(feature = "json", serde(serialize_with = "serialize_hash"))]
+    pub hash: u64,
+}
+
+impl Display for GridMeta {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f,
+               "{} ({}, {}x{})",
+               self.name.as_deref().unwrap_or("unnamed"),
+               self.map_type.name(),
+               self.height,
+               self.width)?;
+        match self.path {
+            Some(ref path) => write!(f, " from {}", path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn serialize_hash<S>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    serializer.serialize_str(&format!("{:016x}", hash))
 }
 
 impl Display for Point {
@@ -72,16 +278,8 @@ impl Measure for Distance {
     }
 
     fn octile_heuristic(from: &Point, to: &Point) -> Distance {
-        let dy = if to.y > from.y {
-            to.y - from.y
-        } else {
-            from.y - to.y
-        };
-        let dx = if to.x > from.x {
-            to.x - from.x
-        } else {
-            from.x - to.x
-        };
+        let dy = to.y.abs_diff(from.y);
+        let dx = to.x.abs_diff(from.x);
 
         let cartesian = max(dy, dx) as Distance;
         let diagonal = min(dy, dx) as Distance;
@@ -90,7 +288,7 @@ impl Measure for Distance {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Terrain {
     Ground,
     OutOfBounds,
@@ -103,17 +301,34 @@ impl Terrain {
     fn passable(&self) -> bool {
         *self == Terrain::Ground
     }
+
+    /// Returns the symbol representing the terrain in maps.
+    pub fn symbol(&self) -> char {
+        match *self {
+            Terrain::Ground => '.',
+            Terrain::OutOfBounds => '@',
+            Terrain::Trees => 'T',
+            Terrain::Swamp => 'S',
+            Terrain::Water => 'W',
+        }
+    }
+
+    /// Returns the numeric code of the terrain, as stored in
+    /// `Grid::terrain_matrix`.
+    pub fn code(&self) -> u8 {
+        match *self {
+            Terrain::Ground => 0,
+            Terrain::OutOfBounds => 1,
+            Terrain::Trees => 2,
+            Terrain::Swamp => 3,
+            Terrain::Water => 4,
+        }
+    }
 }
 
 impl Display for Terrain {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        match *self {
-            Terrain::Ground => write!(f, "."),
-            Terrain::OutOfBounds => write!(f, "@"),
-            Terrain::Trees => write!(f, "T"),
-            Terrain::Swamp => write!(f, "S"),
-            Terrain::Water => write!(f, "W"),
-        }
+        write!(f, "{}", self.symbol())
     }
 }
 
@@ -122,6 +337,23 @@ pub enum Belief {
     Unknown,
     Passable,
     Impassable,
+    /// The exact terrain was observed, rather than just whether it is
+    /// passable.
+    Known(Terrain),
+}
+
+impl Belief {
+    /// Returns the numeric code of the belief, as stored in
+    /// `Grid::belief_matrix`. Known terrain is coded as 3 plus the code of
+    /// the terrain.
+    pub fn code(&self) -> u8 {
+        match *self {
+            Belief::Unknown => 0,
+            Belief::Passable => 1,
+            Belief::Impassable => 2,
+            Belief::Known(terrain) => 3 + terrain.code(),
+        }
+    }
 }
 
 impl Display for Belief {
@@ -130,6 +362,7 @@ impl Display for Belief {
             Belief::Unknown => write!(f, "?"),
             Belief::Passable => write!(f, "."),
             Belief::Impassable => write!(f, "X"),
+            Belief::Known(ref terrain) => write!(f, "{}", terrain),
         }
     }
 }
@@ -138,6 +371,12 @@ impl Display for Belief {
 pub struct Tile {
     terrain: Terrain,
     belief: Belief,
+    /// Whether the tile is believed to be too close to an obstacle for the
+    /// agent to fit, given the grid's clearance.
+    inflated: bool,
+    /// Whether the tile is believed to be next to an obstacle, which may make
+    /// moving onto it more expensive under the grid's cost model.
+    near_obstacle: bool,
     parent: Option<Point>,
     g: Distance,
     h: Distance,
@@ -149,6 +388,8 @@ impl Tile {
         Tile {
             terrain: terrain,
             belief: Belief::Unknown,
+            inflated: false,
+            near_obstacle: false,
             parent: None,
             g: 0.0,
             h: 0.0,
@@ -156,13 +397,30 @@ impl Tile {
         }
     }
 
-    pub fn look(&mut self) {
+    /// Observes the tile's terrain, returning whether this changed the
+    /// belief about it.
+    pub fn look(&mut self) -> bool {
         if self.belief == Belief::Unknown {
             if self.terrain.passable() {
                 self.belief = Belief::Passable;
             } else {
                 self.belief = Belief::Impassable;
             }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Observes the tile's exact terrain, returning whether this changed the
+    /// belief about it.
+    pub fn look_terrain(&mut self) -> bool {
+        let known = Belief::Known(self.terrain);
+        if self.belief != known {
+            self.belief = known;
+            true
+        } else {
+            false
         }
     }
 
@@ -170,12 +428,28 @@ impl Tile {
         self.terrain.passable()
     }
 
+    pub fn terrain(&self) -> Terrain {
+        self.terrain
+    }
+
     pub fn belief(&self) -> &Belief {
         &self.belief
     }
 
+    /// Whether the agent believes it may occupy the tile, assuming that
+    /// unknown tiles are passable. Tiles within the grid's clearance of a
+    /// tile believed to be impassable are not free.
     pub fn freespace(&self) -> bool {
-        self.belief != Belief::Impassable
+        !self.inflated && !self.obstacle()
+    }
+
+    /// Whether the tile is believed to be impassable.
+    fn obstacle(&self) -> bool {
+        match self.belief {
+            Belief::Impassable => true,
+            Belief::Known(ref terrain) => !terrain.passable(),
+            Belief::Unknown | Belief::Passable => false,
+        }
     }
 
     pub fn parent(&self) -> Option<Point> {
@@ -190,6 +464,10 @@ impl Tile {
         self.g
     }
 
+    pub fn h(&self) -> Distance {
+        self.h
+    }
+
     pub fn visited(&self, episode: usize) -> bool {
         self.visited == episode
     }
@@ -214,6 +492,8 @@ impl Tile {
 
     pub fn forget(&mut self) {
         self.belief = Belief::Unknown;
+        self.inflated = false;
+        self.near_obstacle = false;
     }
 }
 
@@ -223,26 +503,515 @@ impl Display for Tile {
     }
 }
 
+/// When a tile of a downsampled grid (see `Grid::downsample`) is blocked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coarsening {
+    /// Blocked if any tile of its block is impassable, so that paths over
+    /// the coarse grid are possible over the fine one.
+    Conservative,
+    /// Blocked only if every tile of its block is impassable, so that no
+    /// path over the fine grid is lost.
+    Optimistic,
+}
+
+impl Coarsening {
+    /// The terrain of a coarse tile covering tiles of the given terrains.
+    fn terrain<I>(&self, block: I) -> Terrain
+        where I: Iterator<Item = Terrain>
+    {
+        let mut blocked = None;
+        let mut open = false;
+        for terrain in block {
+            if terrain.passable() {
+                open = true;
+            } else if blocked.is_none() {
+                blocked = Some(terrain);
+            }
+        }
+        match (*self, blocked) {
+            (Coarsening::Conservative, Some(terrain)) => terrain,
+            (Coarsening::Optimistic, Some(terrain)) if !open => terrain,
+            _ => Terrain::Ground,
+        }
+    }
+}
+
+/// Bits set in `NeighborTable` masks, one per neighbor in the order used by
+/// `Point::neighbors`.
+const ALL_NEIGHBORS: u8 = 0b1111_1111;
+const TOP_NEIGHBORS: u8 = 0b0000_0111;
+const BOTTOM_NEIGHBORS: u8 = 0b1110_0000;
+const LEFT_NEIGHBORS: u8 = 0b0010_1001;
+const RIGHT_NEIGHBORS: u8 = 0b1001_0100;
+
+/// Flat-index offsets to each of the eight neighbors of a tile, and masks of
+/// the neighbors that exist for tiles in each row and column band (interior,
+/// first, last, or both for single-row or single-column grids).
+#[derive(Debug)]
+struct NeighborTable {
+    offsets: [isize; 8],
+    row_masks: [u8; 4],
+    column_masks: [u8; 4],
+}
+
+impl NeighborTable {
+    fn new(width: usize) -> NeighborTable {
+        let w = width as isize;
+        NeighborTable {
+            offsets: [-w - 1, -w, -w + 1, -1, 1, w - 1, w, w + 1],
+            row_masks: [ALL_NEIGHBORS,
+                        !TOP_NEIGHBORS,
+                        !BOTTOM_NEIGHBORS,
+                        !(TOP_NEIGHBORS | BOTTOM_NEIGHBORS)],
+            column_masks: [ALL_NEIGHBORS,
+                           !LEFT_NEIGHBORS,
+                           !RIGHT_NEIGHBORS,
+                           !(LEFT_NEIGHBORS | RIGHT_NEIGHBORS)],
+        }
+    }
+}
+
+fn band(value: usize, size: usize) -> usize {
+    let first = (value == 0) as usize;
+    let last = (value + 1 == size) as usize;
+    first | last << 1
+}
+
+/// Iterator over the in-bounds neighbors of a tile, yielding the neighbor's
+/// direction (its position in `Point::neighbors`) and its flat index.
+#[derive(Debug)]
+pub struct Neighbors {
+    offsets: [isize; 8],
+    index: usize,
+    mask: u8,
+}
+
+impl Iterator for Neighbors {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.mask == 0 {
+            return None;
+        }
+        let direction = self.mask.trailing_zeros() as usize;
+        self.mask &= self.mask - 1;
+        let neighbor = self.index as isize + self.offsets[direction];
+        Some((direction, neighbor as usize))
+    }
+}
+
+/// Scrambles `value` (using the SplitMix64 finalizer), so that the keys
+/// combined into the belief hash are spread over all bits.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// How far around itself the agent observes the grid, in moves: a radius of
+/// 1 covers the eight neighbors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensor {
+    /// Tiles up to this far away are seen to be passable or impassable.
+    pub radius: usize,
+    /// Tiles up to this far away, if set, have their exact terrain seen.
+    pub terrain_radius: Option<usize>,
+}
+
+impl Default for Sensor {
+    fn default() -> Sensor {
+        Sensor {
+            radius: 1,
+            terrain_radius: None,
+        }
+    }
+}
+
+/// A dense, row-major matrix of per-tile codes, suitable for handing over to
+/// numerical libraries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    height: usize,
+    width: usize,
+    data: Vec<u8>,
+}
+
+impl Matrix {
+    /// Builds a matrix out of `data`, given in row-major order.
+    pub fn new(height: usize, width: usize, data: Vec<u8>) -> Matrix {
+        assert_eq!(data.len(), height * width, "Matrix size mismatch.");
+        Matrix {
+            height: height,
+            width: width,
+            data: data,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get(&self, point: &Point) -> Option<u8> {
+        if point.y() < self.height && point.x() < self.width {
+            Some(self.data[point.y() * self.width + point.x()])
+        } else {
+            None
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// The error returned when accessing a point outside a grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfBounds {
+    pub point: Point,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Display for OutOfBounds {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f,
+               "{} is outside the {}x{} map.",
+               self.point,
+               self.height,
+               self.width)
+    }
+}
+
+/// A tile whose terrain differs between two versions of a map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileChange {
+    pub point: Point,
+    pub from: Terrain,
+    pub to: Terrain,
+}
+
+impl TileChange {
+    /// Returns the change that undoes this one.
+    pub fn reversed(&self) -> TileChange {
+        TileChange {
+            point: self.point,
+            from: self.to,
+            to: self.from,
+        }
+    }
+}
+
+impl Display for TileChange {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}: {} -> {}", self.point, self.from, self.to)
+    }
+}
+
+/// A node expanded by a search over the grid, with its estimated total cost
+/// `f`, the cost `g` of reaching it, and the heuristic estimate `h` from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Expansion {
+    pub point: Point,
+    pub f: Distance,
+    pub g: Distance,
+    pub h: Distance,
+    /// The episode of the search that expanded the node (see
+    /// `Grid::next_episode`).
+    pub episode: usize,
+}
+
 #[derive(Debug)]
 pub struct Grid {
-    tiles: Vec<Vec<Tile>>,
+    tiles: Vec<Tile>,
+    height: usize,
+    width: usize,
+    neighbor_table: NeighborTable,
     episode: usize,
+    belief_hash: u64,
+    sensor: Sensor,
+    clearance: usize,
+    cost_model: Box<dyn CostModel>,
+    /// How many moves away from every tile the nearest tile believed to be
+    /// impassable is. Only maintained while the clearance or the cost model
+    /// need it.
+    obstacle_field: Option<DistanceField>,
+    /// How many moves away from every tile the nearest observed tile is.
+    /// Only maintained while the cost model needs it.
+    observed_field: Option<DistanceField>,
+    /// The regions of free tiles, built when first needed and dropped when
+    /// they may have changed.
+    connectivity: Option<Connectivity>,
+    /// The nodes expanded by searches since the trace was last taken, if
+    /// searches are being traced.
+    search_trace: Option<Vec<Expansion>>,
+    /// The tiles whose beliefs changed since they were last taken, if
+    /// changes are being recorded.
+    belief_changes: Option<Vec<Point>>,
+    overlay: Overlay,
+    movement: Movement,
+    map_type: MapType,
+    name: Option<String>,
+    path: Option<PathBuf>,
 }
 
 impl Grid {
+    /// Creates a grid out of its rows of tiles, which must all have the same
+    /// length. `GridBuilder` checks that they do.
     pub fn new(tiles: Vec<Vec<Tile>>) -> Grid {
+        let height = tiles.len();
+        let width = tiles.first().map_or(0, |row| row.len());
         Grid {
-            tiles: tiles,
+            tiles: tiles.into_iter().flat_map(|row| row.into_iter()).collect(),
+            height: height,
+            width: width,
+            neighbor_table: NeighborTable::new(width),
             episode: 0,
+            belief_hash: 0,
+            sensor: Sensor::default(),
+            clearance: 0,
+            cost_model: Box::new(Composite::default()),
+            obstacle_field: None,
+            observed_field: None,
+            connectivity: None,
+            search_trace: None,
+            belief_changes: None,
+            overlay: Overlay::new(),
+            movement: Movement::default(),
+            map_type: MapType::default(),
+            name: None,
+            path: None,
         }
     }
 
+    /// Returns the flat index of `point`, if it lies within the grid.
+    pub fn index_of(&self, point: &Point) -> Option<usize> {
+        if point.y() < self.height && point.x() < self.width {
+            Some(point.y() * self.width + point.x())
+        } else {
+            None
+        }
+    }
+
+    pub fn point_of(&self, index: usize) -> Point {
+        Point::new(index / self.width, index % self.width)
+    }
+
     pub fn get(&self, point: &Point) -> Option<&Tile> {
-        self.tiles.get(point.y()).and_then(|row| row.get(point.x()))
+        self.index_of(point).map(|index| &self.tiles[index])
     }
 
     pub fn get_mut(&mut self, point: &Point) -> Option<&mut Tile> {
-        self.tiles.get_mut(point.y()).and_then(|row| row.get_mut(point.x()))
+        match self.index_of(point) {
+            Some(index) => Some(&mut self.tiles[index]),
+            None => None,
+        }
+    }
+
+    /// Like `get`, but describes where the point lies if it is outside the
+    /// grid.
+    pub fn try_get(&self, point: &Point) -> Result<&Tile, OutOfBounds> {
+        let error = self.out_of_bounds(point);
+        self.get(point).ok_or(error)
+    }
+
+    pub fn try_get_mut(&mut self,
+                       point: &Point)
+                       -> Result<&mut Tile, OutOfBounds> {
+        let error = self.out_of_bounds(point);
+        self.get_mut(point).ok_or(error)
+    }
+
+    fn out_of_bounds(&self, point: &Point) -> OutOfBounds {
+        OutOfBounds {
+            point: *point,
+            height: self.height,
+            width: self.width,
+        }
+    }
+
+    pub fn tile(&self, index: usize) -> &Tile {
+        &self.tiles[index]
+    }
+
+    pub fn tile_mut(&mut self, index: usize) -> &mut Tile {
+        &mut self.tiles[index]
+    }
+
+    /// Returns the neighbors of `point` that lie within the grid, in the
+    /// order of `Point::neighbors`. Unlike `neighbors`, the point itself need
+    /// not be within the grid.
+    pub fn neighbor_points(&self, point: &Point) -> [Option<Point>; 8] {
+        point.neighbors_within(self.height, self.width)
+    }
+
+    /// Iterates over the neighbors of `point` that lie within the grid. The
+    /// point itself must be within the grid.
+    pub fn neighbors(&self, point: &Point) -> Neighbors {
+        let index = self.index_of(point)
+            .expect("Attempted to find neighbors of an out-of-bounds tile.");
+        self.neighbors_at(point, index)
+    }
+
+    /// Like `neighbors`, for the tile at `point` whose index is known.
+    fn neighbors_at(&self, point: &Point, index: usize) -> Neighbors {
+        let table = &self.neighbor_table;
+        Neighbors {
+            offsets: table.offsets,
+            index: index,
+            mask: table.row_masks[band(point.y(), self.height)] &
+                  table.column_masks[band(point.x(), self.width)],
+        }
+    }
+
+    pub fn movement(&self) -> Movement {
+        self.movement
+    }
+
+    /// Sets which moves agents that plan with `successors` may make.
+    pub fn set_movement(&mut self, movement: Movement) {
+        if movement.connects_diagonally() !=
+           self.movement.connects_diagonally() {
+            self.connectivity = None;
+        }
+        self.movement = movement;
+    }
+
+    /// The type the map declared, if it was read from a file, which tells
+    /// the movement model it was meant for.
+    pub fn map_type(&self) -> MapType {
+        self.map_type
+    }
+
+    /// Records the type the map declared, and moves by its rules.
+    pub fn set_map_type(&mut self, map_type: MapType) {
+        self.map_type = map_type;
+        self.set_movement(map_type.movement());
+    }
+
+    /// The name of the map, if it was given one or read from a file.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| {
+            self.path
+                .as_ref()
+                .and_then(|path| path.file_stem())
+                .and_then(|stem| stem.to_str())
+        })
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_owned());
+    }
+
+    /// The file the map was read from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Records that the map was read from the file at `path`.
+    pub fn set_path<P>(&mut self, path: &P)
+        where P: AsRef<Path> + ?Sized
+    {
+        self.path = Some(path.as_ref().to_owned());
+    }
+
+    /// Describes the map as it is now: where it came from, its type, its
+    /// dimensions and the hash of its terrain.
+    pub fn meta(&self) -> GridMeta {
+        GridMeta {
+            name: self.name().map(str::to_owned),
+            path: self.path.clone(),
+            map_type: self.map_type,
+            height: self.height,
+            width: self.width,
+            hash: self.content_hash(),
+        }
+    }
+
+    /// Iterates over the moves out of the tile at `index`, after arriving
+    /// there in the `previous` direction, onto the tiles `passable` holds
+    /// for, as allowed by the movement rules. Yields the index of the tile
+    /// moved onto, the direction of the move, and its cost under the cost
+    /// model (see `move_cost`). Planners that expand nodes through it follow
+    /// every rule without knowing about them.
+    pub fn successors<'a, P>(&'a self,
+                             index: usize,
+                             previous: Option<Direction>,
+                             passable: P)
+                             -> impl Iterator<Item = (usize, Direction, Distance)> + 'a
+        where P: Fn(&Tile) -> bool + 'a
+    {
+        let offsets = self.neighbor_table.offsets;
+        let movement = self.movement;
+        self.neighbors_at(&self.point_of(index), index)
+            .filter_map(move |(direction, neighbor)| {
+                if !passable(&self.tiles[neighbor]) {
+                    return None;
+                }
+                let direction = Direction::ALL[direction];
+                let corners = direction.corners();
+                let allowed = match movement {
+                    Movement::Octile => true,
+                    Movement::Cardinal => corners.is_none(),
+                    Movement::NoCornerCutting => {
+                        corners.is_none_or(|(a, b)| {
+                            [a, b].iter().all(|corner| {
+                                let corner = index as isize +
+                                             offsets[corner.index()];
+                                passable(&self.tiles[corner as usize])
+                            })
+                        })
+                    }
+                };
+                if !allowed {
+                    return None;
+                }
+                let cost = self.move_cost(previous, direction, neighbor);
+                Some((neighbor, direction, cost))
+            })
+    }
+
+    /// Sets whether searches over the grid record the nodes they expand, to
+    /// be collected with `take_search_trace`.
+    pub fn set_trace_search(&mut self, trace: bool) {
+        self.search_trace = if trace { Some(Vec::new()) } else { None };
+    }
+
+    /// Records the expansion of a node by a search, if searches are being
+    /// traced.
+    pub fn trace_expansion(&mut self, expansion: Expansion) {
+        if let Some(ref mut trace) = self.search_trace {
+            trace.push(expansion);
+        }
+    }
+
+    /// Returns the nodes expanded since the trace was last taken, in order.
+    pub fn take_search_trace(&mut self) -> Vec<Expansion> {
+        self.search_trace.as_mut().map_or_else(Vec::new, take)
+    }
+
+    /// Starts recording the tiles whose beliefs change in ways that may
+    /// change plans, to be collected with `take_belief_changes`, unless they
+    /// are being recorded already.
+    pub fn record_belief_changes(&mut self) {
+        if self.belief_changes.is_none() {
+            self.belief_changes = Some(Vec::new());
+        }
+    }
+
+    /// Returns the tiles found to be impassable, or to be of some terrain
+    /// other than open ground, since the changes were last taken.
+    pub fn take_belief_changes(&mut self) -> Vec<Point> {
+        self.belief_changes.as_mut().map_or_else(Vec::new, take)
     }
 
     pub fn next_episode(&mut self) -> usize {
@@ -251,33 +1020,569 @@ impl Grid {
     }
 
     pub fn forget(&mut self) {
-        for row in &mut self.tiles {
-            for cell in row.iter_mut() {
-                cell.forget();
+        for cell in &mut self.tiles {
+            cell.forget();
+        }
+        self.belief_hash = 0;
+        if let Some(ref mut field) = self.obstacle_field {
+            field.clear();
+        }
+        if let Some(ref mut field) = self.observed_field {
+            field.clear();
+        }
+        self.connectivity = None;
+        if let Some(ref mut changes) = self.belief_changes {
+            changes.clear();
+        }
+    }
+
+    /// Returns a hash of the beliefs about every tile, maintained as tiles
+    /// are observed. Equal beliefs always have equal hashes.
+    pub fn belief_hash(&self) -> u64 {
+        self.belief_hash
+    }
+
+    pub fn sensor(&self) -> Sensor {
+        self.sensor
+    }
+
+    /// Sets how much of the grid is observed every time the agent looks
+    /// around.
+    pub fn set_sensor(&mut self, sensor: Sensor) {
+        self.sensor = sensor;
+    }
+
+    pub fn clearance(&self) -> usize {
+        self.clearance
+    }
+
+    /// Sets how many moves away from every tile believed to be impassable
+    /// the agent must stay, as if obstacles were inflated by `clearance`
+    /// tiles. Tiles closer than that are not free, so agents that plan with
+    /// `Tile::freespace` keep away from them, and cannot reach targets that
+    /// close to an obstacle. Zero, the default, lets the agent brush past
+    /// obstacles.
+    pub fn set_clearance(&mut self, clearance: usize) {
+        self.clearance = clearance;
+        self.rebuild_obstacle_field();
+    }
+
+    pub fn cost_model(&self) -> &dyn CostModel {
+        self.cost_model.as_ref()
+    }
+
+    /// Sets how agents that plan with `move_cost` measure the cost of moves,
+    /// and how the moves they make are judged.
+    pub fn set_cost_model<M>(&mut self, cost_model: M)
+        where M: CostModel + 'static
+    {
+        self.cost_model = Box::new(cost_model);
+        self.rebuild_obstacle_field();
+        self.observed_field = if self.cost_model.doubt_matters() {
+            Some(DistanceField::from_grid(self, Metric::Chebyshev, |tile| {
+                tile.belief != Belief::Unknown
+            }))
+        } else {
+            None
+        };
+    }
+
+    /// Builds the obstacle field out of the current beliefs, if the
+    /// clearance or the cost model need it, and marks every tile
+    /// accordingly.
+    fn rebuild_obstacle_field(&mut self) {
+        let needed = self.clearance > 0 ||
+                     self.cost_model.exposure_matters();
+        self.obstacle_field = if needed {
+            Some(DistanceField::from_grid(self, Metric::Chebyshev, |tile| {
+                tile.obstacle()
+            }))
+        } else {
+            None
+        };
+        for index in 0..self.tiles.len() {
+            self.mark(index);
+        }
+        self.connectivity = None;
+    }
+
+    /// Marks the tile at `index` as inflated or next to an obstacle,
+    /// following the obstacle field.
+    fn mark(&mut self, index: usize) {
+        let distance = self.obstacle_field
+            .as_ref()
+            .map_or(Distance::INFINITY, |field| field.distances()[index]);
+        let safety = self.cost_model.exposure_matters();
+        let tile = &mut self.tiles[index];
+        let free = tile.freespace();
+        tile.inflated = self.clearance > 0 &&
+                        distance <= self.clearance as Distance;
+        tile.near_obstacle = safety && distance <= 1.0;
+        if free && !tile.freespace() {
+            self.block(index);
+        }
+    }
+
+    /// Drops the regions of free tiles if the tile at `index`, which has
+    /// just stopped being free, may have split its region.
+    fn block(&mut self, index: usize) {
+        if self.connectivity.is_some() &&
+           Connectivity::separates(self, &self.point_of(index)) {
+            self.connectivity = None;
+        }
+    }
+
+    /// Updates the obstacle field around the tile at `index`, which has just
+    /// come to be believed impassable, marking the tiles around it. Since
+    /// beliefs only ever become more certain until the grid is forgotten,
+    /// tiles are never unmarked.
+    fn add_obstacle(&mut self, index: usize) {
+        let point = self.point_of(index);
+        match self.obstacle_field {
+            Some(ref mut field) => field.set_obstacle(&point, true),
+            None => return,
+        }
+
+        let reach = max(self.clearance, 1);
+        let rows = point.y().saturating_sub(reach)..
+                   min(point.y() + reach + 1, self.height);
+        for y in rows {
+            let columns = point.x().saturating_sub(reach)..
+                          min(point.x() + reach + 1, self.width);
+            for x in columns {
+                self.mark(y * self.width + x);
+            }
+        }
+    }
+
+    /// Returns the cost the agent expects to pay for moving in `direction`
+    /// onto the tile at `index`, after moving in the `previous` direction,
+    /// under the cost model. Tiles whose
+    /// terrain has not been seen are taken to be open ground, and unobserved
+    /// tiles are as far from known space as the nearest observed tile, or
+    /// not at all if nothing has been observed yet.
+    pub fn move_cost(&self,
+                     previous: Option<Direction>,
+                     direction: Direction,
+                     index: usize)
+                     -> Distance {
+        let tile = &self.tiles[index];
+        let terrain = match tile.belief {
+            Belief::Known(terrain) => terrain,
+            _ => Terrain::Ground,
+        };
+        let unknown = self.observed_field
+            .as_ref()
+            .map(|field| field.distances()[index])
+            .filter(|distance| distance.is_finite())
+            .unwrap_or(0.0);
+        self.cost_model.cost(&Step {
+                                 direction: direction,
+                                 previous: previous,
+                                 terrain: terrain,
+                                 exposed: tile.near_obstacle,
+                                 unknown: unknown,
+                                 layer: self.layer_cost(index),
+                             })
+    }
+
+    /// Returns the cost of moving from `from` to `to` (after arriving at
+    /// `from` by moving in the `previous` direction, if given) under the cost
+    /// model, judged by the true terrain of `to` and around it, or `None` if
+    /// the model measures just the length of moves. Used to measure how
+    /// costly executed paths actually were. Staying in place costs
+    /// `WAIT_COST`.
+    pub fn model_cost(&self,
+                      previous: Option<Direction>,
+                      from: &Point,
+                      to: &Point)
+                      -> Option<Distance> {
+        if self.cost_model.is_length() {
+            return None;
+        }
+        Some(self.cost_under(&*self.cost_model, previous, from, to))
+    }
+
+    /// Returns the cost of moving from `from` to `to` under `model`, like
+    /// `model_cost`, but for any model, such as the metric agents measure
+    /// their moves by.
+    pub fn cost_under(&self,
+                      model: &dyn CostModel,
+                      previous: Option<Direction>,
+                      from: &Point,
+                      to: &Point)
+                      -> Distance {
+        let direction = match Direction::between(from, to) {
+            Some(direction) => direction,
+            None => return WAIT_COST,
+        };
+        let exposed = self.index_of(to).is_some() &&
+                      self.neighbors(to)
+                          .any(|(_, index)| !self.tiles[index].passable());
+        model.cost(&Step {
+                       direction: direction,
+                       previous: previous,
+                       terrain: self.get(to)
+                           .map_or(Terrain::OutOfBounds, Tile::terrain),
+                       exposed: exposed,
+                       unknown: 0.0,
+                       layer: self.index_of(to)
+                           .map_or(0.0, |to| self.layer_cost(to)),
+                   })
+    }
+
+    /// Returns the value of the tile at `index` in the cost layer, if the
+    /// cost model takes it into account and there is one, or zero.
+    fn layer_cost(&self, index: usize) -> Distance {
+        if !self.cost_model.layer_matters() {
+            return 0.0;
+        }
+        self.overlay
+            .get(COST_LAYER)
+            .map_or(0.0, |layer| layer.value(index))
+    }
+
+    /// Returns the layers of values over the tiles of the grid.
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+
+    /// Adds `layer` to the overlay under `name`, replacing any layer of that
+    /// name. Fails, leaving the overlay untouched, if the layer is not the
+    /// size of the grid, or cannot be laid under `name` (see `check_layer`).
+    pub fn set_layer(&mut self,
+                     name: &str,
+                     layer: Layer)
+                     -> Result<(), String> {
+        if (layer.height(), layer.width()) != (self.height, self.width) {
+            return Err(format!("Cannot lay a {}x{} layer over a {}x{} map.",
+                               layer.height(),
+                               layer.width(),
+                               self.height,
+                               self.width));
+        }
+        check_layer(name, &layer)?;
+        self.overlay.insert(name, layer);
+        Ok(())
+    }
+
+    /// Adds every layer of `overlay`, as `set_layer` does. Fails, leaving
+    /// the overlay untouched, if some layer is not the size of the grid or
+    /// cannot be laid under its name.
+    pub fn set_overlay(&mut self, overlay: Overlay) -> Result<(), String> {
+        for (name, layer) in overlay.iter() {
+            if (layer.height(), layer.width()) != (self.height, self.width) {
+                return Err(format!("Layer '{}' is {}x{}, but the map is \
+                                    {}x{}.",
+                                   name,
+                                   layer.height(),
+                                   layer.width(),
+                                   self.height,
+                                   self.width));
+            }
+            check_layer(name, layer)?;
+        }
+        for (name, layer) in overlay.iter() {
+            self.overlay.insert(name, layer.clone());
+        }
+        Ok(())
+    }
+
+    /// Returns how many moves away the nearest tile believed to be
+    /// impassable is, if there is one within the clearance.
+    pub fn obstacle_distance(&self, point: &Point) -> Option<usize> {
+        self.obstacle_field
+            .as_ref()
+            .and_then(|field| field.get(point))
+            .filter(|&distance| distance <= self.clearance as Distance)
+            .map(|distance| distance as usize)
+    }
+
+    /// Returns the key under which the belief about the tile at `index` is
+    /// combined into the belief hash. Unknown tiles are left out.
+    fn belief_key(&self, index: usize) -> u64 {
+        match self.tiles[index].belief {
+            Belief::Unknown => 0,
+            ref belief => mix((index as u64) << 3 | u64::from(belief.code())),
+        }
+    }
+
+    fn look_at(&mut self, index: usize, terrain: bool) {
+        let before = self.belief_key(index);
+        let unknown = self.tiles[index].belief == Belief::Unknown;
+        let free = self.tiles[index].freespace();
+        let changed = if terrain {
+            self.tiles[index].look_terrain()
+        } else {
+            self.tiles[index].look()
+        };
+        if changed {
+            self.belief_hash ^= before ^ self.belief_key(index);
+            if unknown {
+                let point = self.point_of(index);
+                if let Some(ref mut field) = self.observed_field {
+                    field.set_obstacle(&point, true);
+                }
+            }
+            if self.tiles[index].obstacle() {
+                if free {
+                    self.block(index);
+                }
+                self.add_obstacle(index);
             }
+            let planned = match self.tiles[index].belief {
+                Belief::Impassable => true,
+                Belief::Known(terrain) => terrain != Terrain::Ground,
+                _ => false,
+            };
+            if planned {
+                let point = self.point_of(index);
+                if let Some(ref mut changes) = self.belief_changes {
+                    changes.push(point);
+                }
+            }
+        }
+    }
+
+    /// Observes the single tile at `point`, whatever the sensor reaches, as
+    /// when the agent bumps into it.
+    pub fn reveal(&mut self, point: &Point) {
+        if let Some(index) = self.index_of(point) {
+            self.look_at(index, false);
         }
     }
 
+    /// Observes the tiles around `point`, as far as the sensor reaches.
     pub fn look(&mut self, point: &Point) {
-        self.get_mut(point).map(|p| p.look());
-        for neighbor in &point.neighbors() {
-            if let Some(ref mut tile) =
-                neighbor.and_then(|n| self.get_mut(&n)) {
-                tile.look();
+        let sensor = self.sensor;
+        self.look_with(point, sensor);
+    }
+
+    /// Observes the tiles around `point` as far as `sensor` reaches, rather
+    /// than the grid's own sensor, as with a long-range scanner.
+    pub fn look_with(&mut self, point: &Point, sensor: Sensor) {
+        if self.index_of(point).is_none() {
+            return;
+        }
+
+        let reach = max(sensor.radius, sensor.terrain_radius.unwrap_or(0));
+        let rows = point.y().saturating_sub(reach)..
+                   min(point.y() + reach + 1, self.height);
+        for y in rows {
+            let columns = point.x().saturating_sub(reach)..
+                          min(point.x() + reach + 1, self.width);
+            for x in columns {
+                let distance = max(y.abs_diff(point.y()), x.abs_diff(point.x()));
+                let index = y * self.width + x;
+                if sensor.terrain_radius.is_some_and(|r| distance <= r) {
+                    self.look_at(index, true);
+                } else if distance <= sensor.radius {
+                    self.look_at(index, false);
+                }
             }
         }
     }
 
-    pub fn iter(&self) -> Iter<Vec<Tile>> {
-        self.tiles.iter()
+    fn matrix<F>(&self, code: F) -> Matrix
+        where F: Fn(&Tile) -> u8
+    {
+        Matrix {
+            height: self.height,
+            width: self.width,
+            data: self.tiles.iter().map(code).collect(),
+        }
+    }
+
+    /// Returns the current beliefs about every tile, encoded with
+    /// `Belief::code`. This is the agent's observation of the grid.
+    pub fn belief_matrix(&self) -> Matrix {
+        self.matrix(|tile| tile.belief.code())
+    }
+
+    /// Returns the true terrain of every tile, encoded with `Terrain::code`.
+    pub fn terrain_matrix(&self) -> Matrix {
+        self.matrix(|tile| tile.terrain.code())
+    }
+
+    pub fn iter(&self) -> Chunks<'_, Tile> {
+        self.tiles.chunks(max(self.width, 1))
     }
 
     pub fn height(&self) -> usize {
-        self.tiles.len()
+        self.height
     }
 
     pub fn width(&self) -> usize {
-        self.tiles.get(0).map_or(0, |row| row.len())
+        self.width
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the grid's dimensions and terrain.
+    /// Unlike `std::hash`, the result is stable across platforms and
+    /// compiler versions, so it can be stored alongside results.
+    pub fn content_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |byte: u8| {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        };
+
+        for byte in (self.height as u64)
+                .to_le_bytes()
+                .iter()
+                .chain((self.width as u64).to_le_bytes().iter()) {
+            feed(*byte);
+        }
+        for tile in &self.tiles {
+            feed(tile.terrain.symbol() as u8);
+        }
+        hash
+    }
+
+    /// Lists the tiles whose terrain is different in `other`, in row-major
+    /// order. Fails if the grids do not have the same size.
+    pub fn diff(&self, other: &Grid) -> Result<Vec<TileChange>, String> {
+        if (self.height, self.width) != (other.height, other.width) {
+            return Err(format!("Cannot compare a {}x{} map to a {}x{} map.",
+                               self.height,
+                               self.width,
+                               other.height,
+                               other.width));
+        }
+
+        Ok(self.tiles
+               .iter()
+               .zip(&other.tiles)
+               .enumerate()
+               .filter(|&(_, (a, b))| a.terrain != b.terrain)
+               .map(|(index, (a, b))| {
+                        TileChange {
+                            point: self.point_of(index),
+                            from: a.terrain,
+                            to: b.terrain,
+                        }
+                    })
+               .collect())
+    }
+
+    /// Changes the terrain of the tiles listed in `changes`, as returned by
+    /// `diff`. Fails, leaving the grid untouched, if some change lies
+    /// outside the grid or does not start from the tile's current terrain.
+    pub fn apply(&mut self, changes: &[TileChange]) -> Result<(), String> {
+        for change in changes {
+            match self.try_get(&change.point) {
+                Err(e) => return Err(e.to_string()),
+                Ok(tile) if tile.terrain != change.from => {
+                    return Err(format!("{} is {}, not {}.",
+                                       change.point,
+                                       tile.terrain,
+                                       change.from));
+                }
+                Ok(_) => {}
+            }
+        }
+
+        for change in changes {
+            self[&change.point].terrain = change.to;
+        }
+        Ok(())
+    }
+
+    /// Builds a coarser grid, where every tile stands for a block of
+    /// `factor` by `factor` tiles of this one (fewer along the last row and
+    /// column of blocks, if the sides are not multiples of `factor`). Coarse
+    /// tiles are ground or, when `coarsening` calls them blocked, the
+    /// terrain of the first impassable tile of their block, in row-major
+    /// order.
+    ///
+    /// Panics if `factor` is zero.
+    pub fn downsample(&self, factor: usize, coarsening: Coarsening) -> Grid {
+        assert!(factor > 0, "Cannot downsample a grid by a factor of zero.");
+        let height = self.height.div_ceil(factor);
+        let width = self.width.div_ceil(factor);
+        let rows = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let block = (y * factor..min((y + 1) * factor,
+                                                     self.height))
+                            .flat_map(|fy| {
+                                (x * factor..min((x + 1) * factor, self.width))
+                                    .map(move |fx| Point::new(fy, fx))
+                            })
+                            .map(|point| self[&point].terrain);
+                        Tile::new(coarsening.terrain(block))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut grid = Grid::new(rows);
+        grid.set_map_type(self.map_type);
+        grid
+    }
+
+    /// Maps every point of a path over a grid downsampled from this one by
+    /// `factor` onto a tile of its block: the passable tile nearest the
+    /// block's center, if any, or else the tile at its center. Points keep
+    /// their order, so paths stored backwards (as returned by searches) are
+    /// lifted backwards too. Consecutive lifted points are not necessarily
+    /// adjacent; searches between them refine the path.
+    pub fn lift_path(&self, path: &[Point], factor: usize) -> Vec<Point> {
+        path.iter().map(|point| self.lift_point(point, factor)).collect()
+    }
+
+    fn lift_point(&self, coarse: &Point, factor: usize) -> Point {
+        let top = coarse.y() * factor;
+        let left = coarse.x() * factor;
+        let bottom = min(top + factor, self.height);
+        let right = min(left + factor, self.width);
+        let center = Point::new((top + bottom.max(top + 1) - 1) / 2,
+                                (left + right.max(left + 1) - 1) / 2);
+        (top..bottom)
+            .flat_map(|y| (left..right).map(move |x| Point::new(y, x)))
+            .filter(|point| self[point].passable())
+            .min_by_key(|point| {
+                            max(point.y().abs_diff(center.y()),
+                                point.x().abs_diff(center.x()))
+                        })
+            .unwrap_or(center)
+    }
+
+    /// Whether the agent believes it can get from `source` to `target`:
+    /// whether `target` is free and connected through free tiles to
+    /// `source` or, since the agent may always step off the tile it is on,
+    /// to one of its free neighbors. Much cheaper than searching, since the
+    /// regions of free tiles are kept as beliefs change, and only rebuilt
+    /// after a tile that may have split one is blocked.
+    pub fn belief_reachable(&mut self, source: &Point, target: &Point) -> bool {
+        let (from, to) = match (self.index_of(source), self.index_of(target)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false,
+        };
+        if from == to {
+            return true;
+        }
+        if !self.tiles[to].freespace() {
+            return false;
+        }
+        if self.connectivity.is_none() {
+            self.connectivity = Some(Connectivity::from_grid(self));
+        }
+        let connectivity = match self.connectivity {
+            Some(ref connectivity) => connectivity,
+            None => return false,
+        };
+        // Tiles blocked since the regions were built keep their labels.
+        let diagonal = self.movement.connects_diagonally();
+        Some(from)
+            .into_iter()
+            .chain(self.neighbors(source)
+                       .filter(|&(direction, _)| {
+                                   diagonal ||
+                                   !Direction::ALL[direction].is_diagonal()
+                               })
+                       .map(|(_, index)| index))
+            .filter(|&index| self.tiles[index].freespace())
+            .any(|index| connectivity.connected(index, to))
     }
 
     pub fn has_path(&mut self, source: &Point, target: &Point) -> bool {
@@ -290,27 +1595,142 @@ impl Grid {
     }
 }
 
+/// Why a `GridBuilder` could not build a grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildError {
+    /// Row `row` has `width` tiles, rather than as many as the first row.
+    Ragged {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    /// The terrain of a tile outside the grid was set.
+    OutOfBounds(OutOfBounds),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            BuildError::Ragged { row, width, expected } => {
+                write!(f,
+                       "Row {} has {} tiles, but the first row has {}.",
+                       row,
+                       width,
+                       expected)
+            }
+            BuildError::OutOfBounds(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Builds a `Grid` out of its dimensions and terrain, without going through
+/// a map file. Every tile is ground unless set otherwise. Mistakes, such as
+/// setting a tile outside the grid, are reported by `build`.
+#[derive(Debug, Clone)]
+pub struct GridBuilder {
+    height: usize,
+    width: usize,
+    terrain: Vec<Terrain>,
+    error: Option<BuildError>,
+}
+
+impl GridBuilder {
+    pub fn new(height: usize, width: usize) -> GridBuilder {
+        GridBuilder {
+            height: height,
+            width: width,
+            terrain: vec![Terrain::Ground; height * width],
+            error: None,
+        }
+    }
+
+    /// Takes the terrain of every tile from `rows`, which must all have the
+    /// same length.
+    pub fn from_rows(rows: Vec<Vec<Terrain>>) -> GridBuilder {
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        let error = rows.iter()
+            .enumerate()
+            .find(|&(_, row)| row.len() != width)
+            .map(|(y, row)| {
+                     BuildError::Ragged {
+                         row: y,
+                         width: row.len(),
+                         expected: width,
+                     }
+                 });
+        GridBuilder {
+            height: height,
+            width: width,
+            terrain: rows.into_iter().flatten().collect(),
+            error: error,
+        }
+    }
+
+    /// Sets every tile to `terrain`.
+    pub fn fill(self, terrain: Terrain) -> GridBuilder {
+        self.terrain_with(|_| terrain)
+    }
+
+    /// Sets every tile to the terrain `terrain` returns for its point.
+    pub fn terrain_with<F>(mut self, terrain: F) -> GridBuilder
+        where F: Fn(&Point) -> Terrain
+    {
+        let width = self.width;
+        for (index, tile) in self.terrain.iter_mut().enumerate() {
+            *tile = terrain(&Point::new(index / width, index % width));
+        }
+        self
+    }
+
+    /// Sets the tile at `point` to `terrain`.
+    pub fn set(mut self, point: Point, terrain: Terrain) -> GridBuilder {
+        if point.y() < self.height && point.x() < self.width {
+            self.terrain[point.y() * self.width + point.x()] = terrain;
+        } else if self.error.is_none() {
+            self.error = Some(BuildError::OutOfBounds(OutOfBounds {
+                                                          point: point,
+                                                          height: self.height,
+                                                          width: self.width,
+                                                      }));
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Grid, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let width = self.width;
+        let mut terrain = self.terrain.into_iter();
+        let tiles = (0..self.height)
+            .map(|_| terrain.by_ref().take(width).map(Tile::new).collect())
+            .collect();
+        Ok(Grid::new(tiles))
+    }
+}
+
 impl<'a> Index<&'a Point> for Grid {
     type Output = Tile;
 
     fn index(&self, index: &'a Point) -> &Tile {
-        self.get(index).expect("Attempted to grab an out-of-bounds tile.")
+        self.try_get(index).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
 impl<'a> IndexMut<&'a Point> for Grid {
     fn index_mut(&mut self, index: &'a Point) -> &mut Tile {
-        self.get_mut(index).expect("Attempted to grab an out-of-bounds tile.")
+        self.try_get_mut(index).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
 impl Display for Grid {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        for row in &self.tiles {
-            for tile in row.iter() {
+        for row in self.iter() {
+            for tile in row {
                 write!(f, "{}", tile)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -320,6 +1740,8 @@ impl Display for Grid {
 mod tests {
     use super::*;
 
+    use parser::grid_from_str;
+
     #[test]
     fn octile_heuristic_distance() {
         let p0 = Point::new(0, 0);
@@ -344,6 +1766,293 @@ mod tests {
         assert_eq!(Distance::euclidean_heuristic(&p2, &p3), 4.0 * SQRT_2);
     }
 
+    #[test]
+    fn content_hash_depends_on_terrain() {
+        let open = GridBuilder::new(1, 2).build().unwrap();
+        let same = GridBuilder::new(1, 2).build().unwrap();
+        let blocked = GridBuilder::new(1, 2)
+            .set(Point::new(0, 1), Terrain::Trees)
+            .build()
+            .unwrap();
+        let tall = GridBuilder::new(2, 1).build().unwrap();
+
+        assert_eq!(open.content_hash(), same.content_hash());
+        assert!(open.content_hash() != blocked.content_hash());
+        assert!(open.content_hash() != tall.content_hash());
+    }
+
+    #[test]
+    fn describe_maps() {
+        let mut grid = GridBuilder::new(1, 2).build().unwrap();
+        assert_eq!(grid.meta().to_string(), "unnamed (octile, 1x2)");
+
+        grid.set_path("maps/Tiny.map");
+        grid.set_map_type(MapType::Tile);
+        let meta = grid.meta();
+        assert_eq!(meta.name.as_deref(), Some("Tiny"));
+        assert_eq!(meta.hash, grid.content_hash());
+        assert_eq!(meta.to_string(), "Tiny (tile, 1x2) from maps/Tiny.map");
+
+        grid.set_name("tiny");
+        assert_eq!(grid.name(), Some("tiny"));
+        assert_eq!(grid.path(), Some(Path::new("maps/Tiny.map")));
+    }
+
+    #[test]
+    fn build_grids() {
+        let grid = GridBuilder::new(3, 4)
+            .terrain_with(|point| if point.x() == 2 {
+                              Terrain::Trees
+                          } else {
+                              Terrain::Ground
+                          })
+            .set(Point::new(1, 2), Terrain::Ground)
+            .build()
+            .unwrap();
+        assert_eq!((grid.height(), grid.width()), (3, 4));
+        assert_eq!(grid.to_string(), "..T.\n....\n..T.\n");
+
+        let ragged = GridBuilder::from_rows(vec![vec![Terrain::Ground; 3],
+                                                 vec![Terrain::Ground; 2]]);
+        assert_eq!(ragged.build().err(),
+                   Some(BuildError::Ragged {
+                            row: 1,
+                            width: 2,
+                            expected: 3,
+                        }));
+
+        let outside = GridBuilder::new(2, 2)
+            .set(Point::new(2, 0), Terrain::Water)
+            .build();
+        assert_eq!(outside.err().map(|e| e.to_string()),
+                   Some("(2, 0) is outside the 2x2 map.".to_owned()));
+    }
+
+    #[test]
+    fn belief_hash_follows_observations() {
+        let mut grid = GridBuilder::new(3, 3).build().unwrap();
+        assert_eq!(grid.belief_hash(), 0);
+
+        grid.look(&Point::new(0, 0));
+        let corner = grid.belief_hash();
+        assert!(corner != 0);
+
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid.belief_hash(), corner);
+
+        grid.look(&Point::new(2, 2));
+        assert!(grid.belief_hash() != corner);
+
+        grid.forget();
+        assert_eq!(grid.belief_hash(), 0);
+    }
+
+    #[test]
+    fn matrices_follow_grid() {
+        let mut grid = GridBuilder::from_rows(vec![vec![Terrain::Ground,
+                                                        Terrain::Trees,
+                                                        Terrain::Water],
+                                                   vec![Terrain::Ground,
+                                                        Terrain::OutOfBounds,
+                                                        Terrain::Swamp]])
+            .build()
+            .unwrap();
+
+        let terrain = grid.terrain_matrix();
+        assert_eq!((terrain.height(), terrain.width()), (2, 3));
+        assert_eq!(terrain.as_slice(), &[0, 2, 4, 0, 1, 3]);
+        assert_eq!(terrain.get(&Point::new(1, 2)), Some(3));
+        assert_eq!(terrain.get(&Point::new(2, 0)), None);
+
+        assert_eq!(grid.belief_matrix().into_vec(), vec![0; 6]);
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid.belief_matrix().into_vec(), vec![1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn observe_terrain() {
+        let mut grid = GridBuilder::new(1, 3)
+            .set(Point::new(0, 1), Terrain::Water)
+            .set(Point::new(0, 2), Terrain::Swamp)
+            .build()
+            .unwrap();
+        grid.look(&Point::new(0, 0));
+        let passability = grid.belief_hash();
+        assert_eq!(grid[&Point::new(0, 1)].belief(), &Belief::Impassable);
+
+        grid.set_sensor(Sensor {
+                            radius: 1,
+                            terrain_radius: Some(1),
+                        });
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid[&Point::new(0, 1)].belief(),
+                   &Belief::Known(Terrain::Water));
+        assert!(!grid[&Point::new(0, 1)].freespace());
+        assert!(grid[&Point::new(0, 0)].freespace());
+        assert!(grid.belief_hash() != passability);
+        assert_eq!(grid.belief_matrix().into_vec(), vec![3, 7, 0]);
+
+        // Observing the same terrain again changes nothing, and the hash
+        // only depends on the beliefs.
+        let terrain = grid.belief_hash();
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid.belief_hash(), terrain);
+        grid.forget();
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid.belief_hash(), terrain);
+    }
+
+    #[test]
+    fn successors_follow_movement() {
+        let mut grid = GridBuilder::from_rows(vec![vec![Terrain::Ground,
+                                                        Terrain::OutOfBounds,
+                                                        Terrain::Ground]; 3])
+            .build()
+            .unwrap();
+        let center = Point::new(1, 1);
+        grid[&center] = Tile::new(Terrain::Ground);
+        grid[&Point::new(0, 1)] = Tile::new(Terrain::Ground);
+        let index = grid.index_of(&center).unwrap();
+        let directions = |grid: &Grid| {
+            grid.successors(index, None, Tile::passable)
+                .map(|(_, direction, _)| direction)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(directions(&grid).len(), 7);
+        let (next, direction, cost) = grid.successors(index,
+                                                      None,
+                                                      Tile::passable)
+            .last()
+            .unwrap();
+        assert_eq!((grid.point_of(next), direction),
+                   (Point::new(2, 2), Direction::SouthEast));
+        assert_eq!(cost, SQRT_2);
+
+        grid.set_movement(Movement::NoCornerCutting);
+        assert_eq!(directions(&grid),
+                   vec![Direction::NorthWest,
+                        Direction::North,
+                        Direction::NorthEast,
+                        Direction::West,
+                        Direction::East]);
+
+        grid.set_movement(Movement::Cardinal);
+        assert_eq!(directions(&grid),
+                   vec![Direction::North, Direction::West, Direction::East]);
+
+        assert_eq!(Direction::between(&center, &Point::new(2, 2)),
+                   Some(Direction::SouthEast));
+        assert_eq!(Direction::between(&center, &Point::new(0, 0)),
+                   Some(Direction::NorthWest));
+        assert_eq!(Direction::between(&center, &center), None);
+        assert_eq!(Direction::between(&center, &Point::new(1, 3)), None);
+        assert_eq!(Direction::West.from(&Point::new(0, 0)), None);
+        assert_eq!(Direction::from_index(4), Some(Direction::East));
+    }
+
+    #[test]
+    fn cost_overlay_layers() {
+        let mut grid = GridBuilder::new(1, 3).build().unwrap();
+        let layer = Layer::from_rows(vec![vec![0.0, 2.0, 4.0]]).unwrap();
+        assert!(grid.set_layer(COST_LAYER, Layer::new(3, 1, 0.0)).is_err());
+        assert!(grid.set_layer(COST_LAYER, Layer::new(1, 3, -1.0)).is_err());
+        let mut overlay = Overlay::new();
+        overlay.insert(COST_LAYER, Layer::new(1, 3, -1.0));
+        assert!(grid.set_overlay(overlay).is_err());
+        assert!(grid.overlay().is_empty());
+        grid.set_layer(COST_LAYER, layer).unwrap();
+        assert!(grid.overlay().get(COST_LAYER).is_some());
+
+        // The layer is ignored until the cost model weighs it.
+        assert_eq!(grid.move_cost(None, Direction::East, 1), 1.0);
+        grid.set_cost_model(Composite {
+                                layer: 0.5,
+                                ..Composite::default()
+                            });
+        assert_eq!(grid.move_cost(None, Direction::East, 1), 2.0);
+        assert_eq!(grid.model_cost(None, &Point::new(0, 1), &Point::new(0, 2)),
+                   Some(3.0));
+
+        let mut overlay = Overlay::new();
+        overlay.insert("marks", Layer::new(1, 3, 1.0));
+        overlay.insert("wrong", Layer::new(2, 3, 1.0));
+        assert!(grid.set_overlay(overlay.clone()).is_err());
+        assert_eq!(grid.overlay().names(), vec!["cost"]);
+        overlay.remove("wrong");
+        grid.set_overlay(overlay).unwrap();
+        assert_eq!(grid.overlay().names(), vec!["cost", "marks"]);
+    }
+
+    #[test]
+    fn doubt_unknown_space() {
+        let mut grid = GridBuilder::new(1, 6).build().unwrap();
+        grid.look(&Point::new(0, 0));
+        grid.set_cost_model(Composite {
+                                doubt: 0.5,
+                                ..Composite::default()
+                            });
+        assert_eq!(grid.move_cost(None, Direction::East, 1), 1.0);
+        assert_eq!(grid.move_cost(None, Direction::East, 2), 1.5);
+        assert_eq!(grid.move_cost(None, Direction::East, 4), 2.5);
+
+        // The penalty shrinks as the agent sees more, and vanishes once it
+        // has seen nothing at all.
+        grid.look(&Point::new(0, 5));
+        assert_eq!(grid.move_cost(None, Direction::East, 2), 1.5);
+        assert_eq!(grid.move_cost(None, Direction::East, 3), 1.5);
+        assert_eq!(grid.move_cost(None, Direction::East, 4), 1.0);
+        grid.forget();
+        assert_eq!(grid.move_cost(None, Direction::East, 4), 1.0);
+        grid.look(&Point::new(0, 0));
+        assert_eq!(grid.move_cost(None, Direction::East, 4), 2.5);
+    }
+
+    #[test]
+    fn sense_in_two_tiers() {
+        let mut grid = GridBuilder::new(5, 5)
+            .fill(Terrain::Trees)
+            .build()
+            .unwrap();
+        grid.set_sensor(Sensor {
+                            radius: 2,
+                            terrain_radius: Some(1),
+                        });
+        grid.look(&Point::new(1, 1));
+
+        let beliefs = grid.belief_matrix();
+        let known = Belief::Known(Terrain::Trees).code();
+        assert_eq!(beliefs.get(&Point::new(0, 0)), Some(known));
+        assert_eq!(beliefs.get(&Point::new(2, 2)), Some(known));
+        assert_eq!(beliefs.get(&Point::new(3, 0)), Some(2));
+        assert_eq!(beliefs.get(&Point::new(3, 3)), Some(2));
+        assert_eq!(beliefs.get(&Point::new(4, 1)), Some(0));
+        assert_eq!(beliefs.get(&Point::new(0, 4)), Some(0));
+    }
+
+    #[test]
+    fn grid_neighbors_match_points() {
+        let grid = GridBuilder::new(3, 4).build().unwrap();
+
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let point = Point::new(y, x);
+                let expected: Vec<(usize, Point)> = grid.neighbor_points(&point)
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, n)| n.map(|n| (i, n)))
+                    .collect();
+                let found: Vec<(usize, Point)> = grid.neighbors(&point)
+                    .map(|(i, n)| (i, grid.point_of(n)))
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+
+        let single = GridBuilder::new(1, 1).build().unwrap();
+        assert_eq!(single.neighbors(&Point::new(0, 0)).count(), 0);
+    }
+
     #[test]
     fn neighbors() {
         let corner = Point::new(0, 0);
@@ -389,5 +2098,230 @@ mod tests {
                                Some(Point::new(11, 10)),
                                Some(Point::new(11, 11))];
         assert_eq!(inner.neighbors(), inner_neighbors);
+
+        let last = Point::new(usize::MAX, usize::MAX);
+        assert_eq!(last.neighbors()[..5],
+                   [Some(Point::new(usize::MAX - 1, usize::MAX - 1)),
+                    Some(Point::new(usize::MAX - 1, usize::MAX)),
+                    None,
+                    Some(Point::new(usize::MAX, usize::MAX - 1)),
+                    None]);
+        assert_eq!(last.neighbors()[5..], [None; 3]);
+    }
+
+    #[test]
+    fn neighbors_within() {
+        let corner = Point::new(1, 2);
+        assert_eq!(corner.neighbors_within(2, 3),
+                   [Some(Point::new(0, 1)),
+                    Some(Point::new(0, 2)),
+                    None,
+                    Some(Point::new(1, 1)),
+                    None,
+                    None,
+                    None,
+                    None]);
+        assert_eq!(Point::new(10, 10).neighbors_within(2, 2), [None; 8]);
+
+        let grid = GridBuilder::new(3, 4).build().unwrap();
+        assert_eq!(grid.neighbor_points(&Point::new(1, 1)),
+                   Point::new(1, 1).neighbors());
+        assert_eq!(grid.neighbor_points(&Point::new(5, 5)), [None; 8]);
+    }
+
+    #[test]
+    fn downsample() {
+        let grid = grid_from_str("type octile
+height 5
+width 5
+map
+..T..
+.....
+@@...
+@@..W
+.....");
+        let conservative = grid.downsample(2, Coarsening::Conservative);
+        assert_eq!(conservative.to_string(), ".T.\n@.W\n...\n");
+        let optimistic = grid.downsample(2, Coarsening::Optimistic);
+        assert_eq!(optimistic.to_string(), "...\n@..\n...\n");
+        assert_eq!(grid.downsample(1, Coarsening::Conservative).to_string(),
+                   grid.to_string());
+        assert_eq!(grid.downsample(9, Coarsening::Optimistic).to_string(),
+                   ".\n");
+
+        // Coarse paths are stored backwards, like the ones searches return.
+        let coarse = vec![Point::new(2, 2), Point::new(1, 1), Point::new(0, 2)];
+        assert_eq!(grid.lift_path(&coarse, 2),
+                   vec![Point::new(4, 4), Point::new(2, 2), Point::new(0, 4)]);
+        // The block at (1, 0) is all impassable, so its center is kept.
+        assert_eq!(grid.lift_path(&[Point::new(1, 0)], 2),
+                   vec![Point::new(2, 0)]);
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        let mut grid = grid_from_str("type octile
+height 2
+width 3
+map
+..T
+.@.");
+        let edited = grid_from_str("type octile
+height 2
+width 3
+map
+...
+W@.");
+
+        let changes = grid.diff(&edited).unwrap();
+        assert_eq!(changes,
+                   vec![TileChange {
+                            point: Point::new(0, 2),
+                            from: Terrain::Trees,
+                            to: Terrain::Ground,
+                        },
+                        TileChange {
+                            point: Point::new(1, 0),
+                            from: Terrain::Ground,
+                            to: Terrain::Water,
+                        }]);
+        assert_eq!(changes[0].to_string(), "(0, 2): T -> .");
+
+        // Applying the changes twice fails, since they no longer start from
+        // the grid's terrain.
+        grid.apply(&changes).unwrap();
+        assert!(grid.diff(&edited).unwrap().is_empty());
+        assert!(grid.apply(&changes).is_err());
+        assert!(grid.diff(&edited).unwrap().is_empty());
+
+        let small = grid_from_str("type octile
+height 1
+width 1
+map
+.");
+        assert!(grid.diff(&small).is_err());
+    }
+
+    #[test]
+    fn access_out_of_bounds() {
+        let mut grid = grid_from_str("type octile
+height 2
+width 3
+map
+...
+.T.");
+
+        assert!(grid.try_get(&Point::new(1, 2)).is_ok());
+        let error = grid.try_get_mut(&Point::new(2, 1)).err().unwrap();
+        assert_eq!(error,
+                   OutOfBounds {
+                       point: Point::new(2, 1),
+                       height: 2,
+                       width: 3,
+                   });
+        assert_eq!(error.to_string(), "(2, 1) is outside the 2x3 map.");
+    }
+
+    #[test]
+    #[should_panic(expected = "(0, 3) is outside the 2x3 map.")]
+    fn index_out_of_bounds() {
+        let grid = grid_from_str("type octile
+height 2
+width 3
+map
+...
+.T.");
+        grid[&Point::new(0, 3)].passable();
+    }
+
+    #[test]
+    fn inflate_obstacles() {
+        let mut grid = grid_from_str("type octile
+height 5
+width 5
+map
+.....
+.....
+..T..
+.....
+.....");
+        grid.set_sensor(Sensor {
+                            radius: 2,
+                            terrain_radius: None,
+                        });
+        grid.set_clearance(1);
+        grid.look(&Point::new(0, 0));
+
+        assert!(!grid[&Point::new(1, 1)].freespace());
+        assert!(!grid[&Point::new(3, 2)].freespace());
+        assert!(grid[&Point::new(0, 0)].freespace());
+        assert_eq!(grid.obstacle_distance(&Point::new(2, 2)), Some(0));
+        assert_eq!(grid.obstacle_distance(&Point::new(1, 3)), Some(1));
+        assert_eq!(grid.obstacle_distance(&Point::new(0, 0)), None);
+
+        // Setting the clearance again recomputes it from the beliefs.
+        grid.set_clearance(0);
+        assert!(grid[&Point::new(1, 1)].freespace());
+        grid.set_clearance(2);
+        assert!(!grid[&Point::new(0, 0)].freespace());
+
+        grid.forget();
+        assert!(grid[&Point::new(1, 1)].freespace());
+        assert_eq!(grid.obstacle_distance(&Point::new(2, 2)), None);
+    }
+
+    #[test]
+    fn reach_through_believed_freespace() {
+        use rand::{Rng, SeedableRng, StdRng};
+
+        let mut grid = grid_from_str("type octile\nheight 3\nwidth 4\nmap\n\
+                                      ..T.\n..T.\n....");
+        let (start, goal) = (Point::new(0, 0), Point::new(0, 3));
+        assert!(grid.belief_reachable(&start, &goal));
+        assert!(!grid.belief_reachable(&start, &Point::new(3, 0)));
+        grid.look(&Point::new(1, 1));
+        assert!(grid.belief_reachable(&start, &goal));
+        assert!(!grid.belief_reachable(&start, &Point::new(0, 2)));
+        assert!(grid.belief_reachable(&Point::new(0, 2), &start));
+        grid.set_clearance(1);
+        assert!(!grid.belief_reachable(&start, &goal));
+        grid.forget();
+        assert!(grid.belief_reachable(&start, &goal));
+
+        // Kept up to date as random tiles are observed, whatever the
+        // clearance.
+        let mut rng: StdRng = SeedableRng::from_seed(&[7][..]);
+        for clearance in 0..2 {
+            let rows = (0..12)
+                .map(|_| {
+                    (0..12)
+                        .map(|_| if rng.gen_range(0, 4) == 0 {
+                                 Terrain::OutOfBounds
+                             } else {
+                                 Terrain::Ground
+                             })
+                        .collect()
+                })
+                .collect();
+            let mut grid = GridBuilder::from_rows(rows).build().unwrap();
+            grid.set_clearance(clearance);
+            for _ in 0..40 {
+                let mut random = || {
+                    Point::new(rng.gen_range(0, 12), rng.gen_range(0, 12))
+                };
+                grid.look(&random());
+                let (from, to) = (random(), random());
+                let labels = Connectivity::from_grid(&grid);
+                let to_index = grid.index_of(&to).unwrap();
+                let expected = from == to ||
+                               Some(grid.index_of(&from).unwrap())
+                                   .into_iter()
+                                   .chain(grid.neighbors(&from)
+                                              .map(|(_, index)| index))
+                                   .any(|index| labels.connected(index,
+                                                                 to_index));
+                assert_eq!(grid.belief_reachable(&from, &to), expected);
+            }
+        }
     }
 }
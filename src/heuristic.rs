@@ -0,0 +1,107 @@
+//! Heuristic caching: the estimates of a heuristic from every tile of a map
+//! to a single target, kept as they are computed, since the agents of a
+//! trial search towards the same target again and again.
+
+use std::cell::{Cell, RefCell};
+
+use grid::{Distance, Grid, Point};
+
+/// Wraps a heuristic, keeping the estimate from every tile of a map to the
+/// target it was last asked about. Estimates are only computed the first
+/// time they are needed, and are all dropped when the target changes.
+///
+/// Looking up an estimate is not free: it pays off with heuristics that are
+/// costlier than the octile distance, such as landmarks, or on maps where
+/// searches expand the same tiles many times.
+#[derive(Debug)]
+pub struct HeuristicCache<H> {
+    heuristic: H,
+    height: usize,
+    width: usize,
+    target: Cell<Option<Point>>,
+    /// The estimate from every tile, by index, or NaN where it is not known
+    /// yet.
+    estimates: RefCell<Vec<Distance>>,
+}
+
+impl<H> HeuristicCache<H>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    /// Caches `heuristic` for tiles of a map as large as `grid`.
+    pub fn new(heuristic: H, grid: &Grid) -> HeuristicCache<H> {
+        HeuristicCache {
+            heuristic: heuristic,
+            height: grid.height(),
+            width: grid.width(),
+            target: Cell::new(None),
+            estimates: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Estimates the cost of moving from `from` to `to`, as the wrapped
+    /// heuristic does. Points outside the map are never cached.
+    pub fn estimate(&self, from: &Point, to: &Point) -> Distance {
+        if from.y() >= self.height || from.x() >= self.width {
+            return (self.heuristic)(from, to);
+        }
+        let mut estimates = self.estimates.borrow_mut();
+        if self.target.get() != Some(*to) {
+            self.target.set(Some(*to));
+            estimates.clear();
+            estimates.resize(self.height * self.width, Distance::NAN);
+        }
+        let estimate = &mut estimates[from.y() * self.width + from.x()];
+        if estimate.is_nan() {
+            *estimate = (self.heuristic)(from, to);
+        }
+        *estimate
+    }
+
+    /// The target the cached estimates lead to, if any.
+    pub fn target(&self) -> Option<Point> {
+        self.target.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::Measure;
+    use parser::grid_from_str;
+
+    #[test]
+    fn cache_estimates_until_the_target_changes() {
+        let grid = grid_from_str("type octile\nheight 2\nwidth 3\nmap\n\
+                                  ...\n...");
+        let calls = Cell::new(0);
+        let cache = HeuristicCache::new(|from: &Point, to: &Point| {
+                                            calls.set(calls.get() + 1);
+                                            Distance::octile_heuristic(from,
+                                                                       to)
+                                        },
+                                        &grid);
+        let (from, to) = (Point::new(0, 0), Point::new(1, 2));
+        assert_eq!(cache.target(), None);
+        assert_eq!(cache.estimate(&from, &to),
+                   Distance::octile_heuristic(&from, &to));
+        assert_eq!(cache.estimate(&from, &to),
+                   Distance::octile_heuristic(&from, &to));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(cache.target(), Some(to));
+
+        // A new target drops every estimate.
+        let other = Point::new(0, 2);
+        assert_eq!(cache.estimate(&from, &other), 2.0);
+        assert_eq!(cache.estimate(&from, &to),
+                   Distance::octile_heuristic(&from, &to));
+        assert_eq!(calls.get(), 3);
+
+        // Points outside the map are estimated every time.
+        let outside = Point::new(5, 5);
+        cache.estimate(&outside, &to);
+        cache.estimate(&outside, &to);
+        assert_eq!(calls.get(), 5);
+        assert_eq!(cache.target(), Some(to));
+    }
+}
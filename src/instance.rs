@@ -1,272 +1,3349 @@
-use std::mem::replace;
+//! Trials: an `Instance` runs an agent through a grid, from a source to a
+//! target, measuring how it fares in a `Datum`, and collects the results of
+//! many trials in `Data`. Instances can also be driven a move at a time,
+//! with `start` and `move_agent`, by programs that choose the moves.
+
+use std::any::Any;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{stdout, Result as IOResult, Write};
+use std::mem::take;
 use std::ops::Index;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice::Iter;
+use std::vec::IntoIter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use rand::{SeedableRng, StdRng};
+use rand::{Rng, SeedableRng, StdRng};
 use rand::distributions::{IndependentSample, Range};
 
-use agent::Agent;
-use experiment::Verbosity;
-use grid::{Distance, Grid, Point};
+use agent::{Action, Agent};
+use execution::{ExecutionModel, Heatmap, Scanner};
+use experiment::{Perturbation, Region, Sampling, StepLimit, Verbosity,
+                 AUTO_STEP_FACTOR};
+use metrics::Metric;
+use observer::{Observer, Printer, Trace};
+use pursuit::Pursuit;
+use grid::{Belief, Direction, Distance, Grid, Measure, Point, Tile,
+           TileChange};
+use search::astar;
+
+/// How a single trial ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum Outcome {
+    /// The agent reached the target.
+    Solved,
+    /// The agent gave up before reaching the target.
+    #[default]
+    Unsolved,
+    /// The trial ran for longer than the experiment's timeout.
+    TimedOut,
+    /// The target cannot be reached from the source, so the trial could
+    /// not have been solved.
+    Unreachable,
+    /// The agent returned to a location it had already occupied, without
+    /// having learned anything about the grid since, so it would loop
+    /// forever.
+    Oscillating,
+    /// The agent came to believe that the target cannot be reached from its
+    /// location, and the trial was ended then, rather than when the agent
+    /// gave up.
+    Disconnected,
+    /// The agent panicked, and the trial was abandoned (see
+    /// `Instance::set_catch_panics`).
+    Errored,
+    /// The agent made as many moves as the step limit allowed without
+    /// reaching the target.
+    OutOfSteps,
+    /// The agent tried to move off the grid, onto impassable terrain, or
+    /// against the movement rules of the grid, and the trial was ended then
+    /// (see `Instance::set_validate_moves`).
+    IllegalMove,
+}
+
+impl Outcome {
+    /// Returns the name of the outcome, in snake case.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Solved => "solved",
+            Outcome::Unsolved => "unsolved",
+            Outcome::TimedOut => "timed_out",
+            Outcome::Unreachable => "unreachable",
+            Outcome::Oscillating => "oscillating",
+            Outcome::Disconnected => "disconnected",
+            Outcome::Errored => "errored",
+            Outcome::OutOfSteps => "out_of_steps",
+            Outcome::IllegalMove => "illegal_move",
+        }
+    }
+}
+
+/// A plan the agent made in some planning episode, and how much of it it
+/// followed before planning again.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct PlanEpisode {
+    /// The moves in the plan, starting with the one made right after it.
+    pub planned: usize,
+    /// The moves made before the next episode (or the end of the trial).
+    pub executed: usize,
+}
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Datum {
+    /// The source and target of the trial (where the target started, in
+    /// pursuits), once it has started.
+    pub endpoints: Option<(Point, Point)>,
     pub cost: Distance,
     pub steps: usize,
     pub episodes: usize,
     pub expansions: usize,
+    pub outcome: Outcome,
+    /// Moves onto tiles the agent had already occupied during the trial.
+    pub revisits: usize,
+    /// The largest number of times the agent occupied any single tile.
+    pub max_visits: usize,
+    /// The moves in loops the agent closed by returning to a tile it had
+    /// occupied before, which made no progress.
+    pub backtracking: usize,
+    /// The part of `cost` spent on the moves that were backtracked, rather
+    /// than on those left once loops are removed, which made progress.
+    pub exploration_cost: Distance,
+    /// The cost paid by a clairvoyant agent on the same trial, if it was
+    /// run and solved it: under the cost model, if it measures more than
+    /// the length of moves, like `model_cost`.
+    pub baseline: Option<Distance>,
+    /// The cost of the executed path under the grid's cost model, judged by
+    /// the true map, if the model measures more than the length of moves.
+    pub model_cost: Option<Distance>,
+    /// The expansions performed before each move, in order, since the
+    /// guarantees of real-time agents bound these rather than the total.
+    pub step_expansions: Vec<usize>,
+    /// The expansions performed before the agent's first move, if it made
+    /// one, which bound how long a real-time agent waits before moving.
+    pub first_move_expansions: Option<usize>,
+    /// The time the agent took to decide on its first move, if it made one.
+    pub first_move_time: Option<Duration>,
+    /// The expansions performed after the agent came to believe that the
+    /// target could not be reached, when trials are not ended then.
+    pub wasted_expansions: usize,
+    /// The message the agent panicked with, or the illegal move it tried to
+    /// make, if any.
+    pub error: Option<String>,
+    /// The moves the agent took longer to decide on than its budget allowed,
+    /// if it had one (see `Instance::set_move_budget`).
+    pub budget_overruns: Option<usize>,
+    /// The moves that failed against impassable terrain, when moves are
+    /// carried out with bumps (see `Instance::set_execution`).
+    pub bumps: Option<usize>,
+    /// The moves onto tiles next to some tile the agent had not seen yet,
+    /// which it could only assume to be free, when moves are carried out
+    /// with bumps. These measure how much the agent relies on the freespace
+    /// assumption.
+    pub near_misses: Option<usize>,
+    /// The scans the agent made with its long-range scanner, if it had one
+    /// (see `Instance::set_scanner`).
+    pub scans: Option<usize>,
+    /// The moves the agent made before catching the target, in pursuits
+    /// (see `Instance::set_pursuit`), if it caught it.
+    pub capture_time: Option<usize>,
+    /// Every planning episode, in order, with the length of its plan and
+    /// how much of it was executed.
+    pub plans: Vec<PlanEpisode>,
+    /// The values measured by the metrics added with `Instance::add_metric`,
+    /// by name, in the order the metrics were added.
+    pub custom: Vec<(String, f64)>,
 }
 
-#[derive(Debug, Default)]
-pub struct Data(Vec<Option<Datum>>);
+/// Agents that occupy some tile more than this many times in a trial are
+/// considered to be oscillating.
+pub const OSCILLATION_VISITS: usize = 3;
 
-impl Data {
-    pub fn new(capacity: usize) -> Data {
-        Data(Vec::with_capacity(capacity))
+impl Datum {
+    pub fn solved(&self) -> bool {
+        self.outcome == Outcome::Solved
     }
 
-    pub fn push(&mut self, datum: Option<Datum>) {
-        self.0.push(datum);
+    pub fn oscillating(&self) -> bool {
+        self.max_visits > OSCILLATION_VISITS
     }
 
-    pub fn print(&self) {
-        for (i, datum) in self.0.iter().enumerate() {
-            print!("Trial {}: ", i);
-            if let Some(ref datum) = *datum {
-                println!("{} ({} steps, {} episodes, {} expansions)",
-                         datum.cost,
-                         datum.steps,
-                         datum.episodes,
-                         datum.expansions);
-            } else {
-                println!("<none>");
+    /// Returns how much more the agent paid than the clairvoyant baseline,
+    /// for solved trials with a baseline, under the cost model if it
+    /// measures more than the length of moves.
+    pub fn regret(&self) -> Option<Distance> {
+        self.baseline
+            .filter(|_| self.solved())
+            .map(|baseline| self.model_cost.unwrap_or(self.cost) - baseline)
+    }
+
+    /// Returns the most expansions performed before any single move.
+    pub fn max_step_expansions(&self) -> usize {
+        self.step_expansions.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// The fraction of the moves planned over the trial that were dropped by
+    /// planning again before making them, if the agent planned at all.
+    /// Agents that commit to their plans churn little; those that replan
+    /// after every move churn the most.
+    pub fn plan_churn(&self) -> Option<f64> {
+        let planned: usize = self.plans.iter().map(|plan| plan.planned).sum();
+        let executed: usize = self.plans
+            .iter()
+            .map(|plan| min(plan.executed, plan.planned))
+            .sum();
+        if planned == 0 {
+            None
+        } else {
+            Some(1.0 - executed as f64 / planned as f64)
+        }
+    }
+
+    /// Lists every metric measured in the trial by name, leaving out those
+    /// that were not measured.
+    pub fn metrics(&self) -> Vec<(&str, String)> {
+        let mut metrics = vec![("outcome", self.outcome.name().to_owned()),
+                               ("cost", self.cost.to_string()),
+                               ("steps", self.steps.to_string()),
+                               ("episodes", self.episodes.to_string()),
+                               ("expansions", self.expansions.to_string()),
+                               ("revisits", self.revisits.to_string()),
+                               ("max_visits", self.max_visits.to_string()),
+                               ("backtracking", self.backtracking.to_string()),
+                               ("exploration_cost",
+                                self.exploration_cost.to_string()),
+                               ("max_step_expansions",
+                                self.max_step_expansions().to_string()),
+                               ("wasted_expansions",
+                                self.wasted_expansions.to_string())];
+        let optional = [("baseline", self.baseline),
+                        ("regret", self.regret()),
+                        ("model_cost", self.model_cost),
+                        ("first_move_expansions",
+                         self.first_move_expansions.map(|e| e as f64)),
+                        ("first_move_time",
+                         self.first_move_time.map(|t| t.as_secs_f64())),
+                        ("budget_overruns",
+                         self.budget_overruns.map(|o| o as f64)),
+                        ("bumps", self.bumps.map(|b| b as f64)),
+                        ("near_misses", self.near_misses.map(|n| n as f64)),
+                        ("scans", self.scans.map(|s| s as f64)),
+                        ("capture_time",
+                         self.capture_time.map(|t| t as f64)),
+                        ("plan_churn", self.plan_churn())];
+        for &(name, value) in &optional {
+            if let Some(value) = value {
+                metrics.push((name, value.to_string()));
+            }
+        }
+        for (name, value) in &self.custom {
+            metrics.push((name, value.to_string()));
+        }
+        metrics
+    }
+
+    /// Writes the results of the trial as a row of results, without the
+    /// label that identifies the trial.
+    pub fn write<W>(&self, out: &mut W) -> IOResult<()>
+        where W: Write + ?Sized
+    {
+        match self.outcome {
+            Outcome::Solved => {
+                write!(out,
+                       "{} ({} steps, {} episodes, {} expansions, {} \
+                        revisits, at most {} expansions per step)",
+                       self.cost,
+                       self.steps,
+                       self.episodes,
+                       self.expansions,
+                       self.revisits,
+                       self.max_step_expansions())?;
+                if let Some(regret) = self.regret() {
+                    write!(out, " [regret {}]", regret)?;
+                }
+                if let Some(model_cost) = self.model_cost {
+                    write!(out, " [model cost {}]", model_cost)?;
+                }
+                if self.backtracking > 0 {
+                    write!(out,
+                           " [backtracked {} moves, exploration cost {}]",
+                           self.backtracking,
+                           self.exploration_cost)?;
+                }
+                if self.oscillating() {
+                    write!(out, " [oscillating]")?;
+                }
+                if let Some(overruns) = self.budget_overruns.filter(|&o| o > 0) {
+                    write!(out, " [{} budget overruns]", overruns)?;
+                }
+                if let (Some(bumps), Some(near_misses)) =
+                    (self.bumps, self.near_misses) {
+                    write!(out,
+                           " [{} bumps, {} near misses]",
+                           bumps,
+                           near_misses)?;
+                }
+                if let Some(scans) = self.scans.filter(|&s| s > 0) {
+                    write!(out, " [{} scans]", scans)?;
+                }
+                for (name, value) in &self.custom {
+                    write!(out, " [{} {}]", name, value)?;
+                }
+                writeln!(out)?
+            }
+            Outcome::Unsolved if self.wasted_expansions > 0 => {
+                writeln!(out,
+                         "<none> [{} expansions after disconnection]",
+                         self.wasted_expansions)?
+            }
+            Outcome::Unsolved => writeln!(out, "<none>")?,
+            Outcome::TimedOut => writeln!(out, "<timeout>")?,
+            Outcome::Oscillating => writeln!(out, "<oscillating>")?,
+            Outcome::Unreachable => writeln!(out, "<unreachable>")?,
+            Outcome::Disconnected => writeln!(out, "<disconnected>")?,
+            Outcome::OutOfSteps => writeln!(out, "<out of steps>")?,
+            Outcome::Errored => {
+                writeln!(out,
+                         "<error: {}>",
+                         self.error.as_deref().unwrap_or("unknown"))?
+            }
+            Outcome::IllegalMove => {
+                writeln!(out,
+                         "<illegal move: {}>",
+                         self.error.as_deref().unwrap_or("unknown"))?
             }
         }
+        Ok(())
     }
 }
 
-impl Index<usize> for Data {
-    type Output = Option<Datum>;
+/// The distribution of the expansions performed before each move.
+#[derive(Debug, Default, PartialEq)]
+pub struct StepExpansions {
+    pub median: usize,
+    pub p95: usize,
+    pub max: usize,
+}
 
-    fn index(&self, index: usize) -> &Option<Datum> {
-        &self.0[index]
+impl StepExpansions {
+    /// Describes the expansions performed before each of the given moves.
+    pub fn new<I>(expansions: I) -> StepExpansions
+        where I: IntoIterator<Item = usize>
+    {
+        let mut expansions: Vec<usize> = expansions.into_iter().collect();
+        if expansions.is_empty() {
+            return StepExpansions::default();
+        }
+        expansions.sort_unstable();
+        // Nearest-rank percentiles.
+        let percentile = |p: usize| {
+            expansions[(p * expansions.len()).div_ceil(100).max(1) - 1]
+        };
+        StepExpansions {
+            median: percentile(50),
+            p95: percentile(95),
+            max: expansions[expansions.len() - 1],
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct Instance<'a, A> {
-    grid: &'a mut Grid,
-    agent: A,
-    location: Point,
-    data: Datum,
-    verbosity: Verbosity,
+/// Statistics aggregated over the trials in a `Data`. Means are taken over
+/// the solved trials only.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub trials: usize,
+    pub solved: usize,
+    /// Trials known to be unsolvable, which are not held against the agent.
+    pub unreachable: usize,
+    pub cost: Distance,
+    pub steps: f64,
+    pub episodes: f64,
+    pub expansions: f64,
+    /// The mean regret over solved trials with a baseline, if there are any.
+    pub regret: Option<Distance>,
+    /// The mean cost under the cost model over solved trials, if it was
+    /// measured.
+    pub model_cost: Option<Distance>,
+    /// The mean number of backtracked moves over solved trials, and the
+    /// mean cost spent on them.
+    pub backtracking: f64,
+    pub exploration_cost: Distance,
+    /// The expansions performed before each move, over the solved trials.
+    pub step_expansions: StepExpansions,
+    /// The mean expansions performed, and time taken, before the first
+    /// move of solved trials.
+    pub first_move_expansions: f64,
+    pub first_move_time: Duration,
+    /// Trials ended once the agent believed the target to be unreachable.
+    pub disconnected: usize,
+    /// Trials abandoned because the agent panicked.
+    pub errored: usize,
+    /// Trials ended at an illegal move of the agent.
+    pub illegal: usize,
+    /// The expansions performed, over every trial, after the agent believed
+    /// the target to be unreachable.
+    pub wasted_expansions: usize,
+    /// The moves, over every trial, that the agent took longer to decide on
+    /// than its budget allowed.
+    pub budget_overruns: usize,
+    /// The moves, over every trial, that failed against impassable terrain.
+    pub bumps: usize,
+    /// The moves, over every trial, onto tiles next to some tile the agent
+    /// had not seen yet, when they were counted.
+    pub near_misses: usize,
+    /// The scans made with the long-range scanner, over every trial.
+    pub scans: usize,
+    /// The mean number of moves taken to catch the target, over the trials
+    /// where it fled and was caught, if there are any.
+    pub capture_time: Option<f64>,
+    /// The mean plan churn over solved trials where the agent planned, if
+    /// there are any (see `Datum::plan_churn`).
+    pub plan_churn: Option<f64>,
+    /// The mean of every value measured by custom metrics, by name, over the
+    /// solved trials that measured it.
+    pub custom: Vec<(String, f64)>,
 }
 
-impl<'a, A> Instance<'a, A>
-    where A: Agent
-{
-    pub fn new(grid: &'a mut Grid,
-               agent: A,
-               verbosity: Verbosity)
-               -> Instance<'a, A> {
-        Instance {
-            grid: grid,
-            agent: agent,
-            location: Point::new(0, 0),
-            data: Datum::default(),
-            verbosity: verbosity,
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}/{} solved", self.solved, self.trials)?;
+        if self.unreachable > 0 {
+            write!(f, " ({} unreachable)", self.unreachable)?;
+        }
+        if self.disconnected > 0 {
+            write!(f, " ({} disconnected)", self.disconnected)?;
+        }
+        if self.errored > 0 {
+            write!(f, " ({} errored)", self.errored)?;
+        }
+        if self.illegal > 0 {
+            write!(f, " ({} illegal)", self.illegal)?;
+        }
+        write!(f,
+               ", mean {} ({} steps, {} episodes, {} expansions)",
+               self.cost,
+               self.steps,
+               self.episodes,
+               self.expansions)?;
+        write!(f,
+               ", per-step expansions median {}, 95th percentile {}, max {}",
+               self.step_expansions.median,
+               self.step_expansions.p95,
+               self.step_expansions.max)?;
+        write!(f,
+               ", mean first move after {} expansions ({:.6}s)",
+               self.first_move_expansions,
+               self.first_move_time.as_secs_f64())?;
+        if let Some(regret) = self.regret {
+            write!(f, ", mean regret {}", regret)?;
+        }
+        if let Some(model_cost) = self.model_cost {
+            write!(f, ", mean model cost {}", model_cost)?;
         }
+        if self.backtracking > 0.0 {
+            write!(f,
+                   ", mean backtracking {} moves (exploration cost {})",
+                   self.backtracking,
+                   self.exploration_cost)?;
+        }
+        if self.wasted_expansions > 0 {
+            write!(f,
+                   ", {} expansions after disconnection",
+                   self.wasted_expansions)?;
+        }
+        if self.budget_overruns > 0 {
+            write!(f, ", {} budget overruns", self.budget_overruns)?;
+        }
+        if self.bumps > 0 || self.near_misses > 0 {
+            write!(f,
+                   ", {} bumps, {} near misses",
+                   self.bumps,
+                   self.near_misses)?;
+        }
+        if self.scans > 0 {
+            write!(f, ", {} scans", self.scans)?;
+        }
+        if let Some(capture_time) = self.capture_time {
+            write!(f, ", mean capture time {}", capture_time)?;
+        }
+        if let Some(plan_churn) = self.plan_churn {
+            write!(f, ", mean plan churn {}", plan_churn)?;
+        }
+        for (name, value) in &self.custom {
+            write!(f, ", mean {} {}", name, value)?;
+        }
+        Ok(())
     }
+}
 
-    fn move_agent(&mut self, point: Point) {
-        self.data.steps += 1;
-        self.data.cost += self.grid[&point].g() - self.grid[&self.location].g();
-        self.location = point;
-        self.grid.look(&self.location);
+/// Statistics aggregated over the trials of a scenario bucket, which share
+/// roughly the same optimal length.
+#[derive(Debug, Default, PartialEq)]
+pub struct Bucket {
+    pub bucket: usize,
+    pub trials: usize,
+    pub solved: usize,
+    /// The mean ratio of the cost paid to the optimal length given by the
+    /// scenario, over the solved trials.
+    pub suboptimality: f64,
+    /// The mean expansions over the solved trials.
+    pub expansions: f64,
+}
+
+impl Bucket {
+    pub fn success_rate(&self) -> f64 {
+        if self.trials == 0 {
+            0.0
+        } else {
+            self.solved as f64 / self.trials as f64
+        }
     }
+}
 
-    fn print(&self, target: &Point) {
-        for (y, row) in self.grid.iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                let point = Point::new(y, x);
-                if self.location == point {
-                    print!("a");
-                } else if *target == point {
-                    print!("*");
-                } else {
-                    print!("{}", cell.belief());
+impl Display for Bucket {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f,
+               "Bucket {}: {}/{} solved (success rate {:.4}), mean \
+                suboptimality {:.4}, mean {} expansions",
+               self.bucket,
+               self.solved,
+               self.trials,
+               self.success_rate(),
+               self.suboptimality,
+               self.expansions)
+    }
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Data {
+    trials: Vec<Datum>,
+    heatmap: Heatmap,
+    lines: Option<Vec<usize>>,
+    /// The scenario bucket and optimal length of every trial that was to
+    /// run, when they were read from a scenario.
+    buckets: Option<Vec<(usize, Distance)>>,
+    /// The position of every trial among all those that were to run, when
+    /// only some of them did.
+    positions: Option<Vec<usize>>,
+    /// The seed the trials were drawn with, if they were random.
+    seed: Option<usize>,
+    tag: Option<String>,
+}
+
+impl Data {
+    pub fn new(capacity: usize) -> Data {
+        Data {
+            trials: Vec::with_capacity(capacity),
+            heatmap: Heatmap::default(),
+            lines: None,
+            buckets: None,
+            positions: None,
+            seed: None,
+            tag: None,
+        }
+    }
+
+    /// Identifies every trial by the line of the file it was read from,
+    /// instead of by its position. `lines` lists the lines of all the trials
+    /// that were to run, including any that did not.
+    pub fn set_lines(&mut self, lines: Vec<usize>) {
+        self.lines = Some(lines);
+    }
+
+    /// Records the scenario bucket and optimal length of every trial that
+    /// was to run, including any that did not, to report results by bucket.
+    pub fn set_buckets(&mut self, buckets: Vec<(usize, Distance)>) {
+        self.buckets = Some(buckets);
+    }
+
+    /// Aggregates the trials by scenario bucket, in increasing order, if
+    /// they came from a scenario.
+    pub fn buckets(&self) -> Option<Vec<Bucket>> {
+        let scenario = self.buckets.as_ref()?;
+        let mut buckets: Vec<Bucket> = Vec::new();
+        for (i, datum) in self.trials.iter().enumerate() {
+            let (number, optimal) = scenario[self.position(i)];
+            let index = match buckets.binary_search_by_key(&number,
+                                                           |b| b.bucket) {
+                Ok(index) => index,
+                Err(index) => {
+                    buckets.insert(index,
+                                   Bucket {
+                                       bucket: number,
+                                       ..Bucket::default()
+                                   });
+                    index
                 }
+            };
+            let bucket = &mut buckets[index];
+            bucket.trials += 1;
+            if datum.solved() {
+                bucket.solved += 1;
+                bucket.expansions += datum.expansions as f64;
+                bucket.suboptimality += if optimal > 0.0 {
+                    datum.cost / optimal
+                } else {
+                    1.0
+                };
             }
-            println!();
         }
-        println!();
+        for bucket in &mut buckets {
+            if bucket.solved > 0 {
+                bucket.expansions /= bucket.solved as f64;
+                bucket.suboptimality /= bucket.solved as f64;
+            }
+        }
+        Some(buckets)
     }
 
-    pub fn run_once(&mut self, source: Point, target: Point) -> Option<Datum> {
-        self.data = Datum::default();
-        self.agent.reset();
-        self.location = source;
-        self.grid.look(&self.location);
-
-        while let Some(agent_datum) =
-            self.agent.act(self.grid, &self.location, &target) {
-            if self.verbosity >= Verbosity::Two {
-                self.print(&target);
+    /// Writes the results of every trial in long format, as comma-separated
+    /// `prefix,trial,metric,value` rows, where trials are labeled by line or
+    /// position as in `write`.
+    pub fn write_tidy<W>(&self, out: &mut W, prefix: &str) -> IOResult<()>
+        where W: Write
+    {
+        for (id, datum) in self.iter_ids() {
+            for (metric, value) in datum.metrics() {
+                writeln!(out, "{},{},{},{}", prefix, id, metric, value)?;
             }
+        }
+        Ok(())
+    }
 
-            if agent_datum.expansions > 0 {
-                self.data.episodes += 1;
-                self.data.expansions += agent_datum.expansions;
+    /// Writes one row per scenario bucket, if the trials came from a
+    /// scenario.
+    pub fn write_buckets<W>(&self, out: &mut W) -> IOResult<()>
+        where W: Write
+    {
+        for bucket in self.buckets().unwrap_or_default() {
+            if let Some(ref tag) = self.tag {
+                write!(out, "[{}] ", tag)?;
             }
+            writeln!(out, "{}", bucket)?;
+        }
+        Ok(())
+    }
 
-            self.move_agent(agent_datum.action);
+    /// Returns the position of the trial at `index` among all those that
+    /// were to run.
+    fn position(&self, index: usize) -> usize {
+        self.positions.as_ref().map_or(index, |positions| positions[index])
+    }
 
-            if agent_datum.action == target {
-                if self.verbosity >= Verbosity::Two {
-                    self.print(&target);
-                }
-                return Some(replace(&mut self.data, Datum::default()));
+    /// Returns the id of the trial at `index`, which labels it in the
+    /// results: the line of the file it was read from, if it was read from
+    /// one, or its position among all the trials that were to run. Ids do
+    /// not depend on the order the trials ran in.
+    pub fn id(&self, index: usize) -> usize {
+        let position = self.position(index);
+        self.lines.as_ref().map_or(position, |lines| lines[position])
+    }
+
+    /// Returns the trial with the given `id`, if it ran.
+    pub fn get(&self, id: usize) -> Option<&Datum> {
+        (0..self.trials.len())
+            .find(|&i| self.id(i) == id)
+            .map(|i| &self.trials[i])
+    }
+
+    /// Iterates over the trials along with their ids, in order.
+    pub fn iter_ids(&self) -> impl Iterator<Item = (usize, &Datum)> {
+        self.trials
+            .iter()
+            .enumerate()
+            .map(move |(i, datum)| (self.id(i), datum))
+    }
+
+    /// Puts back in order trials that ran in the given `order` of positions,
+    /// up to those that did. If they were not the first trials, they are
+    /// still identified by their positions among all of them.
+    pub fn restore_order(&mut self, order: &[usize]) {
+        let trials = take(&mut self.trials)
+            .into_iter()
+            .zip(order.iter().cloned())
+            .map(|(datum, position)| (position, datum))
+            .collect();
+        self.set_positioned(trials);
+    }
+
+    /// Adds the trials in `other`, which ran some other trials of those
+    /// that were to run (as when trials are split between workers, or a run
+    /// is resumed), keeping every trial at its position among all of them.
+    /// Where both ran the same trial, the one in these results is kept. The
+    /// lines, buckets, seed and tag of these results are kept, or taken from
+    /// `other` if these have none, and visits are added up.
+    pub fn merge(&mut self, other: Data) {
+        let mut trials = take(&mut self.trials)
+            .into_iter()
+            .enumerate()
+            .map(|(i, datum)| (self.position(i), datum))
+            .collect::<Vec<_>>();
+        let positions = (0..other.len()).map(|i| other.position(i));
+        trials.extend(positions.collect::<Vec<_>>()
+                          .into_iter()
+                          .zip(other.trials));
+        self.positions = None;
+        self.set_positioned(trials);
+        self.heatmap.merge(&other.heatmap);
+        self.lines = self.lines.take().or(other.lines);
+        self.buckets = self.buckets.take().or(other.buckets);
+        self.seed = self.seed.or(other.seed);
+        self.tag = self.tag.take().or(other.tag);
+    }
+
+    /// Keeps `trials`, each at the given position among all those that were
+    /// to run, in order of position, and only the first of any at the same
+    /// position.
+    fn set_positioned(&mut self, mut trials: Vec<(usize, Datum)>) {
+        trials.sort_by_key(|&(position, _)| position);
+        trials.dedup_by_key(|&mut (position, _)| position);
+        if trials.iter().enumerate().any(|(i, &(position, _))| i != position) {
+            self.positions =
+                Some(trials.iter().map(|&(position, _)| position).collect());
+        }
+        self.trials = trials.into_iter().map(|(_, datum)| datum).collect();
+    }
+
+    /// Labels every row written for these trials with `tag`.
+    pub fn set_tag(&mut self, tag: Option<String>) {
+        self.tag = tag;
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Records the seed the trials were drawn with, when they were random.
+    pub fn set_seed(&mut self, seed: Option<usize>) {
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> Option<usize> {
+        self.seed
+    }
+
+    pub fn push(&mut self, datum: Datum) {
+        self.trials.push(datum);
+    }
+
+    /// Marks the trials that were not `reachable` and that the agent gave up
+    /// on, or found to be disconnected, as `Outcome::Unreachable`.
+    pub fn mark_unreachable<I>(&mut self, reachable: I)
+        where I: IntoIterator<Item = bool>
+    {
+        let reachable = reachable.into_iter().collect::<Vec<_>>();
+        for i in 0..self.trials.len() {
+            let reachable = reachable.get(self.position(i)) != Some(&false);
+            let datum = &mut self.trials[i];
+            let failed = datum.outcome == Outcome::Unsolved ||
+                         datum.outcome == Outcome::Disconnected;
+            if !reachable && failed {
+                datum.outcome = Outcome::Unreachable;
             }
         }
+    }
 
-        None
+    /// Records the costs paid in `baseline`, which ran the same trials under
+    /// the same cost model, as the baselines of these trials.
+    pub fn set_baselines(&mut self, baseline: &Data) {
+        for i in 0..self.trials.len() {
+            let position = self.position(i);
+            let reference = (0..baseline.len())
+                .find(|&j| baseline.position(j) == position)
+                .map(|j| &baseline.trials[j]);
+            self.trials[i].baseline = reference.filter(|r| r.solved())
+                .map(|r| r.model_cost.unwrap_or(r.cost));
+        }
     }
 
-    fn build_trials(&mut self,
-                    start: usize,
-                    end: usize,
-                    seed: usize)
-                    -> Vec<(Point, Point)> {
-        let mut rng: StdRng = SeedableRng::from_seed([seed,
-                                                      self.grid.height(),
-                                                      self.grid.width()]
-                                                             .as_ref());
-        let mut trials = Vec::with_capacity(end - start);
-        let yrange = Range::new(0, self.grid.height());
-        let xrange = Range::new(0, self.grid.width());
-
-        for trial_idx in 0..end {
-            loop {
-                let source = Point::new(yrange.ind_sample(&mut rng),
-                                        xrange.ind_sample(&mut rng));
-                let target = Point::new(yrange.ind_sample(&mut rng),
-                                        xrange.ind_sample(&mut rng));
-                if source != target && self.grid[&source].passable() &&
-                   self.grid[&target].passable() &&
-                   self.grid.has_path(&source, &target) {
-                    if trial_idx >= start {
-                        trials.push((source, target));
+    /// Adds the tiles visited during a trial to the heat map of all trials.
+    pub fn record_visits(&mut self, visits: &Heatmap) {
+        self.heatmap.merge(visits);
+    }
+
+    /// Returns the number of times each tile was visited over all trials.
+    pub fn heatmap(&self) -> &Heatmap {
+        &self.heatmap
+    }
+
+    pub fn len(&self) -> usize {
+        self.trials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trials.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, Datum> {
+        self.trials.iter()
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            trials: self.trials.len(),
+            solved: self.count(Outcome::Solved),
+            unreachable: self.count(Outcome::Unreachable),
+            disconnected: self.count(Outcome::Disconnected),
+            errored: self.count(Outcome::Errored),
+            illegal: self.count(Outcome::IllegalMove),
+            wasted_expansions: self.trials
+                .iter()
+                .map(|datum| datum.wasted_expansions)
+                .sum(),
+            budget_overruns: self.trials
+                .iter()
+                .filter_map(|datum| datum.budget_overruns)
+                .sum(),
+            bumps: self.trials.iter().filter_map(|datum| datum.bumps).sum(),
+            near_misses: self.trials
+                .iter()
+                .filter_map(|datum| datum.near_misses)
+                .sum(),
+            scans: self.trials.iter().filter_map(|datum| datum.scans).sum(),
+            ..Summary::default()
+        };
+        if summary.solved == 0 {
+            return summary;
+        }
+
+        for datum in self.trials.iter().filter(|datum| datum.solved()) {
+            summary.cost += datum.cost;
+            summary.steps += datum.steps as f64;
+            summary.episodes += datum.episodes as f64;
+            summary.expansions += datum.expansions as f64;
+            summary.backtracking += datum.backtracking as f64;
+            summary.exploration_cost += datum.exploration_cost;
+            summary.first_move_expansions +=
+                datum.first_move_expansions.unwrap_or(0) as f64;
+            summary.first_move_time += datum.first_move_time
+                .unwrap_or_default();
+        }
+        let regrets: Vec<Distance> =
+            self.trials.iter().filter_map(Datum::regret).collect();
+        if !regrets.is_empty() {
+            summary.regret = Some(regrets.iter().sum::<Distance>() /
+                                  regrets.len() as f64);
+        }
+        summary.step_expansions = StepExpansions::new(self.trials
+            .iter()
+            .filter(|datum| datum.solved())
+            .flat_map(|datum| datum.step_expansions.iter().cloned()));
+        let model_costs: Vec<Distance> = self.trials
+            .iter()
+            .filter(|datum| datum.solved())
+            .filter_map(|datum| datum.model_cost)
+            .collect();
+        if !model_costs.is_empty() {
+            summary.model_cost = Some(model_costs.iter().sum::<Distance>() /
+                                      model_costs.len() as f64);
+        }
+        let churns: Vec<f64> = self.trials
+            .iter()
+            .filter(|datum| datum.solved())
+            .filter_map(Datum::plan_churn)
+            .collect();
+        if !churns.is_empty() {
+            summary.plan_churn = Some(churns.iter().sum::<f64>() /
+                                      churns.len() as f64);
+        }
+        let capture_times: Vec<usize> =
+            self.trials.iter().filter_map(|datum| datum.capture_time).collect();
+        if !capture_times.is_empty() {
+            summary.capture_time = Some(capture_times.iter().sum::<usize>() as
+                                        f64 /
+                                        capture_times.len() as f64);
+        }
+        let mut custom: Vec<(String, f64, usize)> = Vec::new();
+        for datum in self.trials.iter().filter(|datum| datum.solved()) {
+            for (name, value) in &datum.custom {
+                match custom.iter_mut().find(|metric| metric.0 == *name) {
+                    Some(metric) => {
+                        metric.1 += value;
+                        metric.2 += 1;
                     }
-                    break;
+                    None => custom.push((name.clone(), *value, 1)),
                 }
             }
         }
-        trials
+        summary.custom = custom.into_iter()
+            .map(|(name, sum, count)| (name, sum / count as f64))
+            .collect();
+
+        let solved = summary.solved as f64;
+        summary.cost /= solved;
+        summary.steps /= solved;
+        summary.episodes /= solved;
+        summary.expansions /= solved;
+        summary.backtracking /= solved;
+        summary.exploration_cost /= solved;
+        summary.first_move_expansions /= solved;
+        summary.first_move_time /= summary.solved as u32;
+        summary
+    }
+
+    /// Returns the number of trials that ended with `outcome`.
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.trials.iter().filter(|datum| datum.outcome == outcome).count()
+    }
+
+    /// Returns the number of trials that were solved.
+    pub fn success_count(&self) -> usize {
+        self.count(Outcome::Solved)
     }
 
-    pub fn run_trials(&mut self,
-                      start: usize,
-                      end: usize,
-                      seed: usize)
-                      -> Data {
-        let trials = self.build_trials(start, end, seed);
+    pub fn print(&self) {
+        let stdout = stdout();
+        self.write(&mut stdout.lock())
+            .expect("Could not write results to stdout");
+    }
 
-        let mut results = Data::new(end - start);
-        for trial in &trials {
-            if self.verbosity >= Verbosity::One {
-                println!("Running search from {} to {}.", trial.0, trial.1);
+    pub fn write<W>(&self, out: &mut W) -> IOResult<()>
+        where W: Write
+    {
+        for (id, datum) in self.iter_ids() {
+            if let Some(ref tag) = self.tag {
+                write!(out, "[{}] ", tag)?;
             }
-            self.grid.forget();
-            results.push(self.run_once(trial.0, trial.1));
+            match self.lines {
+                Some(_) => write!(out, "Line {}: ", id)?,
+                None => write!(out, "Trial {}: ", id)?,
+            }
+            datum.write(out)?;
         }
-        results
+        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Index<usize> for Data {
+    type Output = Datum;
 
-    use agent::{AlwaysAstar, RepeatedAstar};
-    use grid::Measure;
-    use parser::grid_from_str;
+    fn index(&self, index: usize) -> &Datum {
+        &self.trials[index]
+    }
+}
 
-    use std::f64::consts::SQRT_2;
+impl<'a> IntoIterator for &'a Data {
+    type Item = &'a Datum;
+    type IntoIter = Iter<'a, Datum>;
 
-    #[test]
-    fn always_astar() {
-        let mut grid = grid_from_str("type octile
-height 4
-width 4
-map
-....
-.TT.
-.TT.
-....");
+    fn into_iter(self) -> Iter<'a, Datum> {
+        self.trials.iter()
+    }
+}
 
-        let start = Point::new(0, 0);
-        let goal = Point::new(3, 3);
+impl IntoIterator for Data {
+    type Item = Datum;
+    type IntoIter = IntoIter<Datum>;
 
-        let agent = AlwaysAstar::new(Distance::octile_heuristic);
-        let mut instance = Instance::new(&mut grid, agent, Verbosity::Two);
+    fn into_iter(self) -> IntoIter<Datum> {
+        self.trials.into_iter()
+    }
+}
 
-        let results = instance.run_once(start, goal).unwrap();
+/// Draws points uniformly from a `Region` of a particular grid.
+enum RegionSampler {
+    Rectangle(Range<usize>, Range<usize>),
+    Points(Vec<Point>),
+}
 
-        assert_eq!(results.steps, 5);
-        assert_eq!(results.cost, 4.0 + SQRT_2);
-        assert_eq!(results.episodes, 5);
+impl RegionSampler {
+    fn new(region: &Region,
+           height: usize,
+           width: usize)
+           -> Result<RegionSampler, String> {
+        let rectangle = |top, left, bottom, right| {
+            if top <= bottom && bottom < height && left <= right &&
+               right < width {
+                Ok(RegionSampler::Rectangle(Range::new(top, bottom + 1),
+                                            Range::new(left, right + 1)))
+            } else {
+                Err(format!("Region {:?} does not fit in a {}x{} map.",
+                            region,
+                            height,
+                            width))
+            }
+        };
+
+        match *region {
+            Region::Anywhere => {
+                rectangle(0, 0, height.wrapping_sub(1), width.wrapping_sub(1))
+            }
+            Region::Interior => {
+                rectangle(1, 1, height.wrapping_sub(2), width.wrapping_sub(2))
+            }
+            Region::Rectangle { top, left, bottom, right } => {
+                rectangle(top, left, bottom, right)
+            }
+            Region::Edges => {
+                let on_edge = |p: &Point| {
+                    p.y() == 0 || p.x() == 0 || p.y() + 1 == height ||
+                    p.x() + 1 == width
+                };
+                let points: Vec<Point> = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| Point::new(y, x)))
+                    .filter(on_edge)
+                    .collect();
+                if points.is_empty() {
+                    Err("The map has no edges to sample from.".to_owned())
+                } else {
+                    Ok(RegionSampler::Points(points))
+                }
+            }
+        }
     }
 
-    #[test]
-    fn repeated_astar() {
-        let mut grid = grid_from_str("type octile
-height 4
-width 4
-map
-....
-.TT.
-.TT.
-....");
+    fn sample<R>(&self, rng: &mut R) -> Point
+        where R: Rng
+    {
+        match *self {
+            RegionSampler::Rectangle(ref ys, ref xs) => {
+                Point::new(ys.ind_sample(rng), xs.ind_sample(rng))
+            }
+            RegionSampler::Points(ref points) => {
+                points[Range::new(0, points.len()).ind_sample(rng)]
+            }
+        }
+    }
+}
 
-        let start = Point::new(0, 0);
-        let goal = Point::new(3, 3);
+/// Generates the random trials numbered `start` to `end` for `grid`. Every
+/// trial is a pair of distinct passable points, along with whether they are
+/// connected by a path (which they always are, unless `sampling` allows
+/// otherwise), and the trials generated for a given seed are the same
+/// regardless of `start`. Sources and targets are drawn from the regions
+/// given by `sampling`. Fails if some trial cannot be found within the
+/// number of attempts allowed by `sampling`, or if a region does not fit in
+/// the grid.
+pub fn build_trials(grid: &mut Grid,
+                    start: usize,
+                    end: usize,
+                    seed: usize,
+                    sampling: &Sampling)
+                    -> Result<Vec<(Point, Point, bool)>, String> {
+    profile_span!("build_trials", start = start, end = end);
 
-        let agent = RepeatedAstar::new(Distance::octile_heuristic);
-        let mut instance = Instance::new(&mut grid, agent, Verbosity::Two);
+    let mut rng: StdRng =
+        SeedableRng::from_seed([seed, grid.height(), grid.width()].as_ref());
+    let mut trials = Vec::with_capacity(end - start);
+    let sources =
+        RegionSampler::new(&sampling.sources, grid.height(), grid.width())?;
+    let targets =
+        RegionSampler::new(&sampling.targets, grid.height(), grid.width())?;
 
-        let results = instance.run_once(start, goal).unwrap();
+    for trial_idx in 0..end {
+        let mut attempts = 0;
+        loop {
+            if attempts == sampling.attempts {
+                return Err(format!("Could not find a valid pair of points \
+                                    for trial {} in {} attempts. The map may \
+                                    have too few connected passable tiles.",
+                                   trial_idx,
+                                   attempts));
+            }
+            attempts += 1;
 
-        assert_eq!(results.steps, 5);
-        assert_eq!(results.cost, 4.0 + SQRT_2);
-        assert_eq!(results.episodes, 2);
+            let source = sources.sample(&mut rng);
+            let target = targets.sample(&mut rng);
+            if source == target || !grid[&source].passable() ||
+               !grid[&target].passable() {
+                continue;
+            }
+            let reachable = grid.has_path(&source, &target);
+            if reachable || sampling.allow_unreachable {
+                if trial_idx >= start {
+                    trials.push((source, target, reachable));
+                }
+                break;
+            }
+        }
     }
+    Ok(trials)
+}
 
-    #[test]
-    fn repeated_astar_trials() {
-        let mut grid = grid_from_str("type octile
-height 4
-width 4
-map
-....
-.TT.
-.TT.
-....");
+pub struct Instance<'a, A> {
+    grid: &'a mut Grid,
+    agent: A,
+    location: Point,
+    /// The direction the agent last moved in, during the current trial.
+    previous: Option<Direction>,
+    data: Datum,
+    printer: Printer,
+    observers: Vec<Box<dyn Observer>>,
+    metrics: Vec<Box<dyn Metric>>,
+    timeout: Option<Duration>,
+    move_budget: Option<Duration>,
+    perturbation: Option<Perturbation>,
+    /// Set from elsewhere (such as a signal handler) to stop running trials.
+    interrupt: Option<Arc<AtomicBool>>,
+    visits: Heatmap,
+    /// The locations the agent occupied during the current trial, in order.
+    trajectory: Vec<Point>,
+    detect_oscillation: bool,
+    /// The (location, target, beliefs) states seen during the current trial.
+    states: HashSet<(Point, Point, u64)>,
+    detect_disconnection: bool,
+    catch_panics: bool,
+    validate_moves: bool,
+    execution: ExecutionModel,
+    scanner: Option<Scanner>,
+    pursuit: Option<Pursuit>,
+    retain_beliefs: bool,
+    step_limit: Option<StepLimit>,
+    /// The expansions performed when the agent first believed the target to
+    /// be unreachable, during the current trial.
+    disconnected_at: Option<usize>,
+}
+
+impl<'a, A> Instance<'a, A>
+    where A: Agent
+{
+    pub fn new(grid: &'a mut Grid,
+               agent: A,
+               verbosity: Verbosity)
+               -> Instance<'a, A> {
+        Instance {
+            grid: grid,
+            agent: agent,
+            location: Point::new(0, 0),
+            previous: None,
+            data: Datum::default(),
+            printer: Printer::new(verbosity),
+            observers: Vec::new(),
+            metrics: Vec::new(),
+            timeout: None,
+            move_budget: None,
+            perturbation: None,
+            interrupt: None,
+            visits: Heatmap::default(),
+            trajectory: Vec::new(),
+            detect_oscillation: true,
+            states: HashSet::new(),
+            detect_disconnection: true,
+            catch_panics: false,
+            validate_moves: false,
+            execution: ExecutionModel::default(),
+            scanner: None,
+            pursuit: None,
+            retain_beliefs: false,
+            step_limit: None,
+            disconnected_at: None,
+        }
+    }
+
+    /// Sets what the printer traces, besides what the verbosity implies.
+    /// Tracing or recording searches makes the grid record the nodes they
+    /// expand.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.printer.set_trace(trace);
+        self.grid.set_trace_search(trace.search || trace.record_search);
+    }
+
+    /// Adds `observer` to be notified of the events of every trial, after
+    /// the ones added before it.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Removes every observer added with `add_observer`, returning them.
+    pub fn take_observers(&mut self) -> Vec<Box<dyn Observer>> {
+        take(&mut self.observers)
+    }
+
+    /// Adds `metric` to be measured over every trial, its values following
+    /// those of the metrics added before it in `Datum::custom`.
+    pub fn add_metric(&mut self, metric: Box<dyn Metric>) {
+        self.metrics.push(metric);
+    }
+
+    /// Removes every metric added with `add_metric`, returning them.
+    pub fn take_metrics(&mut self) -> Vec<Box<dyn Metric>> {
+        take(&mut self.metrics)
+    }
+
+    /// Records the values of every metric in the results of the trial.
+    fn finish_metrics(&mut self) {
+        for metric in &mut self.metrics {
+            let values = metric.finish(self.grid, &self.data);
+            self.data.custom.extend(values);
+        }
+    }
+
+    /// Calls `event` on the printer, and then on every other observer.
+    fn notify<F>(&mut self, event: F)
+        where F: Fn(&mut dyn Observer, &Grid)
+    {
+        event(&mut self.printer, self.grid);
+        for observer in &mut self.observers {
+            event(observer.as_mut(), self.grid);
+        }
+    }
+
+    /// Sets whether trials end with `Outcome::Oscillating` as soon as the
+    /// agent returns to a location with the same beliefs it had there
+    /// before, while the target is where it was then. Waiting in place is
+    /// not returning, since agents may wait for good reason, as for a path
+    /// to clear. Enabled by default.
+    pub fn set_detect_oscillation(&mut self, detect: bool) {
+        self.detect_oscillation = detect;
+    }
+
+    /// Records the current (location, target, beliefs) state, returning
+    /// whether it had been seen before during this trial.
+    fn repeated_state(&mut self, target: &Point) -> bool {
+        self.detect_oscillation &&
+        !self.states
+             .insert((self.location, *target, self.grid.belief_hash()))
+    }
+
+    /// Sets whether trials end with `Outcome::Disconnected` as soon as the
+    /// agent believes the target to be unreachable from its location.
+    /// Otherwise, they go on until the agent gives up, and the expansions it
+    /// performs meanwhile are counted as wasted. Enabled by default.
+    pub fn set_detect_disconnection(&mut self, detect: bool) {
+        self.detect_disconnection = detect;
+    }
+
+    /// Limits how many moves the agent may make in each trial. Trials that
+    /// reach the limit end with `Outcome::OutOfSteps`.
+    pub fn set_step_limit(&mut self, step_limit: Option<StepLimit>) {
+        self.step_limit = step_limit;
+    }
+
+    /// Works out how many moves the agent may make on the way from `source`
+    /// to `target`, if it is limited.
+    fn max_steps(&mut self, source: &Point, target: &Point) -> Option<usize> {
+        let limit = match self.step_limit? {
+            StepLimit::Absolute(steps) => return Some(steps),
+            StepLimit::Straight(factor) => {
+                factor * Distance::euclidean_heuristic(source, target)
+            }
+            StepLimit::Auto => {
+                let path = astar(self.grid,
+                                 source,
+                                 target,
+                                 Distance::octile_heuristic,
+                                 Tile::passable)?
+                        .path;
+                // The search is not the agent's, so it is left out of traces.
+                self.grid.take_search_trace();
+                AUTO_STEP_FACTOR * path.len() as f64
+            }
+        };
+        Some(limit.ceil() as usize)
+    }
+
+    /// Sets whether `run_trial` catches panics in the agent, ending the trial
+    /// with `Outcome::Errored` instead of unwinding. Disabled by default.
+    pub fn set_catch_panics(&mut self, catch: bool) {
+        self.catch_panics = catch;
+    }
+
+    /// Sets whether every move is checked before it is made, ending the
+    /// trial with `Outcome::IllegalMove` at the first that is not legal
+    /// instead of carrying it out. Legal moves stay in place, or reach a
+    /// neighbor that the true terrain and the movement rules of the grid
+    /// allow entering. Disabled by default, which trusts agents to move as
+    /// they should.
+    pub fn set_validate_moves(&mut self, validate: bool) {
+        self.validate_moves = validate;
+    }
+
+    /// Sets how the moves the agent chooses are carried out. With
+    /// `ExecutionModel::Bump`, moves onto impassable terrain are bumps,
+    /// rather than illegal moves, even when moves are validated.
+    pub fn set_execution(&mut self, execution: ExecutionModel) {
+        self.execution = execution;
+    }
+
+    /// Gives the agent a long-range scanner, which it uses by sensing (see
+    /// `Action::Sense`), if given. Disabled by default, which leaves sensing
+    /// no different from waiting.
+    pub fn set_scanner(&mut self, scanner: Option<Scanner>) {
+        self.scanner = scanner;
+        self.agent.set_scanner(scanner);
+    }
+
+    pub fn scanner(&self) -> Option<Scanner> {
+        self.scanner
+    }
+
+    /// Makes the target flee from the agent after every move it makes, as
+    /// `pursuit` decides, until the agent catches it, which solves the trial.
+    /// Disabled by default, which keeps the target in place.
+    pub fn set_pursuit(&mut self, pursuit: Option<Pursuit>) {
+        self.pursuit = pursuit;
+    }
+
+    /// Removes the pursuit set with `set_pursuit`, returning it.
+    pub fn take_pursuit(&mut self) -> Option<Pursuit> {
+        self.pursuit.take()
+    }
+
+    /// Whether the agent has reached the target, or caught it in pursuits.
+    fn caught(&self, target: &Point) -> bool {
+        match self.pursuit {
+            Some(ref pursuit) => {
+                pursuit.capture.captures(self.grid, &self.location, target)
+            }
+            None => self.location == *target,
+        }
+    }
+
+    /// Moves the target at `target` as the pursuit's policy decides, if
+    /// there is a pursuit, returning where it is then.
+    fn move_target(&mut self, target: Point) -> Point {
+        let next = match self.pursuit {
+            Some(ref mut pursuit) => {
+                pursuit.policy.next(self.grid, &target, &self.location)
+            }
+            None => return target,
+        };
+        if next != target {
+            self.agent.target_moved(&next);
+        }
+        next
+    }
+
+    /// Carries out the agent's attempt to move to `point` as a bump, if the
+    /// execution model makes it one, returning whether it did. A bump costs
+    /// a step, and leaves the agent where it was, having seen the tile.
+    fn bump(&mut self, point: &Point) -> bool {
+        let cost = match self.execution {
+            ExecutionModel::Bump(cost) if !self.grid[point].passable() => cost,
+            _ => return false,
+        };
+        self.data.steps += 1;
+        self.data.cost += cost;
+        *self.data.bumps.get_or_insert(0) += 1;
+        self.grid.reveal(point);
+        self.agent.bumped(point);
+        let location = self.location;
+        self.notify(|observer, grid| observer.on_observe(grid, &location));
+        true
+    }
+
+    /// Whether the agent may move from its location to `point`.
+    fn legal_move(&self, point: &Point) -> bool {
+        if *point == self.location {
+            return true;
+        }
+        match (self.grid.index_of(&self.location),
+               Direction::between(&self.location, point)) {
+            (Some(index), Some(direction)) => {
+                self.grid
+                    .successors(index, None, Tile::passable)
+                    .any(|(_, allowed, _)| allowed == direction)
+            }
+            _ => false,
+        }
+    }
+
+    /// Ends the trial at an illegal move, described by `what`.
+    fn reject(&mut self, what: String) {
+        self.data.outcome = Outcome::IllegalMove;
+        self.data.error = Some(what);
+    }
+
+    /// Records when the agent first comes to believe that `target` cannot be
+    /// reached, returning whether the trial should end because of it.
+    fn disconnected(&mut self, target: &Point) -> bool {
+        if self.disconnected_at.is_some() ||
+           self.grid.belief_reachable(&self.location, target) {
+            return false;
+        }
+        self.disconnected_at = Some(self.data.expansions);
+        if self.detect_disconnection {
+            self.data.outcome = Outcome::Disconnected;
+        }
+        self.detect_disconnection
+    }
+
+    /// Returns the number of times the agent occupied each tile during the
+    /// last trial.
+    pub fn visits(&self) -> &Heatmap {
+        &self.visits
+    }
+
+    fn visit(&mut self, point: &Point) {
+        self.trajectory.push(*point);
+        let visits = self.visits.visit(point);
+        if visits > 1 {
+            self.data.revisits += 1;
+        }
+        self.data.max_visits = max(self.data.max_visits, visits);
+    }
+
+    /// Erases the loops from the trajectory of the trial, in the order they
+    /// were closed, counting the moves in them as backtracking, and the cost
+    /// of all but the moves left as exploration.
+    fn measure_progress(&mut self) {
+        let mut progress: Vec<Point> = Vec::new();
+        let mut positions = HashMap::new();
+        for point in &self.trajectory {
+            match positions.get(point) {
+                Some(&position) => {
+                    for erased in progress.drain(position + 1..) {
+                        positions.remove(&erased);
+                    }
+                }
+                None => {
+                    positions.insert(*point, progress.len());
+                    progress.push(*point);
+                }
+            }
+        }
+        self.data.backtracking = self.trajectory.len() - progress.len();
+        let progress_cost = progress.windows(2)
+            .map(|pair| {
+                     self.grid.cost_under(&self.agent.metric(),
+                                          None,
+                                          &pair[0],
+                                          &pair[1])
+                 })
+            .sum::<Distance>();
+        self.data.exploration_cost = self.data.cost - progress_cost;
+    }
+
+    /// Limits the wall-clock time each trial may run for. Trials that go
+    /// over the limit end with `Outcome::TimedOut`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Gives the agent this long to decide on every move, if given. Agents
+    /// capable of anytime planning follow the best plan they found by then
+    /// (see `Agent::set_deadline`), and the moves decided on later than that
+    /// are counted in `Datum::budget_overruns`.
+    pub fn set_move_budget(&mut self, budget: Option<Duration>) {
+        self.move_budget = budget;
+    }
+
+    /// Makes `run_trial` perturb the map for every trial, if given, undoing
+    /// the changes once the trial ends.
+    pub fn set_perturbation(&mut self, perturbation: Option<Perturbation>) {
+        self.perturbation = perturbation;
+    }
+
+    /// Makes `run_pairs` stop starting new trials once `interrupt` is set,
+    /// returning the results of those that ran.
+    pub fn set_interrupt(&mut self, interrupt: Option<Arc<AtomicBool>>) {
+        self.interrupt = interrupt;
+    }
+
+    pub fn location(&self) -> Point {
+        self.location
+    }
+
+    /// The statistics of the current trial so far.
+    pub fn datum(&self) -> &Datum {
+        &self.data
+    }
+
+    pub fn grid(&self) -> &Grid {
+        self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        self.grid
+    }
+
+    /// Moves the agent to `point`, returning whether it moved. When moves
+    /// are validated (see `set_validate_moves`), illegal ones are refused,
+    /// which ends the trial. Moving to where the agent is waits, which
+    /// costs `WAIT_COST`.
+    pub fn move_agent(&mut self, point: Point) -> bool {
+        profile_span!("move", y = point.y(), x = point.x());
+        if self.validate_moves && !self.legal_move(&point) {
+            let what = format!("from {} to {}", self.location, point);
+            self.reject(what);
+            return false;
+        }
+
+        self.data.steps += 1;
+        self.data.cost += self.grid.cost_under(&self.agent.metric(),
+                                               self.previous,
+                                               &self.location,
+                                               &point);
+        let cost = self.grid
+            .model_cost(self.previous, &self.location, &point);
+        if let Some(cost) = cost {
+            *self.data.model_cost.get_or_insert(0.0) += cost;
+        }
+        if let Some(ref mut near_misses) = self.data.near_misses {
+            let grid = &self.grid;
+            if grid.neighbors(&point)
+                   .any(|(_, index)| {
+                            *grid.tile(index).belief() == Belief::Unknown
+                        }) {
+                *near_misses += 1;
+            }
+        }
+        let from = self.location;
+        self.previous = Direction::between(&from, &point);
+        self.location = point;
+        self.visit(&point);
+        for metric in &mut self.metrics {
+            metric.on_move(self.grid, &from, &point);
+        }
+        self.notify(|observer, grid| observer.on_move(grid, &from, &point));
+        self.look();
+        true
+    }
+
+    fn look(&mut self) {
+        let location = self.location;
+        self.grid.look(&location);
+        self.notify(|observer, grid| observer.on_observe(grid, &location));
+    }
+
+    /// Scans around the agent with the scanner, if it has one and a scan
+    /// left, returning whether it did. A scan costs a step, and leaves the
+    /// agent where it was, having seen as far as the scanner reaches.
+    pub fn scan(&mut self) -> bool {
+        let scans = self.data.scans.unwrap_or(0);
+        let scanner = match self.scanner {
+            Some(scanner) if scanner.available(scans) => scanner,
+            _ => return false,
+        };
+        self.data.steps += 1;
+        self.data.cost += scanner.cost;
+        self.data.scans = Some(scans + 1);
+        let location = self.location;
+        self.grid.look_with(&location, scanner.sensor);
+        self.notify(|observer, grid| observer.on_observe(grid, &location));
+        true
+    }
+
+    /// Places the agent at `source` to begin a new trial, discarding the
+    /// statistics of the previous one. The grid's beliefs are kept.
+    pub fn start(&mut self, source: Point) {
+        let bumps = match self.execution {
+            ExecutionModel::Bump(_) => Some(0),
+            ExecutionModel::Direct => None,
+        };
+        self.data = Datum {
+            model_cost: self.grid
+                .model_cost(None, &source, &source)
+                .map(|_| 0.0),
+            budget_overruns: self.move_budget.map(|_| 0),
+            bumps: bumps,
+            near_misses: bumps,
+            scans: self.scanner.map(|_| 0),
+            ..Datum::default()
+        };
+        self.visits = Heatmap::new(self.grid.height(), self.grid.width());
+        self.agent.reset();
+        self.location = source;
+        self.previous = None;
+        self.trajectory.clear();
+        self.visit(&source);
+        self.look();
+        self.states.clear();
+        self.disconnected_at = None;
+    }
+
+    pub fn run_once(&mut self, source: Point, mut target: Point) -> Datum {
+        profile_span!("trial");
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let max_steps = self.max_steps(&source, &target);
+        self.notify(|observer, grid| {
+                        observer.on_trial_start(grid, &source, &target)
+                    });
+        for metric in &mut self.metrics {
+            metric.start(self.grid, &source, &target);
+        }
+        self.start(source);
+        self.data.endpoints = Some((source, target));
+        self.repeated_state(&target);
+
+        while !self.disconnected(&target) {
+            let deciding = Instant::now();
+            self.agent.set_deadline(self.move_budget
+                                        .map(|budget| deciding + budget));
+            let action = self.agent.act(self.grid, &self.location, &target);
+            let decided = deciding.elapsed();
+            if self.move_budget.is_some_and(|budget| decided > budget) {
+                *self.data.budget_overruns.get_or_insert(0) += 1;
+            }
+            let trace = self.grid.take_search_trace();
+            if !trace.is_empty() {
+                let location = self.location;
+                self.notify(|observer, grid| {
+                                observer.on_search(grid, &location, &trace)
+                            });
+            }
+            let agent_datum = match action {
+                Some(agent_datum) => agent_datum,
+                None => break,
+            };
+            self.data.step_expansions.push(agent_datum.expansions);
+            let next = match agent_datum.action
+                      .target(&self.location)
+                      .filter(|next| self.grid.try_get(next).is_ok()) {
+                Some(next) => next,
+                None if self.validate_moves => {
+                    let what = format!("{:?} off the grid from {}",
+                                       agent_datum.action,
+                                       self.location);
+                    self.reject(what);
+                    break;
+                }
+                // Moving off the grid is giving up.
+                None => break,
+            };
+            if self.data.first_move_expansions.is_none() {
+                self.data.first_move_expansions = Some(agent_datum.expansions);
+                self.data.first_move_time = Some(decided);
+            }
+            if agent_datum.expansions > 0 {
+                self.data.episodes += 1;
+                self.data.expansions += agent_datum.expansions;
+                let location = self.location;
+                for metric in &mut self.metrics {
+                    metric.on_episode(self.grid,
+                                      &location,
+                                      agent_datum.expansions);
+                }
+                // Sensing is not a move along the plan.
+                let mut path = if agent_datum.action == Action::Sense {
+                    Vec::new()
+                } else {
+                    vec![next]
+                };
+                path.extend(self.agent.planned_path());
+                self.data.plans.push(PlanEpisode {
+                                         planned: path.len(),
+                                         executed: 0,
+                                     });
+                self.notify(|observer, grid| {
+                                observer.on_plan(grid,
+                                                 &location,
+                                                 agent_datum.expansions,
+                                                 &path)
+                            });
+            }
+
+            if agent_datum.action == Action::Sense {
+                // Sensing without a scan left is waiting.
+                if !self.scan() && !self.move_agent(next) {
+                    break;
+                }
+            } else if !self.bump(&next) {
+                if !self.move_agent(next) {
+                    break;
+                }
+                if let Some(plan) = self.data.plans.last_mut() {
+                    plan.executed += 1;
+                }
+            }
+
+            if !self.caught(&target) {
+                target = self.move_target(target);
+            }
+            if self.caught(&target) {
+                if self.pursuit.is_some() {
+                    self.data.capture_time = Some(self.data.steps);
+                }
+                self.data.outcome = Outcome::Solved;
+                break;
+            }
+
+            if agent_datum.action != Action::Wait &&
+               self.repeated_state(&target) {
+                self.data.outcome = Outcome::Oscillating;
+                break;
+            }
+
+            if max_steps.is_some_and(|max_steps| self.data.steps >= max_steps) {
+                self.data.outcome = Outcome::OutOfSteps;
+                break;
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.data.outcome = Outcome::TimedOut;
+                break;
+            }
+        }
+
+        if let Some(expansions) = self.disconnected_at {
+            self.data.wasted_expansions = self.data.expansions - expansions;
+        }
+        self.measure_progress();
+        self.finish_metrics();
+        let data = take(&mut self.data);
+        self.notify(|observer, grid| observer.on_trial_end(grid, &data));
+        data
+    }
+
+    /// Runs one trial with `run_once`, over the map perturbed for it, if it
+    /// is perturbed. If panics are caught and the agent panics, the trial
+    /// ends with `Outcome::Errored`, recording the panic's message and
+    /// whatever was measured until then.
+    pub fn run_trial(&mut self, source: Point, target: Point) -> Datum {
+        let changes = self.perturbation
+            .map(|perturbation| {
+                     perturbation.changes(self.grid, &source, &target)
+                 })
+            .unwrap_or_default();
+        self.grid
+            .apply(&changes)
+            .expect("Perturbations start from the current terrain.");
+        let datum = self.run_guarded(source, target);
+        let undo = changes.iter().rev().map(TileChange::reversed);
+        self.grid
+            .apply(&undo.collect::<Vec<_>>())
+            .expect("Perturbations are undone over the perturbed terrain.");
+        datum
+    }
+
+    fn run_guarded(&mut self, source: Point, target: Point) -> Datum {
+        if !self.catch_panics {
+            return self.run_once(source, target);
+        }
+        let trial = AssertUnwindSafe(|| self.run_once(source, target));
+        catch_unwind(trial).unwrap_or_else(|payload| {
+            self.finish_metrics();
+            let mut data = take(&mut self.data);
+            data.outcome = Outcome::Errored;
+            data.error = Some(panic_message(payload.as_ref()));
+            self.notify(|observer, grid| observer.on_trial_end(grid, &data));
+            data
+        })
+    }
+
+    /// Sets whether each trial run by `run_pairs` after the first begins
+    /// with the beliefs left by the one before it, rather than forgetting
+    /// them. Disabled by default.
+    pub fn set_retain_beliefs(&mut self, retain: bool) {
+        self.retain_beliefs = retain;
+    }
+
+    /// Runs one trial for each (source, target) pair in `trials`.
+    pub fn run_pairs(&mut self, trials: &[(Point, Point)]) -> Data {
+        let mut results = Data::new(trials.len());
+        for (i, trial) in trials.iter().enumerate() {
+            if self.interrupt
+                   .as_ref()
+                   .is_some_and(|interrupt| interrupt.load(Ordering::SeqCst)) {
+                break;
+            }
+            if i == 0 || !self.retain_beliefs {
+                self.grid.forget();
+            }
+            results.push(self.run_trial(trial.0, trial.1));
+            results.record_visits(&self.visits);
+        }
+        results
+    }
+}
+
+/// Returns the message a panic was raised with, if it had one.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use agent::{self, AlwaysAstar, Bug2, CompressedPaths, InteractiveAgent,
+                PotentialField, RepeatedAstar, ScriptedAgent, SpaceTimeAstar};
+    use reservation::ReservationTable;
+    use cost::{Composite, MoveMetric, WAIT_COST};
+    use grid::{Measure, Movement, Sensor};
+    use observer::ResultLog;
+    use parser::grid_from_str;
+    use pursuit::{Capture, Flee, TargetPolicy};
+
+    use std::cell::RefCell;
+    use std::f64::consts::SQRT_2;
+    use std::rc::Rc;
+
+    #[test]
+    fn always_astar() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let start = Point::new(0, 0);
+        let goal = Point::new(3, 3);
+
+        let agent = AlwaysAstar::new(Distance::octile_heuristic,
+                                     MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Two);
+
+        let results = instance.run_once(start, goal);
+        assert!(results.solved());
+
+        assert_eq!(results.steps, 5);
+        assert_eq!(results.cost, 4.0 + SQRT_2);
+        assert_eq!(results.episodes, 5);
+    }
+
+    #[test]
+    fn repeated_astar() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let start = Point::new(0, 0);
+        let goal = Point::new(3, 3);
+
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Two);
+
+        let results = instance.run_once(start, goal);
+        assert!(results.solved());
+
+        assert_eq!(results.steps, 5);
+        assert_eq!(results.cost, 4.0 + SQRT_2);
+        assert_eq!(results.episodes, 2);
+    }
+
+    #[test]
+    fn executed_cost_uses_agent_metric() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let start = Point::new(0, 0);
+        let goal = Point::new(3, 3);
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let measured = instance.run_once(start, goal);
+        assert_eq!(measured.cost, 4.0 + SQRT_2);
+
+        // The same moves, counted instead of measured by their length. How
+        // the agent plans is unaffected.
+        grid.forget();
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Unit);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let counted = instance.run_once(start, goal);
+        assert_eq!(counted.steps, measured.steps);
+        assert_eq!(counted.episodes, measured.episodes);
+        assert_eq!(counted.cost, 5.0);
+        assert_eq!(counted.model_cost, None);
+    }
+
+    #[test]
+    fn replan_near_the_path() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 5\nmap\n\
+                                      .....\n..T..");
+        let (start, goal) = (Point::new(0, 0), Point::new(0, 4));
+        let mut episodes = Vec::new();
+        for &radius in &[None, Some(0), Some(1)] {
+            grid.forget();
+            let mut agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                               MoveMetric::Length);
+            agent.set_replan_radius(radius);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            let results = instance.run_once(start, goal);
+            assert!(results.solved());
+            assert_eq!(results.steps, 4);
+            episodes.push(results.episodes);
+        }
+        // The tree next to the path is only close enough to it to replan
+        // with a radius of 1.
+        assert_eq!(episodes, [1, 1, 2]);
+    }
+
+    #[test]
+    fn blocked_way_ends_trial() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 5
+map
+.T...");
+
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_detect_disconnection(false);
+        // Finding the way blocked for good ends the trial where it is.
+        let results = instance.run_once(Point::new(0, 3), Point::new(0, 0));
+        assert_eq!(results.outcome, Outcome::Unsolved);
+        assert_eq!(results.steps, 1);
+    }
+
+    #[test]
+    fn measure_the_first_move() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 5\nmap\n\
+                                      ..T..\n..T..");
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let datum = instance.run_once(Point::new(0, 0), Point::new(0, 1));
+        assert!(datum.solved());
+        assert_eq!(datum.first_move_expansions, Some(datum.step_expansions[0]));
+        assert!(datum.first_move_expansions.unwrap() > 0);
+        assert!(datum.first_move_time.is_some());
+
+        // The wall was seen in the trial before, so the agent never moves.
+        let datum = instance.run_once(Point::new(0, 0), Point::new(0, 4));
+        assert!(!datum.solved());
+        assert_eq!((datum.first_move_expansions, datum.first_move_time),
+                   (None, None));
+    }
+
+    #[test]
+    fn step_expansions_sum_to_total() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let results = instance.run_once(Point::new(0, 0), Point::new(3, 3));
+
+        // One entry per move, with expansions only at the moves that began
+        // with a search.
+        assert_eq!(results.step_expansions.len(), results.steps);
+        assert_eq!(results.step_expansions.iter().sum::<usize>(),
+                   results.expansions);
+        assert_eq!(results.step_expansions.iter().filter(|&&e| e > 0).count(),
+                   results.episodes);
+    }
+
+    #[test]
+    fn measure_plan_churn() {
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 4\nmap\n\
+                                      ....");
+        let (start, goal) = (Point::new(0, 0), Point::new(0, 3));
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let datum = instance.run_once(start, goal);
+        assert_eq!(datum.plans,
+                   [PlanEpisode {
+                        planned: 3,
+                        executed: 3,
+                    }]);
+        assert_eq!(datum.plan_churn(), Some(0.0));
+        assert!(datum.metrics().contains(&("plan_churn", "0".to_string())));
+
+        // Every plan but the last is dropped after its first move.
+        grid.forget();
+        let agent = AlwaysAstar::new(Distance::octile_heuristic,
+                                     MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let datum = instance.run_once(start, goal);
+        let planned: Vec<_> = datum.plans.iter().map(|p| p.planned).collect();
+        assert_eq!(planned, [3, 2, 1]);
+        assert!(datum.plans.iter().all(|p| p.executed == 1));
+        assert_eq!(datum.plan_churn(), Some(0.5));
+
+        let mut data = Data::new(1);
+        data.push(datum);
+        assert_eq!(data.summary().plan_churn, Some(0.5));
+    }
+
+    #[test]
+    fn budget_every_move() {
+        let mut grid = grid_from_str("type octile\nheight 3\nwidth 5\nmap\n\
+                                      .....\n.....\n.....");
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_move_budget(Some(Duration::from_secs(0)));
+        // Out of time as soon as the source is expanded, the agent moves to
+        // the most promising neighbor every time.
+        let datum = instance.run_once(Point::new(1, 0), Point::new(1, 4));
+        assert!(datum.solved());
+        assert_eq!(datum.step_expansions, vec![1, 1, 1, 1]);
+        assert!(datum.budget_overruns.is_some());
+
+        struct Slow;
+
+        impl Agent for Slow {
+            fn act(&mut self,
+                   _grid: &mut Grid,
+                   _location: &Point,
+                   _target: &Point)
+                   -> Option<agent::Datum> {
+                ::std::thread::sleep(Duration::from_millis(2));
+                Some(agent::Datum {
+                         action: agent::Action::Move(Direction::East),
+                         expansions: 0,
+                     })
+            }
+        }
+
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 3\nmap\n\
+                                      ...");
+        let mut instance = Instance::new(&mut grid, Slow, Verbosity::Zero);
+        let datum = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert_eq!(datum.budget_overruns, None);
+        instance.set_move_budget(Some(Duration::from_millis(1)));
+        let datum = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert_eq!(datum.budget_overruns, Some(2));
+        let mut out = Vec::new();
+        datum.write(&mut out).unwrap();
+        assert!(String::from_utf8(out)
+                    .unwrap()
+                    .contains(" [2 budget overruns]"));
+    }
+
+    #[test]
+    fn inspect_the_current_plan() {
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 4\nmap\n\
+                                      ....");
+        let mut agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+        assert!(agent.current_plan().is_none());
+        let datum = agent.act(&mut grid, &Point::new(0, 0), &Point::new(0, 3))
+            .unwrap();
+        assert_eq!(datum.action, agent::Action::Move(Direction::East));
+        assert_eq!(agent.current_plan(),
+                   Some(&vec![Point::new(0, 3), Point::new(0, 2)]));
+        assert_eq!(agent.planned_path(),
+                   vec![Point::new(0, 2), Point::new(0, 3)]);
+
+        let boxed: Box<dyn Agent> = Box::new(agent);
+        assert_eq!(boxed.current_plan().map(Vec::len), Some(2));
+        let idle = AlwaysAstar::new(Distance::octile_heuristic,
+                                    MoveMetric::Length);
+        assert!(idle.current_plan().is_none());
+    }
+
+    #[test]
+    fn retain_beliefs_and_paths() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 5\nmap\n\
+                                      ..T..\n.....");
+        let trial = (Point::new(0, 0), Point::new(0, 4));
+        let mut run = |retain: bool, reuse: bool| {
+            let mut agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                               MoveMetric::Length);
+            agent.set_reuse_paths(reuse);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.set_retain_beliefs(retain);
+            let data = instance.run_pairs(&[trial, trial, trial]);
+            assert!(data.trials.iter().all(Datum::solved));
+            data.trials.iter().map(|datum| datum.episodes).collect::<Vec<_>>()
+        };
+
+        // The tree is only seen from next to it, unless it is remembered.
+        assert_eq!(run(false, false), [2, 2, 2]);
+        assert_eq!(run(true, false), [2, 1, 1]);
+        // The path planned around it is kept for the trials after that.
+        assert_eq!(run(true, true), [2, 1, 0]);
+    }
+
+    #[test]
+    fn restore_the_order_of_trials() {
+        let trial = |steps| {
+            Datum {
+                steps: steps,
+                ..Datum::default()
+            }
+        };
+        // Trials 2 and 0 ran, in that order, before the rest were cut short.
+        let mut data = Data::new(2);
+        data.push(trial(2));
+        data.push(trial(0));
+        data.restore_order(&[2, 0, 3, 1]);
+        assert_eq!(data.iter().map(|datum| datum.steps).collect::<Vec<_>>(),
+                   [0, 2]);
+        let mut output = Vec::new();
+        data.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Trial 0: "));
+        assert!(output.contains("\nTrial 2: "));
+        assert_eq!(data.iter_ids().map(|(id, _)| id).collect::<Vec<_>>(),
+                   [0, 2]);
+        assert_eq!(data.get(2).map(|datum| datum.steps), Some(2));
+        assert!(data.get(1).is_none());
+
+        data.set_lines(vec![10, 11, 12, 13]);
+        let mut output = Vec::new();
+        data.write(&mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("\nLine 12: "));
+        assert_eq!(data.id(1), 12);
+        assert_eq!(data.get(12).map(|datum| datum.steps), Some(2));
+        assert!(data.get(2).is_none());
+    }
+
+    #[test]
+    fn merge_trials_from_workers() {
+        let trial = |steps| {
+            Datum {
+                steps: steps,
+                ..Datum::default()
+            }
+        };
+        // One worker ran trials 0 and 3, the other 1 and 3.
+        let mut data = Data::new(2);
+        data.push(trial(3));
+        data.push(trial(0));
+        data.restore_order(&[3, 0]);
+        let mut other = Data::new(2);
+        other.push(trial(1));
+        other.push(trial(30));
+        other.restore_order(&[1, 3]);
+        other.set_tag(Some("other".to_owned()));
+        data.merge(other);
+        assert_eq!(data.iter_ids()
+                       .map(|(id, datum)| (id, datum.steps))
+                       .collect::<Vec<_>>(),
+                   [(0, 0), (1, 1), (3, 3)]);
+        assert_eq!(data.tag(), Some("other"));
+
+        let mut rest = Data::new(1);
+        rest.push(trial(2));
+        rest.restore_order(&[2]);
+        data.merge(rest);
+        assert_eq!(data.iter().map(|datum| datum.steps).collect::<Vec<_>>(),
+                   [0, 1, 2, 3]);
+        assert_eq!(data.get(2).map(|datum| datum.steps), Some(2));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn serialize_trials() {
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 4\nmap\n\
+                                      ....");
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let mut data = instance.run_pairs(&[(Point::new(0, 0),
+                                             Point::new(0, 3))]);
+        data.restore_order(&[1]);
+        data.set_tag(Some("json".to_owned()));
+
+        let json = ::serde_json::to_string(&data).unwrap();
+        let read: Data = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read.tag(), Some("json"));
+        assert_eq!(read.get(1).map(Datum::metrics), Some(data[0].metrics()));
+        assert_eq!(read[0].endpoints, data[0].endpoints);
+        assert_eq!(read[0].plans, data[0].plans);
+        assert_eq!(read.heatmap(), data.heatmap());
+    }
+
+    #[test]
+    fn post_process_trials() {
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 4\nmap\n\
+                                      ..@.");
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let (a, b, c) = (Point::new(0, 0), Point::new(0, 1), Point::new(0, 3));
+        let data = instance.run_pairs(&[(a, b), (a, c), (b, a)]);
+        assert_eq!(data.len(), 3);
+        assert_eq!(data.success_count(), 2);
+        assert_eq!(data.seed(), None);
+
+        let endpoints: Vec<_> = (&data)
+            .into_iter()
+            .map(|datum| datum.endpoints.unwrap())
+            .collect();
+        assert_eq!(endpoints, [(a, b), (a, c), (b, a)]);
+        let mut solved = 0;
+        for datum in data {
+            if datum.solved() {
+                solved += 1;
+            }
+        }
+        assert_eq!(solved, 2);
+    }
+
+    /// Moves back and forth between two tiles a fixed number of times.
+    struct Bounce(usize);
+
+    impl Agent for Bounce {
+        fn act(&mut self,
+               _grid: &mut Grid,
+               location: &Point,
+               _target: &Point)
+               -> Option<agent::Datum> {
+            if self.0 == 0 {
+                return None;
+            }
+            self.0 -= 1;
+            Some(agent::Datum::towards(location,
+                                       &Point::new(0, 1 - location.x()),
+                                       0))
+        }
+    }
+
+    #[test]
+    fn count_revisits() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let mut instance = Instance::new(&mut grid, Bounce(6), Verbosity::Zero);
+        instance.set_detect_oscillation(false);
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+
+        assert!(!results.solved());
+        assert_eq!(results.steps, 6);
+        assert_eq!(results.revisits, 5);
+        assert_eq!(results.max_visits, 4);
+        assert!(results.oscillating());
+        assert_eq!(instance.visits().get(&Point::new(0, 1)), 3);
+        // Every move was undone, back to where the agent started.
+        assert_eq!(results.backtracking, 6);
+        assert_eq!(results.exploration_cost, results.cost);
+    }
+
+    #[test]
+    fn visits_are_counted() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let start = Point::new(0, 0);
+        let goal = Point::new(3, 3);
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let results = instance.run_once(start, goal);
+
+        // Going around the trees never steps on a tile twice.
+        assert_eq!(results.revisits, 0);
+        assert_eq!(results.max_visits, 1);
+        assert!(!results.oscillating());
+        assert_eq!(instance.visits().get(&start), 1);
+        assert_eq!(instance.visits().get(&goal), 1);
+    }
+
+    #[test]
+    fn measure_backtracking() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 4\nmap\n\
+                                      ....\n....");
+        // A loop through the bottom row, back to (0, 1).
+        let script = "0 1\n1 2\n1 1\n0 1\n0 2\n0 3\n";
+        let agent = ScriptedAgent::from_reader(script.as_bytes()).unwrap();
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 3));
+        assert!(results.solved());
+        assert_eq!(results.steps, 6);
+        assert_eq!(results.revisits, 1);
+        assert_eq!(results.backtracking, 3);
+        assert_eq!(results.exploration_cost, 2.0 + SQRT_2);
+
+        let mut output = Vec::new();
+        results.write(&mut output).unwrap();
+        assert!(String::from_utf8(output)
+                    .unwrap()
+                    .ends_with(" [backtracked 3 moves, exploration cost \
+                                3.414213562373095]\n"));
+        let mut data = Data::new(1);
+        data.push(results);
+        assert_eq!(data.summary().backtracking, 3.0);
+    }
+
+    /// Bounces like `Bounce`, claiming to expand a tile before every move.
+    struct Busy(Bounce);
+
+    impl Agent for Busy {
+        fn act(&mut self,
+               grid: &mut Grid,
+               location: &Point,
+               target: &Point)
+               -> Option<agent::Datum> {
+            self.0
+                .act(grid, location, target)
+                .map(|datum| agent::Datum { expansions: 1, ..datum })
+        }
+    }
+
+    #[test]
+    fn end_disconnected_trials() {
+        let run = |detect| {
+            let mut grid = grid_from_str("type octile
+height 1
+width 4
+map
+..T.");
+            let mut instance =
+                Instance::new(&mut grid, Busy(Bounce(6)), Verbosity::Zero);
+            instance.set_detect_oscillation(false);
+            instance.set_detect_disconnection(detect);
+            instance.run_once(Point::new(0, 0), Point::new(0, 3))
+        };
+
+        // The wall is only seen after the first move.
+        let ended = run(true);
+        assert_eq!(ended.outcome, Outcome::Disconnected);
+        assert_eq!(ended.steps, 1);
+        assert_eq!(ended.wasted_expansions, 0);
+
+        let kept = run(false);
+        assert_eq!(kept.outcome, Outcome::Unsolved);
+        assert_eq!(kept.steps, 6);
+        assert_eq!(kept.wasted_expansions, 5);
+
+        let mut data = Data::new(2);
+        data.push(ended);
+        data.push(kept);
+        let summary = data.summary();
+        assert_eq!(summary.disconnected, 1);
+        assert_eq!(summary.wasted_expansions, 5);
+        let mut output = Vec::new();
+        data.write(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Trial 0: <disconnected>\nTrial 1: <none> [5 expansions \
+                    after disconnection]\n");
+
+        data.mark_unreachable(vec![false, true]);
+        assert_eq!(data[0].outcome, Outcome::Unreachable);
+        assert_eq!(data[1].outcome, Outcome::Unsolved);
+    }
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn on_trial_start(&mut self, _: &Grid, source: &Point, target: &Point) {
+            self.0.borrow_mut().push(format!("start {} {}", source, target));
+        }
+
+        fn on_plan(&mut self,
+                   _: &Grid,
+                   location: &Point,
+                   _: usize,
+                   path: &[Point]) {
+            self.0
+                .borrow_mut()
+                .push(format!("plan {} {}", location, path.len()));
+        }
+
+        fn on_move(&mut self, _: &Grid, from: &Point, to: &Point) {
+            self.0.borrow_mut().push(format!("move {} {}", from, to));
+        }
+
+        fn on_observe(&mut self, _: &Grid, location: &Point) {
+            self.0.borrow_mut().push(format!("observe {}", location));
+        }
+
+        fn on_trial_end(&mut self, _: &Grid, datum: &Datum) {
+            self.0.borrow_mut().push(format!("end {}", datum.steps));
+        }
+    }
+
+    #[test]
+    fn notify_observers() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.add_observer(Box::new(Recorder(events.clone())));
+        instance.run_once(Point::new(0, 0), Point::new(0, 2));
+
+        let a = Point::new(0, 0);
+        let b = Point::new(0, 1);
+        let c = Point::new(0, 2);
+        assert_eq!(*events.borrow(),
+                   vec![format!("start {} {}", a, c),
+                        format!("observe {}", a),
+                        format!("plan {} 2", a),
+                        format!("move {} {}", a, b),
+                        format!("observe {}", b),
+                        format!("move {} {}", b, c),
+                        format!("observe {}", c),
+                        "end 2".to_string()]);
+
+        assert_eq!(instance.take_observers().len(), 1);
+    }
+
+    /// A sink whose contents can be read after the printer is done with it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<String>>);
+
+    impl ::std::fmt::Write for Shared {
+        fn write_str(&mut self, s: &str) -> ::std::fmt::Result {
+            self.0.borrow_mut().push_str(s);
+            Ok(())
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            self.0.borrow_mut().push_str(&String::from_utf8_lossy(buf));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    /// Sets its flag when the first trial ends.
+    struct Interrupter(Arc<AtomicBool>);
+
+    impl Observer for Interrupter {
+        fn on_trial_end(&mut self, _: &Grid, _: &Datum) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn render_beliefs_as_in_the_snapshot() {
+        let mut grid = grid_from_str("type octile
+height 3
+width 5
+map
+.....
+.TTT.
+.....");
+        grid.set_sensor(Sensor {
+                            radius: 1,
+                            terrain_radius: None,
+                        });
+
+        let out = Shared::default();
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.add_observer(Box::new(Printer::to(Verbosity::Two,
+                                                   out.clone())));
+        assert!(instance.run_once(Point::new(1, 0), Point::new(1, 4)).solved());
+
+        // Changes to the rendering must update the snapshot on purpose.
+        assert_eq!(*out.0.borrow(),
+                   include_str!("../tests/snapshots/verbosity-two.txt"));
+    }
+
+    #[test]
+    fn trace_searches() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let out = Shared::default();
+        let mut printer = Printer::to(Verbosity::Zero, out.clone());
+        printer.set_trace(Trace {
+                              steps: false,
+                              search: true,
+                              record_search: false,
+                          });
+        grid.set_trace_search(true);
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.add_observer(Box::new(printer));
+        instance.run_once(Point::new(0, 0), Point::new(0, 2));
+
+        assert_eq!(*out.0.borrow(),
+                   "Search from (0, 0):\n  expand (0, 0) f=2 g=0 h=2\n  \
+                    expand (0, 1) f=2 g=1 h=1\n  expand (0, 2) f=2 g=2 h=0\n");
+        assert!(instance.grid_mut().take_search_trace().is_empty());
+    }
+
+    #[test]
+    fn log_results_until_interrupted() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let out = Shared::default();
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_interrupt(Some(interrupt.clone()));
+        instance.add_observer(Box::new(ResultLog::new(out.clone(),
+                                                      Some("t".to_owned()),
+                                                      Some(vec![4, 7]))));
+        instance.add_observer(Box::new(Interrupter(interrupt)));
+        let data = instance.run_pairs(&[(Point::new(0, 0), Point::new(0, 2)),
+                                        (Point::new(0, 2), Point::new(0, 0))]);
+
+        // The first trial was logged as it ended, and the second never ran.
+        assert_eq!(data.len(), 1);
+        assert_eq!(*out.0.borrow(),
+                   "[t] Line 4: 2 (2 steps, 1 episodes, 3 expansions, 0 \
+                    revisits, at most 3 expansions per step)\n");
+    }
+
+    /// Moves right, but panics on reaching the second column.
+    struct Faulty;
+
+    impl Agent for Faulty {
+        fn act(&mut self,
+               _: &mut Grid,
+               location: &Point,
+               _: &Point)
+               -> Option<agent::Datum> {
+            if location.x() == 1 {
+                panic!("lost at {}", location);
+            }
+            Some(agent::Datum {
+                     action: agent::Action::Move(Direction::East),
+                     expansions: 1,
+                 })
+        }
+    }
+
+    #[test]
+    fn catch_panicking_agents() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let mut instance = Instance::new(&mut grid, Faulty, Verbosity::Zero);
+        instance.set_catch_panics(true);
+        let data = instance.run_pairs(&[(Point::new(0, 0), Point::new(0, 2)),
+                                        (Point::new(0, 2), Point::new(0, 0))]);
+
+        // The first trial is abandoned, but the second one still runs.
+        assert_eq!(data[0].outcome, Outcome::Errored);
+        assert_eq!(data[0].error.as_deref(), Some("lost at (0, 1)"));
+        assert_eq!(data[0].steps, 1);
+        assert_eq!(data[1].outcome, Outcome::Unsolved);
+        assert_eq!(data.summary().errored, 1);
+        let mut output = Vec::new();
+        data.write(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Trial 0: <error: lost at (0, 1)>\nTrial 1: <none>\n");
+    }
+
+    #[test]
+    fn replay_script() {
+        let mut grid = grid_from_str("type octile
+height 2
+width 3
+map
+...
+...");
+
+        let script = "# A detour through the bottom row.
+1 1
+(0, 2)
+";
+        let agent = ScriptedAgent::from_reader(script.as_bytes()).unwrap();
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert!(results.solved());
+        assert_eq!(results.steps, 2);
+        assert_eq!(results.expansions, 0);
+
+        // The script starts over, but runs out before reaching (1, 2).
+        let results = instance.run_once(Point::new(0, 0), Point::new(1, 2));
+        assert_eq!(results.outcome, Outcome::Unsolved);
+        assert_eq!(results.steps, 2);
+
+        assert!(ScriptedAgent::from_reader("1 x".as_bytes()).is_err());
+        assert!(ScriptedAgent::from_reader("up".as_bytes()).is_err());
+
+        // Actions replay alongside locations, sensing included.
+        grid.forget();
+        let script = "sense\nsoutheast\nwait\n(0, 2)\n";
+        let agent = ScriptedAgent::from_reader(script.as_bytes()).unwrap();
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_scanner(Some(Scanner {
+                                      sensor: Sensor {
+                                          radius: 2,
+                                          terrain_radius: None,
+                                      },
+                                      cost: 0.5,
+                                      budget: None,
+                                  }));
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert!(results.solved());
+        assert_eq!(results.steps, 4);
+        assert_eq!(results.scans, Some(1));
+        assert!((results.cost - (2.0 * SQRT_2 + WAIT_COST + 0.5)).abs() <
+                1e-9);
+
+        let actions = vec![Action::Move(Direction::East), Action::Sense];
+        let agent = ScriptedAgent::from_actions(actions);
+        assert_eq!(agent.planned_path(), vec![]);
+        let mut agent = ScriptedAgent::from_reader("east\n1 2\nnorth\n"
+                                                       .as_bytes())
+            .unwrap();
+        agent.act(&mut grid, &Point::new(0, 0), &Point::new(0, 2));
+        assert_eq!(agent.planned_path(),
+                   vec![Point::new(1, 2), Point::new(0, 2)]);
+    }
+
+    #[test]
+    fn validate_moves() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 3\nmap\n\
+                                      .T.\n...");
+        let run = |grid: &mut Grid, script: &str, validate| {
+            let agent = ScriptedAgent::from_reader(script.as_bytes()).unwrap();
+            let mut instance = Instance::new(grid, agent, Verbosity::Zero);
+            instance.set_validate_moves(validate);
+            instance.run_once(Point::new(0, 0), Point::new(0, 2))
+        };
+
+        // Walking into the trees goes unnoticed unless moves are validated.
+        let results = run(&mut grid, "0 1\n0 2\n", false);
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 2));
+        let results = run(&mut grid, "0 1\n0 2\n", true);
+        assert_eq!((results.outcome, results.steps),
+                   (Outcome::IllegalMove, 0));
+        let mut out = Vec::new();
+        results.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "<illegal move: from (0, 0) to (0, 1)>\n");
+
+        grid.set_movement(Movement::Cardinal);
+        let results = run(&mut grid, "1 1\n", true);
+        assert_eq!(results.outcome, Outcome::IllegalMove);
+        assert_eq!(run(&mut grid, "1 0\n", true).outcome, Outcome::Unsolved);
+
+        let mut data = Data::new(1);
+        data.push(results);
+        assert_eq!(data.summary().illegal, 1);
+    }
+
+    #[test]
+    fn bump_into_obstacles() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 3\nmap\n\
+                                      .T.\n...");
+        grid.set_sensor(Sensor {
+                            radius: 0,
+                            terrain_radius: None,
+                        });
+        let run = |grid: &mut Grid, script: &str, execution| {
+            let agent = ScriptedAgent::from_reader(script.as_bytes()).unwrap();
+            let mut instance = Instance::new(grid, agent, Verbosity::Zero);
+            instance.set_execution(execution);
+            instance.run_once(Point::new(0, 0), Point::new(0, 2))
+        };
+
+        // Unseen trees are walked through, unless moves into them fail.
+        let results = run(&mut grid, "0 1\n0 2\n", ExecutionModel::Direct);
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 2));
+        assert_eq!((results.bumps, results.near_misses), (None, None));
+        grid.forget();
+        let results = run(&mut grid, "0 1\n0 2\n", ExecutionModel::Bump(2.0));
+        assert_eq!((results.outcome, results.steps), (Outcome::Unsolved, 1));
+        assert_eq!(results.cost, 2.0);
+        assert_eq!(grid[&Point::new(0, 1)].belief(), &Belief::Impassable);
+
+        grid.forget();
+        let results = run(&mut grid,
+                          "0 1\n1 1\n0 2\n",
+                          ExecutionModel::Bump(2.0));
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 3));
+        assert_eq!(results.cost, 2.0 + 2.0 * SQRT_2);
+        // Both moves were made next to tiles never seen.
+        assert_eq!((results.bumps, results.near_misses), (Some(1), Some(2)));
+        let mut out = Vec::new();
+        results.write(&mut out).unwrap();
+        assert!(String::from_utf8(out)
+                    .unwrap()
+                    .ends_with(" [1 bumps, 2 near misses]\n"));
+        let mut data = Data::new(1);
+        data.push(results);
+        assert_eq!((data.summary().bumps, data.summary().near_misses), (1, 2));
+
+        // Agents drop plans through the tiles they bump into.
+        grid.forget();
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_execution(ExecutionModel::Bump(2.0));
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 3));
+    }
+
+    #[test]
+    fn pursue_fleeing_targets() {
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 5\nmap\n\
+                                      .....");
+        let mut run = |capture| {
+            let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.set_pursuit(Some(Pursuit::new(Flee, capture)));
+            instance.run_once(Point::new(0, 0), Point::new(0, 2))
+        };
+
+        // The target keeps a tile ahead until it is cornered at the end.
+        let results = run(Capture::Colocation);
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 4));
+        assert_eq!(results.capture_time, Some(4));
+        // It is caught before it can move at all when next to the agent.
+        let results = run(Capture::Adjacency);
+        assert_eq!(results.capture_time, Some(1));
+
+        let mut data = Data::new(2);
+        data.push(results);
+        data.push(Datum::default());
+        assert_eq!(data.summary().capture_time, Some(1.0));
+        assert!(data.summary().to_string().contains(", mean capture time 1"));
+    }
+
+    #[test]
+    fn pursue_with_compressed_paths() {
+        let mut grid = grid_from_str("type octile\nheight 2\nwidth 5\nmap\n\
+                                      .....\n.....");
+        let agent = CompressedPaths::new(None, MoveMetric::Unit);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_pursuit(Some(Pursuit::new(Flee, Capture::Colocation)));
+
+        // The moves looked up towards where the target was are dropped once
+        // it flees, rather than followed to where it no longer is.
+        let results = instance.run_once(Point::new(1, 0), Point::new(0, 2));
+        assert_eq!((results.outcome, results.steps), (Outcome::Solved, 4));
+        assert_eq!(results.capture_time, Some(4));
+        // The diagonal move up to the row of the target is measured with
+        // the metric of the agent.
+        assert_eq!(results.cost, 4.0);
+    }
+
+    #[test]
+    fn scan_before_moving_on_faith() {
+        let mut grid = grid_from_str("type octile\nheight 3\nwidth 9\nmap\n\
+                                      .........\n.@@@@@@@.\n.....@...");
+        let (start, goal) = (Point::new(2, 0), Point::new(2, 8));
+        let mut run = |scanner| {
+            grid.forget();
+            let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.set_scanner(scanner);
+            instance.run_once(start, goal)
+        };
+        let scanner = Scanner {
+            sensor: Sensor {
+                radius: 5,
+                terrain_radius: None,
+            },
+            cost: 1.0,
+            budget: None,
+        };
+
+        // Without a scanner, the agent walks into the dead end before it
+        // sees the wall.
+        let blind = run(None);
+        assert_eq!((blind.steps, blind.scans), (17, None));
+        // With one, it sees the wall from the start, and goes around, but
+        // also scans as it goes along the unseen top row.
+        let scanning = run(Some(scanner));
+        assert_eq!((scanning.steps, scanning.scans), (14, Some(4)));
+        assert!((scanning.cost - (8.0 + 2.0 * SQRT_2 + 4.0)).abs() < 1e-9);
+        // Only the first scan is needed to avoid the dead end.
+        let limited = run(Some(Scanner { budget: Some(1), ..scanner }));
+        assert_eq!((limited.steps, limited.scans), (11, Some(1)));
+        assert!(limited.cost < scanning.cost && scanning.cost < blind.cost);
+        assert!(limited.metrics().contains(&("scans", "1".to_owned())));
+    }
+
+    #[test]
+    fn act_towards_neighbors() {
+        use agent::Action;
+
+        let location = Point::new(1, 1);
+        assert_eq!(Action::towards(&location, &Point::new(0, 2)),
+                   Some(Action::Move(Direction::NorthEast)));
+        assert_eq!(Action::towards(&location, &location), Some(Action::Wait));
+        assert_eq!(Action::towards(&location, &Point::new(3, 1)), None);
+        assert_eq!(Action::Move(Direction::West).target(&Point::new(0, 0)),
+                   None);
+        assert_eq!(Action::from_index(8), Some(Action::Wait));
+        assert_eq!(Action::from_index(9), Some(Action::Sense));
+        assert_eq!(Action::from_index(Action::COUNT), None);
+        assert_eq!(Action::Move(Direction::South).index(), 6);
+        assert_eq!(Action::Sense.target(&location), Some(location));
+
+        // A script that jumps across the grid gives up instead.
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 3\nmap\n\
+                                      ...");
+        let agent = ScriptedAgent::from_reader("0 2\n".as_bytes()).unwrap();
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert_eq!(results.outcome, Outcome::Unsolved);
+        assert_eq!(results.steps, 0);
+    }
+
+    #[test]
+    fn follow_keystrokes() {
+        let mut grid = grid_from_str("type octile
+height 2
+width 3
+map
+.T.
+...");
+
+        // Moving into the trees is refused, other keys (here a newline and
+        // an unknown escape sequence) are ignored, and arrow keys move.
+        let keys = "d\nk\x1b[Hx\x1b[C9";
+        let out = Shared::default();
+        let results = {
+            let agent = InteractiveAgent::new(keys.as_bytes(), out.clone());
+            let mut instance =
+                Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.run_once(Point::new(0, 0), Point::new(0, 2))
+        };
+        assert!(results.solved());
+        assert_eq!(results.steps, 3);
+
+        let output = out.0.borrow();
+        assert!(output.starts_with("aX*\n..?\n\n"));
+        assert!(output.contains("Cannot move there."));
+        assert_eq!(output.matches("Move (").count(), 3);
+
+        let agent = InteractiveAgent::new("Q".as_bytes(), Shared::default());
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert_eq!(results.outcome, Outcome::Unsolved);
+    }
+
+    #[test]
+    fn wait_out_reservations() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let mut reservations = ReservationTable::new();
+        reservations.reserve_path(1, &[Point::new(0, 1), Point::new(0, 1)]);
+        let agent = SpaceTimeAstar::new(Distance::octile_heuristic,
+                                        MoveMetric::Length,
+                                        reservations,
+                                        10);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_detect_oscillation(false);
+
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+        assert!(results.solved());
+        assert_eq!(results.steps, 4);
+        // Two moves and two waits.
+        assert_eq!(results.cost, 2.0 + 2.0 * WAIT_COST);
+        assert_eq!(results.episodes, 1);
+    }
+
+    #[test]
+    fn keep_clearance() {
+        let map = "type octile
+height 5
+width 5
+map
+.....
+.....
+..T..
+.....
+.....";
+        let run = |clearance| {
+            let mut grid = grid_from_str(map);
+            grid.set_sensor(Sensor {
+                                radius: 2,
+                                terrain_radius: None,
+                            });
+            grid.set_clearance(clearance);
+            let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            let results = instance.run_once(Point::new(2, 0), Point::new(2, 4));
+            assert!(results.solved());
+            let near = (1..4)
+                .flat_map(|y| (1..4).map(move |x| Point::new(y, x)))
+                .map(|point| instance.visits().get(&point))
+                .sum::<usize>();
+            (results.cost, near)
+        };
+
+        // Squeezing past the tree, or going around it by a wide margin.
+        assert_eq!(run(0), (2.0 + 2.0 * SQRT_2, 3));
+        assert_eq!(run(1), (4.0 + 2.0 * SQRT_2, 0));
+    }
+
+    #[test]
+    fn keep_away_from_walls() {
+        let map = "type octile
+height 4
+width 5
+map
+.....
+.....
+.....
+TTTTT";
+        let run = |model| {
+            let mut grid = grid_from_str(map);
+            grid.set_sensor(Sensor {
+                                radius: 2,
+                                terrain_radius: None,
+                            });
+            grid.set_cost_model(model);
+            let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            let results = instance.run_once(Point::new(2, 0), Point::new(2, 4));
+            assert!(results.solved());
+            (results.cost, results.model_cost)
+        };
+
+        // Hugging the wall, or stepping away from it for the middle moves,
+        // which only pays the safety weight on the final one.
+        assert_eq!(run(Composite::default()), (4.0, None));
+        assert_eq!(run(Composite {
+                           risk: 1.0,
+                           ..Composite::default()
+                       }),
+                   (2.0 + 2.0 * SQRT_2, Some(2.0 + 3.0 * SQRT_2)));
+    }
+
+    #[test]
+    fn escape_local_minima() {
+        let run = |map| {
+            let mut grid = grid_from_str(map);
+            let agent = PotentialField::new(Distance::octile_heuristic,
+                                            MoveMetric::Length,
+                                            1.0,
+                                            3);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            let results = instance.run_once(Point::new(2, 1), Point::new(2, 5));
+            assert!(results.solved());
+            (results.cost, results.expansions)
+        };
+
+        // Open ground is crossed by the gradient alone.
+        assert_eq!(run("type octile
+height 5
+width 7
+map
+.......
+.......
+.......
+.......
+......."),
+                   (4.0, 0));
+
+        // The wall traps the agent right before it, until A* leads it out.
+        let (cost, expansions) = run("type octile
+height 5
+width 7
+map
+.......
+...T...
+...T...
+...T...
+.......");
+        assert!(cost > 4.0);
+        assert!(expansions > 0);
+    }
+
+    #[test]
+    fn follow_obstacle_boundaries() {
+        let run = |map| {
+            let mut grid = grid_from_str(map);
+            let mut instance =
+                Instance::new(&mut grid, Bug2::new(), Verbosity::Zero);
+            instance.set_detect_disconnection(false);
+            let results = instance.run_once(Point::new(2, 1), Point::new(2, 5));
+            assert_eq!(results.expansions, 0);
+            (results.outcome, results.cost)
+        };
+
+        // Around the wall, leaving it on the left, and back onto the line.
+        assert_eq!(run("type octile
+height 5
+width 7
+map
+.......
+...T...
+...T...
+...T...
+......."),
+                   (Outcome::Solved, 4.0 + 2.0 * SQRT_2));
+
+        // Once around the enclosure without finding a way in.
+        let (outcome, _) = run("type octile
+height 5
+width 7
+map
+....TTT
+....T..
+....T..
+....T..
+....TTT");
+        assert_eq!(outcome, Outcome::Unsolved);
+    }
+
+    #[test]
+    fn abort_oscillating_trials() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let mut instance =
+            Instance::new(&mut grid, Bounce(100), Verbosity::Zero);
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 2));
+
+        // The first return to (0, 0) comes after (0, 2) was seen, but the
+        // second return to (0, 1) repeats its state.
+        assert_eq!(results.outcome, Outcome::Oscillating);
+        assert_eq!(results.steps, 3);
+    }
+
+    /// Waits in place a number of times before every move east.
+    struct Hesitate(usize);
+
+    impl Agent for Hesitate {
+        fn act(&mut self,
+               _grid: &mut Grid,
+               location: &Point,
+               _target: &Point)
+               -> Option<agent::Datum> {
+            let waits = self.0;
+            self.0 = if waits == 0 { 2 } else { waits - 1 };
+            let next = if waits == 0 {
+                Point::new(location.y(), location.x() + 1)
+            } else {
+                *location
+            };
+            Some(agent::Datum::towards(location, &next, 0))
+        }
+    }
+
+    /// Sends the target to the far end of a row once the pursuer is next to
+    /// it, and leaves it there.
+    struct Relocate;
+
+    impl TargetPolicy for Relocate {
+        fn next(&mut self, _: &Grid, target: &Point, pursuer: &Point) -> Point {
+            if *target == Point::new(0, 4) && pursuer.x() == 3 {
+                Point::new(0, 0)
+            } else {
+                *target
+            }
+        }
+    }
+
+    #[test]
+    fn tell_oscillation_from_waiting_and_pursuing() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 5
+map
+.....");
+        grid.set_sensor(Sensor {
+            radius: 4,
+            terrain_radius: None,
+        });
+
+        // Waiting leaves the agent where it was, with the same beliefs.
+        let mut instance =
+            Instance::new(&mut grid, Hesitate(2), Verbosity::Zero);
+        let results = instance.run_once(Point::new(0, 0), Point::new(0, 4));
+        assert_eq!(results.outcome, Outcome::Solved);
+        assert_eq!(results.steps, 12);
+
+        // The agent goes back through (0, 2), but the target has moved.
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        instance.set_pursuit(Some(Pursuit::new(Relocate,
+                                               Capture::Colocation)));
+        let results = instance.run_once(Point::new(0, 2), Point::new(0, 4));
+        assert_eq!(results.outcome, Outcome::Solved);
+        assert_eq!(results.steps, 4);
+    }
+
+    #[test]
+    fn summarize_solved_trials() {
+        let mut data = Data::new(3);
+        data.push(Datum {
+                      cost: 2.0,
+                      steps: 2,
+                      episodes: 1,
+                      expansions: 4,
+                      outcome: Outcome::Solved,
+                      first_move_expansions: Some(4),
+                      first_move_time: Some(Duration::from_millis(3)),
+                      ..Datum::default()
+                  });
+        data.push(Datum {
+                      cost: 4.0,
+                      steps: 4,
+                      episodes: 3,
+                      expansions: 8,
+                      outcome: Outcome::Solved,
+                      first_move_expansions: Some(2),
+                      first_move_time: Some(Duration::from_millis(1)),
+                      ..Datum::default()
+                  });
+        data.push(Datum {
+                      steps: 100,
+                      ..Datum::default()
+                  });
+
+        assert_eq!(data.summary(),
+                   Summary {
+                       trials: 3,
+                       solved: 2,
+                       unreachable: 0,
+                       cost: 3.0,
+                       steps: 3.0,
+                       episodes: 2.0,
+                       expansions: 6.0,
+                       regret: None,
+                       model_cost: None,
+                       backtracking: 0.0,
+                       exploration_cost: 0.0,
+                       step_expansions: StepExpansions::default(),
+                       first_move_expansions: 3.0,
+                       first_move_time: Duration::from_millis(2),
+                       disconnected: 0,
+                       errored: 0,
+                       illegal: 0,
+                       wasted_expansions: 0,
+                       budget_overruns: 0,
+                       bumps: 0,
+                       near_misses: 0,
+                       scans: 0,
+                       capture_time: None,
+                       plan_churn: None,
+                       custom: Vec::new(),
+                   });
+        assert_eq!(Data::new(0).summary(), Summary::default());
+
+        let mut custom = Data::new(3);
+        for &(outcome, turns) in &[(Outcome::Solved, 2.0),
+                                   (Outcome::Solved, 4.0),
+                                   (Outcome::Unsolved, 9.0)] {
+            custom.push(Datum {
+                            outcome: outcome,
+                            custom: vec![("turns".to_owned(), turns)],
+                            ..Datum::default()
+                        });
+        }
+        let summary = custom.summary();
+        assert_eq!(summary.custom, vec![("turns".to_owned(), 3.0)]);
+        assert!(summary.to_string().ends_with(", mean turns 3"));
+
+        assert_eq!(StepExpansions::new((1..21).rev()),
+                   StepExpansions {
+                       median: 10,
+                       p95: 19,
+                       max: 20,
+                   });
+
+        let mut baseline = Data::new(3);
+        for cost in &[1.5, 4.0, 1.0] {
+            baseline.push(Datum {
+                              cost: *cost,
+                              outcome: Outcome::Solved,
+                              ..Datum::default()
+                          });
+        }
+        data.set_baselines(&baseline);
+        assert_eq!(data[0].regret(), Some(0.5));
+        assert_eq!(data[2].regret(), None);
+        assert_eq!(data.summary().regret, Some(0.25));
+    }
+
+    #[test]
+    fn sample_unreachable_trials() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+.T.");
+
+        let strict = Sampling {
+            attempts: 1000,
+            ..Sampling::default()
+        };
+        assert!(build_trials(&mut grid, 0, 1, 0, &strict).is_err());
+
+        let lenient = Sampling {
+            allow_unreachable: true,
+            ..strict
+        };
+        let trials = build_trials(&mut grid, 0, 2, 0, &lenient).unwrap();
+        assert!(trials.iter().all(|&(_, _, reachable)| !reachable));
+
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
+        let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+        let mut results = instance.run_pairs(&[(trials[0].0, trials[0].1)]);
+        results.mark_unreachable(vec![false]);
+        assert_eq!(results[0].outcome, Outcome::Unreachable);
+        assert_eq!(results.summary().unreachable, 1);
+    }
+
+    #[test]
+    fn sample_from_regions() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 5
+map
+.....
+.....
+.....
+.....");
+
+        let sampling = Sampling {
+            sources: Region::Edges,
+            targets: Region::Interior,
+            ..Sampling::default()
+        };
+        for (source, target, _) in
+            build_trials(&mut grid, 0, 20, 0, &sampling).unwrap() {
+            assert!(source.y() == 0 || source.y() == 3 || source.x() == 0 ||
+                    source.x() == 4);
+            assert!((1..3).contains(&target.y()) &&
+                    (1..4).contains(&target.x()));
+        }
+
+        let corner = Region::Rectangle {
+            top: 2,
+            left: 3,
+            bottom: 3,
+            right: 4,
+        };
+        let sampling = Sampling {
+            sources: corner,
+            targets: corner,
+            ..Sampling::default()
+        };
+        for (source, target, _) in
+            build_trials(&mut grid, 0, 20, 0, &sampling).unwrap() {
+            assert!(source.y() >= 2 && source.x() >= 3);
+            assert!(target.y() >= 2 && target.x() >= 3);
+        }
+
+        let outside = Sampling {
+            targets: "3,3,4,4".parse().unwrap(),
+            ..Sampling::default()
+        };
+        assert!(build_trials(&mut grid, 0, 1, 0, &outside).is_err());
+        assert!("1,1,0,0".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn limit_steps() {
+        assert_eq!("12".parse(), Ok(StepLimit::Absolute(12)));
+        assert_eq!("2.5x".parse(), Ok(StepLimit::Straight(2.5)));
+        assert_eq!("auto".parse(), Ok(StepLimit::Auto));
+        assert!("0x".parse::<StepLimit>().is_err());
+        assert!("often".parse::<StepLimit>().is_err());
+
+        // The agent needs a move more than the straight line would take,
+        // finding the wall on its way.
+        let map = "type octile
+height 3
+width 5
+map
+..T..
+..T..
+.....";
+        let run = |limit| {
+            let mut grid = grid_from_str(map);
+            let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                           MoveMetric::Length);
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.set_step_limit(limit);
+            let results = instance.run_once(Point::new(0, 0), Point::new(0, 4));
+            (results.outcome, results.steps)
+        };
+
+        assert_eq!(run(None), (Outcome::Solved, 5));
+        assert_eq!(run(Some(StepLimit::Absolute(3))), (Outcome::OutOfSteps, 3));
+        assert_eq!(run(Some(StepLimit::Straight(1.0))),
+                   (Outcome::OutOfSteps, 4));
+        assert_eq!(run(Some(StepLimit::Straight(2.0))), (Outcome::Solved, 5));
+        assert_eq!(run(Some(StepLimit::Auto)), (Outcome::Solved, 5));
+    }
+
+    #[test]
+    fn repeated_astar_trials() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 4
+map
+....
+.TT.
+.TT.
+....");
+
+        let sampling = Sampling::default();
+        let pairs = |trials: Vec<(Point, Point, bool)>| {
+            trials.into_iter()
+                .map(|(source, target, _)| (source, target))
+                .collect::<Vec<_>>()
+        };
+        let trials = pairs(build_trials(&mut grid, 98, 100, 0, &sampling)
+                               .unwrap());
+        let new_trials = pairs(build_trials(&mut grid, 99, 100, 0, &sampling)
+                                   .unwrap());
+        assert_eq!(trials[1], new_trials[0]);
 
-        let agent = RepeatedAstar::new(Distance::octile_heuristic);
+        let agent = RepeatedAstar::new(Distance::octile_heuristic,
+                                       MoveMetric::Length);
         let mut instance = Instance::new(&mut grid, agent, Verbosity::Two);
 
-        let results = instance.run_trials(98, 100, 0);
+        let results = instance.run_pairs(&trials);
 
-        let first = results[0].as_ref().unwrap();
+        let first = &results[0];
         assert_eq!(first.steps, 4);
         assert_eq!(first.episodes, 2);
 
-        let second = results[1].as_ref().unwrap();
+        let second = &results[1];
         assert_eq!(second.steps, 3);
         assert_eq!(second.episodes, 1);
 
-        let new_results = instance.run_trials(99, 100, 0);
+        let new_results = instance.run_pairs(&new_trials);
 
-        let new_result = new_results[0].as_ref().unwrap();
+        let new_result = &new_results[0];
 
         assert_eq!(second.cost, new_result.cost);
         assert_eq!(second.steps, new_result.steps);
@@ -0,0 +1,131 @@
+//! Landmark heuristics: the true costs of optimal paths from a few chosen
+//! tiles to every other, which bound the cost of moving between any two
+//! tiles by the triangle inequality.
+
+use grid::{Distance, Grid, Measure, Point};
+use search::dijkstra;
+
+/// The costs from a set of landmarks to every tile of a grid, over its true
+/// terrain.
+#[derive(Clone, Debug)]
+pub struct Landmarks {
+    width: usize,
+    points: Vec<Point>,
+    /// The cost from each landmark to every tile, by index.
+    costs: Vec<Vec<Distance>>,
+}
+
+impl Landmarks {
+    /// Places up to `count` landmarks on the passable tiles of `grid`, each
+    /// as far as possible from those placed before it (the first one as far
+    /// as possible from the first passable tile), so that they end up on
+    /// the edges of the map, and in every region that cannot be reached
+    /// from the others.
+    pub fn from_grid(grid: &Grid, count: usize) -> Landmarks {
+        let tiles = grid.height() * grid.width();
+        let mut landmarks = Landmarks {
+            width: grid.width(),
+            points: Vec::new(),
+            costs: Vec::new(),
+        };
+        let first = match (0..tiles).find(|&i| grid.tile(i).passable()) {
+            Some(first) => first,
+            None => return landmarks,
+        };
+
+        // The cost from the nearest landmark to every tile.
+        let mut nearest = dijkstra(grid, &grid.point_of(first)).costs;
+        while landmarks.points.len() < count {
+            let farthest = (0..tiles)
+                .filter(|&i| grid.tile(i).passable() && nearest[i] > 0.0)
+                .max_by(|&a, &b| nearest[a].partial_cmp(&nearest[b]).unwrap());
+            let farthest = match farthest {
+                Some(farthest) => grid.point_of(farthest),
+                None => break,
+            };
+            let costs = dijkstra(grid, &farthest).costs;
+            if landmarks.points.is_empty() {
+                nearest = costs.clone();
+            } else {
+                for (nearest, &cost) in nearest.iter_mut().zip(&costs) {
+                    if cost < *nearest {
+                        *nearest = cost;
+                    }
+                }
+            }
+            landmarks.points.push(farthest);
+            landmarks.costs.push(costs);
+        }
+        landmarks
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Estimates the cost of moving from `from` to `to` as the largest
+    /// difference between their costs from any landmark that reaches both,
+    /// or the octile distance between them if that is larger. Never
+    /// overestimates the cost over the grid's true terrain.
+    pub fn estimate(&self, from: &Point, to: &Point) -> Distance {
+        let (from_index, to_index) = (from.y() * self.width + from.x(),
+                                      to.y() * self.width + to.x());
+        self.costs
+            .iter()
+            .map(|costs| (costs[from_index], costs[to_index]))
+            .filter(|&(a, b)| a.is_finite() && b.is_finite())
+            .map(|(a, b)| (a - b).abs())
+            .fold(Distance::octile_heuristic(from, to), Distance::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::Tile;
+    use parser::grid_from_str;
+    use search::astar;
+
+    #[test]
+    fn bound_costs_with_landmarks() {
+        let mut grid = grid_from_str("type octile\nheight 5\nwidth 7\nmap\n\
+                                      .......\n.TTTTT.\n.T...T.\n.TTT.T.\n\
+                                      .....T.");
+        let landmarks = Landmarks::from_grid(&grid, 3);
+        assert_eq!(landmarks.points().len(), 3);
+        // The first landmark is the tile farthest from the top left corner,
+        // at the end of the corridor that winds inside the walls.
+        assert_eq!(landmarks.points()[0], Point::new(2, 2));
+
+        let mut tighter = false;
+        for from in 0..35 {
+            for to in 0..35 {
+                let (from, to) = (grid.point_of(from), grid.point_of(to));
+                if !grid[&from].passable() || !grid[&to].passable() {
+                    continue;
+                }
+                let path = astar(&mut grid,
+                                 &from,
+                                 &to,
+                                 Distance::octile_heuristic,
+                                 Tile::passable)
+                        .unwrap()
+                        .path;
+                let mut cost = 0.0;
+                let mut location = from;
+                for point in path.iter().rev() {
+                    cost += Distance::octile_heuristic(&location, point);
+                    location = *point;
+                }
+                let estimate = landmarks.estimate(&from, &to);
+                assert!(estimate <= cost + 1e-9);
+                tighter |= estimate > Distance::octile_heuristic(&from, &to);
+            }
+        }
+        assert!(tighter);
+
+        // Every landmark is placed once, however many are asked for.
+        assert_eq!(Landmarks::from_grid(&grid, 100).points().len(), 23);
+    }
+}
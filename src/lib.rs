@@ -1,15 +1,94 @@
+//! Online grid pathfinding: agents that move through maps they only know
+//! as far as they have observed them, run through trials and measured.
+//!
+//! To use gridist purely as a pathfinding library, load a map with
+//! `parser::try_grid_from_file`, observe it with `Grid::look` as needed, and
+//! plan over what has been observed with `plan_on_beliefs`, which needs no
+//! agent and leaves the grid untouched. Grids may be shared between threads
+//! that plan over them at once, each with `plan_with_scratch` and a
+//! `SearchScratch` of its own.
+//!
+//! The programs under `examples/` embed gridist end to end: running an agent
+//! of their own, stepping through a trial while rendering it, and comparing
+//! agents on a generated map.
+
+#[cfg(feature = "cli")]
+extern crate ctrlc;
+#[cfg(feature = "cli")]
 extern crate docopt;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
 extern crate rand;
+#[cfg(feature = "json")]
 extern crate serde;
+#[cfg(feature = "json")]
 #[macro_use]
 extern crate serde_derive;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "profiling")]
+extern crate tracing;
+#[cfg(feature = "live")]
+extern crate tungstenite;
+
+/// Enters a profiling span named `$name` that lasts until the end of the
+/// enclosing block. Expands to nothing unless the `profiling` feature is
+/// enabled.
+#[cfg(feature = "profiling")]
+macro_rules! profile_span {
+    ($name:expr $(, $field:ident = $value:expr)*) => {
+        let _span = ::tracing::info_span!($name $(, $field = $value)*)
+            .entered();
+    };
+}
 
+#[cfg(not(feature = "profiling"))]
+macro_rules! profile_span {
+    ($name:expr $(, $field:ident = $value:expr)*) => {
+        $(let _ = &$value;)*
+    };
+}
 
 pub mod agent;
+#[cfg(feature = "cli")]
 pub mod arguments;
+pub mod comparison;
+pub mod connectivity;
+pub mod cost;
+pub mod cpd;
+pub mod env;
+pub mod execution;
 pub mod experiment;
+pub mod field;
+pub mod generator;
 pub mod grid;
+pub mod heuristic;
+pub mod instance;
+pub mod landmarks;
+#[cfg(feature = "live")]
+pub mod live;
+#[cfg(feature = "cli")]
+pub mod manifest;
+pub mod metrics;
+pub mod observer;
+pub mod overlay;
 pub mod parser;
+pub mod paths;
+pub mod plan;
+pub mod pursuit;
+pub mod quadtree;
+pub mod registry;
+pub mod reservation;
+pub mod rle;
+pub mod search;
+#[cfg(feature = "json")]
+pub mod serve;
+pub mod suite;
 
-mod instance;
-mod search;
+pub use instance::{Bucket, Data, Datum, Outcome, PlanEpisode, StepExpansions,
+                   Summary};
+pub use plan::{plan_on_beliefs, plan_with_scratch, Plan, PlanOptions,
+               SearchScratch};
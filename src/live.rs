@@ -0,0 +1,202 @@
+//! Streams the progress of trials over websockets, as JSON frames, so that
+//! a browser can render experiments while they run. Only built with the
+//! `live` feature.
+
+use std::io::Result as IOResult;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json;
+use tungstenite::{accept, Message, WebSocket};
+
+use grid::{Distance, Grid, Point};
+use instance::{Datum, Outcome};
+use observer::Observer;
+
+/// A single message sent to clients. Points are sent as `[y, x]` pairs,
+/// and beliefs as a row-major array of `Belief::code` values.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Frame {
+    Start {
+        height: usize,
+        width: usize,
+        source: (usize, usize),
+        target: (usize, usize),
+    },
+    Plan {
+        location: (usize, usize),
+        expansions: usize,
+        path: Vec<(usize, usize)>,
+    },
+    Move {
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    Observe {
+        location: (usize, usize),
+        beliefs: Vec<u8>,
+    },
+    End {
+        outcome: Outcome,
+        steps: usize,
+        cost: Distance,
+    },
+}
+
+fn pair(point: &Point) -> (usize, usize) {
+    (point.y, point.x)
+}
+
+/// An observer that sends every event to all connected websocket clients.
+/// Clients that disconnect are dropped, and events are lost while nobody is
+/// connected.
+pub struct Streamer {
+    address: SocketAddr,
+    clients: Arc<Mutex<Vec<WebSocket<TcpStream>>>>,
+    delay: Option<Duration>,
+}
+
+impl Streamer {
+    /// Listens for websocket connections on `address`, accepting them in
+    /// the background for as long as the process runs.
+    pub fn bind<A>(address: A) -> IOResult<Streamer>
+        where A: ToSocketAddrs
+    {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(Ok(client)) = stream.map(accept) {
+                    accepted.lock().unwrap().push(client);
+                }
+            }
+        });
+
+        Ok(Streamer {
+               address: address,
+               clients: clients,
+               delay: None,
+           })
+    }
+
+    /// The address the streamer listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Blocks until at least one client is connected.
+    pub fn wait_for_client(&self) {
+        while self.clients.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Pauses for `delay` after every observation, so that runs can be
+    /// followed at a human pace.
+    pub fn set_delay(&mut self, delay: Option<Duration>) {
+        self.delay = delay;
+    }
+
+    fn send(&mut self, frame: &Frame) {
+        let text = serde_json::to_string(frame).unwrap();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| {
+                               client.send(Message::Text(text.clone())).is_ok()
+                           });
+    }
+}
+
+impl Observer for Streamer {
+    fn on_trial_start(&mut self, grid: &Grid, source: &Point, target: &Point) {
+        self.send(&Frame::Start {
+                       height: grid.height(),
+                       width: grid.width(),
+                       source: pair(source),
+                       target: pair(target),
+                   });
+    }
+
+    fn on_plan(&mut self,
+               _grid: &Grid,
+               location: &Point,
+               expansions: usize,
+               path: &[Point]) {
+        self.send(&Frame::Plan {
+                       location: pair(location),
+                       expansions: expansions,
+                       path: path.iter().map(pair).collect(),
+                   });
+    }
+
+    fn on_move(&mut self, _grid: &Grid, from: &Point, to: &Point) {
+        self.send(&Frame::Move {
+                       from: pair(from),
+                       to: pair(to),
+                   });
+    }
+
+    fn on_observe(&mut self, grid: &Grid, location: &Point) {
+        self.send(&Frame::Observe {
+                       location: pair(location),
+                       beliefs: grid.belief_matrix().into_vec(),
+                   });
+        if let Some(delay) = self.delay {
+            thread::sleep(delay);
+        }
+    }
+
+    fn on_trial_end(&mut self, _grid: &Grid, datum: &Datum) {
+        self.send(&Frame::End {
+                       outcome: datum.outcome,
+                       steps: datum.steps,
+                       cost: datum.cost,
+                   });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value;
+    use tungstenite::client;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn stream_events() {
+        let grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let mut streamer = Streamer::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(streamer.local_addr()).unwrap();
+        let url = format!("ws://{}/", streamer.local_addr());
+        let (mut socket, _) = client(url.as_str(), stream).unwrap();
+        streamer.wait_for_client();
+
+        streamer.on_trial_start(&grid, &Point::new(0, 0), &Point::new(0, 2));
+        streamer.on_observe(&grid, &Point::new(0, 0));
+
+        let frame = socket.read().unwrap();
+        let start: Value = serde_json::from_str(frame.to_text().unwrap())
+            .unwrap();
+        assert_eq!(start["event"], "start");
+        assert_eq!(start["width"], 3);
+        assert_eq!(start["target"], serde_json::json!([0, 2]));
+
+        let frame = socket.read().unwrap();
+        let observe: Value = serde_json::from_str(frame.to_text().unwrap())
+            .unwrap();
+        assert_eq!(observe["event"], "observe");
+        assert_eq!(observe["beliefs"], serde_json::json!([0, 0, 0]));
+    }
+}
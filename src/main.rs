@@ -1,9 +1,33 @@
 extern crate gridist;
+#[cfg(feature = "profiling")]
+extern crate tracing_flame;
+#[cfg(feature = "profiling")]
+extern crate tracing_subscriber;
+
+use std::process::exit;
 
 use gridist::arguments::run_experiment_from_cli;
 
+/// Records profiling spans into `tracing.folded`, ready for `inferno` or
+/// `flamegraph.pl`. The returned guard flushes the file when dropped.
+#[cfg(feature = "profiling")]
+fn start_profiling() -> impl Drop {
+    use tracing_subscriber::prelude::*;
+
+    let (layer, guard) = tracing_flame::FlameLayer::with_file("tracing.folded")
+        .expect("Could not create tracing.folded");
+    tracing_subscriber::registry().with(layer).init();
+    guard
+}
+
 fn main() {
-    let data = run_experiment_from_cli();
+    #[cfg(feature = "profiling")]
+    let _profiling = start_profiling();
+
+    let status = run_experiment_from_cli();
+
+    #[cfg(feature = "profiling")]
+    drop(_profiling);
 
-    data.print();
+    exit(status);
 }
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, File};
+use std::io::{Error as IOError, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use grid::{Grid, GridMeta};
+
+/// Provenance information for a single run of an experiment, written as JSON
+/// next to its results.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    version: &'static str,
+    map: String,
+    map_hash: String,
+    grid: GridMeta,
+    parameters: BTreeMap<String, String>,
+    hostname: String,
+    start_time: u64,
+    duration: f64,
+    #[serde(skip)]
+    timer: Instant,
+}
+
+impl Manifest {
+    /// Starts recording a run on `grid`, read from the file `map`, with the
+    /// given effective parameters.
+    pub fn new(map: &str,
+               grid: &Grid,
+               parameters: BTreeMap<String, String>)
+               -> Manifest {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        Manifest {
+            version: env!("CARGO_PKG_VERSION"),
+            map: map.to_owned(),
+            map_hash: format!("{:016x}", grid.content_hash()),
+            grid: grid.meta(),
+            parameters: parameters,
+            hostname: hostname(),
+            start_time: start_time,
+            duration: 0.0,
+            timer: Instant::now(),
+        }
+    }
+
+    /// Returns the hash of the map's contents, as a hexadecimal string.
+    pub fn map_hash(&self) -> &str {
+        &self.map_hash
+    }
+
+    /// Describes the map the run was on.
+    pub fn grid(&self) -> &GridMeta {
+        &self.grid
+    }
+
+    /// Records the time elapsed since the manifest was created as the
+    /// duration of the run.
+    pub fn finish(&mut self) {
+        self.duration = self.timer.elapsed().as_secs_f64();
+    }
+
+    pub fn write<P>(&self, path: &P) -> Result<(), IOError>
+        where P: AsRef<Path> + ?Sized
+    {
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)
+    }
+}
+
+/// Returns the path at which the manifest for results written to `output` is
+/// stored by default: the same path, with its extension replaced by
+/// `manifest.json`.
+pub fn default_path<P>(output: &P) -> PathBuf
+    where P: AsRef<Path> + ?Sized
+{
+    output.as_ref().with_extension("manifest.json")
+}
+
+fn hostname() -> String {
+    read_to_string("/etc/hostname")
+        .ok()
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .or_else(|| ::std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn default_path_replaces_extension() {
+        assert_eq!(default_path("results/run.txt"),
+                   PathBuf::from("results/run.manifest.json"));
+        assert_eq!(default_path("run"), PathBuf::from("run.manifest.json"));
+    }
+
+    #[test]
+    fn serializes_provenance() {
+        let grid = grid_from_str("type octile
+height 1
+width 2
+map
+..");
+        let mut parameters = BTreeMap::new();
+        parameters.insert("seed".to_owned(), "3".to_owned());
+
+        let mut manifest = Manifest::new("maps/Tiny.map", &grid, parameters);
+        manifest.finish();
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["map"], "maps/Tiny.map");
+        assert_eq!(json["map_hash"],
+                   format!("{:016x}", grid.content_hash()).as_str());
+        assert_eq!(json["grid"]["map_type"], "octile");
+        assert_eq!(json["grid"]["width"], 2);
+        assert_eq!(json["grid"]["hash"], json["map_hash"]);
+        assert!(json["grid"]["path"].is_null());
+        assert_eq!(json["parameters"]["seed"], "3");
+        assert!(json["version"].is_string());
+        assert!(json["hostname"].is_string());
+        assert!(json["start_time"].is_u64());
+        assert!(json.get("timer").is_none());
+    }
+}
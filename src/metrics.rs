@@ -0,0 +1,136 @@
+//! Metrics measured over every trial besides the ones built into `Datum`,
+//! which are reported by name alongside them.
+
+use std::f64::consts::PI;
+
+use grid::{Grid, Point};
+use instance::Datum;
+
+/// Measures something about every trial run by an `Instance`, as named
+/// values. Metrics follow trials like observers do, but their values are
+/// recorded in the trial's `Datum` (see `Datum::custom`), so they are
+/// written with the rest of its results.
+pub trait Metric {
+    /// A trial from `source` to `target` is about to start, and whatever was
+    /// measured in the one before it should be discarded.
+    fn start(&mut self, _grid: &Grid, _source: &Point, _target: &Point) {}
+
+    /// The agent moved from `from` to `to`.
+    fn on_move(&mut self, _grid: &Grid, _from: &Point, _to: &Point) {}
+
+    /// The agent at `location` planned, expanding `expansions` nodes.
+    fn on_episode(&mut self,
+                  _grid: &Grid,
+                  _location: &Point,
+                  _expansions: usize) {
+    }
+
+    /// The trial ended, with the results in `datum`. Returns the values
+    /// measured, by name, which should not clash with those of `Datum` or
+    /// of other metrics.
+    fn finish(&mut self, grid: &Grid, datum: &Datum) -> Vec<(String, f64)>;
+}
+
+/// Counts how many times the agent changed heading along the path it
+/// executed, and by how many degrees it turned in all, as measures of how
+/// smooth the path was. Headings are taken from the line between successive
+/// locations, so paths that are not made of octile moves (as those of
+/// any-angle planners, or smoothed afterwards) are measured alike.
+#[derive(Debug, Default)]
+pub struct Turns {
+    /// The heading of the last move, in radians.
+    heading: Option<f64>,
+    turns: usize,
+    angle: f64,
+}
+
+impl Turns {
+    pub fn new() -> Turns {
+        Turns::default()
+    }
+}
+
+/// Turns smaller than this many radians are taken to be rounding errors
+/// rather than changes of heading.
+const TURN_EPSILON: f64 = 1e-9;
+
+impl Metric for Turns {
+    fn start(&mut self, _grid: &Grid, _source: &Point, _target: &Point) {
+        *self = Turns::default();
+    }
+
+    fn on_move(&mut self, _grid: &Grid, from: &Point, to: &Point) {
+        if from == to {
+            return;
+        }
+        let heading = (to.y() as f64 - from.y() as f64)
+            .atan2(to.x() as f64 - from.x() as f64);
+        if let Some(previous) = self.heading.replace(heading) {
+            let turn = (heading - previous).abs();
+            let turn = turn.min(2.0 * PI - turn);
+            if turn > TURN_EPSILON {
+                self.turns += 1;
+                self.angle += turn.to_degrees();
+            }
+        }
+    }
+
+    fn finish(&mut self, _grid: &Grid, _datum: &Datum) -> Vec<(String, f64)> {
+        vec![("turns".to_owned(), self.turns as f64),
+             ("turning_angle".to_owned(), self.angle)]
+    }
+}
+
+/// Builds the metric named `name`, for the command line.
+pub fn build(name: &str) -> Result<Box<dyn Metric>, String> {
+    match name {
+        "turns" => Ok(Box::new(Turns::new())),
+        _ => Err(format!("Unknown metric '{}'.", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn count_turns() {
+        let grid = grid_from_str("type octile
+height 3
+width 3
+map
+...
+...
+...");
+        let path = [Point::new(0, 0),
+                    Point::new(0, 1),
+                    Point::new(0, 2),
+                    Point::new(1, 1),
+                    Point::new(2, 1),
+                    Point::new(2, 2)];
+        let mut metric = Turns::new();
+        metric.start(&grid, &path[0], &path[5]);
+        for pair in path.windows(2) {
+            metric.on_move(&grid, &pair[0], &pair[1]);
+        }
+        let values = metric.finish(&grid, &Datum::default());
+        // East, then southwest (135), south (45) and east (90).
+        assert_eq!(values[0], ("turns".to_owned(), 3.0));
+        assert_eq!(values[1].0, "turning_angle");
+        assert!((values[1].1 - 270.0).abs() < 1e-9);
+
+        // Standing still, or going on straight, is not turning.
+        metric.start(&grid, &path[0], &path[2]);
+        metric.on_move(&grid, &path[0], &path[1]);
+        metric.on_move(&grid, &path[1], &path[1]);
+        metric.on_move(&grid, &path[1], &path[2]);
+        assert_eq!(metric.finish(&grid, &Datum::default()),
+                   vec![("turns".to_owned(), 0.0),
+                        ("turning_angle".to_owned(), 0.0)]);
+
+        metric.start(&grid, &path[0], &path[5]);
+        assert_eq!(metric.finish(&grid, &Datum::default())[0].1, 0.0);
+    }
+}
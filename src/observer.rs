@@ -0,0 +1,541 @@
+//! Hooks into the simulation loop, for visualizers and statistics
+//! collectors that need to follow trials as they run.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{stdout, BufRead, BufWriter, Result as IOResult, Write};
+use std::path::PathBuf;
+
+use experiment::Verbosity;
+use grid::{Expansion, Grid, Point};
+use instance::Datum;
+use rle;
+#[cfg(feature = "json")]
+use serde_json;
+
+/// Receives the events of every trial run by an `Instance`. Every method
+/// does nothing by default, so implementors only handle the events they
+/// care about.
+pub trait Observer {
+    /// A trial from `source` to `target` is about to start.
+    fn on_trial_start(&mut self,
+                      _grid: &Grid,
+                      _source: &Point,
+                      _target: &Point) {
+    }
+
+    /// The agent at `location` planned, expanding `expansions` nodes. The
+    /// moves it intends to make from there are in `path`, which holds only
+    /// the next one if the agent does not keep a plan.
+    fn on_plan(&mut self,
+               _grid: &Grid,
+               _location: &Point,
+               _expansions: usize,
+               _path: &[Point]) {
+    }
+
+    /// The agent at `location` searched the grid, expanding the nodes in
+    /// `trace` in order. Only called while searches are traced (see
+    /// `Grid::set_trace_search`).
+    fn on_search(&mut self,
+                 _grid: &Grid,
+                 _location: &Point,
+                 _trace: &[Expansion]) {
+    }
+
+    /// The agent moved from `from` to `to`.
+    fn on_move(&mut self, _grid: &Grid, _from: &Point, _to: &Point) {}
+
+    /// The agent looked around from `location`, possibly updating the
+    /// beliefs in `grid`.
+    fn on_observe(&mut self, _grid: &Grid, _location: &Point) {}
+
+    /// The trial ended, with the results in `datum`.
+    fn on_trial_end(&mut self, _grid: &Grid, _datum: &Datum) {}
+}
+
+/// Adapts an `io::Write` (such as stdout or a file) into the `fmt::Write`
+/// sinks that `Printer` renders into.
+#[derive(Debug)]
+pub struct IoSink<W>(pub W);
+
+impl<W> fmt::Write for IoSink<W>
+    where W: Write
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Renders the agent's beliefs about `grid` as one line per row, with the
+/// agent at `location` shown as `a` and the target as `*`, followed by an
+/// empty line.
+pub fn render_beliefs<W>(grid: &Grid,
+                         location: &Point,
+                         target: &Point,
+                         out: &mut W)
+                         -> fmt::Result
+    where W: fmt::Write + ?Sized
+{
+    for (y, row) in grid.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let point = Point::new(y, x);
+            if *location == point {
+                write!(out, "a")?;
+            } else if *target == point {
+                write!(out, "*")?;
+            } else {
+                write!(out, "{}", cell.belief())?;
+            }
+        }
+        writeln!(out)?;
+    }
+    writeln!(out)
+}
+
+/// Renders the nodes expanded by a search from `location`, one per line, in
+/// the order they were expanded.
+pub fn render_search<W>(location: &Point,
+                        trace: &[Expansion],
+                        out: &mut W)
+                        -> fmt::Result
+    where W: fmt::Write + ?Sized
+{
+    writeln!(out, "Search from {}:", location)?;
+    for expansion in trace {
+        writeln!(out,
+                 "  expand {} f={} g={} h={}",
+                 expansion.point,
+                 expansion.f,
+                 expansion.g,
+                 expansion.h)?;
+    }
+    Ok(())
+}
+
+/// What a `Printer` reports besides the start of every trial.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Trace {
+    /// Render the agent's beliefs after every observation.
+    pub steps: bool,
+    /// List every node expanded by the agent's searches, with its f, g and h
+    /// values.
+    pub search: bool,
+    /// Record the nodes expanded by the agent's searches for observers (such
+    /// as `SearchLog`), even if they are not listed.
+    pub record_search: bool,
+}
+
+/// Prints the progress of trials, to stdout unless given another sink:
+/// every trial's endpoints from `Verbosity::One`, and the agent's beliefs
+/// after every observation from `Verbosity::Two` (or when tracing steps),
+/// and the nodes it expands when tracing searches.
+pub struct Printer {
+    verbosity: Verbosity,
+    trace: Trace,
+    target: Point,
+    out: Box<dyn fmt::Write>,
+}
+
+impl Printer {
+    pub fn new(verbosity: Verbosity) -> Printer {
+        Printer::to(verbosity, IoSink(stdout()))
+    }
+
+    /// Creates a printer that renders into `out` instead of stdout. Added as
+    /// an observer to an experiment run at `Verbosity::Zero`, it redirects
+    /// the progress of trials to a file (through `IoSink`) or a string.
+    pub fn to<W>(verbosity: Verbosity, out: W) -> Printer
+        where W: fmt::Write + 'static
+    {
+        Printer {
+            verbosity: verbosity,
+            trace: Trace {
+                steps: verbosity >= Verbosity::Two,
+                search: false,
+                record_search: false,
+            },
+            target: Point::new(0, 0),
+            out: Box::new(out),
+        }
+    }
+
+    /// Sets what the printer traces, besides what its verbosity implies.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace.steps = trace.steps || self.verbosity >= Verbosity::Two;
+        self.trace.search = trace.search;
+    }
+
+    /// Renders the agent's beliefs about `grid` (see `render_beliefs`),
+    /// whatever the printer traces.
+    pub fn draw(&mut self, grid: &Grid, location: &Point, target: &Point) {
+        self.print(|out| render_beliefs(grid, location, target, out));
+    }
+
+    /// Prints `message` on a line of its own, whatever the verbosity.
+    pub fn message(&mut self, message: &str) {
+        self.print(|out| writeln!(out, "{}", message));
+    }
+
+    fn print<F>(&mut self, render: F)
+        where F: FnOnce(&mut dyn fmt::Write) -> fmt::Result
+    {
+        if render(self.out.as_mut()).is_err() {
+            eprintln!("Cannot print the progress of the trial.");
+        }
+    }
+}
+
+impl fmt::Debug for Printer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Printer")
+            .field("verbosity", &self.verbosity)
+            .field("trace", &self.trace)
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl Observer for Printer {
+    fn on_trial_start(&mut self, _grid: &Grid, source: &Point, target: &Point) {
+        self.target = *target;
+        if self.verbosity >= Verbosity::One {
+            self.print(|out| {
+                           writeln!(out,
+                                    "Running search from {} to {}.",
+                                    source,
+                                    target)
+                       });
+        }
+    }
+
+    fn on_search(&mut self,
+                 _grid: &Grid,
+                 location: &Point,
+                 trace: &[Expansion]) {
+        if self.trace.search {
+            self.print(|out| render_search(location, trace, out));
+        }
+    }
+
+    fn on_observe(&mut self, grid: &Grid, location: &Point) {
+        if self.trace.steps {
+            let target = self.target;
+            self.draw(grid, location, &target);
+        }
+    }
+}
+
+/// The symbol for each `Belief::code` in belief dumps: unknown, passable,
+/// impassable, and then the known terrains (ground, out of bounds, trees,
+/// swamp, water).
+pub const BELIEF_SYMBOLS: [char; 8] = ['?', '+', 'X', '.', '@', 'T', 'S', 'W'];
+
+/// Writes the agent's beliefs about `grid` with a header like that of the
+/// movingai format, and every row run-length encoded with the symbols in
+/// `BELIEF_SYMBOLS`.
+pub fn write_beliefs<W>(grid: &Grid, out: &mut W) -> IOResult<()>
+    where W: Write
+{
+    let beliefs = grid.belief_matrix();
+    writeln!(out, "type beliefs-rle")?;
+    writeln!(out, "height {}", beliefs.height())?;
+    writeln!(out, "width {}", beliefs.width())?;
+    writeln!(out, "map")?;
+    for row in beliefs.as_slice().chunks(beliefs.width().max(1)) {
+        let symbols = row.iter().map(|&code| BELIEF_SYMBOLS[code as usize]);
+        writeln!(out, "{}", rle::encode(symbols))?;
+    }
+    Ok(())
+}
+
+/// Returns the position in the results of the trial that runs at `index` in
+/// `order`, if given, or else in order.
+fn position(order: &Option<Vec<usize>>, index: usize) -> usize {
+    order.as_ref().map_or(index, |order| order[index])
+}
+
+/// Dumps the agent's final beliefs at the end of every trial to a file in
+/// `directory`, named after the trial's position in the results (as in
+/// `trial-0.rle`).
+#[derive(Debug)]
+pub struct BeliefDump {
+    directory: PathBuf,
+    trial: usize,
+    /// The positions of the trials, in the order they run, if not in order.
+    order: Option<Vec<usize>>,
+}
+
+impl BeliefDump {
+    pub fn new<P>(directory: P) -> BeliefDump
+        where P: Into<PathBuf>
+    {
+        BeliefDump {
+            directory: directory.into(),
+            trial: 0,
+            order: None,
+        }
+    }
+
+    /// Names the files after the positions of trials that run in `order`.
+    pub fn set_order(&mut self, order: Vec<usize>) {
+        self.order = Some(order);
+    }
+
+    fn dump(&self, grid: &Grid) -> IOResult<PathBuf> {
+        let position = position(&self.order, self.trial);
+        let path = self.directory.join(format!("trial-{}.rle", position));
+        let mut out = BufWriter::new(File::create(&path)?);
+        write_beliefs(grid, &mut out)?;
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+impl Observer for BeliefDump {
+    fn on_trial_end(&mut self, grid: &Grid, _datum: &Datum) {
+        if let Err(e) = self.dump(grid) {
+            eprintln!("Cannot dump the beliefs of trial {} to {}: {}",
+                      position(&self.order, self.trial),
+                      self.directory.display(),
+                      e);
+        }
+        self.trial += 1;
+    }
+}
+
+/// Writes the row of results of every trial as soon as it ends, flushing it,
+/// so that the trials that ran survive the run being cut short. Rows are
+/// labeled like those of `Data::write`, but are written before the trials
+/// are compared to a baseline or marked unreachable.
+#[derive(Debug)]
+pub struct ResultLog<W> {
+    out: W,
+    tag: Option<String>,
+    lines: Option<Vec<usize>>,
+    trial: usize,
+    order: Option<Vec<usize>>,
+}
+
+impl<W> ResultLog<W>
+    where W: Write
+{
+    /// Creates a log that labels its rows with `tag`, if given, and
+    /// identifies trials by their `lines`, if given, or by their position.
+    pub fn new(out: W, tag: Option<String>, lines: Option<Vec<usize>>)
+               -> ResultLog<W> {
+        ResultLog {
+            out: out,
+            tag: tag,
+            lines: lines,
+            trial: 0,
+            order: None,
+        }
+    }
+
+    /// Labels the rows of trials that run in `order` by their positions.
+    pub fn set_order(&mut self, order: Vec<usize>) {
+        self.order = Some(order);
+    }
+
+    fn log(&mut self, datum: &Datum) -> IOResult<()> {
+        if let Some(ref tag) = self.tag {
+            write!(self.out, "[{}] ", tag)?;
+        }
+        let trial = position(&self.order, self.trial);
+        match self.lines {
+            Some(ref lines) => write!(self.out, "Line {}: ", lines[trial])?,
+            None => write!(self.out, "Trial {}: ", trial)?,
+        }
+        datum.write(&mut self.out)?;
+        self.out.flush()
+    }
+}
+
+impl<W> Observer for ResultLog<W>
+    where W: Write
+{
+    fn on_trial_end(&mut self, _grid: &Grid, datum: &Datum) {
+        if let Err(e) = self.log(datum) {
+            eprintln!("Cannot log the results of trial {}: {}",
+                      position(&self.order, self.trial),
+                      e);
+        }
+        self.trial += 1;
+    }
+}
+
+/// A search run by the agent during a trial, as written by `SearchLog`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct SearchRecord {
+    /// The position of the trial in the results.
+    pub trial: usize,
+    /// Where the agent was when it searched.
+    pub location: Point,
+    /// The target of the trial.
+    pub target: Point,
+    /// The nodes the search expanded, in order.
+    pub expansions: Vec<Expansion>,
+}
+
+/// Writes every search the agent runs as a line of JSON (a `SearchRecord`),
+/// to be studied or replayed step by step with `replay_search`. Searches
+/// are only seen while they are traced (see `Trace::record_search`).
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub struct SearchLog<W> {
+    out: W,
+    trial: usize,
+    target: Point,
+    order: Option<Vec<usize>>,
+}
+
+#[cfg(feature = "json")]
+impl<W> SearchLog<W>
+    where W: Write
+{
+    pub fn new(out: W) -> SearchLog<W> {
+        SearchLog {
+            out: out,
+            trial: 0,
+            target: Point::new(0, 0),
+            order: None,
+        }
+    }
+
+    /// Labels the searches of trials that run in `order` by the trials'
+    /// positions.
+    pub fn set_order(&mut self, order: Vec<usize>) {
+        self.order = Some(order);
+    }
+
+    fn log(&mut self, location: &Point, trace: &[Expansion]) -> IOResult<()> {
+        let record = SearchRecord {
+            trial: position(&self.order, self.trial),
+            location: *location,
+            target: self.target,
+            expansions: trace.to_vec(),
+        };
+        serde_json::to_writer(&mut self.out, &record)?;
+        writeln!(self.out)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W> Observer for SearchLog<W>
+    where W: Write
+{
+    fn on_trial_start(&mut self, _grid: &Grid, _source: &Point, target: &Point) {
+        self.target = *target;
+    }
+
+    fn on_search(&mut self,
+                 _grid: &Grid,
+                 location: &Point,
+                 trace: &[Expansion]) {
+        if let Err(e) = self.log(location, trace) {
+            eprintln!("Cannot log the searches of trial {}: {}",
+                      position(&self.order, self.trial),
+                      e);
+        }
+    }
+
+    fn on_trial_end(&mut self, _grid: &Grid, _datum: &Datum) {
+        if let Err(e) = self.out.flush() {
+            eprintln!("Cannot log the searches of trial {}: {}",
+                      position(&self.order, self.trial),
+                      e);
+        }
+        self.trial += 1;
+    }
+}
+
+/// Reads the searches written by a `SearchLog`, skipping empty lines.
+#[cfg(feature = "json")]
+pub fn read_searches<R>(reader: R) -> IOResult<Vec<SearchRecord>>
+    where R: BufRead
+{
+    let mut searches = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            searches.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(searches)
+}
+
+/// Renders `grid` as one line per row, with the agent at `location` shown as
+/// `a`, the target as `*`, the node just expanded as `#`, and the nodes
+/// expanded before it as `o`, followed by the values of the node just
+/// expanded.
+pub fn render_expansion<W>(grid: &Grid,
+                           search: &SearchRecord,
+                           step: usize,
+                           out: &mut W)
+                           -> fmt::Result
+    where W: fmt::Write + ?Sized
+{
+    let expanded = &search.expansions[..step + 1];
+    let current = &expanded[step];
+    for (y, row) in grid.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let point = Point::new(y, x);
+            if point == current.point {
+                write!(out, "#")?;
+            } else if point == search.location {
+                write!(out, "a")?;
+            } else if point == search.target {
+                write!(out, "*")?;
+            } else if expanded.iter().any(|e| e.point == point) {
+                write!(out, "o")?;
+            } else {
+                write!(out, "{}", tile)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    writeln!(out,
+             "Expansion {} of {} (episode {}): {} f={} g={} h={}",
+             step + 1,
+             search.expansions.len(),
+             current.episode,
+             current.point,
+             current.f,
+             current.g,
+             current.h)
+}
+
+/// Replays `search` over `grid` one expansion at a time, rendering each into
+/// `output` and waiting for a line of `input` before the next one. Stops
+/// early at the end of `input` or when the line is `q`, returning whether
+/// the whole search was replayed.
+pub fn replay_search<R, W>(grid: &Grid,
+                           search: &SearchRecord,
+                           input: &mut R,
+                           output: &mut W)
+                           -> IOResult<bool>
+    where R: BufRead,
+          W: Write
+{
+    writeln!(output,
+             "Search in trial {} from {} to {}:",
+             search.trial,
+             search.location,
+             search.target)?;
+    for step in 0..search.expansions.len() {
+        let mut frame = String::new();
+        let _ = render_expansion(grid, search, step, &mut frame);
+        write!(output, "{}", frame)?;
+        write!(output, "Enter for the next expansion, q to quit: ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 || line.trim() == "q" {
+            writeln!(output)?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
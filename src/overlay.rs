@@ -0,0 +1,257 @@
+//! Named layers of values over the tiles of a grid, for annotations (extra
+//! costs, risk estimates, markings) that are kept apart from the terrain.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "json")]
+use std::fs::File;
+#[cfg(feature = "json")]
+use std::io::{BufReader, Read};
+#[cfg(feature = "json")]
+use std::path::Path;
+
+use grid::{Distance, Point};
+#[cfg(feature = "json")]
+use serde_json;
+
+/// The layer whose values cost models add to the cost of moving onto each
+/// tile (see `Step::layer`).
+pub const COST_LAYER: &str = "cost";
+
+/// A value for every tile of a grid of `height` rows and `width` columns,
+/// in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Layer {
+    height: usize,
+    width: usize,
+    values: Vec<Distance>,
+}
+
+impl Layer {
+    /// Creates a layer with `value` on every tile.
+    pub fn new(height: usize, width: usize, value: Distance) -> Layer {
+        Layer {
+            height: height,
+            width: width,
+            values: vec![value; height * width],
+        }
+    }
+
+    /// Creates a layer out of its rows of values. Fails if they do not all
+    /// have the same length.
+    pub fn from_rows(rows: Vec<Vec<Distance>>) -> Result<Layer, String> {
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+        if let Some(y) = rows.iter().position(|row| row.len() != width) {
+            return Err(format!("Row {} has {} values, not {}.",
+                               y,
+                               rows[y].len(),
+                               width));
+        }
+        Ok(Layer {
+               height: height,
+               width: width,
+               values: rows.into_iter()
+                   .flat_map(|row| row.into_iter())
+                   .collect(),
+           })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the value of the tile at `point`, if it lies within the layer.
+    pub fn get(&self, point: &Point) -> Option<Distance> {
+        if point.y() < self.height && point.x() < self.width {
+            Some(self.values[point.y() * self.width + point.x()])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the tile at the flat `index` of a grid of the
+    /// same size.
+    pub fn value(&self, index: usize) -> Distance {
+        self.values[index]
+    }
+
+    /// Sets the value of the tile at `point`, if it lies within the layer.
+    pub fn set(&mut self, point: &Point, value: Distance) {
+        if point.y() < self.height && point.x() < self.width {
+            self.values[point.y() * self.width + point.x()] = value;
+        }
+    }
+
+    /// Checks that the layer has a value for every tile, as layers read from
+    /// files may not.
+    #[cfg(feature = "json")]
+    fn check(&self) -> Result<(), String> {
+        if self.values.len() == self.height * self.width {
+            Ok(())
+        } else {
+            Err(format!("A {}x{} layer needs {} values, not {}.",
+                        self.height,
+                        self.width,
+                        self.height * self.width,
+                        self.values.len()))
+        }
+    }
+}
+
+/// Checks that `layer` may be laid under `name`: any values will do for
+/// annotations, but those of the cost layer are added to the cost of moves,
+/// so none of them may be negative, or searches could not trust the costs
+/// they have found.
+pub fn check_layer(name: &str, layer: &Layer) -> Result<(), String> {
+    if name != COST_LAYER {
+        return Ok(());
+    }
+    let negative = layer.values
+        .iter()
+        .position(|value| *value < 0.0 || value.is_nan());
+    match negative {
+        Some(index) => {
+            Err(format!("Layer '{}' has a negative value {} at ({}, {}).",
+                        name,
+                        layer.values[index],
+                        index / layer.width,
+                        index % layer.width))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Layers of values over a grid, by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(transparent))]
+pub struct Overlay {
+    layers: BTreeMap<String, Layer>,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay::default()
+    }
+
+    /// Adds `layer` under `name`, replacing any layer of that name.
+    pub fn insert(&mut self, name: &str, layer: Layer) {
+        self.layers.insert(name.to_owned(), layer);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Layer> {
+        self.layers.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Layer> {
+        self.layers.remove(name)
+    }
+
+    /// Lists the names of the layers, in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the value of layer `name` at `point`, if there is such a
+    /// layer and the point lies within it.
+    pub fn value(&self, name: &str, point: &Point) -> Option<Distance> {
+        self.get(name).and_then(|layer| layer.get(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Iterates over the layers by name, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Layer)> {
+        self.layers.iter().map(|(name, layer)| (name.as_str(), layer))
+    }
+}
+
+/// Reads an overlay written as a JSON object that maps the name of every
+/// layer to its height, width and values, in row-major order. Fails if it
+/// is not valid, some layer does not have a value for every tile, or the
+/// cost layer has negative values (see `check_layer`).
+#[cfg(feature = "json")]
+pub fn read_overlay<R>(reader: R) -> Result<Overlay, String>
+    where R: Read
+{
+    let overlay: Overlay = serde_json::from_reader(reader)
+        .map_err(|e| e.to_string())?;
+    for (name, layer) in overlay.iter() {
+        layer.check().map_err(|e| format!("Layer '{}': {}", name, e))?;
+        check_layer(name, layer)?;
+    }
+    Ok(overlay)
+}
+
+/// Reads the overlay in the file at `path`, as `read_overlay` does.
+#[cfg(feature = "json")]
+pub fn read_overlay_file<P>(path: P) -> Result<Overlay, String>
+    where P: AsRef<Path>
+{
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    read_overlay(BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_layers() {
+        let mut layer = Layer::from_rows(vec![vec![0.0, 1.0, 2.0],
+                                              vec![3.0, 4.0, 5.0]])
+                .unwrap();
+        assert_eq!((layer.height(), layer.width()), (2, 3));
+        assert_eq!(layer.get(&Point::new(1, 0)), Some(3.0));
+        assert_eq!(layer.get(&Point::new(0, 3)), None);
+        layer.set(&Point::new(1, 2), 9.0);
+        assert_eq!(layer.value(5), 9.0);
+        assert!(Layer::from_rows(vec![vec![0.0], vec![]]).is_err());
+
+        let mut overlay = Overlay::new();
+        overlay.insert("risk", layer);
+        overlay.insert(COST_LAYER, Layer::new(2, 3, 0.5));
+        assert_eq!(overlay.names(), vec!["cost", "risk"]);
+        assert_eq!(overlay.value("risk", &Point::new(0, 1)), Some(1.0));
+        assert_eq!(overlay.value("marks", &Point::new(0, 1)), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn read_overlays() {
+        let overlay = read_overlay(r#"{"cost": {"height": 1, "width": 2,
+                                                "values": [0.5, 2]}}"#
+                                           .as_bytes())
+                .unwrap();
+        assert_eq!(overlay.value(COST_LAYER, &Point::new(0, 1)), Some(2.0));
+        assert_eq!(read_overlay(serde_json::to_string(&overlay)
+                                    .unwrap()
+                                    .as_bytes()),
+                   Ok(overlay));
+
+        let error = read_overlay(r#"{"cost": {"height": 2, "width": 2,
+                                              "values": [0.5, 2]}}"#
+                                         .as_bytes())
+                .unwrap_err();
+        assert_eq!(error, "Layer 'cost': A 2x2 layer needs 4 values, not 2.");
+        assert!(read_overlay("[1, 2]".as_bytes()).is_err());
+
+        // Only annotations may have negative values.
+        let error = read_overlay(r#"{"cost": {"height": 1, "width": 2,
+                                              "values": [0.5, -2]}}"#
+                                         .as_bytes())
+                .unwrap_err();
+        assert_eq!(error, "Layer 'cost' has a negative value -2 at (0, 1).");
+        assert!(read_overlay(r#"{"risk": {"height": 1, "width": 2,
+                                          "values": [0.5, -2]}}"#
+                                     .as_bytes())
+                        .is_ok());
+    }
+}
@@ -1,17 +1,134 @@
-use std::fmt::Display;
+use std::cmp::min;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::fs::File;
-use std::io::{BufReader, Error as IOError, Read};
-use std::path::Path;
+use std::io::{BufReader, Error as IOError, Read, Result as IOResult, Write};
+use std::path::{Path, PathBuf};
 
-use grid::{Grid, Terrain, Tile};
+use grid::{Distance, Grid, MapType, Point, Terrain, Tile, TileChange};
+use rle;
+use suite::Entry;
 
+/// The formats maps can be read from and written to. When reading, the
+/// format is recognized by the `type` line of the map's header, which also
+/// declares the type of the map (see `MapType`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapFormat {
+    /// The format specified in http://movingai.com/benchmarks/formats.html
+    Movingai,
+    /// Like `Movingai`, but with every row of the map run-length encoded as
+    /// described in the `rle` module, to keep large maps small on disk.
+    Rle,
+}
+
+impl MapFormat {
+    /// Recognizes a format, and the type of the map, by the type given in a
+    /// map's header: `octile` or `tile`, followed by `-rle` for run-length
+    /// encoded maps.
+    fn from_type(name: &str) -> Option<(MapFormat, MapType)> {
+        match name {
+            "octile" => Some((MapFormat::Movingai, MapType::Octile)),
+            "octile-rle" => Some((MapFormat::Rle, MapType::Octile)),
+            "tile" => Some((MapFormat::Movingai, MapType::Tile)),
+            "tile-rle" => Some((MapFormat::Rle, MapType::Tile)),
+            _ => None,
+        }
+    }
+
+    /// The type written in the header of maps of `map_type` in this format.
+    fn type_name(&self, map_type: MapType) -> String {
+        match *self {
+            MapFormat::Movingai => map_type.name().to_owned(),
+            MapFormat::Rle => format!("{}-rle", map_type.name()),
+        }
+    }
+}
+
+/// The largest number of tiles a map may have, well above that of every
+/// map in the benchmarks. Run-length encoded maps can describe huge maps in
+/// a few bytes, so the header alone cannot be trusted.
+pub const MAX_TILES: usize = 1 << 24;
+
+/// The largest height or width a map may have, whatever its number of tiles.
+pub const MAX_SIDE: usize = u32::MAX as usize;
+
+/// Reads the terrain represented by `symbol` in a map.
+pub fn terrain_from_symbol(symbol: char) -> Option<Terrain> {
+    match symbol {
+        '.' | 'G' => Some(Terrain::Ground),
+        '@' | 'O' => Some(Terrain::OutOfBounds),
+        'T' => Some(Terrain::Trees),
+        'S' => Some(Terrain::Swamp),
+        'W' => Some(Terrain::Water),
+        _ => None,
+    }
+}
+
+/// A malformed map, along with the position at which parsing failed.
 #[derive(Debug)]
-struct ParseError {
+pub struct ParseError {
     line: usize,
     column: usize,
     description: String,
 }
 
+impl ParseError {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+/// An error found while loading a map.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The map could not be read.
+    Read(IOError),
+    /// The map was read, but is malformed.
+    Parse(ParseError),
+}
+
+impl LoadError {
+    /// Describes the error, naming `source` as the origin of the map.
+    fn describe<N>(&self, source: &N) -> String
+        where N: Display + ?Sized
+    {
+        match *self {
+            LoadError::Read(ref e) => {
+                format!("Could not read from {}: {}", source, e)
+            }
+            LoadError::Parse(ref e) => {
+                format!("Parsing error: {} ({}@{}:{})",
+                        e.description,
+                        source,
+                        e.line,
+                        e.column)
+            }
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            LoadError::Read(ref e) => write!(f, "Could not read map: {}", e),
+            LoadError::Parse(ref e) => {
+                write!(f,
+                       "Parsing error: {} ({}:{})",
+                       e.description,
+                       e.line,
+                       e.column)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Parser {
     data: Vec<char>,
@@ -30,6 +147,11 @@ impl Parser {
         }
     }
 
+    /// The number of characters left to read.
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
     fn shift(&mut self) {
         if self.position < self.data.len() {
             if self.data[self.position] == '\n' {
@@ -42,7 +164,8 @@ impl Parser {
         }
     }
 
-    fn consume_word(&mut self, word: &str) -> Result<(), ParseError> {
+    /// Reads the next word, skipping any whitespace before it.
+    fn parse_word(&mut self) -> String {
         self.consume_ws();
 
         let mut read = Vec::new();
@@ -56,7 +179,11 @@ impl Parser {
             }
         }
 
-        let read: String = read.into_iter().collect();
+        read.into_iter().collect()
+    }
+
+    fn consume_word(&mut self, word: &str) -> Result<(), ParseError> {
+        let read = self.parse_word();
         if read == word {
             Ok(())
         } else {
@@ -91,6 +218,20 @@ impl Parser {
         self.parse_int()
     }
 
+    /// Parses the constant `name` as a side of the map, no larger than
+    /// `MAX_SIDE`.
+    fn parse_dimension(&mut self, name: &str) -> Result<usize, ParseError> {
+        let size = self.parse_constant(name)?;
+        if size > MAX_SIDE {
+            return Err(self.error(format!("A {} of {} is larger than the {} \
+                                           supported.",
+                                          name,
+                                          size,
+                                          MAX_SIDE)));
+        }
+        Ok(size)
+    }
+
     fn parse_int(&mut self) -> Result<usize, ParseError> {
         let mut word = Vec::new();
         while self.position < self.data.len() &&
@@ -99,7 +240,7 @@ impl Parser {
             self.shift();
         }
         let word: String = word.into_iter().collect();
-        match usize::from_str_radix(&word, 10) {
+        match word.parse() {
             Ok(size) => Ok(size),
             Err(_) => {
                 Err(self.error(format!("Expected integer, found '{}'.", word)))
@@ -107,61 +248,178 @@ impl Parser {
         }
     }
 
-    fn parse_grid(&mut self) -> Result<Grid, ParseError> {
+    /// Parses a map, reading its rows in `format` if given, or in the
+    /// format declared by its header otherwise.
+    fn parse_grid(&mut self,
+                  format: Option<MapFormat>)
+                  -> Result<Grid, ParseError> {
         self.consume_word("type")?;
-        self.consume_word("octile")?;
+        let name = self.parse_word();
+        let (declared, map_type) = MapFormat::from_type(&name).ok_or_else(|| {
+                self.error(format!("Unknown map type '{}'.", name))
+            })?;
+        let format = format.unwrap_or(declared);
 
-        let height = self.parse_constant("height")?;
-        let width = self.parse_constant("width")?;
+        let height = self.parse_dimension("height")?;
+        let width = self.parse_dimension("width")?;
+        if width == 0 && height > 0 {
+            return Err(self.error("Rows must not be empty.".to_owned()));
+        }
+        if height.checked_mul(width).is_none_or(|tiles| tiles > MAX_TILES) {
+            return Err(self.error(format!("Maps of {}x{} tiles are larger \
+                                           than the {} supported.",
+                                          height,
+                                          width,
+                                          MAX_TILES)));
+        }
 
         self.consume_word("map")?;
 
-        let mut tiles = Vec::with_capacity(height);
+        // Every row takes up at least one character, so the map cannot be
+        // larger than what is left to read, whatever the header says.
+        let mut tiles = Vec::with_capacity(min(height, self.remaining()));
 
         for _ in 0..height {
-            let mut row = Vec::with_capacity(width);
             self.consume_ws();
-            for _ in 0..width {
-                let value = match self.data[self.position] {
-                    '.' | 'G' => Terrain::Ground,
-                    '@' | 'O' => Terrain::OutOfBounds,
-                    'T' => Terrain::Trees,
-                    'S' => Terrain::Swamp,
-                    'W' => Terrain::Water,
-                    '\n' => {
-                        let message = "Unexpected end of line.".to_owned();
-                        return Err(self.error(message));
-                    }
-                    other => {
-                        let message = format!("Unrecognized symbol: {}", other);
-                        return Err(self.error(message));
-                    }
-                };
-                row.push(Tile::new(value));
-                self.shift();
-            }
+            let row = match format {
+                MapFormat::Movingai => self.parse_row(width)?,
+                MapFormat::Rle => self.parse_rle_row(width)?,
+            };
             tiles.push(row);
         }
-        Ok(Grid::new(tiles))
+        let mut grid = Grid::new(tiles);
+        grid.set_map_type(map_type);
+        Ok(grid)
+    }
+
+    fn parse_row(&mut self, width: usize) -> Result<Vec<Tile>, ParseError> {
+        let mut row = Vec::with_capacity(min(width, self.remaining()));
+        for _ in 0..width {
+            let value = match self.data.get(self.position).cloned() {
+                None => {
+                    let message = "Unexpected end of map.".to_owned();
+                    return Err(self.error(message));
+                }
+                Some('\n') => {
+                    let message = "Unexpected end of line.".to_owned();
+                    return Err(self.error(message));
+                }
+                Some(other) => {
+                    terrain_from_symbol(other).ok_or_else(|| {
+                            self.error(format!("Unrecognized symbol: {}",
+                                               other))
+                        })?
+                }
+            };
+            row.push(Tile::new(value));
+            self.shift();
+        }
+        Ok(row)
+    }
+
+    fn parse_rle_row(&mut self,
+                     width: usize)
+                     -> Result<Vec<Tile>, ParseError> {
+        let (line, column) = (self.line, self.column);
+        let error = |description| {
+            ParseError {
+                line: line,
+                column: column,
+                description: description,
+            }
+        };
+
+        let symbols = rle::decode_at_most(&self.parse_word(), width)
+            .map_err(&error)?;
+        if symbols.len() != width {
+            return Err(error(format!("Expected {} tiles, found {}.",
+                                     width,
+                                     symbols.len())));
+        }
+        symbols.into_iter()
+            .map(|symbol| {
+                terrain_from_symbol(symbol)
+                    .map(Tile::new)
+                    .ok_or_else(|| {
+                                    error(format!("Unrecognized symbol: {}",
+                                                  symbol))
+                                })
+            })
+            .collect()
     }
 }
 
-fn grid_from_chars(data: Vec<char>) -> Result<Grid, ParseError> {
+fn grid_from_chars(data: Vec<char>,
+                   format: Option<MapFormat>)
+                   -> Result<Grid, ParseError> {
+    profile_span!("parse", chars = data.len());
+
     let mut parser = Parser::new(data);
 
-    parser.parse_grid()
+    parser.parse_grid(format)
 }
 
-fn chars_from_file<P>(filename: &P) -> Result<Vec<char>, IOError>
-    where P: AsRef<Path> + ?Sized
+fn chars_from_reader<R>(mut reader: R) -> Result<Vec<char>, IOError>
+    where R: Read
 {
-    let file = File::open(filename)?;
-    let mut reader = BufReader::new(file);
     let mut buffer = String::new();
     reader.read_to_string(&mut buffer)?;
     Ok(buffer.chars().collect())
 }
 
+fn chars_from_file<P>(filename: &P) -> Result<Vec<char>, IOError>
+    where P: AsRef<Path> + ?Sized
+{
+    let file = File::open(filename)?;
+    chars_from_reader(BufReader::new(file))
+}
+
+/// Like `grid_from_file`, but returns an error instead of panicking when the
+/// file cannot be read or parsed.
+pub fn try_grid_from_file<P>(filename: &P) -> Result<Grid, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let chars = chars_from_file(filename).map_err(LoadError::Read)?;
+    let mut grid = grid_from_chars(chars, None).map_err(LoadError::Parse)?;
+    grid.set_path(filename);
+    Ok(grid)
+}
+
+/// Like `try_grid_from_file`, but reads the rows of the map in `format`,
+/// whatever the map's header declares. The header still gives the type of
+/// the map.
+pub fn try_grid_from_file_as<P>(filename: &P,
+                                format: MapFormat)
+                                -> Result<Grid, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let chars = chars_from_file(filename).map_err(LoadError::Read)?;
+    let mut grid = grid_from_chars(chars, Some(format))
+        .map_err(LoadError::Parse)?;
+    grid.set_path(filename);
+    Ok(grid)
+}
+
+/// Like `grid_from_reader`, but returns an error instead of panicking when
+/// the map cannot be read or parsed.
+pub fn try_grid_from_reader<R>(reader: R) -> Result<Grid, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    grid_from_chars(chars, None).map_err(LoadError::Parse)
+}
+
+/// Like `try_grid_from_reader`, but reads the rows of the map in `format`,
+/// as `try_grid_from_file_as` does.
+pub fn try_grid_from_reader_as<R>(reader: R,
+                                  format: MapFormat)
+                                  -> Result<Grid, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    grid_from_chars(chars, Some(format)).map_err(LoadError::Parse)
+}
+
 /// Builds a `Grid` out of all the (first) map in the file pointed at by
 /// `filename`.
 ///
@@ -170,21 +428,361 @@ fn chars_from_file<P>(filename: &P) -> Result<Vec<char>, IOError>
 pub fn grid_from_file<P>(filename: &P) -> Grid
     where P: AsRef<Path> + Display + ?Sized
 {
-    let chars =
-        chars_from_file(filename).expect(&format!("Could not read from file {}",
-                                                  filename));
-    grid_from_chars(chars).unwrap_or_else(|e| {
-        panic!("Parsing error: {} ({}@{}:{})",
-               e.description,
-               filename,
-               e.line,
-               e.column)
-    })
+    try_grid_from_file(filename)
+        .unwrap_or_else(|e| panic!("{}", e.describe(filename)))
+}
+
+/// Builds a `Grid` out of the (first) map read from `reader`, such as the
+/// standard input. `name` identifies the source in error messages.
+///
+/// The map should be in the same format as for `grid_from_file`.
+pub fn grid_from_reader<R, N>(reader: R, name: &N) -> Grid
+    where R: Read,
+          N: Display + ?Sized
+{
+    try_grid_from_reader(reader)
+        .unwrap_or_else(|e| panic!("{}", e.describe(name)))
+}
+
+/// Reads a list of trials, with one `sy sx ty tx` line (start and goal
+/// coordinates) per trial. Blank lines and lines starting with `#` are
+/// skipped. Every trial comes with the (1-based) number of its line.
+pub fn try_pairs_from_reader<R>(reader: R)
+                                -> Result<Vec<(usize, Point, Point)>, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    let text: String = chars.into_iter().collect();
+
+    let mut pairs = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let trimmed = content.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut coordinates = Vec::with_capacity(4);
+        for word in content.split_whitespace() {
+            let column = word.as_ptr() as usize - content.as_ptr() as usize;
+            let error = |description| {
+                LoadError::Parse(ParseError {
+                                     line: line,
+                                     column: column,
+                                     description: description,
+                                 })
+            };
+            if coordinates.len() == 4 {
+                return Err(error(format!("Unexpected '{}' after goal.", word)));
+            }
+            match word.parse() {
+                Ok(value) => coordinates.push(value),
+                Err(_) => {
+                    return Err(error(format!("Expected integer, found '{}'.",
+                                             word)))
+                }
+            }
+        }
+        if coordinates.len() < 4 {
+            return Err(LoadError::Parse(ParseError {
+                                            line: line,
+                                            column: content.len(),
+                                            description: "Expected sy sx ty \
+                                                          tx."
+                                                    .to_owned(),
+                                        }));
+        }
+
+        pairs.push((line + 1,
+                    Point::new(coordinates[0], coordinates[1]),
+                    Point::new(coordinates[2], coordinates[3])));
+    }
+    Ok(pairs)
+}
+
+/// Like `try_pairs_from_reader`, reading from the file at `filename`.
+pub fn try_pairs_from_file<P>(filename: &P)
+                              -> Result<Vec<(usize, Point, Point)>, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let file = File::open(filename).map_err(LoadError::Read)?;
+    try_pairs_from_reader(BufReader::new(file))
+}
+
+/// A trial of a movingai scenario, as read by `try_scenarios_from_reader`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scenario {
+    /// The (1-based) number of the line the trial was read from.
+    pub line: usize,
+    /// The bucket the trial belongs to, by the length of its optimal path.
+    pub bucket: usize,
+    pub source: Point,
+    pub target: Point,
+    /// The length of the optimal path, as given by the scenario.
+    pub optimal: Distance,
+}
+
+/// Reads a scenario in the format specified in
+/// http://movingai.com/benchmarks/formats.html, with a `version` line
+/// followed by one `bucket map width height startx starty goalx goaly
+/// optimal` line per trial. Blank lines are skipped.
+pub fn try_scenarios_from_reader<R>(reader: R)
+                                    -> Result<Vec<Scenario>, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    let text: String = chars.into_iter().collect();
+
+    let mut scenarios = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let words = content.split_whitespace().collect::<Vec<_>>();
+        if words.is_empty() || (line == 0 && words[0] == "version") {
+            continue;
+        }
+        let error = |word: Option<&str>, description| {
+            let column = word.map_or(content.len(), |word| {
+                word.as_ptr() as usize - content.as_ptr() as usize
+            });
+            LoadError::Parse(ParseError {
+                                 line: line,
+                                 column: column,
+                                 description: description,
+                             })
+        };
+        if words.len() != 9 {
+            return Err(error(words.get(9).cloned(),
+                             "Expected bucket map width height startx \
+                              starty goalx goaly optimal."
+                                     .to_owned()));
+        }
+
+        let mut numbers = Vec::with_capacity(5);
+        for &word in [words[0], words[4], words[5], words[6], words[7]].iter() {
+            match word.parse() {
+                Ok(value) => numbers.push(value),
+                Err(_) => {
+                    return Err(error(Some(word),
+                                     format!("Expected integer, found '{}'.",
+                                             word)))
+                }
+            }
+        }
+        let optimal = words[8].parse().map_err(|_| {
+            error(Some(words[8]),
+                  format!("Expected number, found '{}'.", words[8]))
+        })?;
+
+        scenarios.push(Scenario {
+                           line: line + 1,
+                           bucket: numbers[0],
+                           source: Point::new(numbers[2], numbers[1]),
+                           target: Point::new(numbers[4], numbers[3]),
+                           optimal: optimal,
+                       });
+    }
+    Ok(scenarios)
+}
+
+/// Like `try_scenarios_from_reader`, reading from the file at `filename`.
+pub fn try_scenarios_from_file<P>(filename: &P)
+                                  -> Result<Vec<Scenario>, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let file = File::open(filename).map_err(LoadError::Read)?;
+    try_scenarios_from_reader(BufReader::new(file))
+}
+
+/// Reads a patch, as written by `write_patch`, with one `y x from to` line
+/// per changed tile, where `from` and `to` are terrain symbols. Blank lines
+/// and lines starting with `#` are skipped.
+pub fn try_patch_from_reader<R>(reader: R)
+                                -> Result<Vec<TileChange>, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    let text: String = chars.into_iter().collect();
+
+    let mut changes = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let trimmed = content.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let words = content.split_whitespace().collect::<Vec<_>>();
+        let error = |word: Option<&str>, description| {
+            let column = word.map_or(content.len(), |word| {
+                word.as_ptr() as usize - content.as_ptr() as usize
+            });
+            LoadError::Parse(ParseError {
+                                 line: line,
+                                 column: column,
+                                 description: description,
+                             })
+        };
+        if words.len() != 4 {
+            return Err(error(words.get(4).cloned(),
+                             "Expected y x from to.".to_owned()));
+        }
+
+        let mut coordinates = Vec::with_capacity(2);
+        for word in &words[..2] {
+            match word.parse() {
+                Ok(value) => coordinates.push(value),
+                Err(_) => {
+                    return Err(error(Some(word),
+                                     format!("Expected integer, found '{}'.",
+                                             word)))
+                }
+            }
+        }
+        let mut terrains = Vec::with_capacity(2);
+        for word in &words[2..] {
+            let mut symbols = word.chars();
+            match (symbols.next().and_then(terrain_from_symbol),
+                   symbols.next()) {
+                (Some(terrain), None) => terrains.push(terrain),
+                _ => {
+                    return Err(error(Some(word),
+                                     format!("Unrecognized symbol: {}",
+                                             word)))
+                }
+            }
+        }
+
+        changes.push(TileChange {
+                         point: Point::new(coordinates[0], coordinates[1]),
+                         from: terrains[0],
+                         to: terrains[1],
+                     });
+    }
+    Ok(changes)
+}
+
+/// Like `try_patch_from_reader`, reading from the file at `filename`.
+pub fn try_patch_from_file<P>(filename: &P)
+                              -> Result<Vec<TileChange>, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let file = File::open(filename).map_err(LoadError::Read)?;
+    try_patch_from_reader(BufReader::new(file))
+}
+
+/// Reads a suite, with one `map trials [weight]` line per map, where the
+/// weight is 1 if not given. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn try_suite_from_reader<R>(reader: R) -> Result<Vec<Entry>, LoadError>
+    where R: Read
+{
+    let chars = chars_from_reader(reader).map_err(LoadError::Read)?;
+    let text: String = chars.into_iter().collect();
+
+    let mut entries = Vec::new();
+    for (line, content) in text.lines().enumerate() {
+        let trimmed = content.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let words = content.split_whitespace().collect::<Vec<_>>();
+        let error = |word: Option<&str>, description| {
+            let column = word.map_or(content.len(), |word| {
+                word.as_ptr() as usize - content.as_ptr() as usize
+            });
+            LoadError::Parse(ParseError {
+                                 line: line,
+                                 column: column,
+                                 description: description,
+                             })
+        };
+        if words.len() < 2 || words.len() > 3 {
+            return Err(error(words.get(3).cloned(),
+                             "Expected map trials [weight].".to_owned()));
+        }
+
+        let trials = words[1].parse().map_err(|_| {
+            error(Some(words[1]),
+                  format!("Expected integer, found '{}'.", words[1]))
+        })?;
+        let weight = match words.get(2) {
+            Some(word) => {
+                match word.parse::<f64>() {
+                    Ok(weight) if weight >= 0.0 && weight.is_finite() => weight,
+                    _ => {
+                        return Err(error(Some(word),
+                                         format!("Expected a non-negative \
+                                                  weight, found '{}'.",
+                                                 word)))
+                    }
+                }
+            }
+            None => 1.0,
+        };
+        entries.push(Entry {
+                         map: PathBuf::from(words[0]),
+                         trials: trials,
+                         weight: weight,
+                     });
+    }
+    Ok(entries)
+}
+
+/// Like `try_suite_from_reader`, reading from the file at `filename`. Maps
+/// are found relative to the directory of the suite.
+pub fn try_suite_from_file<P>(filename: &P) -> Result<Vec<Entry>, LoadError>
+    where P: AsRef<Path> + ?Sized
+{
+    let file = File::open(filename).map_err(LoadError::Read)?;
+    let mut entries = try_suite_from_reader(BufReader::new(file))?;
+    if let Some(directory) = filename.as_ref().parent() {
+        for entry in &mut entries {
+            entry.map = directory.join(&entry.map);
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes `changes` as a patch that `try_patch_from_reader` can read back.
+pub fn write_patch<W>(changes: &[TileChange], out: &mut W) -> IOResult<()>
+    where W: Write
+{
+    writeln!(out, "# y x from to")?;
+    for change in changes {
+        writeln!(out,
+                 "{} {} {} {}",
+                 change.point.y(),
+                 change.point.x(),
+                 change.from,
+                 change.to)?;
+    }
+    Ok(())
+}
+
+/// Writes the true terrain of `grid` as a map in `format`.
+pub fn write_grid<W>(grid: &Grid,
+                     format: MapFormat,
+                     out: &mut W)
+                     -> IOResult<()>
+    where W: Write
+{
+    writeln!(out, "type {}", format.type_name(grid.map_type()))?;
+    writeln!(out, "height {}", grid.height())?;
+    writeln!(out, "width {}", grid.width())?;
+    writeln!(out, "map")?;
+    match format {
+        MapFormat::Movingai => write!(out, "{}", grid),
+        MapFormat::Rle => {
+            for row in grid.iter() {
+                let symbols = row.iter().map(|tile| tile.terrain().symbol());
+                writeln!(out, "{}", rle::encode(symbols))?;
+            }
+            Ok(())
+        }
+    }
 }
 
 #[cfg(test)]
 pub fn grid_from_str(grid: &str) -> Grid {
-    grid_from_chars(grid.chars().collect())
+    grid_from_chars(grid.chars().collect(), None)
         .unwrap_or_else(|e| {
             panic!("Parsing error: {} ({}:{})",
                    e.description,
@@ -197,6 +795,8 @@ pub fn grid_from_str(grid: &str) -> Grid {
 mod tests {
     use super::*;
 
+    use grid::Movement;
+
     const GOOD_MAP: &str = "type octile
 height 2
 width 2
@@ -213,13 +813,276 @@ Tf
 
     #[test]
     fn read_grid_from_chars() {
-        let grid = grid_from_chars(GOOD_MAP.chars().collect()).unwrap();
+        let grid = grid_from_chars(GOOD_MAP.chars().collect(), None).unwrap();
         println!("Grid:\n{}", grid);
     }
 
+    #[test]
+    fn read_grid_from_reader() {
+        let grid = grid_from_reader(GOOD_MAP.as_bytes(), "<test>");
+        assert_eq!(grid.height(), 2);
+        assert_eq!(grid.width(), 2);
+    }
+
+    #[test]
+    fn report_unreadable_map() {
+        match try_grid_from_reader(BAD_MAP.as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 4);
+                assert_eq!(e.column(), 1);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+
+        match try_grid_from_file("maps/Missing.map") {
+            Err(LoadError::Read(_)) => {}
+            other => panic!("Expected a read error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_malformed_maps() {
+        let maps: &[&[u8]] = &[b"type octile\nheight 2\nwidth 3\nmap\n...\n..",
+                               b"type octile\nheight 99999999999999999\n\
+                                 width 99999999999999999\nmap\n.",
+                               b"type octile\nheight 3\nwidth 0\nmap\n",
+                               b"type octile-rle\nheight 1\nwidth 4\nmap\n\
+                                 99999999999999999.",
+                               b"type octile-rle\nheight 20\n\
+                                 width 3333333333333310\nmap\n\
+                                 3333333333333.33\n.\n",
+                               b"type octile\nheight 1\nwidth 1\nmap\n\xff",
+                               b"type octile\nheight"];
+        for map in maps {
+            assert!(try_grid_from_reader(*map).is_err());
+        }
+
+        let empty = try_grid_from_reader(&b"type octile\nheight 0\nwidth 0\n\
+                                            map\n"[..])
+                .unwrap();
+        assert_eq!((empty.height(), empty.width()), (0, 0));
+    }
+
+    #[test]
+    fn reject_oversized_dimensions() {
+        let wide = format!("type octile\nheight 0\nwidth {}\nmap\n",
+                           MAX_SIDE + 1);
+        match try_grid_from_reader(wide.as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert!(e.description().contains("width"));
+                assert_eq!(e.line(), 2);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+
+        let tall = format!("type octile\nheight {}\nwidth 1\nmap\n",
+                           MAX_SIDE);
+        match try_grid_from_reader(tall.as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert!(e.description().contains(&MAX_TILES.to_string()));
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_tile_maps() {
+        let map = "type tile\nheight 2\nwidth 2\nmap\n.T\nT.";
+        let mut grid = grid_from_str(map);
+        assert_eq!(grid.map_type(), MapType::Tile);
+        assert_eq!(grid.movement(), Movement::Cardinal);
+        let (from, to) = (Point::new(0, 0), Point::new(1, 1));
+        assert!(!grid.has_path(&from, &to));
+        grid.look(&from);
+        assert!(!grid.belief_reachable(&from, &to));
+
+        let mut out = Vec::new();
+        write_grid(&grid, MapFormat::Rle, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("type tile-rle\n"));
+        assert_eq!(grid_from_str(&out).map_type(), MapType::Tile);
+
+        let mut grid = grid_from_str(&map.replace("tile", "octile"));
+        assert_eq!(grid.map_type(), MapType::Octile);
+        assert!(grid.has_path(&from, &to));
+        grid.look(&from);
+        assert!(grid.belief_reachable(&from, &to));
+    }
+
+    #[test]
+    fn read_rle_grid() {
+        let grid = grid_from_str("type octile-rle
+height 2
+width 4
+map
+2T2.
+.2@W");
+        assert_eq!(grid.to_string(), "TT..\n.@@W\n");
+
+        let mut rle = Vec::new();
+        write_grid(&grid, MapFormat::Rle, &mut rle).unwrap();
+        assert_eq!(String::from_utf8(rle).unwrap(),
+                   "type octile-rle\nheight 2\nwidth 4\nmap\n2T2.\n.2@W\n");
+
+        let mut movingai = Vec::new();
+        write_grid(&grid, MapFormat::Movingai, &mut movingai).unwrap();
+        let grid = grid_from_reader(&movingai[..], "<test>");
+        assert_eq!(grid.to_string(), "TT..\n.@@W\n");
+
+        let short = "type octile-rle\nheight 1\nwidth 4\nmap\n3.\n";
+        match try_grid_from_reader(short.as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 4);
+                assert_eq!(e.column(), 0);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        let unknown = "type hexagonal\nheight 1\nwidth 1\nmap\n.\n";
+        assert!(try_grid_from_reader(unknown.as_bytes()).is_err());
+
+        // A format given explicitly overrides the one the header declares.
+        let mislabeled = "type octile\nheight 2\nwidth 4\nmap\n2T2.\n.2@W\n";
+        let grid = try_grid_from_reader_as(mislabeled.as_bytes(),
+                                           MapFormat::Rle)
+            .unwrap();
+        assert_eq!(grid.to_string(), "TT..\n.@@W\n");
+        assert!(try_grid_from_reader(mislabeled.as_bytes()).is_err());
+        let rle = "type tile-rle\nheight 1\nwidth 4\nmap\nT3.\n";
+        assert!(try_grid_from_reader_as(rle.as_bytes(), MapFormat::Movingai)
+                    .is_err());
+        let grid = try_grid_from_reader_as(rle.as_bytes(), MapFormat::Rle)
+            .unwrap();
+        assert_eq!(grid.to_string(), "T...\n");
+        assert_eq!(grid.map_type(), MapType::Tile);
+    }
+
+    #[test]
+    fn read_patch() {
+        let changes = vec![TileChange {
+                               point: Point::new(3, 1),
+                               from: Terrain::Ground,
+                               to: Terrain::Trees,
+                           },
+                           TileChange {
+                               point: Point::new(0, 4),
+                               from: Terrain::Water,
+                               to: Terrain::OutOfBounds,
+                           }];
+        let mut patch = Vec::new();
+        write_patch(&changes, &mut patch).unwrap();
+        assert_eq!(try_patch_from_reader(&patch[..]).unwrap(), changes);
+
+        match try_patch_from_reader("0 0 . T\n1 1 . X\n".as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 1);
+                assert_eq!(e.column(), 6);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        assert!(try_patch_from_reader("0 0 .".as_bytes()).is_err());
+        assert!(try_patch_from_reader("0 0 . T T".as_bytes()).is_err());
+        assert!(try_patch_from_reader("0 y . T".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn read_pairs() {
+        let pairs = try_pairs_from_reader("# sy sx ty tx
+0 0 1 1
+
+1 0  0 1
+"
+                                                  .as_bytes())
+                .unwrap();
+        assert_eq!(pairs,
+                   vec![(2, Point::new(0, 0), Point::new(1, 1)),
+                        (4, Point::new(1, 0), Point::new(0, 1))]);
+
+        match try_pairs_from_reader("0 0 1 1\n0 0 x 1\n".as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 1);
+                assert_eq!(e.column(), 4);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        assert!(try_pairs_from_reader("0 0 1".as_bytes()).is_err());
+        assert!(try_pairs_from_reader("0 0 1 1 1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn read_scenarios() {
+        let scenarios = try_scenarios_from_reader("version 1
+3\tMini.map\t10\t10\t1\t2\t7\t8\t8.48528137
+
+0\tMini.map\t10\t10\t0\t0\t1\t0\t1
+"
+                                                          .as_bytes())
+                .unwrap();
+        assert_eq!(scenarios,
+                   vec![Scenario {
+                            line: 2,
+                            bucket: 3,
+                            source: Point::new(2, 1),
+                            target: Point::new(8, 7),
+                            optimal: 8.48528137,
+                        },
+                        Scenario {
+                            line: 4,
+                            bucket: 0,
+                            source: Point::new(0, 0),
+                            target: Point::new(0, 1),
+                            optimal: 1.0,
+                        }]);
+
+        match try_scenarios_from_reader("version 1\n0 a.map 9 9 0 0 x 1 1\n"
+                                            .as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 1);
+                assert_eq!(e.column(), 16);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        assert!(try_scenarios_from_reader("0 a.map 9 9 0 0 1 1".as_bytes())
+                    .is_err());
+        assert!(try_scenarios_from_reader("0 a.map 9 9 0 0 1 1 z".as_bytes())
+                    .is_err());
+    }
+
+    #[test]
+    fn read_suites() {
+        let entries = try_suite_from_reader("# map trials weight
+maps/Mini.map 10
+
+maps/Maze.map 5 2.5
+"
+                                                    .as_bytes())
+                .unwrap();
+        assert_eq!(entries,
+                   vec![Entry {
+                            map: PathBuf::from("maps/Mini.map"),
+                            trials: 10,
+                            weight: 1.0,
+                        },
+                        Entry {
+                            map: PathBuf::from("maps/Maze.map"),
+                            trials: 5,
+                            weight: 2.5,
+                        }]);
+
+        match try_suite_from_reader("a.map 1\nb.map 1 -2\n".as_bytes()) {
+            Err(LoadError::Parse(e)) => {
+                assert_eq!(e.line(), 1);
+                assert_eq!(e.column(), 8);
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        assert!(try_suite_from_reader("a.map".as_bytes()).is_err());
+        assert!(try_suite_from_reader("a.map x".as_bytes()).is_err());
+        assert!(try_suite_from_reader("a.map 1 1 1".as_bytes()).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn read_unreadable_map_from_chars() {
-        grid_from_chars(BAD_MAP.chars().collect()).unwrap();
+        grid_from_chars(BAD_MAP.chars().collect(), None).unwrap();
     }
 }
@@ -0,0 +1,103 @@
+//! Path caches: paths planned in earlier trials, kept so that agents going
+//! over the same ground again can follow them instead of searching.
+
+use std::collections::HashMap;
+
+use grid::{Grid, Point, Tile};
+use search::Path;
+
+/// Planned paths, by their target. A path serves every tile along it, since
+/// what is left of an optimal path is an optimal path from where it is left.
+#[derive(Clone, Debug, Default)]
+pub struct PathCache {
+    /// The paths to each target, in reverse like the paths agents follow,
+    /// but ending with the tile they start from.
+    paths: HashMap<Point, Vec<Path>>,
+}
+
+impl PathCache {
+    pub fn new() -> PathCache {
+        PathCache::default()
+    }
+
+    /// Stores `path` (in reverse, as returned by searches) from `source` to
+    /// `target`.
+    pub fn insert(&mut self, source: &Point, target: &Point, path: &Path) {
+        let mut stored = path.clone();
+        stored.push(*source);
+        self.paths.entry(*target).or_default().push(stored);
+    }
+
+    /// Returns a stored path from `source` to `target`, in reverse, if some
+    /// path through `source` to `target` is still believed free along the
+    /// rest of its way in `grid`.
+    pub fn get(&self,
+               grid: &Grid,
+               source: &Point,
+               target: &Point)
+               -> Option<Path> {
+        let free =
+            |point: &Point| grid.try_get(point).is_ok_and(Tile::freespace);
+        self.paths
+            .get(target)?
+            .iter()
+            .filter_map(|path| {
+                            path.iter()
+                                .position(|point| point == source)
+                                .map(|position| &path[..position])
+                        })
+            .find(|rest| !rest.is_empty() && rest.iter().all(&free))
+            .map(|rest| rest.to_vec())
+    }
+
+    /// Returns the number of paths stored.
+    pub fn len(&self) -> usize {
+        self.paths.values().map(|paths| paths.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn reuse_paths_through_their_tiles() {
+        // The tree is not known to be there until it is seen.
+        let mut grid = grid_from_str("type octile\nheight 1\nwidth 5\nmap\n\
+                                      ...T.");
+        let mut cache = PathCache::new();
+        let (source, target) = (Point::new(0, 0), Point::new(0, 4));
+        let path = vec![Point::new(0, 4),
+                        Point::new(0, 3),
+                        Point::new(0, 2),
+                        Point::new(0, 1)];
+        cache.insert(&source, &target, &path);
+        assert_eq!(cache.len(), 1);
+
+        assert_eq!(cache.get(&grid, &source, &target), Some(path.clone()));
+        assert_eq!(cache.get(&grid, &Point::new(0, 2), &target),
+                   Some(path[..2].to_vec()));
+        // Nothing is stored towards another target, or from the target.
+        assert_eq!(cache.get(&grid, &source, &Point::new(0, 3)), None);
+        assert_eq!(cache.get(&grid, &target, &target), None);
+
+        // Paths found blocked ahead are not followed, but behind they are.
+        grid.look(&Point::new(0, 2));
+        assert_eq!(cache.get(&grid, &source, &target), None);
+        assert_eq!(cache.get(&grid, &Point::new(0, 3), &target),
+                   Some(path[..1].to_vec()));
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
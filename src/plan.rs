@@ -0,0 +1,269 @@
+//! Planning over what a grid's agent believes, as a plain function call, for
+//! programs that use gridist as a grid pathfinding library rather than to
+//! run agents through trials.
+
+use std::thread::available_parallelism;
+
+use grid::{Belief, Direction, Distance, Grid, Measure, Point, Tile};
+use search::{detached_astar, parallel_astar};
+pub use search::SearchScratch;
+
+/// The fewest tiles a map must have for `PlanOptions::threads` to take
+/// effect. Smaller maps are searched faster by a single thread than the
+/// threads take to start and talk to each other.
+pub const PARALLEL_MIN_TILES: usize = 1 << 20;
+
+/// How `plan_on_beliefs` searches.
+#[derive(Clone, Copy, Debug)]
+pub struct PlanOptions {
+    /// Estimates the cost of reaching the second point from the first. The
+    /// plan is optimal as long as it never overestimates.
+    pub heuristic: fn(&Point, &Point) -> Distance,
+    /// Whether tiles that have not been observed are taken to be free (the
+    /// freespace assumption), rather than avoided.
+    pub optimistic: bool,
+    /// Give up after expanding this many nodes, if given.
+    pub max_expansions: Option<usize>,
+    /// Search with this many threads at once (at most one per core), on
+    /// maps of at least `PARALLEL_MIN_TILES` tiles. Plans are just as cheap,
+    /// but take more expansions (counted over every thread), and less
+    /// wall-clock time on large maps.
+    pub threads: usize,
+}
+
+impl Default for PlanOptions {
+    /// Optimistic octile search on a single thread, with no limit on
+    /// expansions.
+    fn default() -> PlanOptions {
+        PlanOptions {
+            heuristic: Distance::octile_heuristic,
+            optimistic: true,
+            max_expansions: None,
+            threads: 1,
+        }
+    }
+}
+
+/// A path planned by `plan_on_beliefs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    /// The points to move through, in order, from the first move to the
+    /// target. Empty if the source is the target.
+    pub path: Vec<Point>,
+    /// The cost the path is expected to have under the grid's cost model.
+    pub cost: Distance,
+    pub expansions: usize,
+}
+
+/// Plans a path from `source` to `target` through the tiles believed to be
+/// free, as measured by the grid's cost model, with A*. Unlike agents, it
+/// leaves the grid untouched, so it can plan over shared grids. Returns
+/// `None` if either point lies outside the grid, or no path is believed to
+/// exist within the expansions allowed.
+pub fn plan_on_beliefs(grid: &Grid,
+                       source: &Point,
+                       target: &Point,
+                       options: &PlanOptions)
+                       -> Option<Plan> {
+    plan_with_scratch(grid, source, target, options, &mut SearchScratch::new())
+}
+
+/// Like `plan_on_beliefs`, but searches with `scratch`, which is then
+/// reused by later plans instead of allocated anew. Threads that plan over
+/// the same grid at once need one each.
+pub fn plan_with_scratch(grid: &Grid,
+                         source: &Point,
+                         target: &Point,
+                         options: &PlanOptions,
+                         scratch: &mut SearchScratch)
+                         -> Option<Plan> {
+    let optimistic = options.optimistic;
+    let free = |tile: &Tile| {
+        tile.freespace() && (optimistic || *tile.belief() != Belief::Unknown)
+    };
+    let cores = available_parallelism().map_or(1, |cores| cores.get());
+    let threads = search_threads(grid.height() * grid.width(),
+                                 options.threads,
+                                 cores);
+    let data = if threads > 1 {
+        parallel_astar(grid,
+                       source,
+                       target,
+                       options.heuristic,
+                       free,
+                       options.max_expansions,
+                       threads)?
+    } else {
+        detached_astar(grid,
+                       source,
+                       target,
+                       options.heuristic,
+                       free,
+                       options.max_expansions,
+                       scratch)?
+    };
+
+    let mut path = data.path;
+    path.reverse();
+    let mut cost = 0.0;
+    let mut previous = None;
+    let mut from = *source;
+    for point in &path {
+        let direction = Direction::between(&from, point).unwrap();
+        let index = grid.index_of(point).unwrap();
+        cost += grid.move_cost(previous, direction, index);
+        previous = Some(direction);
+        from = *point;
+    }
+    Some(Plan {
+             path: path,
+             cost: cost,
+             expansions: data.expansions,
+         })
+}
+
+/// The threads to search a map of `tiles` tiles with, when `requested`
+/// are asked for on a machine with `cores` cores: a single one on maps
+/// smaller than `PARALLEL_MIN_TILES`, and otherwise as many as asked for,
+/// up to one per core.
+fn search_threads(tiles: usize, requested: usize, cores: usize) -> usize {
+    if tiles >= PARALLEL_MIN_TILES {
+        requested.min(cores).max(1)
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::SQRT_2;
+    use std::sync::Arc;
+    use std::thread;
+
+    use parser::grid_from_str;
+    use search::astar;
+
+    #[test]
+    fn plan_without_touching_the_grid() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 5
+map
+.....
+.TTT.
+...T.
+.....");
+        let (source, target) = (Point::new(2, 1), Point::new(0, 4));
+
+        // Nothing has been observed, so the wall is planned through.
+        let plan = plan_on_beliefs(&grid, &source, &target,
+                                   &PlanOptions::default())
+            .unwrap();
+        assert_eq!(plan.path.last(), Some(&target));
+        assert!((plan.cost - (1.0 + 2.0 * SQRT_2)).abs() < 1e-9);
+        assert_eq!(plan_on_beliefs(&grid, &source, &target,
+                                   &PlanOptions {
+                                       optimistic: false,
+                                       ..PlanOptions::default()
+                                   }),
+                   None);
+
+        for y in 0..4 {
+            for x in 0..5 {
+                grid.look(&Point::new(y, x));
+            }
+        }
+        let plan = plan_on_beliefs(&grid, &source, &target,
+                                   &PlanOptions::default())
+            .unwrap();
+        let expected = astar(&mut grid,
+                             &source,
+                             &target,
+                             Distance::octile_heuristic,
+                             Tile::freespace)
+                .unwrap();
+        assert_eq!(plan.path.len(), expected.path.len());
+        assert!((plan.cost - (3.0 + 2.0 * SQRT_2)).abs() < 1e-9);
+        assert_eq!(plan_on_beliefs(&grid, &source, &target,
+                                   &PlanOptions {
+                                       optimistic: false,
+                                       ..PlanOptions::default()
+                                   }),
+                   Some(plan));
+
+        assert!(plan_on_beliefs(&grid, &source, &target,
+                                &PlanOptions {
+                                    max_expansions: Some(2),
+                                    ..PlanOptions::default()
+                                })
+                    .is_none());
+        assert!(plan_on_beliefs(&grid, &source, &Point::new(9, 9),
+                                &PlanOptions::default())
+                    .is_none());
+        assert_eq!(plan_on_beliefs(&grid, &source, &source,
+                                   &PlanOptions::default())
+                       .map(|plan| plan.path),
+                   Some(vec![]));
+    }
+
+    #[test]
+    fn search_large_maps_with_threads() {
+        let large = PARALLEL_MIN_TILES;
+        assert_eq!(search_threads(large - 1, 4, 8), 1);
+        assert_eq!(search_threads(large, 4, 8), 4);
+        assert_eq!(search_threads(large, 4, 2), 2);
+        assert_eq!(search_threads(large, 0, 8), 1);
+        assert_eq!(search_threads(large, 1, 8), 1);
+    }
+
+    #[test]
+    fn plan_from_threads() {
+        let mut grid = grid_from_str("type octile
+height 4
+width 5
+map
+.....
+.TTT.
+...T.
+.....");
+        for y in 0..4 {
+            for x in 0..5 {
+                grid.look(&Point::new(y, x));
+            }
+        }
+        let grid = Arc::new(grid);
+        let queries = vec![(Point::new(2, 1), Point::new(0, 4)),
+                           (Point::new(3, 0), Point::new(2, 4)),
+                           (Point::new(0, 0), Point::new(3, 4))];
+        let expected: Vec<_> = queries.iter()
+            .map(|&(source, target)| {
+                     plan_on_beliefs(&grid, &source, &target,
+                                     &PlanOptions::default())
+                 })
+            .collect();
+
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let grid = grid.clone();
+                let queries = queries.clone();
+                thread::spawn(move || {
+                    let mut scratch = SearchScratch::new();
+                    queries.iter()
+                        .map(|&(source, target)| {
+                                 plan_with_scratch(&grid,
+                                                   &source,
+                                                   &target,
+                                                   &PlanOptions::default(),
+                                                   &mut scratch)
+                             })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), expected);
+        }
+    }
+}
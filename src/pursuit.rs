@@ -0,0 +1,139 @@
+//! Pursuits: trials where the target moves away from the agent after every
+//! move it makes, and the agent must catch it.
+
+use grid::{Grid, Point, Tile};
+use search::dijkstra_to;
+
+/// Decides where the target of a pursuit moves, knowing the true map.
+pub trait TargetPolicy {
+    /// Returns the tile the target at `target` moves to (or `target`, to
+    /// stay) once the agent has moved to `pursuer`.
+    fn next(&mut self, grid: &Grid, target: &Point, pursuer: &Point) -> Point;
+}
+
+/// Moves to the tile around it (or stays where it is) that is farthest from
+/// the agent along the cheapest path over the true map, planned anew with
+/// Dijkstra's algorithm from the agent after every move it makes, as far as
+/// the tiles around the target. Ties are broken in favor of staying, and
+/// then in direction order.
+#[derive(Debug, Default)]
+pub struct Flee;
+
+impl TargetPolicy for Flee {
+    fn next(&mut self, grid: &Grid, target: &Point, pursuer: &Point) -> Point {
+        let mut moves = vec![*target];
+        if let Some(index) = grid.index_of(target) {
+            moves.extend(grid.successors(index, None, Tile::passable)
+                             .map(|(next, _, _)| grid.point_of(next)));
+        }
+        let costs = dijkstra_to(grid, pursuer, &moves);
+        let mut best = 0;
+        for (i, cost) in costs.iter().enumerate() {
+            if *cost > costs[best] {
+                best = i;
+            }
+        }
+        moves[best]
+    }
+}
+
+/// When the agent catches the target.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Capture {
+    /// Once they are on the same tile.
+    #[default]
+    Colocation,
+    /// Once they are on the same tile, or the agent could move onto the
+    /// target's tile from its own.
+    Adjacency,
+}
+
+impl Capture {
+    /// Whether the agent at `pursuer` catches the target at `target`, moving
+    /// as the movement rules of `grid` allow.
+    pub fn captures(&self,
+                    grid: &Grid,
+                    pursuer: &Point,
+                    target: &Point)
+                    -> bool {
+        match *self {
+            Capture::Colocation => pursuer == target,
+            Capture::Adjacency => {
+                pursuer == target ||
+                match (grid.index_of(pursuer), grid.index_of(target)) {
+                    (Some(from), Some(to)) => {
+                        grid.successors(from, None, Tile::passable)
+                            .any(|(next, _, _)| next == to)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// The rules of a pursuit: how the target moves, and when it is caught.
+pub struct Pursuit {
+    pub policy: Box<dyn TargetPolicy>,
+    pub capture: Capture,
+}
+
+impl Pursuit {
+    pub fn new<P>(policy: P, capture: Capture) -> Pursuit
+        where P: TargetPolicy + 'static
+    {
+        Pursuit {
+            policy: Box::new(policy),
+            capture: capture,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::Movement;
+    use parser::grid_from_str;
+
+    #[test]
+    fn flee_from_the_pursuer() {
+        let grid = grid_from_str("type octile
+height 3
+width 5
+map
+.....
+.@@@.
+.....");
+        let mut flee = Flee;
+        // Around the wall, the far side is farther than straight ahead.
+        assert_eq!(flee.next(&grid, &Point::new(0, 2), &Point::new(0, 0)),
+                   Point::new(0, 3));
+        assert_eq!(flee.next(&grid, &Point::new(2, 4), &Point::new(0, 0)),
+                   Point::new(2, 4));
+        assert_eq!(flee.next(&grid, &Point::new(1, 4), &Point::new(0, 3)),
+                   Point::new(2, 3));
+
+        // Without diagonal moves, the pursuer is shut in its corner, and
+        // the target has no reason to move.
+        let grid = grid_from_str("type tile\nheight 3\nwidth 3\nmap\n\
+                                  .T.\nT..\n...");
+        assert_eq!(flee.next(&grid, &Point::new(1, 1), &Point::new(0, 0)),
+                   Point::new(1, 1));
+
+        let grid = grid_from_str("type octile\nheight 3\nwidth 3\nmap\n\
+                                  ...\n...\n...");
+        let (pursuer, target) = (Point::new(0, 0), Point::new(1, 1));
+        assert!(!Capture::Colocation.captures(&grid, &pursuer, &target));
+        assert!(Capture::Adjacency.captures(&grid, &pursuer, &target));
+        assert!(!Capture::Adjacency.captures(&grid,
+                                             &pursuer,
+                                             &Point::new(0, 2)));
+        let mut grid = grid;
+        grid.set_movement(Movement::Cardinal);
+        assert!(!Capture::Adjacency.captures(&grid, &pursuer, &target));
+        assert!(Capture::Adjacency.captures(&grid,
+                                            &pursuer,
+                                            &Point::new(0, 1)));
+    }
+}
@@ -0,0 +1,291 @@
+//! Quadtrees: grids partitioned into square blocks of tiles that are all
+//! free or all blocked, so that open areas can be searched as single nodes.
+
+use std::cmp::{max, min};
+
+use grid::{Grid, Point, Tile};
+
+/// A rectangle of tiles that are either all free or all blocked. Blocks are
+/// square, except where the edges of the grid cut them short.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Block {
+    pub top: usize,
+    pub left: usize,
+    pub height: usize,
+    pub width: usize,
+    pub free: bool,
+}
+
+impl Block {
+    pub fn contains(&self, point: &Point) -> bool {
+        point.y() >= self.top && point.y() < self.top + self.height &&
+        point.x() >= self.left && point.x() < self.left + self.width
+    }
+
+    /// Returns the tile of the block nearest to `point`.
+    pub fn clamp(&self, point: &Point) -> Point {
+        Point::new(point.y().clamp(self.top, self.top + self.height - 1),
+                   point.x().clamp(self.left, self.left + self.width - 1))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    /// A block, by its index in `Quadtree::blocks`.
+    Leaf(usize),
+    /// The top left, top right, bottom left and bottom right quadrants, by
+    /// their index in `Quadtree::nodes`. Quadrants that lie entirely outside
+    /// the grid are left out.
+    Split([Option<usize>; 4]),
+}
+
+/// A grid divided into quadrants, and those into quadrants in turn, until
+/// every quadrant is uniformly free or blocked. A mostly open map is
+/// covered by few large blocks, however many tiles it has.
+#[derive(Clone, Debug)]
+pub struct Quadtree {
+    height: usize,
+    width: usize,
+    /// The side of the square covered by the root, a power of two.
+    size: usize,
+    nodes: Vec<Node>,
+    root: Option<usize>,
+    blocks: Vec<Block>,
+}
+
+impl Quadtree {
+    /// Divides `grid` into blocks of tiles for which `free` is uniformly
+    /// true or false.
+    pub fn from_grid<F>(grid: &Grid, free: F) -> Quadtree
+        where F: Fn(&Tile) -> bool
+    {
+        let mut tree = Quadtree {
+            height: grid.height(),
+            width: grid.width(),
+            size: max(grid.height(), grid.width()).next_power_of_two(),
+            nodes: Vec::new(),
+            root: None,
+            blocks: Vec::new(),
+        };
+        if tree.height > 0 && tree.width > 0 {
+            let size = tree.size;
+            tree.root = Some(tree.build(grid, &free, 0, 0, size));
+        }
+        tree
+    }
+
+    /// Builds the node covering the square of side `size` at `(top, left)`,
+    /// merging its quadrants into a single block if they are all blocks of
+    /// the same kind.
+    fn build<F>(&mut self,
+                grid: &Grid,
+                free: &F,
+                top: usize,
+                left: usize,
+                size: usize)
+                -> usize
+        where F: Fn(&Tile) -> bool
+    {
+        if size == 1 {
+            let free = free(&grid[&Point::new(top, left)]);
+            return self.leaf(Block {
+                                 top: top,
+                                 left: left,
+                                 height: 1,
+                                 width: 1,
+                                 free: free,
+                             });
+        }
+
+        let half = size / 2;
+        let mut quadrants = [None; 4];
+        for (i, quadrant) in quadrants.iter_mut().enumerate() {
+            let (y, x) = (top + i / 2 * half, left + i % 2 * half);
+            if y < self.height && x < self.width {
+                *quadrant = Some(self.build(grid, free, y, x, half));
+            }
+        }
+
+        let kinds = quadrants.iter()
+            .flatten()
+            .map(|&node| match self.nodes[node] {
+                     Node::Leaf(block) => Some(self.blocks[block].free),
+                     Node::Split(_) => None,
+                 })
+            .collect::<Vec<_>>();
+        match kinds[0] {
+            Some(free) if kinds.iter().all(|&kind| kind == Some(free)) => {
+                // The quadrants were the last leaves built, so they can be
+                // dropped from the end.
+                self.nodes.truncate(self.nodes.len() - kinds.len());
+                self.blocks.truncate(self.blocks.len() - kinds.len());
+                self.leaf(Block {
+                              top: top,
+                              left: left,
+                              height: min(size, self.height - top),
+                              width: min(size, self.width - left),
+                              free: free,
+                          })
+            }
+            _ => {
+                self.nodes.push(Node::Split(quadrants));
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn leaf(&mut self, block: Block) -> usize {
+        self.blocks.push(block);
+        self.nodes.push(Node::Leaf(self.blocks.len() - 1));
+        self.nodes.len() - 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn block(&self, index: usize) -> &Block {
+        &self.blocks[index]
+    }
+
+    /// Returns the index of the block that contains `point`, if it lies
+    /// within the grid.
+    pub fn locate(&self, point: &Point) -> Option<usize> {
+        if point.y() >= self.height || point.x() >= self.width {
+            return None;
+        }
+        let (mut top, mut left, mut size) = (0, 0, self.size);
+        let mut node = self.root?;
+        loop {
+            match self.nodes[node] {
+                Node::Leaf(block) => return Some(block),
+                Node::Split(ref quadrants) => {
+                    size /= 2;
+                    let bottom = point.y() >= top + size;
+                    let right = point.x() >= left + size;
+                    if bottom {
+                        top += size;
+                    }
+                    if right {
+                        left += size;
+                    }
+                    node = quadrants[2 * bottom as usize + right as usize]?;
+                }
+            }
+        }
+    }
+
+    /// Lists the blocks that touch the block at `index`, along an edge or at
+    /// a corner, in increasing order.
+    pub fn neighbors(&self, index: usize) -> Vec<usize> {
+        let block = self.blocks[index];
+        let bottom = block.top + block.height;
+        let right = block.left + block.width;
+        let mut ring = Vec::new();
+        for x in block.left.saturating_sub(1)..min(right + 1, self.width) {
+            if block.top > 0 {
+                ring.push(Point::new(block.top - 1, x));
+            }
+            if bottom < self.height {
+                ring.push(Point::new(bottom, x));
+            }
+        }
+        for y in block.top..bottom {
+            if block.left > 0 {
+                ring.push(Point::new(y, block.left - 1));
+            }
+            if right < self.width {
+                ring.push(Point::new(y, right));
+            }
+        }
+
+        let mut neighbors = ring.iter()
+            .filter_map(|point| self.locate(point))
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grid::GridBuilder;
+    use parser::grid_from_str;
+
+    #[test]
+    fn divide_into_blocks() {
+        let open = GridBuilder::new(100, 60).build().unwrap();
+        let tree = Quadtree::from_grid(&open, Tile::passable);
+        assert_eq!(tree.blocks(),
+                   &[Block {
+                         top: 0,
+                         left: 0,
+                         height: 100,
+                         width: 60,
+                         free: true,
+                     }]);
+
+        let grid = grid_from_str("type octile\nheight 4\nwidth 5\nmap\n\
+                                  .....\n.....\n..T..\n.....");
+        let tree = Quadtree::from_grid(&grid, Tile::passable);
+        let tree_block = tree.locate(&Point::new(2, 2)).unwrap();
+        assert_eq!(*tree.block(tree_block),
+                   Block {
+                       top: 2,
+                       left: 2,
+                       height: 1,
+                       width: 1,
+                       free: false,
+                   });
+        // The top left quadrant is open, and so is the strip on the right.
+        let open = tree.locate(&Point::new(0, 0)).unwrap();
+        assert_eq!(tree.block(open).height, 2);
+        assert_eq!(tree.block(open).width, 2);
+        let strip = tree.locate(&Point::new(3, 4)).unwrap();
+        assert_eq!(*tree.block(strip),
+                   Block {
+                       top: 0,
+                       left: 4,
+                       height: 4,
+                       width: 1,
+                       free: true,
+                   });
+        assert_eq!(tree.locate(&Point::new(4, 0)), None);
+
+        // Every tile lies in exactly one block, of the right kind.
+        let mut covered = 0;
+        for block in tree.blocks() {
+            covered += block.height * block.width;
+            for y in block.top..block.top + block.height {
+                for x in block.left..block.left + block.width {
+                    let point = Point::new(y, x);
+                    assert_eq!(grid[&point].passable(), block.free);
+                    assert_eq!(tree.block(tree.locate(&point).unwrap()),
+                               block);
+                }
+            }
+        }
+        assert_eq!(covered, 20);
+
+        assert_eq!(tree.blocks().len(), 8);
+
+        let neighbors = tree.neighbors(tree_block);
+        assert_eq!(neighbors.len(), 6);
+        assert!(neighbors.contains(&open));
+        assert!(!neighbors.contains(&strip));
+        // The open quadrant touches the tree at a corner.
+        assert!(tree.neighbors(open).contains(&tree_block));
+        assert_eq!(tree.neighbors(strip).len(), 3);
+    }
+}
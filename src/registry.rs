@@ -0,0 +1,461 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use std::io::stderr;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use agent::{Agent, AlwaysAstar, Bug2, CompressedPaths, InteractiveAgent,
+            Keyboard, PotentialField, QuadtreeAstar, RepeatedAstar,
+            ScriptedAgent};
+use cost::MoveMetric;
+use cpd::Cpd;
+use grid::{Distance, Grid, Measure, Movement, Point};
+use heuristic::HeuristicCache;
+use landmarks::Landmarks;
+use observer::IoSink;
+
+/// Named parameters used to construct an agent, such as `heuristic=octile`.
+pub type Parameters = BTreeMap<String, String>;
+
+/// Estimates the cost of moving between two points.
+pub type Heuristic = Rc<dyn Fn(&Point, &Point) -> Distance>;
+
+/// Builds a heuristic for a map out of the parameters of the agent that will
+/// use it, or describes why it cannot.
+pub type HeuristicConstructor = Box<dyn Fn(&Grid, &Parameters)
+                                           -> Result<Heuristic, String>>;
+
+/// Builds an agent out of its parameters, and the heuristics available for
+/// the map it will run on, or describes why it cannot.
+pub type Constructor = Box<dyn Fn(&Parameters, &Heuristics)
+                                  -> Result<Box<dyn Agent>, String>>;
+
+#[derive(Debug, PartialEq)]
+pub enum RegistryError {
+    /// No agent is registered under the requested name.
+    Unknown(String),
+    /// The agent's constructor rejected the parameters.
+    Parameters(String, String),
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            RegistryError::Unknown(ref name) => {
+                write!(f, "Unknown algorithm '{}'.", name)
+            }
+            RegistryError::Parameters(ref name, ref description) => {
+                write!(f, "Invalid parameters for '{}': {}", name, description)
+            }
+        }
+    }
+}
+
+/// Maps algorithm names to agent constructors, and heuristic names to
+/// heuristic constructors.
+///
+/// `Registry::default()` knows about every agent and heuristic in this
+/// crate; other crates can `register` their own agents, and
+/// `register_heuristic` their own heuristics, on top of those.
+pub struct Registry {
+    constructors: BTreeMap<String, Constructor>,
+    heuristics: BTreeMap<String, HeuristicConstructor>,
+}
+
+impl Registry {
+    /// Creates a registry without any agents or heuristics.
+    pub fn new() -> Registry {
+        Registry {
+            constructors: BTreeMap::new(),
+            heuristics: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `constructor` under `name`, replacing any agent previously
+    /// registered under the same name.
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+        where F: Fn(&Parameters, &Heuristics)
+                    -> Result<Box<dyn Agent>, String> + 'static
+    {
+        self.constructors.insert(name.to_owned(), Box::new(constructor));
+    }
+
+    /// Registers `constructor` under `name`, replacing any heuristic
+    /// previously registered under the same name. Agents use it when given
+    /// `heuristic=<name>`.
+    pub fn register_heuristic<F>(&mut self, name: &str, constructor: F)
+        where F: Fn(&Grid, &Parameters) -> Result<Heuristic, String> + 'static
+    {
+        self.heuristics.insert(name.to_owned(), Box::new(constructor));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    /// Lists the registered names, in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        self.constructors.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Lists the names of the registered heuristics, in alphabetical order.
+    pub fn heuristic_names(&self) -> Vec<&str> {
+        self.heuristics.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Builds the agent registered under `name`, to run on `grid`.
+    pub fn build(&self,
+                 name: &str,
+                 parameters: &Parameters,
+                 grid: &Grid)
+                 -> Result<Box<dyn Agent>, RegistryError> {
+        let constructor =
+            self.constructors
+                .get(name)
+                .ok_or_else(|| RegistryError::Unknown(name.to_owned()))?;
+        let heuristics = Heuristics {
+            registry: self,
+            grid: grid,
+        };
+        constructor(parameters, &heuristics)
+            .map_err(|e| RegistryError::Parameters(name.to_owned(), e))
+    }
+}
+
+/// The heuristics of a registry, for the map an agent is being built for.
+/// Heuristics are only built when an agent asks for one, since some of them
+/// take long to prepare.
+pub struct Heuristics<'a> {
+    registry: &'a Registry,
+    grid: &'a Grid,
+}
+
+impl<'a> Heuristics<'a> {
+    /// The map the agent is being built for.
+    pub fn grid(&self) -> &'a Grid {
+        self.grid
+    }
+
+    /// Builds the heuristic named by the `heuristic` parameter, defaulting to
+    /// the octile heuristic. Given `cache_heuristic=true`, its estimates
+    /// towards the current target are kept (see `HeuristicCache`).
+    pub fn build(&self,
+                 parameters: &Parameters)
+                 -> Result<impl Fn(&Point, &Point) -> Distance, String> {
+        let name = parameters.get("heuristic").map_or("octile", |h| h.as_str());
+        let constructor = self.registry
+            .heuristics
+            .get(name)
+            .ok_or_else(|| format!("Unknown heuristic '{}'.", name))?;
+        let mut heuristic = constructor(self.grid, parameters)?;
+        if parameter(parameters, "cache_heuristic", false)? {
+            let uncached = heuristic;
+            let cache = HeuristicCache::new(move |from: &Point, to: &Point| {
+                                                uncached(from, to)
+                                            },
+                                            self.grid);
+            heuristic = Rc::new(move |from: &Point, to: &Point| {
+                                    cache.estimate(from, to)
+                                });
+        }
+        Ok(move |from: &Point, to: &Point| heuristic(from, to))
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("astar", |parameters, heuristics| {
+            Ok(Box::new(AlwaysAstar::new(heuristics.build(parameters)?,
+                                         metric(parameters)?)))
+        });
+        registry.register("bug2", |_, _| Ok(Box::new(Bug2::new())));
+        registry.register("cpd", |parameters, _| {
+            Ok(Box::new(CompressedPaths::new(cache(parameters),
+                                             metric(parameters)?)))
+        });
+        registry.register("human", |_, _| {
+            Ok(Box::new(InteractiveAgent::new(Keyboard::new(),
+                                              IoSink(stderr()))))
+        });
+        registry.register("potential", |parameters, heuristics| {
+            Ok(Box::new(PotentialField::new(heuristics.build(parameters)?,
+                                            metric(parameters)?,
+                                            parameter(parameters,
+                                                      "repulsion",
+                                                      1.0)?,
+                                            parameter(parameters,
+                                                      "range",
+                                                      3)?)))
+        });
+        registry.register("qastar", |parameters, heuristics| {
+            if heuristics.grid().movement() != Movement::Octile {
+                return Err("Quadtree paths move diagonally, so only maps \
+                            that allow every move are supported."
+                    .to_owned());
+            }
+            Ok(Box::new(QuadtreeAstar::new(heuristics.build(parameters)?,
+                                           metric(parameters)?)))
+        });
+        registry.register("rastar", |parameters, heuristics| {
+            let mut agent = RepeatedAstar::new(heuristics.build(parameters)?,
+                                               metric(parameters)?);
+            agent.set_replan_radius(replan_radius(parameters)?);
+            agent.set_reuse_paths(parameter(parameters, "reuse_paths", false)?);
+            Ok(Box::new(agent))
+        });
+        registry.register("scripted", |parameters, _| {
+            let script = parameters.get("script")
+                .ok_or_else(|| "Missing 'script' parameter.".to_owned())?;
+            Ok(Box::new(ScriptedAgent::from_file(script)?))
+        });
+
+        registry.register_heuristic("euclidean", |_, _| {
+            Ok(Rc::new(Distance::euclidean_heuristic))
+        });
+        registry.register_heuristic("landmarks", |grid, parameters| {
+            let count = parameter(parameters, "landmarks", 8)?;
+            let landmarks = Landmarks::from_grid(grid, count);
+            Ok(Rc::new(move |from: &Point, to: &Point| {
+                           landmarks.estimate(from, to)
+                       }))
+        });
+        registry.register_heuristic("octile", |_, _| {
+            Ok(Rc::new(Distance::octile_heuristic))
+        });
+        registry.register_heuristic("perfect", |grid, parameters| {
+            let cpd = Cpd::cached(grid, cache(parameters).as_deref());
+            Ok(Rc::new(move |from: &Point, to: &Point| {
+                           cpd.distance(from, to).unwrap_or(Distance::INFINITY)
+                       }))
+        });
+        registry
+    }
+}
+
+/// Reads the `metric` parameter, which sets how the agent's executed moves
+/// are measured, defaulting to their length.
+fn metric(parameters: &Parameters) -> Result<MoveMetric, String> {
+    parameter(parameters, "metric", MoveMetric::Length)
+}
+
+/// Reads the `replan_radius` parameter, how close to its path a change of
+/// beliefs makes an agent replan, if it is given.
+fn replan_radius(parameters: &Parameters) -> Result<Option<usize>, String> {
+    if parameters.contains_key("replan_radius") {
+        parameter(parameters, "replan_radius", 0).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads the `cache` parameter, the directory where compressed path
+/// databases are stored.
+fn cache(parameters: &Parameters) -> Option<PathBuf> {
+    parameters.get("cache").map(PathBuf::from)
+}
+
+/// Reads the parameter called `name`, defaulting to `default` when it is not
+/// given.
+pub fn parameter<T>(parameters: &Parameters,
+                    name: &str,
+                    default: T)
+                    -> Result<T, String>
+    where T: FromStr
+{
+    match parameters.get(name) {
+        None => Ok(default),
+        Some(value) => {
+            value.parse()
+                .map_err(|_| format!("Invalid {} '{}'.", name, value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use agent::{Action, Datum};
+    use experiment::Verbosity;
+    use grid::{Grid, GridBuilder};
+    use instance::Instance;
+    use observer::Observer;
+    use parser::grid_from_str;
+    use pursuit::{Capture, Flee, Pursuit};
+
+    use std::cell::RefCell;
+
+    /// Records every move the agent makes.
+    struct Trail(Rc<RefCell<Vec<(Point, Point)>>>);
+
+    impl Observer for Trail {
+        fn on_move(&mut self, _grid: &Grid, from: &Point, to: &Point) {
+            self.0.borrow_mut().push((*from, *to));
+        }
+    }
+
+    struct Stay;
+
+    impl Agent for Stay {
+        fn act(&mut self,
+               _grid: &mut Grid,
+               _location: &Point,
+               _target: &Point)
+               -> Option<Datum> {
+            Some(Datum {
+                     action: Action::Wait,
+                     expansions: 0,
+                 })
+        }
+    }
+
+    #[test]
+    fn builds_default_agents() {
+        let registry = Registry::default();
+        let grid = GridBuilder::new(4, 4).build().unwrap();
+        let mut parameters = Parameters::new();
+
+        assert_eq!(registry.names(),
+                   vec!["astar",
+                        "bug2",
+                        "cpd",
+                        "human",
+                        "potential",
+                        "qastar",
+                        "rastar",
+                        "scripted"]);
+        assert!(registry.build("astar", &parameters, &grid).is_ok());
+
+        parameters.insert("heuristic".to_owned(), "euclidean".to_owned());
+        assert!(registry.build("rastar", &parameters, &grid).is_ok());
+
+        parameters.insert("heuristic".to_owned(), "manhattan".to_owned());
+        assert_eq!(registry.build("rastar", &parameters, &grid).err(),
+                   Some(RegistryError::Parameters("rastar".to_owned(),
+                                                  "Unknown heuristic \
+                                                   'manhattan'."
+                                                          .to_owned())));
+
+        parameters.remove("heuristic");
+        parameters.insert("range".to_owned(), "far".to_owned());
+        assert_eq!(registry.build("potential", &parameters, &grid).err(),
+                   Some(RegistryError::Parameters("potential".to_owned(),
+                                                  "Invalid range 'far'."
+                                                      .to_owned())));
+
+        parameters.remove("range");
+        parameters.insert("metric".to_owned(), "unit".to_owned());
+        assert!(registry.build("astar", &parameters, &grid).is_ok());
+        parameters.insert("metric".to_owned(), "chebyshev".to_owned());
+        assert_eq!(registry.build("astar", &parameters, &grid).err(),
+                   Some(RegistryError::Parameters("astar".to_owned(),
+                                                  "Invalid metric \
+                                                   'chebyshev'."
+                                                          .to_owned())));
+
+        assert_eq!(registry.build("dstar", &parameters, &grid).err(),
+                   Some(RegistryError::Unknown("dstar".to_owned())));
+
+        let tile = grid_from_str("type tile\nheight 2\nwidth 2\nmap\n..\n..");
+        assert!(registry.build("qastar", &Parameters::new(), &grid).is_ok());
+        assert!(registry.build("qastar", &Parameters::new(), &tile).is_err());
+    }
+
+    #[test]
+    fn registers_custom_agents() {
+        let mut registry = Registry::default();
+        registry.register("stay", |_, _| Ok(Box::new(Stay)));
+
+        assert!(registry.contains("stay"));
+        let grid = GridBuilder::new(4, 4).build().unwrap();
+        assert!(registry.build("stay", &Parameters::new(), &grid).is_ok());
+    }
+
+    #[test]
+    fn build_heuristics_for_the_map() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.heuristic_names(),
+                   vec!["euclidean", "landmarks", "octile", "perfect"]);
+
+        // Going around the wall costs more than the octile distance.
+        let grid = grid_from_str("type octile\nheight 3\nwidth 3\nmap\n\
+                                  .T.\n.T.\n...");
+        let heuristics = Heuristics {
+            registry: &registry,
+            grid: &grid,
+        };
+        let (from, to) = (Point::new(0, 0), Point::new(0, 2));
+        let estimate = |name: &str| {
+            let mut parameters = Parameters::new();
+            parameters.insert("heuristic".to_owned(), name.to_owned());
+            heuristics.build(&parameters).map(|h| h(&from, &to))
+        };
+        assert_eq!(estimate("octile"), Ok(2.0));
+        assert_eq!(estimate("euclidean"), Ok(2.0));
+        let cost = 2.0 + 2.0 * 2f64.sqrt();
+        assert!((estimate("perfect").unwrap() - cost).abs() < 1e-9);
+        let landmarks = estimate("landmarks").unwrap();
+        assert!(landmarks > 2.0 && landmarks <= cost + 1e-9);
+        let mut parameters = Parameters::new();
+        parameters.insert("heuristic".to_owned(), "landmarks".to_owned());
+        parameters.insert("cache_heuristic".to_owned(), "true".to_owned());
+        let cached = heuristics.build(&parameters).unwrap();
+        assert_eq!(cached(&from, &to), landmarks);
+        assert_eq!(cached(&from, &to), landmarks);
+        assert_eq!(estimate("manhattan").err(),
+                   Some("Unknown heuristic 'manhattan'.".to_owned()));
+
+        registry.register_heuristic("zero", |_, _| Ok(Rc::new(|_, _| 0.0)));
+        let mut parameters = Parameters::new();
+        parameters.insert("heuristic".to_owned(), "zero".to_owned());
+        assert!(registry.build("rastar", &parameters, &grid).is_ok());
+    }
+
+    #[test]
+    fn move_straight_on_tile_maps() {
+        let map = "type tile\nheight 6\nwidth 6\nmap\n\
+                   ......\n.TT...\n...T..\n.T.T..\n.T....\n......";
+        let registry = Registry::default();
+        let mut perfect = Parameters::new();
+        perfect.insert("heuristic".to_owned(), "perfect".to_owned());
+        let runs = registry.names()
+            .into_iter()
+            .filter(|name| !["human", "qastar", "scripted"].contains(name))
+            .flat_map(|name| {
+                          vec![(name, Parameters::new(), false),
+                               (name, perfect.clone(), false),
+                               (name, Parameters::new(), true)]
+                      });
+
+        for (name, parameters, pursued) in runs {
+            let mut grid = grid_from_str(map);
+            let agent = registry.build(name, &parameters, &grid).unwrap();
+            let moves = Rc::new(RefCell::new(Vec::new()));
+            let mut instance = Instance::new(&mut grid, agent, Verbosity::Zero);
+            instance.add_observer(Box::new(Trail(moves.clone())));
+            if pursued {
+                instance.set_pursuit(Some(Pursuit::new(Flee,
+                                                       Capture::Colocation)));
+            }
+            let datum = instance.run_once(Point::new(0, 0), Point::new(5, 5));
+
+            assert!(!moves.borrow().is_empty(), "{} did not move", name);
+            for &(from, to) in moves.borrow().iter() {
+                assert!(from.y() == to.y() || from.x() == to.x(),
+                        "{} moved diagonally from {:?} to {:?}",
+                        name,
+                        from,
+                        to);
+            }
+            if !pursued && name != "potential" && name != "bug2" {
+                assert!(datum.solved(), "{} did not solve the trial", name);
+            }
+        }
+
+        let grid = grid_from_str(map);
+        assert!(registry.build("qastar", &Parameters::new(), &grid).is_err());
+    }
+}
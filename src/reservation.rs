@@ -0,0 +1,46 @@
+//! Reservations of tiles over time, for planners that must keep out of the
+//! way of other moving things.
+
+use std::collections::{BTreeSet, HashMap};
+
+use grid::Point;
+
+/// The time steps at which each tile is occupied by something other than
+/// the agent planning around them.
+#[derive(Debug, Default, Clone)]
+pub struct ReservationTable {
+    reservations: HashMap<Point, BTreeSet<usize>>,
+}
+
+impl ReservationTable {
+    pub fn new() -> ReservationTable {
+        ReservationTable::default()
+    }
+
+    pub fn reserve(&mut self, point: Point, time: usize) {
+        self.reservations.entry(point).or_default().insert(time);
+    }
+
+    /// Reserves the tiles along `path`, which is occupied from `start` on:
+    /// the first point at time `start`, the second at `start + 1`, and so
+    /// on.
+    pub fn reserve_path(&mut self, start: usize, path: &[Point]) {
+        for (offset, point) in path.iter().enumerate() {
+            self.reserve(*point, start + offset);
+        }
+    }
+
+    pub fn is_reserved(&self, point: &Point, time: usize) -> bool {
+        self.reservations
+            .get(point)
+            .is_some_and(|times| times.contains(&time))
+    }
+
+    /// Returns whether `point` is reserved at any time after `time`, so that
+    /// an agent cannot stay there for good.
+    pub fn is_reserved_after(&self, point: &Point, time: usize) -> bool {
+        self.reservations
+            .get(point)
+            .is_some_and(|times| times.range(time + 1..).next().is_some())
+    }
+}
@@ -0,0 +1,87 @@
+//! Run-length encoding of rows of symbols, for compact files. Each run is
+//! written as its length followed by its symbol, leaving out the length of
+//! runs of a single symbol, so `...TT?` becomes `3.2T?`. Symbols must not be
+//! digits.
+
+/// Encodes `symbols` as a single run-length encoded line.
+pub fn encode<I>(symbols: I) -> String
+    where I: IntoIterator<Item = char>
+{
+    let mut line = String::new();
+    let mut run: Option<(char, usize)> = None;
+    for symbol in symbols.into_iter().map(Some).chain(Some(None)) {
+        match (run, symbol) {
+            (Some((current, length)), Some(symbol)) if current == symbol => {
+                run = Some((current, length + 1));
+                continue;
+            }
+            (Some((current, length)), _) => {
+                if length > 1 {
+                    line.push_str(&length.to_string());
+                }
+                line.push(current);
+            }
+            (None, _) => {}
+        }
+        run = symbol.map(|symbol| (symbol, 1));
+    }
+    line
+}
+
+/// Decodes a line written by `encode`.
+pub fn decode(line: &str) -> Result<Vec<char>, String> {
+    decode_at_most(line, usize::MAX)
+}
+
+/// Like `decode`, but fails without decoding the rest of the line once it
+/// holds more than `limit` symbols, so that malformed lines with huge runs
+/// cannot exhaust memory.
+pub fn decode_at_most(line: &str, limit: usize) -> Result<Vec<char>, String> {
+    let mut symbols = Vec::new();
+    let mut length = String::new();
+    for c in line.chars() {
+        if c.is_ascii_digit() {
+            length.push(c);
+        } else {
+            let count = if length.is_empty() {
+                1
+            } else {
+                length.parse::<usize>().map_err(|e| e.to_string())?
+            };
+            if count == 0 {
+                return Err(format!("Empty run of '{}'.", c));
+            }
+            if count > limit - symbols.len() {
+                return Err(format!("More than {} symbols.", limit));
+            }
+            symbols.extend((0..count).map(|_| c));
+            length.clear();
+        }
+    }
+    if !length.is_empty() {
+        return Err(format!("Run of length {} has no symbol.", length));
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_runs() {
+        assert_eq!(encode("...TT?".chars()), "3.2T?");
+        assert_eq!(encode("".chars()), "");
+        assert_eq!(encode(vec!['@'; 12]), "12@");
+
+        let row = "@@@@@@@@@@@@.....TTTTSW.W";
+        assert_eq!(decode(&encode(row.chars())).unwrap(),
+                   row.chars().collect::<Vec<_>>());
+
+        assert!(decode("3").is_err());
+        assert!(decode("0.").is_err());
+        assert_eq!(decode_at_most("3.2T", 5).unwrap().len(), 5);
+        assert_eq!(decode_at_most("3.99999999999999T", 5),
+                   Err("More than 5 symbols.".to_owned()));
+    }
+}
@@ -1,19 +1,36 @@
+//! The searches agents plan with: A* over what the grid is believed to be,
+//! in its several variants, as well as Dijkstra's algorithm and searches
+//! over quadtrees and through time. Paths are returned backwards, from the
+//! target to the first move, so that the next move can be popped off them.
+
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize,
+                        Ordering as AtomicOrdering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Instant;
+
+use cost::length;
+use grid::{Direction, Distance, Expansion, Grid, Measure, Point, Tile};
+use quadtree::{Block, Quadtree};
+use reservation::ReservationTable;
 
-use grid::{COST, Distance, Grid, Measure, Point, Tile};
+pub use cost::WAIT_COST;
 
 #[derive(Debug)]
 struct Node {
-    point: Point,
+    /// The index of the tile (see `Grid::index_of`), or of the block in
+    /// searches over quadtrees.
+    index: usize,
+    /// The direction of the move the tile was reached by, if any, which
+    /// the cost model may charge for turning on the next one.
+    direction: Option<Direction>,
     f: Distance,
     g: Distance,
-}
-
-impl Node {
-    pub fn point(&self) -> &Point {
-        &self.point
-    }
+    time: usize,
 }
 
 impl PartialEq for Node {
@@ -65,6 +82,8 @@ fn extract_path(grid: &Grid, end: Point) -> Path {
 pub struct Data {
     pub path: Path,
     pub expansions: usize,
+    /// The heuristic estimate the search started from, at the source.
+    pub initial_h: Distance,
 }
 
 pub fn astar<H, P>(grid: &mut Grid,
@@ -75,43 +94,95 @@ pub fn astar<H, P>(grid: &mut Grid,
                    -> Option<Data>
     where H: Fn(&Point, &Point) -> Distance,
           P: Fn(&Tile) -> bool
+{
+    astar_until(grid, source, target, heuristic, passable, None)
+}
+
+/// Like `astar`, but if `deadline` passes before the target is expanded,
+/// stops and returns the path to the most promising node found so far (the
+/// one with the lowest f that would be expanded next), which does not end
+/// at the target. At least the source is expanded, however late it is.
+pub fn astar_until<H, P>(grid: &mut Grid,
+                         source: &Point,
+                         target: &Point,
+                         heuristic: H,
+                         passable: P,
+                         deadline: Option<Instant>)
+                         -> Option<Data>
+    where H: Fn(&Point, &Point) -> Distance,
+          P: Fn(&Tile) -> bool
 {
     let episode = grid.next_episode();
+    profile_span!("episode", episode = episode);
 
     let mut open = BinaryHeap::new();
     let mut expansions = 0;
+    // The successors of the node being expanded, gathered before the grid
+    // is updated with them.
+    let mut successors = Vec::new();
 
-    grid[source].visit_initial(Distance::octile_heuristic(source, target),
-                               episode);
+    let initial_h = heuristic(source, target);
+    let start = grid.index_of(source)?;
+    grid.tile_mut(start).visit_initial(initial_h, episode);
     open.push(Node {
-                  point: *source,
-                  f: grid[source].f(),
-                  g: grid[source].g(),
+                  index: start,
+                  direction: None,
+                  f: grid.tile(start).f(),
+                  g: grid.tile(start).g(),
+                  time: 0,
               });
 
     while let Some(expand) = open.pop() {
+        let index = expand.index;
+        if expand.g > grid.tile(index).g() {
+            // A cheaper path to the point was found after this node was
+            // pushed, and was expanded already.
+            continue;
+        }
+        let point = grid.point_of(index);
+        if expansions > 0 &&
+           deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(Data {
+                            path: extract_path(grid, point),
+                            expansions: expansions,
+                            initial_h: initial_h,
+                        });
+        }
         expansions += 1;
-        let point = expand.point();
-        if point == target {
+        let tile = grid.tile(index);
+        let expansion = Expansion {
+            point: point,
+            f: tile.f(),
+            g: tile.g(),
+            h: tile.h(),
+            episode: episode,
+        };
+        grid.trace_expansion(expansion);
+        if point == *target {
             return Some(Data {
-                            path: extract_path(grid, *point),
+                            path: extract_path(grid, point),
                             expansions: expansions,
+                            initial_h: initial_h,
                         });
         } else {
-            let g = grid[point].g();
-            for (i, neighbor) in point.neighbors().iter().enumerate() {
-                if let Some(neighbor) = *neighbor {
-                    if let Some(ref mut tile) = grid.get_mut(&neighbor) {
-                        if !tile.visited(episode) && passable(tile) {
-                            let h = heuristic(&neighbor, target);
-                            tile.visit(*point, g + COST[i], h, episode);
-                            open.push(Node {
-                                          point: neighbor,
-                                          f: tile.f(),
-                                          g: tile.g(),
-                                      });
-                        }
-                    }
+            let g = grid.tile(index).g();
+            successors.extend(grid.successors(index,
+                                              expand.direction,
+                                              &passable));
+            for (neighbor, direction, cost) in successors.drain(..) {
+                let tile = grid.tile(neighbor);
+                let improved = !tile.visited(episode) || g + cost < tile.g();
+                if improved {
+                    let h = heuristic(&grid.point_of(neighbor), target);
+                    let tile = grid.tile_mut(neighbor);
+                    tile.visit(point, g + cost, h, episode);
+                    open.push(Node {
+                                  index: neighbor,
+                                  direction: Some(direction),
+                                  f: tile.f(),
+                                  g: tile.g(),
+                                  time: 0,
+                              });
                 }
             }
         }
@@ -120,11 +191,728 @@ pub fn astar<H, P>(grid: &mut Grid,
     None
 }
 
+/// What searches that leave the grid untouched keep about every tile: the
+/// cost of reaching it and the tile it was reached from. It is kept from
+/// one search to the next, so that every thread planning over a shared grid
+/// needs one of its own, allocated once.
+#[derive(Debug, Default)]
+pub struct SearchScratch {
+    g: Vec<Distance>,
+    parent: Vec<Option<usize>>,
+    /// The search that last reached every tile, so that tiles need not be
+    /// cleared between searches.
+    reached: Vec<usize>,
+    search: usize,
+}
+
+impl SearchScratch {
+    pub fn new() -> SearchScratch {
+        SearchScratch::default()
+    }
+
+    /// Starts a search over a grid of `tiles` tiles.
+    fn start(&mut self, tiles: usize) {
+        if self.reached.len() != tiles {
+            self.g = vec![0.0; tiles];
+            self.parent = vec![None; tiles];
+            self.reached = vec![0; tiles];
+            self.search = 0;
+        }
+        self.search += 1;
+    }
+
+    /// The cost of reaching the tile at `index` in the current search, and
+    /// the tile it was reached from, if it was reached.
+    fn get(&self, index: usize) -> Option<(Distance, Option<usize>)> {
+        if self.reached[index] == self.search {
+            Some((self.g[index], self.parent[index]))
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, index: usize, g: Distance, parent: Option<usize>) {
+        self.g[index] = g;
+        self.parent[index] = parent;
+        self.reached[index] = self.search;
+    }
+}
+
+/// Like `astar`, but keeps the cost and parent of every tile reached in
+/// `scratch` rather than on the tiles, so that the grid is left untouched
+/// (and no expansions are traced), and may be shared by threads that search
+/// at once. Gives up after `limit` expansions, if given.
+pub fn detached_astar<H, P>(grid: &Grid,
+                            source: &Point,
+                            target: &Point,
+                            heuristic: H,
+                            passable: P,
+                            limit: Option<usize>,
+                            scratch: &mut SearchScratch)
+                            -> Option<Data>
+    where H: Fn(&Point, &Point) -> Distance,
+          P: Fn(&Tile) -> bool
+{
+    let start = grid.index_of(source)?;
+    let goal = grid.index_of(target)?;
+
+    scratch.start(grid.height() * grid.width());
+    let mut open = BinaryHeap::new();
+    let mut expansions = 0;
+
+    let initial_h = heuristic(source, target);
+    scratch.set(start, 0.0, None);
+    open.push(Node {
+                  index: start,
+                  direction: None,
+                  f: initial_h,
+                  g: 0.0,
+                  time: 0,
+              });
+
+    while let Some(expand) = open.pop() {
+        let index = expand.index;
+        if expand.g > scratch.get(index).unwrap().0 {
+            continue;
+        }
+        if limit.is_some_and(|limit| expansions == limit) {
+            return None;
+        }
+        expansions += 1;
+        if index == goal {
+            let mut path = Path::new();
+            let mut step = index;
+            while let Some((_, Some(previous))) = scratch.get(step) {
+                path.push(grid.point_of(step));
+                step = previous;
+            }
+            return Some(Data {
+                            path: path,
+                            expansions: expansions,
+                            initial_h: initial_h,
+                        });
+        }
+
+        let moves = grid.successors(index, expand.direction, &passable);
+        for (neighbor, direction, cost) in moves {
+            let g = expand.g + cost;
+            if scratch.get(neighbor).is_none_or(|(known, _)| g < known) {
+                scratch.set(neighbor, g, Some(index));
+                let h = heuristic(&grid.point_of(neighbor), target);
+                open.push(Node {
+                              index: neighbor,
+                              direction: Some(direction),
+                              f: g + h,
+                              g: g,
+                              time: 0,
+                          });
+            }
+        }
+    }
+
+    None
+}
+
+/// A tile reached by a worker of `parallel_astar`, sent to the worker that
+/// owns it: its index, the cost of reaching it, the tile it was reached
+/// from, and the direction of the move from there.
+type Reached = (usize, Distance, usize, Option<Direction>);
+
+/// The worker of `parallel_astar` that owns the tile at `index`, spreading
+/// neighboring tiles over different workers.
+fn owner(index: usize, workers: usize) -> usize {
+    let hash = (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    hash.rotate_left(32) as usize % workers
+}
+
+/// The state shared by every worker of `parallel_astar`.
+struct Shared<'a, H, P> {
+    grid: &'a Grid,
+    target: Point,
+    heuristic: H,
+    passable: P,
+    limit: Option<usize>,
+    outboxes: Vec<Sender<Reached>>,
+    /// The cost of the cheapest path to the target found so far, as the
+    /// bits of a non-negative float, which are ordered as floats are.
+    incumbent: AtomicU64,
+    expansions: AtomicUsize,
+    /// The tiles sent that have not been taken in yet, plus the workers
+    /// that have nodes left to expand. Once it drops to 0, it stays there,
+    /// and the search is over.
+    work: AtomicUsize,
+    /// Set once the limit on expansions is reached.
+    stopped: AtomicBool,
+}
+
+/// Like `detached_astar`, but searches with `workers` threads at once, as
+/// in hash-distributed A* (HDA*): every tile is owned by one worker, which
+/// keeps its own open list and the cost and parent of its tiles, and tiles
+/// reached by one worker are sent to their owners. Workers go on until no
+/// node left anywhere could lead to a cheaper path than the best found, so
+/// paths are optimal as long as the heuristic never overestimates, but more
+/// nodes are expanded than by a single thread. Only pays off on large maps.
+pub fn parallel_astar<H, P>(grid: &Grid,
+                            source: &Point,
+                            target: &Point,
+                            heuristic: H,
+                            passable: P,
+                            limit: Option<usize>,
+                            workers: usize)
+                            -> Option<Data>
+    where H: Fn(&Point, &Point) -> Distance + Sync,
+          P: Fn(&Tile) -> bool + Sync
+{
+    let start = grid.index_of(source)?;
+    let goal = grid.index_of(target)?;
+    let workers = workers.max(1);
+
+    let (outboxes, inboxes): (Vec<_>, Vec<_>) =
+        (0..workers).map(|_| channel()).unzip();
+    let shared = Shared {
+        grid: grid,
+        target: *target,
+        heuristic: heuristic,
+        passable: passable,
+        limit: limit,
+        outboxes: outboxes,
+        incumbent: AtomicU64::new(Distance::INFINITY.to_bits()),
+        expansions: AtomicUsize::new(0),
+        work: AtomicUsize::new(1),
+        stopped: AtomicBool::new(false),
+    };
+    // The source reaches itself, from nowhere.
+    shared.outboxes[owner(start, workers)]
+        .send((start, 0.0, start, None))
+        .unwrap();
+
+    let tables = thread::scope(|scope| {
+        let handles: Vec<_> = inboxes.into_iter()
+            .map(|inbox| {
+                let shared = &shared;
+                scope.spawn(move || hda_worker(shared, inbox))
+            })
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let cost = shared.incumbent.load(AtomicOrdering::SeqCst);
+    if shared.stopped.load(AtomicOrdering::SeqCst) ||
+       Distance::from_bits(cost).is_infinite() {
+        return None;
+    }
+    let mut path = Path::new();
+    let mut step = goal;
+    while step != start {
+        path.push(grid.point_of(step));
+        step = tables[owner(step, workers)][&step].1;
+    }
+    Some(Data {
+             path: path,
+             expansions: shared.expansions.load(AtomicOrdering::SeqCst),
+             initial_h: (shared.heuristic)(source, target),
+         })
+}
+
+/// Runs one worker of `parallel_astar`, taking in the tiles sent to it from
+/// `inbox`, until the search is over. Returns the cost and parent of every
+/// tile it owns that was reached.
+fn hda_worker<H, P>(shared: &Shared<H, P>,
+                    inbox: Receiver<Reached>)
+                    -> HashMap<usize, (Distance, usize)>
+    where H: Fn(&Point, &Point) -> Distance,
+          P: Fn(&Tile) -> bool
+{
+    let grid = shared.grid;
+    let workers = shared.outboxes.len();
+    let goal = grid.index_of(&shared.target).unwrap();
+    let mut table: HashMap<usize, (Distance, usize)> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    let mut idle = true;
+
+    // Keeps the tile if it was reached more cheaply than before.
+    let take = |table: &mut HashMap<usize, (Distance, usize)>,
+                    open: &mut BinaryHeap<Node>,
+                    (index, g, parent, direction): Reached| {
+        if table.get(&index).is_some_and(|&(known, _)| known <= g) {
+            return;
+        }
+        table.insert(index, (g, parent));
+        if index == goal {
+            shared.incumbent.fetch_min(g.to_bits(), AtomicOrdering::SeqCst);
+        }
+        let point = grid.point_of(index);
+        open.push(Node {
+                      index: index,
+                      direction: direction,
+                      f: g + (shared.heuristic)(&point, &shared.target),
+                      g: g,
+                      time: 0,
+                  });
+    };
+
+    while shared.work.load(AtomicOrdering::SeqCst) > 0 &&
+          !shared.stopped.load(AtomicOrdering::SeqCst) {
+        while let Ok(reached) = inbox.try_recv() {
+            if idle {
+                shared.work.fetch_add(1, AtomicOrdering::SeqCst);
+                idle = false;
+            }
+            take(&mut table, &mut open, reached);
+            shared.work.fetch_sub(1, AtomicOrdering::SeqCst);
+        }
+
+        let incumbent = Distance::from_bits(shared.incumbent
+                                                .load(AtomicOrdering::SeqCst));
+        let expand = match open.pop() {
+            Some(node) if node.f < incumbent => node,
+            _ => {
+                // Nothing left could lead to a cheaper path.
+                open.clear();
+                if !idle {
+                    idle = true;
+                    shared.work.fetch_sub(1, AtomicOrdering::SeqCst);
+                }
+                thread::yield_now();
+                continue;
+            }
+        };
+        let index = expand.index;
+        if expand.g > table[&index].0 || index == goal {
+            continue;
+        }
+        let expansions = shared.expansions
+            .fetch_add(1, AtomicOrdering::SeqCst) + 1;
+        if shared.limit.is_some_and(|limit| expansions > limit) {
+            shared.stopped.store(true, AtomicOrdering::SeqCst);
+            break;
+        }
+
+        let moves = grid.successors(index, expand.direction, &shared.passable);
+        for (neighbor, direction, cost) in moves {
+            let reached = (neighbor, expand.g + cost, index, Some(direction));
+            let to = owner(neighbor, workers);
+            if to == owner(index, workers) {
+                take(&mut table, &mut open, reached);
+            } else {
+                shared.work.fetch_add(1, AtomicOrdering::SeqCst);
+                // The owner may have stopped at the limit on expansions,
+                // and dropped its inbox, in which case the search is over,
+                // and the tile is not needed anymore.
+                let _ = shared.outboxes[to].send(reached);
+            }
+        }
+    }
+    table
+}
+
+/// Searches over (location, time) pairs, starting from `source` at time
+/// `times.start`, for a path to `target` that avoids every tile reserved in
+/// `reservations` at the time it would be occupied. Besides moving, the
+/// agent may wait in place, in which case the path repeats a point. Only
+/// times within `times` are considered, and the target is only reached once
+/// it is not reserved anymore.
+pub fn space_time_astar<H, P>(grid: &mut Grid,
+                              source: &Point,
+                              target: &Point,
+                              times: Range<usize>,
+                              reservations: &ReservationTable,
+                              heuristic: H,
+                              passable: P)
+                              -> Option<Data>
+    where H: Fn(&Point, &Point) -> Distance,
+          P: Fn(&Tile) -> bool
+{
+    // Tiles are not visited, but the episode tells searches apart in traces.
+    let episode = grid.next_episode();
+    let mut open = BinaryHeap::new();
+    let mut parents = HashMap::new();
+    let mut expansions = 0;
+
+    let initial_h = heuristic(source, target);
+    let start = grid.index_of(source)?;
+    // The parent of every state reached, and the cost of reaching it.
+    parents.insert((start, times.start), (None, 0.0));
+    open.push(Node {
+                  index: start,
+                  direction: None,
+                  f: initial_h,
+                  g: 0.0,
+                  time: times.start,
+              });
+
+    while let Some(expand) = open.pop() {
+        let index = expand.index;
+        let time = expand.time;
+        if expand.g > parents[&(index, time)].1 {
+            continue;
+        }
+        expansions += 1;
+        let point = grid.point_of(index);
+        grid.trace_expansion(Expansion {
+                                 point: point,
+                                 f: expand.f,
+                                 g: expand.g,
+                                 h: expand.f - expand.g,
+                                 episode: episode,
+                             });
+        if point == *target && !reservations.is_reserved_after(target, time) {
+            let mut path = Path::new();
+            let mut state = (index, time);
+            while let Some(&(Some(previous), _)) = parents.get(&state) {
+                path.push(grid.point_of(state.0));
+                state = previous;
+            }
+            return Some(Data {
+                            path: path,
+                            expansions: expansions,
+                            initial_h: initial_h,
+                        });
+        }
+        if !times.contains(&(time + 1)) {
+            continue;
+        }
+
+        // Waiting makes no move, so the next one is not a turn.
+        let moves = grid.successors(index, expand.direction, &passable)
+            .map(|(next, direction, cost)| (next, Some(direction), cost))
+            .chain(Some((index, None, WAIT_COST)));
+        for (next, direction, cost) in moves {
+            let next_point = grid.point_of(next);
+            if !passable(grid.tile(next)) ||
+               reservations.is_reserved(&next_point, time + 1) {
+                continue;
+            }
+            let g = expand.g + cost;
+            let improved = match parents.entry((next, time + 1)) {
+                Entry::Vacant(entry) => {
+                    entry.insert((Some((index, time)), g));
+                    true
+                }
+                Entry::Occupied(mut entry) if g < entry.get().1 => {
+                    entry.insert((Some((index, time)), g));
+                    true
+                }
+                Entry::Occupied(_) => false,
+            };
+            if improved {
+                open.push(Node {
+                              index: next,
+                              direction: direction,
+                              f: g + heuristic(&next_point, target),
+                              g: g,
+                              time: time + 1,
+                          });
+            }
+        }
+    }
+
+    None
+}
+
+/// The costs of optimal paths from a source to every tile of a grid, over
+/// its true terrain, with every move costing its length.
+pub struct Costs {
+    /// The cost of reaching every tile, by index, or infinity if it cannot
+    /// be reached.
+    pub costs: Vec<Distance>,
+    /// The first move of an optimal path to every tile (numbered as in
+    /// `Point::neighbors`), or `None` for the source and for tiles that
+    /// cannot be reached.
+    pub first_moves: Vec<Option<u8>>,
+}
+
+/// Finds the cost of an optimal path from `source` to every tile of `grid`
+/// with Dijkstra's algorithm, moving only through passable tiles, as the
+/// grid's movement rules allow.
+pub fn dijkstra(grid: &Grid, source: &Point) -> Costs {
+    let tiles = grid.height() * grid.width();
+    let mut costs = vec![Distance::INFINITY; tiles];
+    let mut first_moves = vec![None; tiles];
+    let start = match grid.index_of(source) {
+        Some(start) if grid.tile(start).passable() => start,
+        _ => {
+            return Costs {
+                       costs: costs,
+                       first_moves: first_moves,
+                   }
+        }
+    };
+
+    let mut open = BinaryHeap::new();
+    costs[start] = 0.0;
+    open.push(Node {
+                  index: start,
+                  direction: None,
+                  f: 0.0,
+                  g: 0.0,
+                  time: 0,
+              });
+    while let Some(expand) = open.pop() {
+        let index = expand.index;
+        if expand.g > costs[index] {
+            continue;
+        }
+        for (neighbor, direction, _) in
+            grid.successors(index, None, Tile::passable) {
+            let g = expand.g + length(direction);
+            if g < costs[neighbor] {
+                costs[neighbor] = g;
+                first_moves[neighbor] = if index == start {
+                    Some(direction.index() as u8)
+                } else {
+                    first_moves[index]
+                };
+                open.push(Node {
+                              index: neighbor,
+                              direction: Some(direction),
+                              f: g,
+                              g: g,
+                              time: 0,
+                          });
+            }
+        }
+    }
+
+    Costs {
+        costs: costs,
+        first_moves: first_moves,
+    }
+}
+
+/// Finds the cost of an optimal path from `source` to each of `goals`, or
+/// infinity for those that cannot be reached, like `dijkstra` does. Stops
+/// as soon as the costs of all of them are known, so it only expands the
+/// tiles nearer to `source` than the farthest goal, however large the map.
+pub fn dijkstra_to(grid: &Grid,
+                   source: &Point,
+                   goals: &[Point])
+                   -> Vec<Distance> {
+    let mut costs = HashMap::new();
+    let mut left = goals.len();
+    let mut found = vec![Distance::INFINITY; goals.len()];
+    let start = match grid.index_of(source) {
+        Some(start) if grid.tile(start).passable() => start,
+        _ => return found,
+    };
+    let goals: Vec<_> = goals.iter().map(|goal| grid.index_of(goal)).collect();
+
+    let mut open = BinaryHeap::new();
+    costs.insert(start, 0.0);
+    open.push(Node {
+                  index: start,
+                  direction: None,
+                  f: 0.0,
+                  g: 0.0,
+                  time: 0,
+              });
+    while let Some(expand) = open.pop() {
+        if left == 0 {
+            break;
+        }
+        if expand.g > costs[&expand.index] {
+            continue;
+        }
+        for (goal, cost) in goals.iter().zip(found.iter_mut()) {
+            if *goal == Some(expand.index) && cost.is_infinite() {
+                *cost = expand.g;
+                left -= 1;
+            }
+        }
+        for (next, direction, _) in
+            grid.successors(expand.index, None, Tile::passable) {
+            let g = expand.g + length(direction);
+            if costs.get(&next).is_none_or(|&cost| g < cost) {
+                costs.insert(next, g);
+                open.push(Node {
+                              index: next,
+                              direction: Some(direction),
+                              f: g,
+                              g: g,
+                              time: 0,
+                          });
+            }
+        }
+    }
+    found
+}
+
+/// Where to cross from the block `from`, entered at `entry`, into the block
+/// `to` that touches it: onto the tile of `to` nearest to `entry`, from the
+/// tile of `from` nearest to that one, which is always next to it. Blocked
+/// blocks are left from where they were entered.
+fn crossing(from: &Block, entry: &Point, to: &Block) -> (Point, Point) {
+    let next = to.clamp(entry);
+    let exit = if from.free { from.clamp(&next) } else { *entry };
+    (exit, next)
+}
+
+/// Adds the tiles of a straight path from `from` to `to`, which moves
+/// diagonally first, to the end of `path` in reverse, leaving `from` out.
+fn straight(from: &Point, to: &Point, path: &mut Path) {
+    let step = |from: usize, to: usize| if from < to {
+        from + 1
+    } else if from > to {
+        from - 1
+    } else {
+        from
+    };
+    let mut tiles = Vec::new();
+    let mut point = *from;
+    while point != *to {
+        point = Point::new(step(point.y(), to.y()), step(point.x(), to.x()));
+        tiles.push(point);
+    }
+    path.extend(tiles.into_iter().rev());
+}
+
+/// Searches over the free blocks of `tree` for a path from `source` to
+/// `target`, and refines it into a path over tiles. Every block is entered
+/// at its tile nearest to where the previous block was entered, and crossed
+/// in straight lines, so paths are not always optimal, but open areas are
+/// expanded once however large they are. Moves cost their length, whatever
+/// the cost model of the grid, and go in all eight directions, whatever its
+/// movement rules. Expansions count blocks, not tiles.
+pub fn quadtree_astar<H>(tree: &Quadtree,
+                         source: &Point,
+                         target: &Point,
+                         heuristic: H)
+                         -> Option<Data>
+    where H: Fn(&Point, &Point) -> Distance
+{
+    let start = tree.locate(source)?;
+    let goal = tree.locate(target)?;
+    if !tree.block(goal).free && start != goal {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    // The block every block was entered from, where it was entered, and the
+    // cost of getting there.
+    let mut parents = HashMap::new();
+    let mut expansions = 0;
+
+    let initial_h = heuristic(source, target);
+    parents.insert(start, (None, *source, 0.0));
+    open.push(Node {
+                  index: start,
+                  direction: None,
+                  f: initial_h,
+                  g: 0.0,
+                  time: 0,
+              });
+
+    while let Some(expand) = open.pop() {
+        let block = expand.index;
+        let (_, entry, g) = parents[&block];
+        if expand.g > g {
+            continue;
+        }
+        expansions += 1;
+        if block == goal {
+            let mut path = Path::new();
+            let (mut block, mut to) = (block, *target);
+            while let (Some(previous), entry, _) = parents[&block] {
+                straight(&entry, &to, &mut path);
+                path.push(entry);
+                let previous_entry = parents[&previous].1;
+                to = crossing(tree.block(previous),
+                              &previous_entry,
+                              tree.block(block))
+                        .0;
+                block = previous;
+            }
+            straight(&parents[&block].1, &to, &mut path);
+            return Some(Data {
+                            path: path,
+                            expansions: expansions,
+                            initial_h: initial_h,
+                        });
+        }
+
+        let from = tree.block(block);
+        let neighbors = if from.free {
+            tree.neighbors(block)
+        } else {
+            // Only the agent's own tile may be blocked, and it can step off
+            // it onto any neighbor.
+            entry.neighbors()
+                .iter()
+                .flatten()
+                .filter_map(|point| tree.locate(point))
+                .collect()
+        };
+        for next in neighbors {
+            let to = tree.block(next);
+            if !to.free {
+                continue;
+            }
+            let (exit, next_entry) = crossing(from, &entry, to);
+            let g = expand.g + Distance::octile_heuristic(&entry, &exit) +
+                    Distance::octile_heuristic(&exit, &next_entry);
+            let improved = match parents.entry(next) {
+                Entry::Vacant(entry) => {
+                    entry.insert((Some(block), next_entry, g));
+                    true
+                }
+                Entry::Occupied(mut entry) if g < entry.get().2 => {
+                    entry.insert((Some(block), next_entry, g));
+                    true
+                }
+                Entry::Occupied(_) => false,
+            };
+            if improved {
+                open.push(Node {
+                              index: next,
+                              direction: None,
+                              f: g + heuristic(&next_entry, target),
+                              g: g,
+                              time: 0,
+                          });
+            }
+        }
+    }
+
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use agent::{Agent, AlwaysAstar, Bug2, Clairvoyant, CompressedPaths,
+                PotentialField, QuadtreeAstar, RepeatedAstar};
+    use cost::MoveMetric;
+    use experiment::Verbosity;
+    use grid::{GridBuilder, Terrain};
+    use instance::Instance;
+    use observer::Observer;
     use parser::grid_from_str;
+    use plan::{plan_on_beliefs, PlanOptions};
+    use proptest::strategy::{BoxedStrategy, Strategy};
+
+    use std::cell::RefCell;
+    use std::f64::consts::SQRT_2;
+    use std::rc::Rc;
+
+    #[test]
+    fn search_as_far_as_the_goals() {
+        let grid = grid_from_str("type octile\nheight 4\nwidth 5\nmap\n\
+                                  .....\n.TTT.\n.T.T.\n.TTT.");
+        let source = Point::new(3, 0);
+        let goals = [Point::new(0, 4), Point::new(3, 0), Point::new(2, 2),
+                     Point::new(3, 4)];
+        let costs = dijkstra(&grid, &source).costs;
+        let expected = goals.iter()
+            .map(|goal| costs[grid.index_of(goal).unwrap()])
+            .collect::<Vec<_>>();
+        assert_eq!(dijkstra_to(&grid, &source, &goals), expected);
+        assert_eq!(expected[2], Distance::INFINITY);
+        assert_eq!(dijkstra_to(&grid, &Point::new(1, 1), &goals),
+                   vec![Distance::INFINITY; 4]);
+    }
 
     #[test]
     fn solve_with_astar() {
@@ -156,5 +944,504 @@ map
                 .path;
 
         assert_eq!(path.len(), 5);
+
+        // Reaching (1, 1) diagonally first must not keep the straight path
+        // from being found.
+        let mut grid = grid_from_str("type octile
+height 2
+width 5
+map
+.T...
+.....");
+        let data = astar(&mut grid,
+                         &Point::new(1, 3),
+                         &Point::new(0, 0),
+                         Distance::octile_heuristic,
+                         Tile::passable)
+                .unwrap();
+        assert_eq!(path_cost(&Point::new(1, 3), &data.path), 2.0 + SQRT_2);
+        // Space-time A* reaches (1, 1) at the same time either way.
+        let data = space_time_astar(&mut grid,
+                                    &Point::new(1, 3),
+                                    &Point::new(0, 0),
+                                    0..11,
+                                    &ReservationTable::new(),
+                                    Distance::octile_heuristic,
+                                    Tile::passable)
+                .unwrap();
+        assert_eq!(path_cost(&Point::new(1, 3), &data.path), 2.0 + SQRT_2);
+    }
+
+    #[test]
+    fn searches_start_from_the_given_heuristic() {
+        let mut grid = grid_from_str("type octile
+height 3
+width 4
+map
+....
+....
+....");
+        let (source, target) = (Point::new(0, 0), Point::new(2, 3));
+        let euclidean = Distance::euclidean_heuristic(&source, &target);
+        assert!(euclidean != Distance::octile_heuristic(&source, &target));
+
+        grid.set_trace_search(true);
+        let data = astar(&mut grid,
+                         &source,
+                         &target,
+                         Distance::euclidean_heuristic,
+                         Tile::passable)
+                .unwrap();
+        assert_eq!(data.initial_h, euclidean);
+        assert_eq!(grid[&source].h(), euclidean);
+        for expansion in grid.take_search_trace() {
+            assert_eq!(expansion.h,
+                       Distance::euclidean_heuristic(&expansion.point,
+                                                     &target));
+        }
+
+        let data = space_time_astar(&mut grid,
+                                    &source,
+                                    &target,
+                                    0..10,
+                                    &ReservationTable::new(),
+                                    Distance::euclidean_heuristic,
+                                    Tile::passable)
+                .unwrap();
+        assert_eq!(data.initial_h, euclidean);
+    }
+
+    #[test]
+    fn wait_for_reserved_tiles() {
+        let mut grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+
+        let source = Point::new(0, 0);
+        let target = Point::new(0, 2);
+        let mut reservations = ReservationTable::new();
+        reservations.reserve_path(1, &[Point::new(0, 1), Point::new(0, 1)]);
+
+        // The middle tile is taken at times 1 and 2, so the only way through
+        // is to wait twice.
+        let mut path = space_time_astar(&mut grid,
+                                        &source,
+                                        &target,
+                                        0..11,
+                                        &reservations,
+                                        Distance::octile_heuristic,
+                                        Tile::passable)
+                .unwrap()
+                .path;
+        path.reverse();
+        assert_eq!(path, vec![source, source, Point::new(0, 1), target]);
+
+        assert!(space_time_astar(&mut grid,
+                                 &source,
+                                 &target,
+                                 0..4,
+                                 &reservations,
+                                 Distance::octile_heuristic,
+                                 Tile::passable)
+                        .is_none());
+
+        // Something passes over the target later, so the agent cannot stop
+        // there before it has gone.
+        reservations.reserve(target, 6);
+        let path = space_time_astar(&mut grid,
+                                    &source,
+                                    &target,
+                                    0..11,
+                                    &reservations,
+                                    Distance::octile_heuristic,
+                                    Tile::passable)
+                .unwrap()
+                .path;
+        assert_eq!(path.len(), 7);
+        assert_eq!(path[0], target);
+    }
+
+    #[test]
+    fn plan_over_quadtrees() {
+        let open = GridBuilder::new(64, 64).build().unwrap();
+        let tree = Quadtree::from_grid(&open, Tile::passable);
+        let (source, target) = (Point::new(0, 0), Point::new(63, 40));
+        let data = quadtree_astar(&tree,
+                                  &source,
+                                  &target,
+                                  Distance::octile_heuristic)
+                .unwrap();
+        assert_eq!(data.expansions, 1);
+        assert_eq!(data.path.len(), 63);
+        assert_eq!(data.path[0], target);
+        assert!((path_cost(&source, &data.path) -
+                 Distance::octile_heuristic(&source, &target))
+                        .abs() < 1e-9);
+
+        let mut grid = grid_from_str("type octile
+height 6
+width 6
+map
+......
+......
+TTTTT.
+......
+......
+......");
+        let tree = Quadtree::from_grid(&grid, Tile::passable);
+        let (source, target) = (Point::new(0, 0), Point::new(5, 0));
+        let data = quadtree_astar(&tree,
+                                  &source,
+                                  &target,
+                                  Distance::octile_heuristic)
+                .unwrap();
+        let optimal = astar(&mut grid,
+                            &source,
+                            &target,
+                            Distance::octile_heuristic,
+                            Tile::passable)
+                .unwrap();
+        assert!(data.expansions < optimal.expansions);
+        assert!(data.path.iter().all(|point| grid[point].passable()));
+        assert!(path_cost(&source, &data.path) >=
+                path_cost(&source, &optimal.path));
+
+        // Nowhere to go from a closed room, but stepping off a blocked tile
+        // is always allowed.
+        let grid = grid_from_str("type octile
+height 3
+width 3
+map
+.T.
+TT.
+...");
+        let tree = Quadtree::from_grid(&grid, Tile::passable);
+        assert!(quadtree_astar(&tree,
+                               &Point::new(0, 0),
+                               &Point::new(2, 2),
+                               Distance::octile_heuristic)
+                        .is_none());
+        let data = quadtree_astar(&tree,
+                                  &Point::new(1, 1),
+                                  &Point::new(0, 2),
+                                  Distance::octile_heuristic)
+                .unwrap();
+        assert_eq!(data.path[0], Point::new(0, 2));
+        assert!(Point::new(1, 1)
+                    .neighbors()
+                    .contains(&data.path.last().cloned()));
+    }
+
+    #[test]
+    fn search_in_parallel() {
+        let grid = grid_from_str("type octile
+height 4
+width 5
+map
+.....
+.TTT.
+...T.
+.....");
+        let (source, target) = (Point::new(2, 1), Point::new(0, 4));
+        let mut scratch = SearchScratch::new();
+        let serial = detached_astar(&grid,
+                                    &source,
+                                    &target,
+                                    Distance::octile_heuristic,
+                                    Tile::passable,
+                                    None,
+                                    &mut scratch)
+                .unwrap();
+        for workers in 1..5 {
+            let parallel = parallel_astar(&grid,
+                                          &source,
+                                          &target,
+                                          Distance::octile_heuristic,
+                                          Tile::passable,
+                                          None,
+                                          workers)
+                    .unwrap();
+            assert_eq!(parallel.path.first(), Some(&target));
+            assert!((path_cost(&source, &parallel.path) -
+                     path_cost(&source, &serial.path))
+                        .abs() < 1e-9);
+            assert!(parallel.expansions >= 1);
+        }
+
+        let search = |target: &Point, limit| {
+            parallel_astar(&grid,
+                           &source,
+                           target,
+                           Distance::octile_heuristic,
+                           Tile::passable,
+                           limit,
+                           2)
+                .map(|data| data.path)
+        };
+        assert_eq!(search(&source, None), Some(vec![]));
+        assert_eq!(search(&target, Some(2)), None);
+        assert_eq!(search(&Point::new(1, 2), None), None);
+        assert_eq!(search(&Point::new(9, 9), None), None);
+    }
+
+    #[test]
+    fn stop_parallel_search_at_the_limit() {
+        let grid = GridBuilder::new(256, 256).build().unwrap();
+        let (source, target) = (Point::new(0, 0), Point::new(255, 255));
+        // Workers that stop at the limit leave the others sending tiles to
+        // them, which must not fail the search.
+        for _ in 0..10 {
+            for &limit in &[1, 10, 100, 1000] {
+                assert!(parallel_astar(&grid,
+                                       &source,
+                                       &target,
+                                       Distance::octile_heuristic,
+                                       Tile::passable,
+                                       Some(limit),
+                                       4)
+                            .is_none());
+            }
+        }
+    }
+
+    /// The cost of following `path`, as returned by the searches, from
+    /// `source`.
+    fn path_cost(source: &Point, path: &[Point]) -> Distance {
+        let mut from = *source;
+        let mut cost = 0.0;
+        for point in path.iter().rev() {
+            cost += Distance::octile_heuristic(&from, point);
+            from = *point;
+        }
+        cost
+    }
+
+    /// Finds the cost of an optimal path by relaxing every tile until
+    /// nothing changes, independently of the searches under test.
+    fn reference_cost(grid: &Grid,
+                      source: &Point,
+                      target: &Point)
+                      -> Option<Distance> {
+        let mut costs = vec![Distance::INFINITY; grid.height() * grid.width()];
+        costs[grid.index_of(source).unwrap()] = 0.0;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..costs.len() {
+                let point = grid.point_of(index);
+                if !grid.tile(index).passable() {
+                    continue;
+                }
+                for (_, neighbor) in grid.neighbors(&point) {
+                    let from = grid.point_of(neighbor);
+                    let cost = costs[neighbor] +
+                               Distance::octile_heuristic(&from, &point);
+                    if cost < costs[index] - 1e-9 {
+                        costs[index] = cost;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Some(costs[grid.index_of(target).unwrap()])
+            .filter(|cost| cost.is_finite())
+    }
+
+    /// Generates small grids with random obstacles, along with a passable
+    /// source and target.
+    fn small_trials() -> BoxedStrategy<(GridBuilder, Point, Point)> {
+        (1..8usize, 1..8usize)
+            .prop_flat_map(|(height, width)| {
+                (proptest::collection::vec(proptest::bool::weighted(0.3),
+                                           height * width),
+                 (0..height, 0..width),
+                 (0..height, 0..width))
+                        .prop_map(move |(trees, source, target)| {
+                    let source = Point::new(source.0, source.1);
+                    let target = Point::new(target.0, target.1);
+                    let grid = GridBuilder::new(height, width)
+                        .terrain_with(|point| {
+                            let tree = trees[point.y() * width + point.x()];
+                            if tree && *point != source && *point != target {
+                                Terrain::Trees
+                            } else {
+                                Terrain::Ground
+                            }
+                        });
+                    (grid, source, target)
+                })
+            })
+            .boxed()
+    }
+
+    /// Records every move of the agent.
+    struct Trail(Rc<RefCell<Vec<(Point, Point)>>>);
+
+    impl Observer for Trail {
+        fn on_move(&mut self, _grid: &Grid, from: &Point, to: &Point) {
+            self.0.borrow_mut().push((*from, *to));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn optimal_planners_agree((grid, source, target) in small_trials()) {
+            let mut grid = grid.build().unwrap();
+            let expected = reference_cost(&grid, &source, &target);
+            let zero = |_: &Point, _: &Point| 0.0;
+            let tiles = grid.height() * grid.width();
+            let costs = vec![
+                astar(&mut grid,
+                      &source,
+                      &target,
+                      Distance::octile_heuristic,
+                      Tile::passable),
+                astar(&mut grid,
+                      &source,
+                      &target,
+                      Distance::euclidean_heuristic,
+                      Tile::passable),
+                astar(&mut grid, &source, &target, zero, Tile::passable),
+                space_time_astar(&mut grid,
+                                 &source,
+                                 &target,
+                                 0..tiles + 1,
+                                 &ReservationTable::new(),
+                                 Distance::octile_heuristic,
+                                 Tile::passable),
+                parallel_astar(&grid,
+                               &source,
+                               &target,
+                               Distance::octile_heuristic,
+                               Tile::passable,
+                               None,
+                               3),
+                detached_astar(&grid,
+                               &source,
+                               &target,
+                               Distance::octile_heuristic,
+                               Tile::passable,
+                               None,
+                               &mut SearchScratch::new()),
+            ];
+            let mut costs = costs.into_iter()
+                .map(|data| data.map(|data| path_cost(&source, &data.path)))
+                .collect::<Vec<_>>();
+
+            let finite = |cost: Distance| Some(cost).filter(|c| c.is_finite());
+            let index = grid.index_of(&target).unwrap();
+            costs.push(finite(dijkstra(&grid, &source).costs[index]));
+            costs.push(finite(dijkstra_to(&grid, &source, &[target])[0]));
+
+            // Planning over beliefs follows the true map once all of it has
+            // been observed.
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    grid.reveal(&Point::new(y, x));
+                }
+            }
+            let plan = plan_on_beliefs(&grid,
+                                       &source,
+                                       &target,
+                                       &PlanOptions::default());
+            if let Some(ref plan) = plan {
+                let mut path = plan.path.clone();
+                path.reverse();
+                prop_assert!((path_cost(&source, &path) - plan.cost).abs() <
+                             1e-9);
+            }
+            costs.push(plan.map(|plan| plan.cost));
+
+            for cost in costs {
+                match (cost, expected) {
+                    (Some(cost), Some(expected)) => {
+                        prop_assert!((cost - expected).abs() < 1e-9,
+                                     "{} != {}",
+                                     cost,
+                                     expected)
+                    }
+                    (cost, expected) => prop_assert_eq!(cost, expected),
+                }
+            }
+        }
+
+        #[test]
+        fn quadtree_paths_are_valid((grid, source, target)
+                                    in small_trials()) {
+            let grid = grid.build().unwrap();
+            let expected = reference_cost(&grid, &source, &target);
+            let tree = Quadtree::from_grid(&grid, Tile::passable);
+            let data = quadtree_astar(&tree,
+                                      &source,
+                                      &target,
+                                      Distance::octile_heuristic);
+            prop_assert_eq!(data.is_some(), expected.is_some());
+            if let Some(data) = data {
+                let mut location = source;
+                for point in data.path.iter().rev() {
+                    prop_assert!(location != *point &&
+                                 location.y().abs_diff(point.y()) <= 1 &&
+                                 location.x().abs_diff(point.x()) <= 1);
+                    prop_assert!(grid[point].passable());
+                    location = *point;
+                }
+                prop_assert_eq!(location, target);
+                prop_assert!(path_cost(&source, &data.path) >=
+                             expected.unwrap() - 1e-9);
+            }
+        }
+
+        #[test]
+        fn executed_paths_are_valid((builder, source, target)
+                                    in small_trials()) {
+            // Trials never start at their target.
+            prop_assume!(source != target);
+            let grid = builder.clone().build().unwrap();
+            let optimal = reference_cost(&grid, &source, &target);
+            // Each agent, and whether it is sure to solve reachable trials.
+            let octile = Distance::octile_heuristic;
+            let length = MoveMetric::Length;
+            let agents: Vec<(Box<dyn Agent>, bool)> = vec![
+                (Box::new(AlwaysAstar::new(octile, length)), true),
+                (Box::new(RepeatedAstar::new(octile, length)), true),
+                (Box::new(Clairvoyant::new(octile, length)), true),
+                (Box::new(PotentialField::new(octile, length, 1.0, 3)),
+                 false),
+                (Box::new(Bug2::new()), false),
+                (Box::new(QuadtreeAstar::new(octile, length)), true),
+                (Box::new(CompressedPaths::new(None, length)), true),
+            ];
+            for (agent, complete) in agents {
+                let mut grid = builder.clone().build().unwrap();
+                let moves = Rc::new(RefCell::new(Vec::new()));
+                let mut instance = Instance::new(&mut grid,
+                                                 agent,
+                                                 Verbosity::Zero);
+                instance.add_observer(Box::new(Trail(moves.clone())));
+                let datum = instance.run_once(source, target);
+
+                let mut location = source;
+                let mut cost = 0.0;
+                for &(from, to) in moves.borrow().iter() {
+                    prop_assert_eq!(from, location);
+                    prop_assert!(from != to &&
+                                 from.y().abs_diff(to.y()) <= 1 &&
+                                 from.x().abs_diff(to.x()) <= 1);
+                    prop_assert!(grid[&to].passable());
+                    cost += Distance::octile_heuristic(&from, &to);
+                    location = to;
+                }
+                prop_assert!((cost - datum.cost).abs() < 1e-9);
+
+                if datum.solved() {
+                    prop_assert_eq!(location, target);
+                    prop_assert!(datum.cost >= optimal.unwrap() - 1e-9);
+                } else if complete {
+                    prop_assert_eq!(optimal, None);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,247 @@
+//! Answers queries for paths between pairs of points on a single map, read
+//! and prepared once, for programs that ask for many paths on the same map.
+//!
+//! Queries are JSON objects, one per line, such as `{"source": [0, 0],
+//! "target": [3, 4]}`, with points given as `[y, x]` pairs. Every query is
+//! answered with a line holding either the path found, as in `{"path":
+//! [[1, 1], ...], "cost": 5.24, "expansions": 12}`, or an error, as in
+//! `{"error": "No path from (0, 0) to (3, 4)."}`.
+
+use std::io::{BufRead, BufReader, BufWriter, Result as IOResult, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+
+use serde_json;
+
+use connectivity::Connectivity;
+use grid::{Distance, Grid, Point};
+use plan::{plan_with_scratch, PlanOptions, SearchScratch};
+
+#[derive(Debug, Deserialize)]
+struct Query {
+    source: (usize, usize),
+    target: (usize, usize),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Answer {
+    Path {
+        path: Vec<(usize, usize)>,
+        cost: Distance,
+        expansions: usize,
+    },
+    Error { error: String },
+}
+
+/// Plans paths over the true terrain of a map, answering queries one at a
+/// time. Services made with `share` answer queries at the same time as this
+/// one, over the same map.
+pub struct Service {
+    grid: Arc<Grid>,
+    regions: Arc<Connectivity>,
+    options: PlanOptions,
+    scratch: SearchScratch,
+}
+
+impl Service {
+    /// Prepares `grid` for answering queries: every tile is observed, so
+    /// that paths go through the true terrain, and the regions of free
+    /// tiles are found, so that queries between points in different regions
+    /// are answered without searching.
+    pub fn new(mut grid: Grid, options: PlanOptions) -> Service {
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                grid.reveal(&Point::new(y, x));
+            }
+        }
+        let regions = Connectivity::from_grid(&grid);
+        Service {
+            grid: Arc::new(grid),
+            regions: Arc::new(regions),
+            options: options,
+            scratch: SearchScratch::new(),
+        }
+    }
+
+    /// Makes a service over the same map, without copying it, to answer
+    /// queries from another thread.
+    pub fn share(&self) -> Service {
+        Service {
+            grid: self.grid.clone(),
+            regions: self.regions.clone(),
+            options: self.options,
+            scratch: SearchScratch::new(),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Answers a single line holding a query, with a line (without its
+    /// newline) holding the answer.
+    pub fn answer(&mut self, line: &str) -> String {
+        let answer = match serde_json::from_str::<Query>(line) {
+            Ok(query) => {
+                let source = Point::new(query.source.0, query.source.1);
+                let target = Point::new(query.target.0, query.target.1);
+                self.query(&source, &target)
+            }
+            Err(e) => Answer::Error { error: format!("Invalid query: {}", e) },
+        };
+        serde_json::to_string(&answer).unwrap()
+    }
+
+    fn query(&mut self, source: &Point, target: &Point) -> Answer {
+        let (from, to) = match (self.grid.index_of(source),
+                                self.grid.index_of(target)) {
+            (Some(from), Some(to)) => (from, to),
+            (None, _) => return outside(source),
+            (_, None) => return outside(target),
+        };
+        let plan = if from == to || self.regions.connected(from, to) {
+            plan_with_scratch(&self.grid,
+                              source,
+                              target,
+                              &self.options,
+                              &mut self.scratch)
+        } else {
+            None
+        };
+        match plan {
+            Some(plan) => {
+                Answer::Path {
+                    path: plan.path.iter().map(|p| (p.y, p.x)).collect(),
+                    cost: plan.cost,
+                    expansions: plan.expansions,
+                }
+            }
+            None => {
+                Answer::Error {
+                    error: format!("No path from {} to {}.", source, target),
+                }
+            }
+        }
+    }
+
+    /// Answers every query read from `input`, writing the answers to
+    /// `output` as soon as they are found, until `input` ends. Blank lines
+    /// are skipped.
+    pub fn serve<R, W>(&mut self, input: R, output: &mut W) -> IOResult<()>
+        where R: BufRead,
+              W: Write
+    {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            writeln!(output, "{}", self.answer(&line))?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Listens for connections on `address`, answering the queries sent
+    /// over each one as `serve` does, every connection on a thread of its
+    /// own, for as long as the process runs. Calls `on_bound` with the
+    /// address once it listens.
+    pub fn listen<A, F>(&self, address: A, on_bound: F) -> IOResult<()>
+        where A: ToSocketAddrs,
+              F: FnOnce(SocketAddr)
+    {
+        let listener = TcpListener::bind(address)?;
+        on_bound(listener.local_addr()?);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let input = BufReader::new(stream.try_clone()?);
+            let mut service = self.share();
+            thread::spawn(move || {
+                // A client that goes away only ends its own connection.
+                let _ = service.serve(input, &mut BufWriter::new(stream));
+            });
+        }
+        Ok(())
+    }
+}
+
+fn outside(point: &Point) -> Answer {
+    Answer::Error { error: format!("{} lies outside the map.", point) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::net::{Shutdown, TcpStream};
+    use std::sync::mpsc::channel;
+
+    use serde_json::Value;
+
+    use parser::grid_from_str;
+
+    #[test]
+    fn answer_queries() {
+        let grid = grid_from_str("type octile
+height 3
+width 4
+map
+..@.
+..@.
+..@.");
+        let mut service = Service::new(grid, PlanOptions::default());
+
+        let input = "{\"source\": [0, 0], \"target\": [2, 0]}\n\n\
+                     {\"source\": [0, 0], \"target\": [0, 3]}\n\
+                     {\"source\": [0, 0], \"target\": [5, 5]}\n\
+                     [0, 0]\n";
+        let mut output = Vec::new();
+        service.serve(input.as_bytes(), &mut output).unwrap();
+        let answers: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(answers.len(), 4);
+        assert_eq!(answers[0]["path"], serde_json::json!([[1, 0], [2, 0]]));
+        assert_eq!(answers[0]["expansions"], 3);
+        assert_eq!(answers[1]["error"], "No path from (0, 0) to (0, 3).");
+        assert_eq!(answers[2]["error"], "(5, 5) lies outside the map.");
+        assert!(answers[3]["error"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("Invalid query: "));
+    }
+
+    #[test]
+    fn answer_connections_at_once() {
+        let grid = grid_from_str("type octile
+height 1
+width 3
+map
+...");
+        let service = Service::new(grid, PlanOptions::default());
+        let (bound, address) = channel();
+        thread::spawn(move || {
+            service.listen("127.0.0.1:0", |address| {
+                                bound.send(address).unwrap();
+                            })
+        });
+        let address = address.recv().unwrap();
+
+        let idle = TcpStream::connect(address).unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(b"{\"source\": [0, 0], \"target\": [0, 2]}\n")
+            .unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut answer = String::new();
+        client.read_to_string(&mut answer).unwrap();
+        assert_eq!(answer,
+                   "{\"path\":[[0,1],[0,2]],\"cost\":2.0,\"expansions\":3}\n");
+        idle.shutdown(Shutdown::Both).unwrap();
+    }
+}
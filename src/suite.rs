@@ -0,0 +1,141 @@
+//! Scenario suites: several maps, each run for its own number of trials,
+//! reported map by map and aggregated over the whole suite, as results on
+//! the movingai benchmarks usually are.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+use grid::Distance;
+use instance::Summary;
+
+/// A map in a suite, as read by `parser::try_suite_from_file`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub map: PathBuf,
+    /// The number of random trials to run on the map.
+    pub trials: usize,
+    /// How much the map counts towards the suite's weighted means. Maps
+    /// count alike by default, however many trials they run.
+    pub weight: f64,
+}
+
+/// Statistics aggregated over every map of a suite. Counts are totals over
+/// all trials; the success rate and means are the means of those of each
+/// map, weighted by the weights of the maps. Maps with no reachable trial
+/// are left out of the success rate, and maps with no solved trial out of
+/// the means.
+#[derive(Debug, Default, PartialEq)]
+pub struct SuiteSummary {
+    pub maps: usize,
+    pub trials: usize,
+    pub solved: usize,
+    pub unreachable: usize,
+    /// The fraction of reachable trials solved.
+    pub success_rate: f64,
+    pub cost: Distance,
+    pub steps: f64,
+    pub episodes: f64,
+    pub expansions: f64,
+    pub first_move_expansions: f64,
+}
+
+impl SuiteSummary {
+    /// Aggregates the summaries of the results on every map, each with the
+    /// weight of its map.
+    pub fn new(summaries: &[(f64, Summary)]) -> SuiteSummary {
+        let mut suite = SuiteSummary {
+            maps: summaries.len(),
+            ..SuiteSummary::default()
+        };
+        let mut reachable_weight = 0.0;
+        let mut solved_weight = 0.0;
+        for &(weight, ref summary) in summaries {
+            suite.trials += summary.trials;
+            suite.solved += summary.solved;
+            suite.unreachable += summary.unreachable;
+
+            let reachable = summary.trials - summary.unreachable;
+            if reachable > 0 {
+                suite.success_rate += weight * summary.solved as f64 /
+                                      reachable as f64;
+                reachable_weight += weight;
+            }
+            if summary.solved > 0 {
+                suite.cost += weight * summary.cost;
+                suite.steps += weight * summary.steps;
+                suite.episodes += weight * summary.episodes;
+                suite.expansions += weight * summary.expansions;
+                suite.first_move_expansions += weight *
+                                               summary.first_move_expansions;
+                solved_weight += weight;
+            }
+        }
+
+        if reachable_weight > 0.0 {
+            suite.success_rate /= reachable_weight;
+        }
+        if solved_weight > 0.0 {
+            suite.cost /= solved_weight;
+            suite.steps /= solved_weight;
+            suite.episodes /= solved_weight;
+            suite.expansions /= solved_weight;
+            suite.first_move_expansions /= solved_weight;
+        }
+        suite
+    }
+}
+
+impl Display for SuiteSummary {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f,
+               "{}/{} solved over {} maps",
+               self.solved,
+               self.trials,
+               self.maps)?;
+        if self.unreachable > 0 {
+            write!(f, " ({} unreachable)", self.unreachable)?;
+        }
+        write!(f,
+               ", weighted success rate {:.4}, weighted mean {} ({} steps, \
+                {} episodes, {} expansions), weighted mean first move after \
+                {} expansions",
+               self.success_rate,
+               self.cost,
+               self.steps,
+               self.episodes,
+               self.expansions,
+               self.first_move_expansions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weigh_maps() {
+        let summary = |trials, solved, unreachable, cost| {
+            Summary {
+                trials: trials,
+                solved: solved,
+                unreachable: unreachable,
+                cost: cost,
+                expansions: 10.0 * cost,
+                ..Summary::default()
+            }
+        };
+
+        let suite = SuiteSummary::new(&[(1.0, summary(10, 10, 0, 2.0)),
+                                        (3.0, summary(4, 1, 2, 6.0)),
+                                        (5.0, summary(2, 0, 2, 0.0))]);
+        assert_eq!(suite.maps, 3);
+        assert_eq!((suite.trials, suite.solved, suite.unreachable),
+                   (16, 11, 4));
+        // The last map has no reachable trial, and counts for nothing.
+        assert_eq!(suite.success_rate, (1.0 + 3.0 * 0.5) / 4.0);
+        assert_eq!(suite.cost, (2.0 + 3.0 * 6.0) / 4.0);
+        assert_eq!(suite.expansions, 10.0 * suite.cost);
+
+        assert_eq!(SuiteSummary::new(&[]), SuiteSummary::default());
+    }
+}